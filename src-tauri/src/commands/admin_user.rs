@@ -0,0 +1,58 @@
+use serde::Deserialize;
+use crate::modules::admin_user_db::{self, AdminRole, AdminUser};
+
+#[derive(Debug, Deserialize)]
+pub struct CreateAdminUserRequest {
+    pub username: String,
+    pub password: String,
+    pub role: AdminRole,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateAdminUserRequest {
+    pub role: Option<AdminRole>,
+    pub password: Option<String>,
+    pub enabled: Option<bool>,
+}
+
+// 命令实现
+
+/// 列出管理员用户
+#[tauri::command]
+pub async fn list_admin_users() -> Result<Vec<AdminUser>, String> {
+    admin_user_db::list_users()
+}
+
+/// 创建管理员用户 (Argon2 哈希较慢，在阻塞线程池中执行)
+#[tauri::command]
+pub async fn create_admin_user(request: CreateAdminUserRequest) -> Result<AdminUser, String> {
+    tokio::task::spawn_blocking(move || {
+        admin_user_db::create_user(&request.username, &request.password, request.role)
+    })
+    .await
+    .map_err(|e| format!("Spawn blocking failed: {}", e))?
+}
+
+/// 更新管理员用户 (角色 / 密码 / 启用状态)
+#[tauri::command]
+pub async fn update_admin_user(
+    id: String,
+    request: UpdateAdminUserRequest,
+) -> Result<AdminUser, String> {
+    tokio::task::spawn_blocking(move || {
+        admin_user_db::update_user(
+            &id,
+            request.role,
+            request.password.as_deref(),
+            request.enabled,
+        )
+    })
+    .await
+    .map_err(|e| format!("Spawn blocking failed: {}", e))?
+}
+
+/// 删除管理员用户
+#[tauri::command]
+pub async fn delete_admin_user(id: String) -> Result<(), String> {
+    admin_user_db::delete_user(&id)
+}
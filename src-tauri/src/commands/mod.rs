@@ -15,6 +15,8 @@ pub mod security;
 pub mod proxy_pool;
 // 导出 user_token 命令
 pub mod user_token;
+// 导出 admin_user 命令 (管理后台多用户)
+pub mod admin_user;
 
 /// 列出所有账号
 #[tauri::command]
@@ -59,6 +61,11 @@ pub async fn delete_account(
         crate::modules::integration::SystemManager::Desktop(app.clone()),
     );
     service.delete_account(&account_id)?;
+    modules::audit_log::record(
+        &modules::audit_log::AuditActor::desktop(),
+        "account.delete",
+        Some(&account_id),
+    );
 
     // Reload token pool
     let _ = crate::commands::proxy::reload_proxy_accounts(proxy_state).await;
@@ -81,6 +88,11 @@ pub async fn delete_accounts(
         modules::logger::log_error(&format!("批量删除失败: {}", e));
         e
     })?;
+    modules::audit_log::record(
+        &modules::audit_log::AuditActor::desktop(),
+        "account.bulk_delete",
+        Some(&account_ids.join(",")),
+    );
 
     // 强制同步托盘
     crate::modules::tray::update_tray_menus(&app);
@@ -157,7 +169,13 @@ use crate::models::AccountExportResponse;
 
 #[tauri::command]
 pub async fn export_accounts(account_ids: Vec<String>) -> Result<AccountExportResponse, String> {
-    modules::account::export_accounts_by_ids(&account_ids)
+    let response = modules::account::export_accounts_by_ids(&account_ids)?;
+    modules::audit_log::record(
+        &modules::audit_log::AuditActor::desktop(),
+        "account.export",
+        Some(&account_ids.join(",")),
+    );
+    Ok(response)
 }
 
 /// 内部辅助功能：在添加或导入账号后自动刷新一次额度
@@ -339,8 +357,60 @@ pub async fn save_config(
     proxy_state: tauri::State<'_, crate::commands::proxy::ProxyServiceState>,
     config: AppConfig,
 ) -> Result<(), String> {
+    let old_config = modules::load_app_config().ok();
     modules::save_app_config(&config)?;
+    if let Some(old) = old_config.as_ref() {
+        modules::audit_log::record_config_change(
+            &modules::audit_log::AuditActor::desktop(),
+            "config.save",
+            None,
+            old,
+            &config,
+        );
+    }
+
+    apply_saved_config(&app, &proxy_state, &config).await;
+    Ok(())
+}
+
+/// 查询审计日志
+#[tauri::command]
+pub async fn query_audit_log(
+    query: Option<modules::audit_log::AuditQuery>,
+) -> Result<Vec<modules::audit_log::AuditRecord>, String> {
+    tokio::task::spawn_blocking(move || modules::audit_log::query(&query.unwrap_or_default()))
+        .await
+        .map_err(|e| format!("Spawn blocking failed: {}", e))?
+}
+
+/// 将配置回滚到某条审计记录变更之前的版本
+#[tauri::command]
+pub async fn revert_config_from_audit(
+    app: tauri::AppHandle,
+    proxy_state: tauri::State<'_, crate::commands::proxy::ProxyServiceState>,
+    id: i64,
+) -> Result<AppConfig, String> {
+    let target = modules::audit_log::get_config_before(id)?;
+    let current = modules::load_app_config()?;
+    modules::save_app_config(&target)?;
+    modules::audit_log::record_config_change(
+        &modules::audit_log::AuditActor::desktop(),
+        "config.revert",
+        Some(&id.to_string()),
+        &current,
+        &target,
+    );
 
+    apply_saved_config(&app, &proxy_state, &target).await;
+    Ok(target)
+}
+
+/// 通知前端并热更新正在运行的反代服务
+async fn apply_saved_config(
+    app: &tauri::AppHandle,
+    proxy_state: &crate::commands::proxy::ProxyServiceState,
+    config: &AppConfig,
+) {
     // 通知托盘配置已更新
     let _ = app.emit("config://updated", ());
 
@@ -376,11 +446,31 @@ pub async fn save_config(
         crate::proxy::update_global_system_prompt_config(config.proxy.global_system_prompt.clone());
         // [NEW] 更新全局图像思维模式配置
         crate::proxy::update_image_thinking_mode(config.proxy.image_thinking_mode.clone());
+        // 更新日志保留策略配置
+        crate::proxy::update_log_retention_config(config.proxy.log_retention.clone());
+        // 更新重试策略配置
+        crate::proxy::update_retry_policy_config(config.proxy.retry_policy.clone());
+        // 更新上游端点配置
+        crate::proxy::update_upstream_endpoints_config(config.proxy.upstream_endpoints.clone());
+        // 更新公平调度配置
+        crate::proxy::update_fair_scheduling_config(config.proxy.fair_scheduling.clone());
+        crate::proxy::update_hedging_config(config.proxy.hedging.clone());
+        crate::proxy::update_stream_heartbeat_config(config.proxy.stream_heartbeat.clone());
+        crate::proxy::update_auto_continue_config(config.proxy.auto_continue.clone());
+        crate::proxy::update_tool_result_compression_config(config.proxy.tool_result_compression.clone());
+        crate::proxy::update_image_preprocess_config(config.proxy.image_preprocess.clone());
+        crate::proxy::update_image_url_config(config.proxy.image_url.clone());
+        crate::proxy::update_image_store_config(config.proxy.image_store.clone());
         // 更新代理池配置
         instance
             .axum_server
             .update_proxy_pool(config.proxy.proxy_pool.clone())
             .await;
+        // 更新准入队列配置
+        instance
+            .token_manager
+            .update_admission_config(config.proxy.admission_queue.clone())
+            .await;
         // 更新熔断配置
         instance
             .token_manager
@@ -388,8 +478,6 @@ pub async fn save_config(
             .await;
         tracing::debug!("已同步热更新反代服务配置");
     }
-
-    Ok(())
 }
 
 // --- OAuth 命令 ---
@@ -147,6 +147,9 @@ pub async fn internal_start_proxy_service(
     token_manager
         .update_sticky_config(config.scheduling.clone())
         .await;
+    token_manager
+        .update_admission_config(config.admission_queue.clone())
+        .await;
 
     // [NEW] 加载熔断配置 (从主配置加载)
     let app_config = crate::modules::config::load_app_config()
@@ -274,6 +277,21 @@ pub async fn ensure_admin_server(
     crate::proxy::update_global_system_prompt_config(config.global_system_prompt.clone());
     // [NEW] 初始化全局图像思维模式配置
     crate::proxy::update_image_thinking_mode(config.image_thinking_mode.clone());
+    // [NEW] 初始化全局日志保留策略配置
+    crate::proxy::update_log_retention_config(config.log_retention.clone());
+    // 初始化全局重试策略配置
+    crate::proxy::update_retry_policy_config(config.retry_policy.clone());
+    // 初始化全局上游端点配置
+    crate::proxy::update_upstream_endpoints_config(config.upstream_endpoints.clone());
+    // 初始化全局公平调度配置
+    crate::proxy::update_fair_scheduling_config(config.fair_scheduling.clone());
+    crate::proxy::update_hedging_config(config.hedging.clone());
+    crate::proxy::update_stream_heartbeat_config(config.stream_heartbeat.clone());
+    crate::proxy::update_auto_continue_config(config.auto_continue.clone());
+    crate::proxy::update_tool_result_compression_config(config.tool_result_compression.clone());
+    crate::proxy::update_image_preprocess_config(config.image_preprocess.clone());
+    crate::proxy::update_image_url_config(config.image_url.clone());
+    crate::proxy::update_image_store_config(config.image_store.clone());
 
     Ok(())
 }
@@ -412,15 +430,42 @@ pub async fn get_proxy_logs_count() -> Result<u64, String> {
 /// 导出所有日志到指定文件
 #[tauri::command]
 pub async fn export_proxy_logs(file_path: String) -> Result<usize, String> {
-    let logs = crate::modules::proxy_db::get_all_logs_for_export()?;
-    let count = logs.len();
-
-    let json = serde_json::to_string_pretty(&logs)
-        .map_err(|e| format!("Failed to serialize logs: {}", e))?;
+    export_proxy_logs_filtered(
+        file_path,
+        crate::modules::log_export::LogExportFormat::Json,
+        None,
+    )
+    .await
+}
 
-    std::fs::write(&file_path, json).map_err(|e| format!("Failed to write file: {}", e))?;
+/// 按过滤条件流式导出日志 (json / jsonl / csv / har)
+#[tauri::command]
+pub async fn export_proxy_logs_filtered(
+    file_path: String,
+    format: crate::modules::log_export::LogExportFormat,
+    query: Option<crate::modules::proxy_db::LogQuery>,
+) -> Result<usize, String> {
+    tokio::task::spawn_blocking(move || {
+        let file = std::fs::File::create(&file_path)
+            .map_err(|e| format!("Failed to create file: {}", e))?;
+        crate::modules::log_export::export_logs(&query.unwrap_or_default(), format, file)
+    })
+    .await
+    .map_err(|e| format!("Spawn blocking failed: {}", e))?
+}
 
-    Ok(count)
+/// 从 JSONL 导出文件导入日志
+#[tauri::command]
+pub async fn import_proxy_logs(
+    file_path: String,
+) -> Result<crate::modules::log_export::ImportReport, String> {
+    tokio::task::spawn_blocking(move || {
+        let file = std::fs::File::open(&file_path)
+            .map_err(|e| format!("Failed to open file: {}", e))?;
+        crate::modules::log_export::import_jsonl(std::io::BufReader::new(file))
+    })
+    .await
+    .map_err(|e| format!("Spawn blocking failed: {}", e))?
 }
 
 /// 导出指定的日志JSON到文件
@@ -460,6 +505,33 @@ pub async fn get_proxy_logs_filtered(
     crate::modules::proxy_db::get_logs_filtered(&filter, errors_only, limit, offset)
 }
 
+/// 结构化日志搜索 (全文检索 + 结构化过滤 + 游标分页)
+#[tauri::command]
+pub async fn search_proxy_logs(
+    query: crate::modules::proxy_db::LogQuery,
+) -> Result<crate::modules::proxy_db::LogPage, String> {
+    tokio::task::spawn_blocking(move || crate::modules::proxy_db::query_logs(&query))
+        .await
+        .map_err(|e| format!("Spawn blocking failed: {}", e))?
+}
+
+/// 获取日志数据库磁盘占用
+#[tauri::command]
+pub async fn get_log_storage_usage() -> Result<crate::modules::log_retention::StorageUsage, String> {
+    tokio::task::spawn_blocking(crate::modules::log_retention::get_storage_usage)
+        .await
+        .map_err(|e| format!("Spawn blocking failed: {}", e))?
+}
+
+/// 立即执行一次日志清理与压缩
+#[tauri::command]
+pub async fn run_log_maintenance() -> Result<crate::modules::log_retention::MaintenanceReport, String> {
+    let cfg = crate::proxy::config::get_log_retention_config();
+    tokio::task::spawn_blocking(move || crate::modules::log_retention::run_maintenance(&cfg, true))
+        .await
+        .map_err(|e| format!("Spawn blocking failed: {}", e))
+}
+
 /// 生成 API Key
 #[tauri::command]
 pub fn generate_api_key() -> String {
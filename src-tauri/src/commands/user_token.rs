@@ -10,6 +10,8 @@ pub struct CreateTokenRequest {
     pub curfew_start: Option<String>,
     pub curfew_end: Option<String>,
     pub custom_expires_at: Option<i64>,  // 自定义过期时间戳 (秒)
+    #[serde(default)]
+    pub priority: i32,                   // 排队优先级，越大越优先
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -20,6 +22,7 @@ pub struct UpdateTokenRequest {
     pub max_ips: Option<i32>,
     pub curfew_start: Option<Option<String>>,
     pub curfew_end: Option<Option<String>>,
+    pub priority: Option<i32>,
 }
 
 // 命令实现
@@ -41,6 +44,7 @@ pub async fn create_user_token(request: CreateTokenRequest) -> Result<UserToken,
         request.curfew_start,
         request.curfew_end,
         request.custom_expires_at,
+        request.priority,
     )
 }
 
@@ -55,6 +59,7 @@ pub async fn update_user_token(id: String, request: UpdateTokenRequest) -> Resul
         request.max_ips,
         request.curfew_start,
         request.curfew_end,
+        request.priority,
     )
 }
 
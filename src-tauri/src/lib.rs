@@ -71,6 +71,26 @@ pub fn run() {
         error!("Failed to initialize user token database: {}", e);
     }
 
+    // Initialize admin user database
+    if let Err(e) = modules::admin_user_db::init_db() {
+        error!("Failed to initialize admin user database: {}", e);
+    }
+
+    // Initialize audit log database
+    if let Err(e) = modules::audit_log::init_db() {
+        error!("Failed to initialize audit log database: {}", e);
+    }
+
+    // Initialize generated image store database
+    if let Err(e) = modules::image_store::init_db() {
+        error!("Failed to initialize image store database: {}", e);
+    }
+
+    // Initialize Gemini files / cachedContents store
+    if let Err(e) = modules::gemini_store::init_db() {
+        error!("Failed to initialize gemini store database: {}", e);
+    }
+
     if is_headless {
         info!("Starting in HEADLESS mode...");
 
@@ -400,10 +420,15 @@ pub fn run() {
             commands::proxy::get_proxy_logs_count,
             commands::proxy::export_proxy_logs,
             commands::proxy::export_proxy_logs_json,
+            commands::proxy::export_proxy_logs_filtered,
+            commands::proxy::import_proxy_logs,
             commands::proxy::get_proxy_logs_count_filtered,
             commands::proxy::get_proxy_logs_filtered,
+            commands::proxy::search_proxy_logs,
             commands::proxy::set_proxy_monitor_enabled,
             commands::proxy::clear_proxy_logs,
+            commands::proxy::get_log_storage_usage,
+            commands::proxy::run_log_maintenance,
             commands::proxy::generate_api_key,
             commands::proxy::reload_proxy_accounts,
             commands::proxy::update_model_mapping,
@@ -494,6 +519,14 @@ pub fn run() {
             commands::user_token::renew_user_token,
             commands::user_token::get_token_ip_bindings,
             commands::user_token::get_user_token_summary,
+            // Admin Users
+            commands::admin_user::list_admin_users,
+            commands::admin_user::create_admin_user,
+            commands::admin_user::update_admin_user,
+            commands::admin_user::delete_admin_user,
+            // Audit Log
+            commands::query_audit_log,
+            commands::revert_config_from_audit,
         ])
         .build(tauri::generate_context!())
         .expect("error while building tauri application")
@@ -502,6 +535,7 @@ pub fn run() {
                 // Handle app exit - cleanup background tasks
                 tauri::RunEvent::Exit => {
                     tracing::info!("Application exiting, cleaning up background tasks...");
+                    crate::proxy::mappers::estimation_calibrator::flush_calibrator();
                     if let Some(state) = app_handle.try_state::<crate::commands::proxy::ProxyServiceState>() {
                         tauri::async_runtime::block_on(async {
                             // Use timeout-based read() instead of try_read() to handle lock contention
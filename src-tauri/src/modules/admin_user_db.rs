@@ -0,0 +1,463 @@
+//! Admin User Database Module
+//! 管理后台多用户 (角色 + 会话) 存储模块
+//!
+//! - 密码使用 Argon2id 哈希存储
+//! - 会话令牌仅存储 SHA-256 摘要，明文只在登录时返回一次
+
+use argon2::password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use chrono::Utc;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::PathBuf;
+use uuid::Uuid;
+
+/// 会话令牌前缀 (用于和 admin_password / api_key 区分)
+pub const SESSION_TOKEN_PREFIX: &str = "ags-";
+
+/// 会话有效期 (秒)
+pub const SESSION_TTL_SECS: i64 = 12 * 3600;
+
+/// 用户不存在或已禁用时用于校验的哈希 (与 hash_password 参数一致)，
+/// 使这类登录与密码错误耗时相同，无法据此枚举用户名
+const DUMMY_PASSWORD_HASH: &str =
+    "$argon2id$v=19$m=19456,t=2,p=1$H6m465I5UDjJmvoIMd5X7A$Vb2IIgWbVOWCSwhw/mvYNoQD34a9ulgKTeDmIzFyQPE";
+
+/// 管理员角色 (权限从低到高)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AdminRole {
+    /// 只读: 查看账号、统计与日志
+    Viewer,
+    /// 运维: 可启停反代、切换账号、修改运行参数，但不能导出凭据或管理用户
+    Operator,
+    /// 所有者: 全部权限
+    Owner,
+}
+
+impl AdminRole {
+    pub fn allows(&self, required: AdminRole) -> bool {
+        *self >= required
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AdminRole::Viewer => "viewer",
+            AdminRole::Operator => "operator",
+            AdminRole::Owner => "owner",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<AdminRole> {
+        match s {
+            "viewer" => Some(AdminRole::Viewer),
+            "operator" => Some(AdminRole::Operator),
+            "owner" => Some(AdminRole::Owner),
+            _ => None,
+        }
+    }
+}
+
+/// 管理员用户 (不包含密码哈希)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AdminUser {
+    pub id: String,
+    pub username: String,
+    pub role: AdminRole,
+    pub enabled: bool,
+    pub created_at: i64,
+    pub updated_at: i64,
+    pub last_login_at: Option<i64>,
+}
+
+/// 登录成功后返回的会话
+#[derive(Debug, Clone, Serialize)]
+pub struct AdminSession {
+    pub token: String,
+    pub expires_at: i64,
+    pub user: AdminUser,
+}
+
+/// 获取数据库路径
+pub fn get_db_path() -> Result<PathBuf, String> {
+    let mut path = crate::modules::account::get_data_dir()?;
+    path.push("admin_users.db");
+    Ok(path)
+}
+
+/// 连接数据库
+pub fn connect_db() -> Result<Connection, String> {
+    let path = get_db_path()?;
+    let conn = Connection::open(&path).map_err(|e| format!("Failed to open database: {}", e))?;
+    conn.execute_batch("PRAGMA foreign_keys = ON;")
+        .map_err(|e| format!("Failed to enable foreign keys: {}", e))?;
+    Ok(conn)
+}
+
+/// 初始化数据库
+pub fn init_db() -> Result<(), String> {
+    let conn = connect_db()?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS admin_users (
+            id TEXT PRIMARY KEY,
+            username TEXT UNIQUE NOT NULL,
+            password_hash TEXT NOT NULL,
+            role TEXT NOT NULL,
+            enabled BOOLEAN NOT NULL DEFAULT 1,
+            created_at INTEGER NOT NULL,
+            updated_at INTEGER NOT NULL,
+            last_login_at INTEGER
+        )",
+        [],
+    )
+    .map_err(|e| format!("Failed to create admin_users table: {}", e))?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS admin_sessions (
+            token_hash TEXT PRIMARY KEY,
+            user_id TEXT NOT NULL,
+            created_at INTEGER NOT NULL,
+            expires_at INTEGER NOT NULL,
+            last_seen_at INTEGER NOT NULL,
+            FOREIGN KEY(user_id) REFERENCES admin_users(id) ON DELETE CASCADE
+        )",
+        [],
+    )
+    .map_err(|e| format!("Failed to create admin_sessions table: {}", e))?;
+
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_admin_sessions_user ON admin_sessions (user_id)",
+        [],
+    )
+    .map_err(|e| format!("Failed to create index: {}", e))?;
+
+    Ok(())
+}
+
+/// 使用 Argon2id 计算密码哈希 (PHC 字符串格式)
+pub fn hash_password(password: &str) -> Result<String, String> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|h| h.to_string())
+        .map_err(|e| format!("Failed to hash password: {}", e))
+}
+
+/// 校验密码
+pub fn verify_password(password: &str, hash: &str) -> bool {
+    match PasswordHash::new(hash) {
+        Ok(parsed) => Argon2::default()
+            .verify_password(password.as_bytes(), &parsed)
+            .is_ok(),
+        Err(_) => false,
+    }
+}
+
+fn hash_session_token(token: &str) -> String {
+    let digest = Sha256::digest(token.as_bytes());
+    digest.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn validate_username(username: &str) -> Result<(), String> {
+    let trimmed = username.trim();
+    if trimmed.is_empty() || trimmed.len() > 64 {
+        return Err("Username must be 1-64 characters".to_string());
+    }
+    Ok(())
+}
+
+fn validate_password(password: &str) -> Result<(), String> {
+    if password.chars().count() < 8 {
+        return Err("Password must be at least 8 characters".to_string());
+    }
+    Ok(())
+}
+
+const USER_COLUMNS: &str = "id, username, role, enabled, created_at, updated_at, last_login_at";
+
+fn row_to_user(row: &rusqlite::Row) -> rusqlite::Result<AdminUser> {
+    let role: String = row.get(2)?;
+    Ok(AdminUser {
+        id: row.get(0)?,
+        username: row.get(1)?,
+        role: AdminRole::parse(&role).unwrap_or(AdminRole::Viewer),
+        enabled: row.get(3)?,
+        created_at: row.get(4)?,
+        updated_at: row.get(5)?,
+        last_login_at: row.get(6)?,
+    })
+}
+
+/// 列出所有管理员用户
+pub fn list_users() -> Result<Vec<AdminUser>, String> {
+    let conn = connect_db()?;
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {} FROM admin_users ORDER BY created_at ASC",
+            USER_COLUMNS
+        ))
+        .map_err(|e| e.to_string())?;
+    let users = stmt
+        .query_map([], row_to_user)
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    Ok(users)
+}
+
+/// 获取单个用户
+pub fn get_user(id: &str) -> Result<Option<AdminUser>, String> {
+    let conn = connect_db()?;
+    conn.query_row(
+        &format!("SELECT {} FROM admin_users WHERE id = ?1", USER_COLUMNS),
+        params![id],
+        row_to_user,
+    )
+    .optional()
+    .map_err(|e| e.to_string())
+}
+
+/// 创建管理员用户
+pub fn create_user(username: &str, password: &str, role: AdminRole) -> Result<AdminUser, String> {
+    validate_username(username)?;
+    validate_password(password)?;
+
+    let conn = connect_db()?;
+    let now = Utc::now().timestamp();
+    let user = AdminUser {
+        id: Uuid::new_v4().to_string(),
+        username: username.trim().to_string(),
+        role,
+        enabled: true,
+        created_at: now,
+        updated_at: now,
+        last_login_at: None,
+    };
+
+    conn.execute(
+        "INSERT INTO admin_users (id, username, password_hash, role, enabled, created_at, updated_at)
+         VALUES (?1, ?2, ?3, ?4, 1, ?5, ?6)",
+        params![
+            user.id,
+            user.username,
+            hash_password(password)?,
+            role.as_str(),
+            now,
+            now
+        ],
+    )
+    .map_err(|e| match e {
+        rusqlite::Error::SqliteFailure(ref err, _)
+            if err.code == rusqlite::ErrorCode::ConstraintViolation =>
+        {
+            format!("Username '{}' already exists", user.username)
+        }
+        other => format!("Failed to create admin user: {}", other),
+    })?;
+
+    Ok(user)
+}
+
+fn count_active_owners(conn: &Connection, excluding: &str) -> Result<i64, String> {
+    conn.query_row(
+        "SELECT COUNT(*) FROM admin_users WHERE role = 'owner' AND enabled = 1 AND id != ?1",
+        params![excluding],
+        |row| row.get(0),
+    )
+    .map_err(|e| e.to_string())
+}
+
+/// 更新用户 (角色 / 密码 / 启用状态)
+/// 角色、密码或启用状态变更后该用户的所有会话失效
+pub fn update_user(
+    id: &str,
+    role: Option<AdminRole>,
+    password: Option<&str>,
+    enabled: Option<bool>,
+) -> Result<AdminUser, String> {
+    let current = get_user(id)?.ok_or_else(|| "Admin user not found".to_string())?;
+    if let Some(pwd) = password {
+        validate_password(pwd)?;
+    }
+
+    let mut conn = connect_db()?;
+    let demotes_owner = current.role == AdminRole::Owner
+        && (role.map_or(false, |r| r != AdminRole::Owner) || enabled == Some(false));
+    if demotes_owner && count_active_owners(&conn, id)? == 0 {
+        return Err("Cannot demote or disable the last owner".to_string());
+    }
+
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let now = Utc::now().timestamp();
+    if let Some(r) = role {
+        tx.execute(
+            "UPDATE admin_users SET role = ?1, updated_at = ?2 WHERE id = ?3",
+            params![r.as_str(), now, id],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    if let Some(pwd) = password {
+        tx.execute(
+            "UPDATE admin_users SET password_hash = ?1, updated_at = ?2 WHERE id = ?3",
+            params![hash_password(pwd)?, now, id],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    if let Some(en) = enabled {
+        tx.execute(
+            "UPDATE admin_users SET enabled = ?1, updated_at = ?2 WHERE id = ?3",
+            params![en, now, id],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    if role.is_some() || password.is_some() || enabled.is_some() {
+        tx.execute("DELETE FROM admin_sessions WHERE user_id = ?1", params![id])
+            .map_err(|e| e.to_string())?;
+    }
+    tx.commit().map_err(|e| e.to_string())?;
+
+    get_user(id)?.ok_or_else(|| "Admin user not found".to_string())
+}
+
+/// 删除用户 (不允许删除最后一个 owner)
+pub fn delete_user(id: &str) -> Result<(), String> {
+    let current = get_user(id)?.ok_or_else(|| "Admin user not found".to_string())?;
+    let conn = connect_db()?;
+    if current.role == AdminRole::Owner && count_active_owners(&conn, id)? == 0 {
+        return Err("Cannot delete the last owner".to_string());
+    }
+    conn.execute("DELETE FROM admin_users WHERE id = ?1", params![id])
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// 用户名密码登录，成功后创建会话
+/// 用户不存在、已禁用或密码错误统一返回 Ok(None)
+pub fn login(username: &str, password: &str) -> Result<Option<AdminSession>, String> {
+    let conn = connect_db()?;
+    let found: Option<(String, String, bool)> = conn
+        .query_row(
+            "SELECT id, password_hash, enabled FROM admin_users WHERE username = ?1",
+            params![username.trim()],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+
+    let (user_id, hash) = match found {
+        Some((id, hash, true)) => (id, hash),
+        _ => {
+            verify_password(password, DUMMY_PASSWORD_HASH);
+            return Ok(None);
+        }
+    };
+    if !verify_password(password, &hash) {
+        return Ok(None);
+    }
+
+    let now = Utc::now().timestamp();
+    let token = format!("{}{}", SESSION_TOKEN_PREFIX, Uuid::new_v4().simple());
+    let expires_at = now + SESSION_TTL_SECS;
+
+    // 顺便清理已过期会话
+    conn.execute(
+        "DELETE FROM admin_sessions WHERE expires_at < ?1",
+        params![now],
+    )
+    .map_err(|e| e.to_string())?;
+    conn.execute(
+        "INSERT INTO admin_sessions (token_hash, user_id, created_at, expires_at, last_seen_at)
+         VALUES (?1, ?2, ?3, ?4, ?3)",
+        params![hash_session_token(&token), user_id, now, expires_at],
+    )
+    .map_err(|e| e.to_string())?;
+    conn.execute(
+        "UPDATE admin_users SET last_login_at = ?1 WHERE id = ?2",
+        params![now, user_id],
+    )
+    .map_err(|e| e.to_string())?;
+
+    let user = get_user(&user_id)?.ok_or_else(|| "Admin user not found".to_string())?;
+    Ok(Some(AdminSession {
+        token,
+        expires_at,
+        user,
+    }))
+}
+
+/// 校验会话令牌，返回对应的 (已启用) 用户
+pub fn validate_session(token: &str) -> Result<Option<AdminUser>, String> {
+    let conn = connect_db()?;
+    let now = Utc::now().timestamp();
+    let token_hash = hash_session_token(token);
+
+    let user = conn
+        .query_row(
+            "SELECT u.id, u.username, u.role, u.enabled, u.created_at, u.updated_at, u.last_login_at
+             FROM admin_users u JOIN admin_sessions s ON s.user_id = u.id
+             WHERE s.token_hash = ?1 AND s.expires_at >= ?2 AND u.enabled = 1",
+            params![token_hash, now],
+            row_to_user,
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+
+    if user.is_some() {
+        let _ = conn.execute(
+            "UPDATE admin_sessions SET last_seen_at = ?1 WHERE token_hash = ?2",
+            params![now, token_hash],
+        );
+    }
+    Ok(user)
+}
+
+/// 注销会话
+pub fn revoke_session(token: &str) -> Result<(), String> {
+    let conn = connect_db()?;
+    conn.execute(
+        "DELETE FROM admin_sessions WHERE token_hash = ?1",
+        params![hash_session_token(token)],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_password_hash_roundtrip() {
+        let hash = hash_password("correct horse").unwrap();
+        assert!(hash.starts_with("$argon2id$"));
+        assert!(verify_password("correct horse", &hash));
+        assert!(!verify_password("wrong horse", &hash));
+        assert!(!verify_password("correct horse", "not-a-phc-string"));
+
+        // 占位哈希须与真实哈希使用相同参数，校验耗时才一致
+        let real = PasswordHash::new(&hash).unwrap();
+        let dummy = PasswordHash::new(DUMMY_PASSWORD_HASH).unwrap();
+        assert_eq!(dummy.algorithm, real.algorithm);
+        assert_eq!(dummy.params, real.params);
+    }
+
+    #[test]
+    fn test_role_ordering() {
+        assert!(AdminRole::Owner.allows(AdminRole::Operator));
+        assert!(AdminRole::Operator.allows(AdminRole::Viewer));
+        assert!(!AdminRole::Viewer.allows(AdminRole::Operator));
+        assert!(!AdminRole::Operator.allows(AdminRole::Owner));
+        assert_eq!(AdminRole::parse("operator"), Some(AdminRole::Operator));
+        assert_eq!(AdminRole::parse("root"), None);
+    }
+
+    #[test]
+    fn test_session_token_hash_is_stable() {
+        let a = hash_session_token("ags-abc");
+        assert_eq!(a, hash_session_token("ags-abc"));
+        assert_ne!(a, hash_session_token("ags-abd"));
+        assert_eq!(a.len(), 64);
+    }
+}
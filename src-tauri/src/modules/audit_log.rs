@@ -0,0 +1,414 @@
+//! Audit Log Module
+//! 管理操作审计日志 (只追加)
+//!
+//! - 记录操作者、来源 IP、动作、目标以及脱敏后的配置差异
+//! - 配置变更额外保存变更前后的完整快照 (加密存储)，用于回滚
+//! - 表上的触发器禁止 UPDATE / DELETE
+
+use crate::models::AppConfig;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::PathBuf;
+
+/// 审计操作者
+#[derive(Debug, Clone)]
+pub struct AuditActor {
+    pub actor: String,
+    pub role: Option<String>,
+    pub source_ip: Option<String>,
+}
+
+impl AuditActor {
+    /// 桌面端本地操作
+    pub fn desktop() -> Self {
+        Self {
+            actor: "desktop".to_string(),
+            role: None,
+            source_ip: None,
+        }
+    }
+}
+
+/// 单个字段的变更 (敏感字段的值以 *** 代替)
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct DiffEntry {
+    pub path: String,
+    pub before: Value,
+    pub after: Value,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditRecord {
+    pub id: i64,
+    pub timestamp: i64,
+    pub actor: String,
+    pub actor_role: Option<String>,
+    pub source_ip: Option<String>,
+    pub action: String,
+    pub target: Option<String>,
+    pub diff: Option<Vec<DiffEntry>>,
+    /// 是否保存了可用于回滚的配置快照
+    pub revertable: bool,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct AuditQuery {
+    pub action: Option<String>,
+    pub actor: Option<String>,
+    pub target: Option<String>,
+    pub start_time: Option<i64>,
+    pub end_time: Option<i64>,
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+}
+
+/// 视为敏感的字段名 (统一为小写下划线形式后完全匹配)
+const SENSITIVE_KEYS: &[&str] = &["password", "token", "secret", "api_key", "apikey"];
+/// 视为敏感的字段名后缀 (如 admin_password、refresh_token、client_secret、encryption_key)
+const SENSITIVE_SUFFIXES: &[&str] = &["_password", "_token", "_secret", "_key", "_apikey"];
+
+const REDACTED: &str = "***";
+
+/// 获取数据库路径
+pub fn get_db_path() -> Result<PathBuf, String> {
+    let mut path = crate::modules::account::get_data_dir()?;
+    path.push("audit.db");
+    Ok(path)
+}
+
+fn connect_db() -> Result<Connection, String> {
+    let path = get_db_path()?;
+    Connection::open(&path).map_err(|e| format!("Failed to open database: {}", e))
+}
+
+/// 初始化数据库
+pub fn init_db() -> Result<(), String> {
+    let conn = connect_db()?;
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS audit_log (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            timestamp INTEGER NOT NULL,
+            actor TEXT NOT NULL,
+            actor_role TEXT,
+            source_ip TEXT,
+            action TEXT NOT NULL,
+            target TEXT,
+            diff TEXT,
+            before_snapshot TEXT,
+            after_snapshot TEXT
+        );
+        CREATE INDEX IF NOT EXISTS idx_audit_timestamp ON audit_log (timestamp DESC);
+        CREATE INDEX IF NOT EXISTS idx_audit_action ON audit_log (action);
+        CREATE TRIGGER IF NOT EXISTS audit_log_no_update BEFORE UPDATE ON audit_log
+        BEGIN SELECT RAISE(ABORT, 'audit_log is append-only'); END;
+        CREATE TRIGGER IF NOT EXISTS audit_log_no_delete BEFORE DELETE ON audit_log
+        BEGIN SELECT RAISE(ABORT, 'audit_log is append-only'); END;",
+    )
+    .map_err(|e| format!("Failed to create audit_log table: {}", e))?;
+    Ok(())
+}
+
+/// camelCase / kebab-case 字段名统一为 snake_case 后按完整名称或后缀匹配
+fn is_sensitive(key: &str) -> bool {
+    let mut normalized = String::with_capacity(key.len() + 4);
+    for (i, c) in key.chars().enumerate() {
+        if c.is_ascii_uppercase() && i > 0 {
+            normalized.push('_');
+        }
+        normalized.push(if c == '-' { '_' } else { c.to_ascii_lowercase() });
+    }
+    SENSITIVE_KEYS.contains(&normalized.as_str())
+        || SENSITIVE_SUFFIXES.iter().any(|s| normalized.ends_with(s))
+}
+
+/// 递归脱敏 JSON：sensitive 表示该值位于敏感字段下，其中的标量替换为 ***，对象继续按子字段判断
+fn redact(value: &Value, sensitive: bool) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(k, v)| (k.clone(), redact(v, is_sensitive(k))))
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(items.iter().map(|v| redact(v, sensitive)).collect()),
+        Value::Null => Value::Null,
+        _ if sensitive => Value::String(REDACTED.to_string()),
+        other => other.clone(),
+    }
+}
+
+/// 计算两个 JSON 的叶子级差异 (敏感路径的值以 *** 表示)
+pub fn diff_json(before: &Value, after: &Value) -> Vec<DiffEntry> {
+    let mut out = Vec::new();
+    diff_inner("", before, after, false, &mut out);
+    out
+}
+
+fn diff_inner(path: &str, before: &Value, after: &Value, sensitive: bool, out: &mut Vec<DiffEntry>) {
+    if before == after {
+        return;
+    }
+    match (before, after) {
+        (Value::Object(a), Value::Object(b)) => {
+            let mut keys: Vec<&String> = a.keys().chain(b.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                let child = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", path, key)
+                };
+                diff_inner(
+                    &child,
+                    a.get(key).unwrap_or(&Value::Null),
+                    b.get(key).unwrap_or(&Value::Null),
+                    is_sensitive(key),
+                    out,
+                );
+            }
+        }
+        _ => {
+            let mask = |v: &Value| redact(v, sensitive);
+            out.push(DiffEntry {
+                path: path.to_string(),
+                before: mask(before),
+                after: mask(after),
+            });
+        }
+    }
+}
+
+fn insert(
+    actor: &AuditActor,
+    action: &str,
+    target: Option<&str>,
+    diff: Option<&[DiffEntry]>,
+    snapshots: Option<(&AppConfig, &AppConfig)>,
+) -> Result<i64, String> {
+    let conn = connect_db()?;
+    let diff_json = diff
+        .map(|d| serde_json::to_string(d))
+        .transpose()
+        .map_err(|e| e.to_string())?;
+    let (before_snapshot, after_snapshot) = match snapshots {
+        Some((before, after)) => (
+            Some(encrypt_snapshot(before)?),
+            Some(encrypt_snapshot(after)?),
+        ),
+        None => (None, None),
+    };
+
+    conn.execute(
+        "INSERT INTO audit_log (timestamp, actor, actor_role, source_ip, action, target, diff, before_snapshot, after_snapshot)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        params![
+            chrono::Utc::now().timestamp_millis(),
+            actor.actor,
+            actor.role,
+            actor.source_ip,
+            action,
+            target,
+            diff_json,
+            before_snapshot,
+            after_snapshot,
+        ],
+    )
+    .map_err(|e| format!("Failed to write audit log: {}", e))?;
+
+    Ok(conn.last_insert_rowid())
+}
+
+fn encrypt_snapshot(config: &AppConfig) -> Result<String, String> {
+    let json = serde_json::to_string(config).map_err(|e| e.to_string())?;
+    crate::utils::crypto::encrypt_blob(&json)
+}
+
+/// 记录一次普通操作 (失败只记日志，不影响业务)
+pub fn record(actor: &AuditActor, action: &str, target: Option<&str>) {
+    if let Err(e) = insert(actor, action, target, None, None) {
+        tracing::error!("[Audit] {}", e);
+    }
+}
+
+/// 记录一次配置变更 (包含脱敏差异与可回滚快照)
+pub fn record_config_change(
+    actor: &AuditActor,
+    action: &str,
+    target: Option<&str>,
+    before: &AppConfig,
+    after: &AppConfig,
+) {
+    let diff = match (serde_json::to_value(before), serde_json::to_value(after)) {
+        (Ok(b), Ok(a)) => diff_json(&b, &a),
+        _ => Vec::new(),
+    };
+    if let Err(e) = insert(actor, action, target, Some(&diff), Some((before, after))) {
+        tracing::error!("[Audit] {}", e);
+    }
+}
+
+/// 查询审计日志 (按时间倒序)
+pub fn query(q: &AuditQuery) -> Result<Vec<AuditRecord>, String> {
+    let conn = connect_db()?;
+
+    let mut clauses: Vec<&str> = Vec::new();
+    let mut args: Vec<Box<dyn rusqlite::ToSql>> = Vec::new();
+    if let Some(action) = q.action.as_ref().filter(|s| !s.is_empty()) {
+        clauses.push("action LIKE ?");
+        args.push(Box::new(format!("{}%", action)));
+    }
+    if let Some(actor) = q.actor.as_ref().filter(|s| !s.is_empty()) {
+        clauses.push("actor = ?");
+        args.push(Box::new(actor.clone()));
+    }
+    if let Some(target) = q.target.as_ref().filter(|s| !s.is_empty()) {
+        clauses.push("target LIKE ?");
+        args.push(Box::new(format!("%{}%", target)));
+    }
+    if let Some(start) = q.start_time {
+        clauses.push("timestamp >= ?");
+        args.push(Box::new(start));
+    }
+    if let Some(end) = q.end_time {
+        clauses.push("timestamp <= ?");
+        args.push(Box::new(end));
+    }
+
+    let where_sql = if clauses.is_empty() {
+        String::new()
+    } else {
+        format!("WHERE {}", clauses.join(" AND "))
+    };
+    let sql = format!(
+        "SELECT id, timestamp, actor, actor_role, source_ip, action, target, diff, before_snapshot IS NOT NULL
+         FROM audit_log {} ORDER BY id DESC LIMIT {} OFFSET {}",
+        where_sql,
+        q.limit.unwrap_or(100).min(1000),
+        q.offset.unwrap_or(0)
+    );
+
+    let mut stmt = conn.prepare(&sql).map_err(|e| e.to_string())?;
+    let records = stmt
+        .query_map(
+            rusqlite::params_from_iter(args.iter().map(|a| a.as_ref())),
+            |row| {
+                let diff: Option<String> = row.get(7)?;
+                Ok(AuditRecord {
+                    id: row.get(0)?,
+                    timestamp: row.get(1)?,
+                    actor: row.get(2)?,
+                    actor_role: row.get(3)?,
+                    source_ip: row.get(4)?,
+                    action: row.get(5)?,
+                    target: row.get(6)?,
+                    diff: diff.and_then(|d| serde_json::from_str(&d).ok()),
+                    revertable: row.get(8)?,
+                })
+            },
+        )
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+
+    Ok(records)
+}
+
+/// 获取某次配置变更之前的完整配置 (用于回滚)
+pub fn get_config_before(id: i64) -> Result<AppConfig, String> {
+    let conn = connect_db()?;
+    let snapshot: Option<Option<String>> = conn
+        .query_row(
+            "SELECT before_snapshot FROM audit_log WHERE id = ?1",
+            params![id],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+
+    let encrypted = snapshot
+        .ok_or_else(|| format!("Audit entry {} not found", id))?
+        .ok_or_else(|| format!("Audit entry {} has no config snapshot", id))?;
+    let json = crate::utils::crypto::decrypt_blob(&encrypted)?;
+    serde_json::from_str(&json).map_err(|e| format!("Failed to parse config snapshot: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_diff_masks_sensitive_paths() {
+        let before = json!({
+            "proxy": { "port": 8045, "api_key": "sk-old", "zai": { "api_key": "z1", "enabled": false } },
+            "language": "zh"
+        });
+        let after = json!({
+            "proxy": { "port": 8046, "api_key": "sk-new", "zai": { "api_key": "z1", "enabled": true } },
+            "language": "zh"
+        });
+
+        let diff = diff_json(&before, &after);
+        assert_eq!(diff.len(), 3);
+        assert_eq!(
+            diff[0],
+            DiffEntry {
+                path: "proxy.api_key".to_string(),
+                before: json!("***"),
+                after: json!("***"),
+            }
+        );
+        assert_eq!(diff[1].path, "proxy.port");
+        assert_eq!(diff[1].after, json!(8046));
+        assert_eq!(diff[2].path, "proxy.zai.enabled");
+    }
+
+    #[test]
+    fn test_redact_nested_objects() {
+        let value = json!({
+            "cloudflared": { "token": "secret-token", "enabled": true },
+            "proxy": { "admin_password": null, "upstream_proxy": { "url": "http://x" } }
+        });
+        let redacted = redact(&value, false);
+        assert_eq!(redacted["cloudflared"]["token"], "***");
+        assert_eq!(redacted["cloudflared"]["enabled"], true);
+        assert!(redacted["proxy"]["admin_password"].is_null());
+        assert_eq!(redacted["proxy"]["upstream_proxy"]["url"], "http://x");
+    }
+
+    #[test]
+    fn test_sensitive_key_matching() {
+        let value = json!({
+            "refresh_token": "r1",
+            "accessToken": "a1",
+            "client-secret": "c1",
+            "encryption_key": "k1",
+            "max_tokens": 4096,
+            "refresh_interval": 30,
+            "token_id": "t1",
+            // 敏感名称下的对象继续按子字段脱敏，而不是整体替换
+            "api_key": { "enabled": true, "value": "sk-1", "keys": ["x"] },
+            "secret": ["s1", "s2"]
+        });
+        let redacted = redact(&value, false);
+        for key in ["refresh_token", "accessToken", "client-secret", "encryption_key"] {
+            assert_eq!(redacted[key], "***", "{}", key);
+        }
+        assert_eq!(redacted["max_tokens"], 4096);
+        assert_eq!(redacted["refresh_interval"], 30);
+        assert_eq!(redacted["token_id"], "t1");
+        assert_eq!(redacted["api_key"]["enabled"], true);
+        assert_eq!(redacted["api_key"]["value"], "sk-1");
+        assert_eq!(redacted["secret"], json!(["***", "***"]));
+
+        let diff = diff_json(
+            &json!({ "proxy": { "refresh_interval": 30, "zai": { "api_key": "z1" } } }),
+            &json!({ "proxy": { "refresh_interval": 60, "zai": { "api_key": "z2" } } }),
+        );
+        assert_eq!(diff[0].after, json!(60));
+        assert_eq!(diff[1].path, "proxy.zai.api_key");
+        assert_eq!(diff[1].after, json!("***"));
+    }
+}
@@ -0,0 +1,492 @@
+//! Gemini Store Module
+//! 本地实现的 Gemini `files` 与 `cachedContents` 资源
+//!
+//! - 上传的文件保存在数据目录的 `gemini_files/` 下，元数据存于 SQLite，48 小时后过期 (与官方一致)
+//! - cachedContents 保存请求中的 contents / systemInstruction / tools，使用时由代理拼接到请求前部
+//! - 资源归属于创建它的 User Token (owner)；未使用 User Token 的调用方共享 owner 为空的资源
+
+use base64::Engine as _;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::path::PathBuf;
+
+/// 上传文件的保留时长
+pub const FILE_TTL_MS: i64 = 48 * 3600 * 1000;
+
+#[derive(Debug, Clone)]
+pub struct FileRecord {
+    pub id: String,
+    pub display_name: String,
+    pub mime_type: String,
+    pub size_bytes: i64,
+    /// base64 编码的 SHA-256
+    pub sha256: String,
+    pub created_at: i64,
+    pub expires_at: i64,
+    /// 创建者的 User Token ID
+    pub owner: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct CachedContentRecord {
+    pub id: String,
+    pub model: String,
+    pub display_name: String,
+    /// contents / systemInstruction / tools / toolConfig
+    pub content: Value,
+    pub token_count: u32,
+    pub created_at: i64,
+    pub updated_at: i64,
+    pub expires_at: i64,
+    pub owner: Option<String>,
+}
+
+/// 获取数据库路径
+pub fn get_db_path() -> Result<PathBuf, String> {
+    let mut path = crate::modules::account::get_data_dir()?;
+    path.push("gemini_store.db");
+    Ok(path)
+}
+
+fn get_files_dir() -> Result<PathBuf, String> {
+    let mut path = crate::modules::account::get_data_dir()?;
+    path.push("gemini_files");
+    if !path.exists() {
+        std::fs::create_dir_all(&path)
+            .map_err(|e| format!("Failed to create files directory: {}", e))?;
+    }
+    Ok(path)
+}
+
+fn connect_db() -> Result<Connection, String> {
+    let path = get_db_path()?;
+    Connection::open(&path).map_err(|e| format!("Failed to open database: {}", e))
+}
+
+/// 初始化数据库
+pub fn init_db() -> Result<(), String> {
+    let conn = connect_db()?;
+    init_schema(&conn)
+}
+
+fn init_schema(conn: &Connection) -> Result<(), String> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS files (
+            id TEXT PRIMARY KEY,
+            display_name TEXT NOT NULL,
+            mime_type TEXT NOT NULL,
+            size_bytes INTEGER NOT NULL,
+            sha256 TEXT NOT NULL,
+            created_at INTEGER NOT NULL,
+            expires_at INTEGER NOT NULL,
+            owner TEXT
+        );
+        CREATE TABLE IF NOT EXISTS cached_contents (
+            id TEXT PRIMARY KEY,
+            model TEXT NOT NULL,
+            display_name TEXT NOT NULL,
+            content TEXT NOT NULL,
+            token_count INTEGER NOT NULL,
+            created_at INTEGER NOT NULL,
+            updated_at INTEGER NOT NULL,
+            expires_at INTEGER NOT NULL,
+            owner TEXT
+        );
+        CREATE INDEX IF NOT EXISTS idx_files_owner ON files (owner);
+        CREATE INDEX IF NOT EXISTS idx_cached_contents_owner ON cached_contents (owner);",
+    )
+    .map_err(|e| format!("Failed to create gemini store tables: {}", e))?;
+    Ok(())
+}
+
+/// ID 由本模块生成，拒绝其他字符防止路径穿越
+fn is_valid_id(id: &str) -> bool {
+    !id.is_empty() && id.len() <= 64 && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+}
+
+fn new_id() -> String {
+    uuid::Uuid::new_v4().simple().to_string()
+}
+
+fn file_path(id: &str) -> Result<PathBuf, String> {
+    let mut path = get_files_dir()?;
+    path.push(id);
+    Ok(path)
+}
+
+fn now_ms() -> i64 {
+    chrono::Utc::now().timestamp_millis()
+}
+
+/// 删除所有已过期的文件与缓存
+fn purge_expired(conn: &Connection) -> Result<(), String> {
+    let now = now_ms();
+    let mut stmt = conn
+        .prepare("SELECT id FROM files WHERE expires_at <= ?1")
+        .map_err(|e| e.to_string())?;
+    let expired = stmt
+        .query_map(params![now], |row| row.get::<_, String>(0))
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    for id in &expired {
+        let _ = std::fs::remove_file(file_path(id)?);
+    }
+    conn.execute("DELETE FROM files WHERE expires_at <= ?1", params![now])
+        .map_err(|e| e.to_string())?;
+    conn.execute(
+        "DELETE FROM cached_contents WHERE expires_at <= ?1",
+        params![now],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+// ===== Files =====
+
+fn row_to_file(row: &rusqlite::Row) -> rusqlite::Result<FileRecord> {
+    Ok(FileRecord {
+        id: row.get(0)?,
+        display_name: row.get(1)?,
+        mime_type: row.get(2)?,
+        size_bytes: row.get(3)?,
+        sha256: row.get(4)?,
+        created_at: row.get(5)?,
+        expires_at: row.get(6)?,
+        owner: row.get(7)?,
+    })
+}
+
+const FILE_COLUMNS: &str =
+    "id, display_name, mime_type, size_bytes, sha256, created_at, expires_at, owner";
+
+/// 保存上传的文件
+pub fn create_file(
+    owner: Option<&str>,
+    display_name: &str,
+    mime_type: &str,
+    bytes: &[u8],
+) -> Result<FileRecord, String> {
+    let conn = connect_db()?;
+    purge_expired(&conn)?;
+
+    let now = now_ms();
+    let record = FileRecord {
+        id: new_id(),
+        display_name: display_name.to_string(),
+        mime_type: mime_type.to_string(),
+        size_bytes: bytes.len() as i64,
+        sha256: base64::engine::general_purpose::STANDARD.encode(Sha256::digest(bytes)),
+        created_at: now,
+        expires_at: now + FILE_TTL_MS,
+        owner: owner.map(|o| o.to_string()),
+    };
+    let path = file_path(&record.id)?;
+    std::fs::write(&path, bytes).map_err(|e| format!("Failed to write file: {}", e))?;
+
+    conn.execute(
+        &format!(
+            "INSERT INTO files ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            FILE_COLUMNS
+        ),
+        params![
+            record.id,
+            record.display_name,
+            record.mime_type,
+            record.size_bytes,
+            record.sha256,
+            record.created_at,
+            record.expires_at,
+            record.owner,
+        ],
+    )
+    .map_err(|e| {
+        let _ = std::fs::remove_file(&path);
+        format!("Failed to save file metadata: {}", e)
+    })?;
+    Ok(record)
+}
+
+/// 获取 owner 名下未过期的文件元数据
+pub fn get_file(id: &str, owner: Option<&str>) -> Result<Option<FileRecord>, String> {
+    if !is_valid_id(id) {
+        return Ok(None);
+    }
+    let conn = connect_db()?;
+    conn.query_row(
+        &format!(
+            "SELECT {} FROM files WHERE id = ?1 AND owner IS ?2 AND expires_at > ?3",
+            FILE_COLUMNS
+        ),
+        params![id, owner, now_ms()],
+        row_to_file,
+    )
+    .optional()
+    .map_err(|e| e.to_string())
+}
+
+/// 读取 owner 名下的文件内容
+pub fn read_file(id: &str, owner: Option<&str>) -> Result<Option<(FileRecord, Vec<u8>)>, String> {
+    let Some(record) = get_file(id, owner)? else {
+        return Ok(None);
+    };
+    match std::fs::read(file_path(&record.id)?) {
+        Ok(bytes) => Ok(Some((record, bytes))),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(format!("Failed to read file: {}", e)),
+    }
+}
+
+pub fn list_files(owner: Option<&str>, limit: usize) -> Result<Vec<FileRecord>, String> {
+    let conn = connect_db()?;
+    purge_expired(&conn)?;
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {} FROM files WHERE owner IS ?1 ORDER BY created_at DESC LIMIT ?2",
+            FILE_COLUMNS
+        ))
+        .map_err(|e| e.to_string())?;
+    let records = stmt
+        .query_map(params![owner, limit as i64], row_to_file)
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    Ok(records)
+}
+
+pub fn delete_file(id: &str, owner: Option<&str>) -> Result<bool, String> {
+    if !is_valid_id(id) {
+        return Ok(false);
+    }
+    let conn = connect_db()?;
+    let deleted = conn
+        .execute(
+            "DELETE FROM files WHERE id = ?1 AND owner IS ?2",
+            params![id, owner],
+        )
+        .map_err(|e| e.to_string())?;
+    if deleted > 0 {
+        let _ = std::fs::remove_file(file_path(id)?);
+    }
+    Ok(deleted > 0)
+}
+
+// ===== Cached Contents =====
+
+fn row_to_cached(row: &rusqlite::Row) -> rusqlite::Result<CachedContentRecord> {
+    let content: String = row.get(3)?;
+    Ok(CachedContentRecord {
+        id: row.get(0)?,
+        model: row.get(1)?,
+        display_name: row.get(2)?,
+        content: serde_json::from_str(&content).unwrap_or(Value::Null),
+        token_count: row.get(4)?,
+        created_at: row.get(5)?,
+        updated_at: row.get(6)?,
+        expires_at: row.get(7)?,
+        owner: row.get(8)?,
+    })
+}
+
+const CACHED_COLUMNS: &str =
+    "id, model, display_name, content, token_count, created_at, updated_at, expires_at, owner";
+
+pub fn create_cached_content(
+    owner: Option<&str>,
+    model: &str,
+    display_name: &str,
+    content: &Value,
+    token_count: u32,
+    expires_at: i64,
+) -> Result<CachedContentRecord, String> {
+    let conn = connect_db()?;
+    purge_expired(&conn)?;
+    insert_cached_content(&conn, owner, model, display_name, content, token_count, expires_at)
+}
+
+fn insert_cached_content(
+    conn: &Connection,
+    owner: Option<&str>,
+    model: &str,
+    display_name: &str,
+    content: &Value,
+    token_count: u32,
+    expires_at: i64,
+) -> Result<CachedContentRecord, String> {
+    let now = now_ms();
+    let record = CachedContentRecord {
+        id: new_id(),
+        model: model.to_string(),
+        display_name: display_name.to_string(),
+        content: content.clone(),
+        token_count,
+        created_at: now,
+        updated_at: now,
+        expires_at,
+        owner: owner.map(|o| o.to_string()),
+    };
+    conn.execute(
+        &format!(
+            "INSERT INTO cached_contents ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            CACHED_COLUMNS
+        ),
+        params![
+            record.id,
+            record.model,
+            record.display_name,
+            serde_json::to_string(content).map_err(|e| e.to_string())?,
+            record.token_count,
+            record.created_at,
+            record.updated_at,
+            record.expires_at,
+            record.owner,
+        ],
+    )
+    .map_err(|e| format!("Failed to save cached content: {}", e))?;
+    Ok(record)
+}
+
+/// 获取 owner 名下未过期的缓存
+pub fn get_cached_content(
+    id: &str,
+    owner: Option<&str>,
+) -> Result<Option<CachedContentRecord>, String> {
+    if !is_valid_id(id) {
+        return Ok(None);
+    }
+    let conn = connect_db()?;
+    query_cached_content(&conn, id, owner)
+}
+
+fn query_cached_content(
+    conn: &Connection,
+    id: &str,
+    owner: Option<&str>,
+) -> Result<Option<CachedContentRecord>, String> {
+    conn.query_row(
+        &format!(
+            "SELECT {} FROM cached_contents WHERE id = ?1 AND owner IS ?2 AND expires_at > ?3",
+            CACHED_COLUMNS
+        ),
+        params![id, owner, now_ms()],
+        row_to_cached,
+    )
+    .optional()
+    .map_err(|e| e.to_string())
+}
+
+pub fn list_cached_contents(
+    owner: Option<&str>,
+    limit: usize,
+) -> Result<Vec<CachedContentRecord>, String> {
+    let conn = connect_db()?;
+    purge_expired(&conn)?;
+    query_cached_contents(&conn, owner, limit)
+}
+
+fn query_cached_contents(
+    conn: &Connection,
+    owner: Option<&str>,
+    limit: usize,
+) -> Result<Vec<CachedContentRecord>, String> {
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {} FROM cached_contents WHERE owner IS ?1 ORDER BY created_at DESC LIMIT ?2",
+            CACHED_COLUMNS
+        ))
+        .map_err(|e| e.to_string())?;
+    let records = stmt
+        .query_map(params![owner, limit as i64], row_to_cached)
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    Ok(records)
+}
+
+/// 更新 owner 名下缓存的过期时间
+pub fn update_cached_content_expiry(
+    id: &str,
+    owner: Option<&str>,
+    expires_at: i64,
+) -> Result<Option<CachedContentRecord>, String> {
+    if !is_valid_id(id) {
+        return Ok(None);
+    }
+    let conn = connect_db()?;
+    let updated = conn
+        .execute(
+            "UPDATE cached_contents SET expires_at = ?1, updated_at = ?2
+             WHERE id = ?3 AND owner IS ?4 AND expires_at > ?2",
+            params![expires_at, now_ms(), id, owner],
+        )
+        .map_err(|e| e.to_string())?;
+    if updated == 0 {
+        return Ok(None);
+    }
+    query_cached_content(&conn, id, owner)
+}
+
+pub fn delete_cached_content(id: &str, owner: Option<&str>) -> Result<bool, String> {
+    if !is_valid_id(id) {
+        return Ok(false);
+    }
+    let conn = connect_db()?;
+    delete_cached_content_on(&conn, id, owner)
+}
+
+fn delete_cached_content_on(
+    conn: &Connection,
+    id: &str,
+    owner: Option<&str>,
+) -> Result<bool, String> {
+    let deleted = conn
+        .execute(
+            "DELETE FROM cached_contents WHERE id = ?1 AND owner IS ?2",
+            params![id, owner],
+        )
+        .map_err(|e| e.to_string())?;
+    Ok(deleted > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_valid_id() {
+        assert!(is_valid_id(&new_id()));
+        assert!(is_valid_id("abc-123"));
+        assert!(!is_valid_id(""));
+        assert!(!is_valid_id("../gemini_store.db"));
+        assert!(!is_valid_id("a/b"));
+        assert!(!is_valid_id(&"a".repeat(65)));
+    }
+
+    #[test]
+    fn test_cached_contents_are_scoped_to_owner() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_schema(&conn).unwrap();
+        let content = serde_json::json!({"contents": []});
+        let expires_at = now_ms() + 60_000;
+        let alice = insert_cached_content(&conn, Some("alice"), "models/m", "", &content, 1, expires_at)
+            .unwrap();
+        let shared = insert_cached_content(&conn, None, "models/m", "", &content, 1, expires_at)
+            .unwrap();
+
+        assert!(query_cached_content(&conn, &alice.id, Some("alice")).unwrap().is_some());
+        assert!(query_cached_content(&conn, &alice.id, Some("bob")).unwrap().is_none());
+        assert!(query_cached_content(&conn, &alice.id, None).unwrap().is_none());
+        assert!(query_cached_content(&conn, &shared.id, Some("alice")).unwrap().is_none());
+
+        let listed: Vec<String> = query_cached_contents(&conn, Some("alice"), 10)
+            .unwrap()
+            .into_iter()
+            .map(|r| r.id)
+            .collect();
+        assert_eq!(listed, vec![alice.id.clone()]);
+
+        assert!(!delete_cached_content_on(&conn, &alice.id, Some("bob")).unwrap());
+        assert!(delete_cached_content_on(&conn, &alice.id, Some("alice")).unwrap());
+    }
+}
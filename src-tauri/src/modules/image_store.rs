@@ -0,0 +1,383 @@
+//! Image Store Module
+//! 生成 / 编辑图片的本地图库
+//!
+//! - 图片文件保存在数据目录的 `generated_images/` 下，元数据 (prompt、模型、用户令牌) 存于 SQLite
+//! - 每次保存后按保留天数、数量与总大小上限清理最旧的图片
+
+use crate::proxy::config::ImageStoreConfig;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ImageRecord {
+    pub id: String,
+    pub created_at: i64,
+    /// "generation" 或 "edit"
+    pub source: String,
+    pub prompt: String,
+    pub model: String,
+    pub mime_type: String,
+    pub size_bytes: i64,
+    pub token_id: Option<String>,
+    pub username: Option<String>,
+}
+
+/// 待保存图片的元数据
+#[derive(Debug, Clone)]
+pub struct NewImage {
+    pub source: String,
+    pub prompt: String,
+    pub model: String,
+    pub token_id: Option<String>,
+    pub username: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ImageQuery {
+    pub token_id: Option<String>,
+    pub username: Option<String>,
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+}
+
+/// 获取数据库路径
+pub fn get_db_path() -> Result<PathBuf, String> {
+    let mut path = crate::modules::account::get_data_dir()?;
+    path.push("images.db");
+    Ok(path)
+}
+
+fn get_images_dir() -> Result<PathBuf, String> {
+    let mut path = crate::modules::account::get_data_dir()?;
+    path.push("generated_images");
+    if !path.exists() {
+        std::fs::create_dir_all(&path)
+            .map_err(|e| format!("Failed to create images directory: {}", e))?;
+    }
+    Ok(path)
+}
+
+fn connect_db() -> Result<Connection, String> {
+    let path = get_db_path()?;
+    Connection::open(&path).map_err(|e| format!("Failed to open database: {}", e))
+}
+
+/// 初始化数据库
+pub fn init_db() -> Result<(), String> {
+    let conn = connect_db()?;
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS images (
+            id TEXT PRIMARY KEY,
+            created_at INTEGER NOT NULL,
+            source TEXT NOT NULL,
+            prompt TEXT NOT NULL,
+            model TEXT NOT NULL,
+            mime_type TEXT NOT NULL,
+            size_bytes INTEGER NOT NULL,
+            token_id TEXT,
+            username TEXT
+        );
+        CREATE INDEX IF NOT EXISTS idx_images_created_at ON images (created_at DESC);
+        CREATE INDEX IF NOT EXISTS idx_images_token_id ON images (token_id);",
+    )
+    .map_err(|e| format!("Failed to create images table: {}", e))?;
+    Ok(())
+}
+
+fn extension_for(mime_type: &str) -> &'static str {
+    match mime_type {
+        "image/png" => "png",
+        "image/jpeg" | "image/jpg" => "jpg",
+        "image/webp" => "webp",
+        "image/gif" => "gif",
+        _ => "bin",
+    }
+}
+
+/// ID 由本模块生成 (UUID simple 格式)，拒绝其他字符防止路径穿越
+fn is_valid_id(id: &str) -> bool {
+    !id.is_empty() && id.len() <= 64 && id.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+fn image_path(id: &str, mime_type: &str) -> Result<PathBuf, String> {
+    let mut path = get_images_dir()?;
+    path.push(format!("{}.{}", id, extension_for(mime_type)));
+    Ok(path)
+}
+
+fn row_to_record(row: &rusqlite::Row) -> rusqlite::Result<ImageRecord> {
+    Ok(ImageRecord {
+        id: row.get(0)?,
+        created_at: row.get(1)?,
+        source: row.get(2)?,
+        prompt: row.get(3)?,
+        model: row.get(4)?,
+        mime_type: row.get(5)?,
+        size_bytes: row.get(6)?,
+        token_id: row.get(7)?,
+        username: row.get(8)?,
+    })
+}
+
+const SELECT_COLUMNS: &str =
+    "id, created_at, source, prompt, model, mime_type, size_bytes, token_id, username";
+
+/// 保存图片并执行保留策略
+pub fn save_image(
+    meta: &NewImage,
+    mime_type: &str,
+    bytes: &[u8],
+    config: &ImageStoreConfig,
+) -> Result<ImageRecord, String> {
+    let record = ImageRecord {
+        id: uuid::Uuid::new_v4().simple().to_string(),
+        created_at: chrono::Utc::now().timestamp_millis(),
+        source: meta.source.clone(),
+        prompt: meta.prompt.clone(),
+        model: meta.model.clone(),
+        mime_type: mime_type.to_string(),
+        size_bytes: bytes.len() as i64,
+        token_id: meta.token_id.clone(),
+        username: meta.username.clone(),
+    };
+
+    let path = image_path(&record.id, mime_type)?;
+    std::fs::write(&path, bytes).map_err(|e| format!("Failed to write image: {}", e))?;
+
+    let conn = connect_db()?;
+    conn.execute(
+        &format!(
+            "INSERT INTO images ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            SELECT_COLUMNS
+        ),
+        params![
+            record.id,
+            record.created_at,
+            record.source,
+            record.prompt,
+            record.model,
+            record.mime_type,
+            record.size_bytes,
+            record.token_id,
+            record.username,
+        ],
+    )
+    .map_err(|e| {
+        let _ = std::fs::remove_file(&path);
+        format!("Failed to save image metadata: {}", e)
+    })?;
+
+    if let Err(e) = enforce_retention(&conn, config) {
+        tracing::warn!("[ImageStore] Retention cleanup failed: {}", e);
+    }
+    Ok(record)
+}
+
+/// 读取图片元数据与内容
+pub fn get_image(id: &str) -> Result<Option<(ImageRecord, Vec<u8>)>, String> {
+    if !is_valid_id(id) {
+        return Ok(None);
+    }
+    let conn = connect_db()?;
+    let record = conn
+        .query_row(
+            &format!("SELECT {} FROM images WHERE id = ?1", SELECT_COLUMNS),
+            params![id],
+            row_to_record,
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+    let Some(record) = record else {
+        return Ok(None);
+    };
+    match std::fs::read(image_path(&record.id, &record.mime_type)?) {
+        Ok(bytes) => Ok(Some((record, bytes))),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(format!("Failed to read image: {}", e)),
+    }
+}
+
+/// 查询图片 (按时间倒序)
+pub fn query(q: &ImageQuery) -> Result<Vec<ImageRecord>, String> {
+    let conn = connect_db()?;
+
+    let mut clauses: Vec<&str> = Vec::new();
+    let mut args: Vec<Box<dyn rusqlite::ToSql>> = Vec::new();
+    if let Some(token_id) = q.token_id.as_ref().filter(|s| !s.is_empty()) {
+        clauses.push("token_id = ?");
+        args.push(Box::new(token_id.clone()));
+    }
+    if let Some(username) = q.username.as_ref().filter(|s| !s.is_empty()) {
+        clauses.push("username = ?");
+        args.push(Box::new(username.clone()));
+    }
+
+    let where_sql = if clauses.is_empty() {
+        String::new()
+    } else {
+        format!("WHERE {}", clauses.join(" AND "))
+    };
+    let sql = format!(
+        "SELECT {} FROM images {} ORDER BY created_at DESC LIMIT {} OFFSET {}",
+        SELECT_COLUMNS,
+        where_sql,
+        q.limit.unwrap_or(100).min(1000),
+        q.offset.unwrap_or(0)
+    );
+
+    let mut stmt = conn.prepare(&sql).map_err(|e| e.to_string())?;
+    let records = stmt
+        .query_map(
+            rusqlite::params_from_iter(args.iter().map(|a| a.as_ref())),
+            row_to_record,
+        )
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    Ok(records)
+}
+
+fn delete_where(conn: &Connection, where_sql: &str, arg: &str) -> Result<usize, String> {
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT id, mime_type FROM images WHERE {}",
+            where_sql
+        ))
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![arg], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    for (id, mime_type) in &rows {
+        let _ = std::fs::remove_file(image_path(id, mime_type)?);
+    }
+    conn.execute(
+        &format!("DELETE FROM images WHERE {}", where_sql),
+        params![arg],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(rows.len())
+}
+
+/// 删除单张图片
+pub fn delete_image(id: &str) -> Result<bool, String> {
+    let conn = connect_db()?;
+    Ok(delete_where(&conn, "id = ?1", id)? > 0)
+}
+
+/// 删除某个用户令牌生成的全部图片
+pub fn delete_by_token(token_id: &str) -> Result<usize, String> {
+    let conn = connect_db()?;
+    delete_where(&conn, "token_id = ?1", token_id)
+}
+
+/// 按保留策略选出需要删除的图片 ID
+/// `rows` 为 (id, created_at, size_bytes)，需按 created_at 倒序排列
+fn select_expired(
+    rows: &[(String, i64, i64)],
+    config: &ImageStoreConfig,
+    now_ms: i64,
+) -> Vec<String> {
+    let cutoff = if config.retention_days > 0 {
+        now_ms - config.retention_days as i64 * 24 * 3600 * 1000
+    } else {
+        i64::MIN
+    };
+    let max_bytes = config.max_total_mb as i64 * 1024 * 1024;
+
+    let mut total = 0i64;
+    rows.iter()
+        .enumerate()
+        .filter(|(i, (_, created_at, size))| {
+            total += size;
+            *created_at < cutoff
+                || (config.max_images > 0 && *i >= config.max_images)
+                || (max_bytes > 0 && total > max_bytes)
+        })
+        .map(|(_, (id, _, _))| id.clone())
+        .collect()
+}
+
+fn enforce_retention(conn: &Connection, config: &ImageStoreConfig) -> Result<usize, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, created_at, size_bytes, mime_type FROM images ORDER BY created_at DESC",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, i64>(1)?,
+                row.get::<_, i64>(2)?,
+                row.get::<_, String>(3)?,
+            ))
+        })
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+
+    let summary: Vec<(String, i64, i64)> = rows
+        .iter()
+        .map(|(id, created_at, size, _)| (id.clone(), *created_at, *size))
+        .collect();
+    let expired = select_expired(&summary, config, chrono::Utc::now().timestamp_millis());
+    for id in &expired {
+        if let Some((_, _, _, mime_type)) = rows.iter().find(|r| &r.0 == id) {
+            let _ = std::fs::remove_file(image_path(id, mime_type)?);
+        }
+        conn.execute("DELETE FROM images WHERE id = ?1", params![id])
+            .map_err(|e| e.to_string())?;
+    }
+    if !expired.is_empty() {
+        tracing::info!("[ImageStore] Removed {} expired image(s)", expired.len());
+    }
+    Ok(expired.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_select_expired_applies_all_limits() {
+        const DAY: i64 = 24 * 3600 * 1000;
+        let now = 100 * DAY;
+        let mb = 1024 * 1024;
+        let rows = vec![
+            ("a".to_string(), now - 1000, 2 * mb),
+            ("b".to_string(), now - DAY, 2 * mb),
+            ("c".to_string(), now - 2 * DAY, 2 * mb),
+            ("d".to_string(), now - 10 * DAY, 1),
+        ];
+
+        let mut config = ImageStoreConfig {
+            retention_days: 7,
+            max_images: 0,
+            max_total_mb: 0,
+            ..Default::default()
+        };
+        assert_eq!(select_expired(&rows, &config, now), vec!["d"]);
+
+        config.max_images = 2;
+        assert_eq!(select_expired(&rows, &config, now), vec!["c", "d"]);
+
+        config.max_images = 0;
+        config.retention_days = 0;
+        config.max_total_mb = 5;
+        assert_eq!(select_expired(&rows, &config, now), vec!["c", "d"]);
+    }
+
+    #[test]
+    fn test_id_validation() {
+        assert!(is_valid_id(&uuid::Uuid::new_v4().simple().to_string()));
+        assert!(!is_valid_id("../images"));
+        assert!(!is_valid_id(""));
+        assert_eq!(extension_for("image/jpeg"), "jpg");
+    }
+}
@@ -0,0 +1,321 @@
+// 请求日志导出/导入模块
+// 导出: JSONL (完整记录，可再导入) / CSV (仅元数据) / HAR 1.2 (浏览器与 HTTP 调试工具)
+//       / JSON 数组 (旧版导出格式)
+// 导入: 将 JSONL 导出文件写入本实例的 proxy_logs.db (用于问题排查)
+
+use crate::modules::proxy_db::{self, LogQuery};
+use crate::proxy::monitor::ProxyRequestLog;
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, Write};
+
+/// 每批导入的记录数
+const IMPORT_BATCH_SIZE: usize = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogExportFormat {
+    Json,
+    Jsonl,
+    Csv,
+    Har,
+}
+
+impl LogExportFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            LogExportFormat::Json => "application/json",
+            LogExportFormat::Jsonl => "application/x-ndjson",
+            LogExportFormat::Csv => "text/csv; charset=utf-8",
+            LogExportFormat::Har => "application/json",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            LogExportFormat::Json => "json",
+            LogExportFormat::Jsonl => "jsonl",
+            LogExportFormat::Csv => "csv",
+            LogExportFormat::Har => "har",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Default)]
+pub struct ImportReport {
+    pub imported: usize,
+    pub duplicates: usize,
+    pub invalid_lines: usize,
+}
+
+const CSV_HEADER: &str = "id,timestamp,method,url,status,duration_ms,model,mapped_model,account_email,client_ip,protocol,username,input_tokens,output_tokens,retries,resumes,image_bytes_saved,image_tokens_saved,error";
+
+/// 将匹配 query 的日志逐行写入 writer (不会一次性加载到内存)
+/// 返回导出的记录数
+pub fn export_logs<W: Write>(
+    query: &LogQuery,
+    format: LogExportFormat,
+    writer: W,
+) -> Result<usize, String> {
+    let mut writer = std::io::BufWriter::new(writer);
+    let io_err = |e: std::io::Error| format!("Failed to write export: {}", e);
+
+    match format {
+        LogExportFormat::Csv => {
+            writeln!(writer, "{}", CSV_HEADER).map_err(io_err)?;
+        }
+        LogExportFormat::Har => {
+            write!(
+                writer,
+                "{{\"log\":{{\"version\":\"1.2\",\"creator\":{{\"name\":\"Antigravity Tools\",\"version\":\"{}\"}},\"entries\":[",
+                env!("CARGO_PKG_VERSION")
+            )
+            .map_err(io_err)?;
+        }
+        LogExportFormat::Json => {
+            write!(writer, "[").map_err(io_err)?;
+        }
+        LogExportFormat::Jsonl => {}
+    }
+
+    let mut first = true;
+    let count = proxy_db::for_each_log(query, |log| {
+        match format {
+            LogExportFormat::Json => {
+                if !first {
+                    write!(writer, ",").map_err(io_err)?;
+                }
+                writeln!(writer).map_err(io_err)?;
+                serde_json::to_writer_pretty(&mut writer, &log).map_err(|e| e.to_string())?;
+            }
+            LogExportFormat::Jsonl => {
+                serde_json::to_writer(&mut writer, &log).map_err(|e| e.to_string())?;
+                writeln!(writer).map_err(io_err)?;
+            }
+            LogExportFormat::Csv => {
+                writeln!(writer, "{}", to_csv_row(&log)).map_err(io_err)?;
+            }
+            LogExportFormat::Har => {
+                if !first {
+                    write!(writer, ",").map_err(io_err)?;
+                }
+                serde_json::to_writer(&mut writer, &to_har_entry(&log))
+                    .map_err(|e| e.to_string())?;
+            }
+        }
+        first = false;
+        Ok(())
+    })?;
+
+    match format {
+        LogExportFormat::Har => write!(writer, "]}}}}").map_err(io_err)?,
+        LogExportFormat::Json => write!(writer, "\n]").map_err(io_err)?,
+        _ => {}
+    }
+    writer.flush().map_err(io_err)?;
+
+    Ok(count)
+}
+
+/// 从 JSONL 导入日志；无法解析的行计入 invalid_lines，已存在的 id 计入 duplicates
+pub fn import_jsonl<R: BufRead>(reader: R) -> Result<ImportReport, String> {
+    let mut report = ImportReport::default();
+    let mut batch: Vec<ProxyRequestLog> = Vec::with_capacity(IMPORT_BATCH_SIZE);
+
+    for line in reader.lines() {
+        let line = line.map_err(|e| format!("Failed to read import: {}", e))?;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        match serde_json::from_str::<ProxyRequestLog>(line) {
+            Ok(log) => batch.push(log),
+            Err(_) => report.invalid_lines += 1,
+        }
+        if batch.len() >= IMPORT_BATCH_SIZE {
+            flush_batch(&mut batch, &mut report)?;
+        }
+    }
+    flush_batch(&mut batch, &mut report)?;
+
+    Ok(report)
+}
+
+fn flush_batch(batch: &mut Vec<ProxyRequestLog>, report: &mut ImportReport) -> Result<(), String> {
+    if batch.is_empty() {
+        return Ok(());
+    }
+    let (imported, duplicates) = proxy_db::import_logs(batch)?;
+    report.imported += imported;
+    report.duplicates += duplicates;
+    batch.clear();
+    Ok(())
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn to_csv_row(log: &ProxyRequestLog) -> String {
+    let opt = |v: &Option<String>| csv_field(v.as_deref().unwrap_or(""));
+    let num = |v: Option<u32>| v.map(|n| n.to_string()).unwrap_or_default();
+    [
+        csv_field(&log.id),
+        log.timestamp.to_string(),
+        csv_field(&log.method),
+        csv_field(&log.url),
+        log.status.to_string(),
+        log.duration.to_string(),
+        opt(&log.model),
+        opt(&log.mapped_model),
+        opt(&log.account_email),
+        opt(&log.client_ip),
+        opt(&log.protocol),
+        opt(&log.username),
+        num(log.input_tokens),
+        num(log.output_tokens),
+        num(log.retries),
+        num(log.resumes),
+        num(log.image_bytes_saved),
+        num(log.image_tokens_saved),
+        opt(&log.error),
+    ]
+    .join(",")
+}
+
+fn guess_mime(body: &str) -> &'static str {
+    let trimmed = body.trim_start();
+    if trimmed.starts_with("data:") || trimmed.starts_with("event:") {
+        "text/event-stream"
+    } else if trimmed.starts_with('{') || trimmed.starts_with('[') {
+        "application/json"
+    } else {
+        "text/plain"
+    }
+}
+
+/// 转换为 HAR 1.2 entry，代理特有字段以 `_` 前缀附加
+fn to_har_entry(log: &ProxyRequestLog) -> serde_json::Value {
+    let started = chrono::DateTime::from_timestamp_millis(log.timestamp)
+        .unwrap_or_default()
+        .to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
+    let url = if log.url.starts_with('/') {
+        format!("http://localhost{}", log.url)
+    } else {
+        log.url.clone()
+    };
+
+    let request_body = log.request_body.as_deref().unwrap_or("");
+    let response_body = log.response_body.as_deref().unwrap_or("");
+
+    let mut request = serde_json::json!({
+        "method": log.method,
+        "url": url,
+        "httpVersion": "HTTP/1.1",
+        "cookies": [],
+        "headers": [],
+        "queryString": [],
+        "headersSize": -1,
+        "bodySize": request_body.len(),
+    });
+    if log.request_body.is_some() {
+        request["postData"] = serde_json::json!({
+            "mimeType": guess_mime(request_body),
+            "text": request_body,
+        });
+    }
+
+    serde_json::json!({
+        "startedDateTime": started,
+        "time": log.duration,
+        "request": request,
+        "response": {
+            "status": log.status,
+            "statusText": "",
+            "httpVersion": "HTTP/1.1",
+            "cookies": [],
+            "headers": [],
+            "content": {
+                "size": response_body.len(),
+                "mimeType": guess_mime(response_body),
+                "text": response_body,
+            },
+            "redirectURL": "",
+            "headersSize": -1,
+            "bodySize": response_body.len(),
+        },
+        "cache": {},
+        "timings": {
+            "send": 0,
+            "wait": log.duration,
+            "receive": 0,
+        },
+        "serverIPAddress": "",
+        "_id": log.id,
+        "_model": log.model,
+        "_mappedModel": log.mapped_model,
+        "_account": log.account_email,
+        "_clientIp": log.client_ip,
+        "_protocol": log.protocol,
+        "_username": log.username,
+        "_inputTokens": log.input_tokens,
+        "_outputTokens": log.output_tokens,
+        "_retries": log.retries,
+        "_resumes": log.resumes,
+        "_image_bytes_saved": log.image_bytes_saved,
+        "_image_tokens_saved": log.image_tokens_saved,
+        "_error": log.error,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_log() -> ProxyRequestLog {
+        ProxyRequestLog {
+            id: "log-1".to_string(),
+            timestamp: 1_700_000_000_000,
+            method: "POST".to_string(),
+            url: "/v1/messages".to_string(),
+            status: 200,
+            duration: 1234,
+            model: Some("claude-sonnet-4-5".to_string()),
+            mapped_model: None,
+            account_email: Some("a@example.com".to_string()),
+            client_ip: None,
+            error: Some("line1, \"quoted\"".to_string()),
+            request_body: Some("{\"model\":\"x\"}".to_string()),
+            response_body: Some("data: {}\n\n".to_string()),
+            input_tokens: Some(10),
+            output_tokens: None,
+            protocol: Some("anthropic".to_string()),
+            username: None,
+            retries: None,
+            resumes: None,
+            image_bytes_saved: None,
+            image_tokens_saved: None,
+        }
+    }
+
+    #[test]
+    fn test_csv_row_escapes_fields() {
+        let row = to_csv_row(&sample_log());
+        assert!(row.starts_with("log-1,1700000000000,POST,/v1/messages,200,1234,"));
+        assert!(row.ends_with(",10,,,,,,\"line1, \"\"quoted\"\"\""));
+        assert_eq!(CSV_HEADER.split(',').count(), 19);
+    }
+
+    #[test]
+    fn test_har_entry_shape() {
+        let entry = to_har_entry(&sample_log());
+        assert_eq!(entry["request"]["url"], "http://localhost/v1/messages");
+        assert_eq!(entry["request"]["postData"]["mimeType"], "application/json");
+        assert_eq!(entry["response"]["content"]["mimeType"], "text/event-stream");
+        assert_eq!(entry["startedDateTime"], "2023-11-14T22:13:20.000Z");
+        assert_eq!(entry["_account"], "a@example.com");
+    }
+}
@@ -0,0 +1,325 @@
+// 日志保留策略模块
+// 负责请求体截断/采样、按表保留天数清理、定期 VACUUM 以及磁盘占用统计
+
+use crate::proxy::config::LogRetentionConfig;
+use crate::proxy::monitor::ProxyRequestLog;
+use serde::Serialize;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::time::Duration;
+
+static MAINTENANCE_LOOP_STARTED: AtomicBool = AtomicBool::new(false);
+
+/// 维护任务检查间隔
+const MAINTENANCE_INTERVAL_SECS: u64 = 3600;
+
+/// 单次维护任务的结果
+#[derive(Debug, Clone, Serialize, Default)]
+pub struct MaintenanceReport {
+    pub request_logs_deleted: usize,
+    pub ip_access_logs_deleted: usize,
+    pub token_usage_logs_deleted: usize,
+    pub token_usage_deleted: usize,
+    pub vacuumed: bool,
+    pub errors: Vec<String>,
+}
+
+/// 单个数据库文件的磁盘占用
+#[derive(Debug, Clone, Serialize)]
+pub struct DbFileUsage {
+    pub name: String,
+    pub path: String,
+    /// 主数据库文件大小
+    pub size_bytes: u64,
+    /// WAL + SHM 文件大小
+    pub wal_bytes: u64,
+    /// 主要日志表的行数
+    pub row_count: Option<u64>,
+}
+
+/// 日志相关数据库的磁盘占用汇总
+#[derive(Debug, Clone, Serialize)]
+pub struct StorageUsage {
+    pub total_bytes: u64,
+    pub databases: Vec<DbFileUsage>,
+}
+
+/// 按配置处理请求/响应体：采样 + 截断
+/// 错误请求 (status < 200 或 >= 400) 使用 error_body_sample_rate，其余使用 success_body_sample_rate
+pub fn apply_body_policy(log: &mut ProxyRequestLog, cfg: &LogRetentionConfig) {
+    if log.request_body.is_none() && log.response_body.is_none() {
+        return;
+    }
+
+    let is_error = log.status < 200 || log.status >= 400;
+    let rate = if is_error {
+        cfg.error_body_sample_rate
+    } else {
+        cfg.success_body_sample_rate
+    };
+
+    if !should_sample(rate) {
+        log.request_body = None;
+        log.response_body = None;
+        return;
+    }
+
+    if cfg.max_body_bytes > 0 {
+        log.request_body = log
+            .request_body
+            .take()
+            .map(|b| truncate_body(b, cfg.max_body_bytes));
+        log.response_body = log
+            .response_body
+            .take()
+            .map(|b| truncate_body(b, cfg.max_body_bytes));
+    }
+}
+
+fn should_sample(rate: f32) -> bool {
+    if rate >= 1.0 {
+        true
+    } else if rate <= 0.0 {
+        false
+    } else {
+        rand::random::<f32>() < rate
+    }
+}
+
+/// 截断到不超过 max_bytes 字节 (保证 UTF-8 字符边界)，并追加截断标记
+pub fn truncate_body(body: String, max_bytes: usize) -> String {
+    if body.len() <= max_bytes {
+        return body;
+    }
+
+    let mut cut = max_bytes;
+    while cut > 0 && !body.is_char_boundary(cut) {
+        cut -= 1;
+    }
+
+    let dropped = body.len() - cut;
+    let mut truncated = body;
+    truncated.truncate(cut);
+    truncated.push_str(&format!("\n...[truncated {} bytes]", dropped));
+    truncated
+}
+
+/// 执行一次清理 (按表保留天数)，可选执行 VACUUM
+pub fn run_maintenance(cfg: &LogRetentionConfig, vacuum: bool) -> MaintenanceReport {
+    let mut report = MaintenanceReport::default();
+
+    if cfg.request_logs_days > 0 {
+        match crate::modules::proxy_db::cleanup_old_logs(cfg.request_logs_days as i64) {
+            Ok(n) => report.request_logs_deleted = n,
+            Err(e) => report.errors.push(format!("request_logs: {}", e)),
+        }
+    }
+
+    if cfg.ip_access_logs_days > 0 {
+        match crate::modules::security_db::cleanup_old_ip_logs(cfg.ip_access_logs_days as i64) {
+            Ok(n) => report.ip_access_logs_deleted = n,
+            Err(e) => report.errors.push(format!("ip_access_logs: {}", e)),
+        }
+    }
+
+    if cfg.token_usage_logs_days > 0 {
+        match crate::modules::user_token_db::cleanup_old_usage_logs(
+            cfg.token_usage_logs_days as i64,
+        ) {
+            Ok(n) => report.token_usage_logs_deleted = n,
+            Err(e) => report.errors.push(format!("token_usage_logs: {}", e)),
+        }
+    }
+
+    if cfg.token_usage_days > 0 {
+        match crate::modules::token_stats::cleanup_old_usage(cfg.token_usage_days as i64) {
+            Ok(n) => report.token_usage_deleted = n,
+            Err(e) => report.errors.push(format!("token_usage: {}", e)),
+        }
+    }
+
+    if vacuum {
+        let results = [
+            ("proxy_logs.db", crate::modules::proxy_db::vacuum_db()),
+            ("security.db", crate::modules::security_db::vacuum_db()),
+            ("user_tokens.db", crate::modules::user_token_db::vacuum_db()),
+            ("token_stats.db", crate::modules::token_stats::vacuum_db()),
+        ];
+        for (name, res) in results {
+            if let Err(e) = res {
+                report.errors.push(format!("vacuum {}: {}", name, e));
+            }
+        }
+        report.vacuumed = true;
+    }
+
+    report
+}
+
+/// 统计日志相关数据库的磁盘占用
+pub fn get_storage_usage() -> Result<StorageUsage, String> {
+    let entries = [
+        (
+            "proxy_logs",
+            crate::modules::proxy_db::get_proxy_db_path()?,
+            crate::modules::proxy_db::get_logs_count().ok(),
+        ),
+        (
+            "security",
+            crate::modules::security_db::get_security_db_path()?,
+            crate::modules::security_db::get_ip_access_logs_count(None, false).ok(),
+        ),
+        (
+            "user_tokens",
+            crate::modules::user_token_db::get_db_path()?,
+            crate::modules::user_token_db::get_usage_logs_count().ok(),
+        ),
+        (
+            "token_stats",
+            crate::modules::token_stats::get_db_path()?,
+            crate::modules::token_stats::get_usage_count().ok(),
+        ),
+    ];
+
+    let databases: Vec<DbFileUsage> = entries
+        .into_iter()
+        .map(|(name, path, row_count)| {
+            let wal = sidecar_path(&path, "-wal");
+            let shm = sidecar_path(&path, "-shm");
+            DbFileUsage {
+                name: name.to_string(),
+                path: path.to_string_lossy().to_string(),
+                size_bytes: file_size(&path),
+                wal_bytes: file_size(&wal) + file_size(&shm),
+                row_count,
+            }
+        })
+        .collect();
+
+    let total_bytes = databases.iter().map(|d| d.size_bytes + d.wal_bytes).sum();
+
+    Ok(StorageUsage {
+        total_bytes,
+        databases,
+    })
+}
+
+fn sidecar_path(path: &Path, suffix: &str) -> std::path::PathBuf {
+    let mut s = path.as_os_str().to_owned();
+    s.push(suffix);
+    std::path::PathBuf::from(s)
+}
+
+fn file_size(path: &Path) -> u64 {
+    std::fs::metadata(path).map(|m| m.len()).unwrap_or(0)
+}
+
+/// 启动后台维护循环 (进程内只启动一次)
+/// 每小时按当前配置清理一次，并按 vacuum_interval_hours 执行 VACUUM
+pub fn start_maintenance_loop() {
+    if MAINTENANCE_LOOP_STARTED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    tokio::spawn(async {
+        let mut last_vacuum = std::time::Instant::now();
+        loop {
+            let cfg = crate::proxy::config::get_log_retention_config();
+            let vacuum_due = cfg.vacuum_interval_hours > 0
+                && last_vacuum.elapsed()
+                    >= Duration::from_secs(cfg.vacuum_interval_hours as u64 * 3600);
+
+            let res =
+                tokio::task::spawn_blocking(move || run_maintenance(&cfg, vacuum_due)).await;
+
+            match res {
+                Ok(report) => {
+                    if report.vacuumed {
+                        last_vacuum = std::time::Instant::now();
+                    }
+                    let deleted = report.request_logs_deleted
+                        + report.ip_access_logs_deleted
+                        + report.token_usage_logs_deleted
+                        + report.token_usage_deleted;
+                    if deleted > 0 || report.vacuumed {
+                        tracing::info!(
+                            "[Log-Retention] Maintenance: request_logs={}, ip_access_logs={}, token_usage_logs={}, token_usage={}, vacuumed={}",
+                            report.request_logs_deleted,
+                            report.ip_access_logs_deleted,
+                            report.token_usage_logs_deleted,
+                            report.token_usage_deleted,
+                            report.vacuumed
+                        );
+                    }
+                    for e in &report.errors {
+                        tracing::error!("[Log-Retention] {}", e);
+                    }
+                }
+                Err(e) => tracing::error!("[Log-Retention] Maintenance task failed: {}", e),
+            }
+
+            tokio::time::sleep(Duration::from_secs(MAINTENANCE_INTERVAL_SECS)).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_log(status: u16) -> ProxyRequestLog {
+        ProxyRequestLog {
+            id: "test".to_string(),
+            timestamp: 0,
+            method: "POST".to_string(),
+            url: "/v1/messages".to_string(),
+            status,
+            duration: 0,
+            model: None,
+            mapped_model: None,
+            account_email: None,
+            client_ip: None,
+            error: None,
+            request_body: Some("a".repeat(100)),
+            response_body: Some("b".repeat(100)),
+            input_tokens: None,
+            output_tokens: None,
+            protocol: None,
+            username: None,
+            retries: None,
+            resumes: None,
+            image_bytes_saved: None,
+            image_tokens_saved: None,
+        }
+    }
+
+    #[test]
+    fn test_truncate_body_respects_char_boundary() {
+        let body = "你好世界".to_string(); // 12 bytes
+        let truncated = truncate_body(body, 4);
+        assert!(truncated.starts_with("你"));
+        assert!(truncated.contains("[truncated 9 bytes]"));
+
+        assert_eq!(truncate_body("short".to_string(), 10), "short");
+    }
+
+    #[test]
+    fn test_sampling_keeps_errors_drops_success() {
+        let cfg = LogRetentionConfig {
+            success_body_sample_rate: 0.0,
+            error_body_sample_rate: 1.0,
+            max_body_bytes: 10,
+            ..Default::default()
+        };
+
+        let mut ok = make_log(200);
+        apply_body_policy(&mut ok, &cfg);
+        assert!(ok.request_body.is_none());
+        assert!(ok.response_body.is_none());
+
+        let mut err = make_log(429);
+        apply_body_policy(&mut err, &cfg);
+        assert!(err.request_body.as_deref().unwrap().starts_with("aaaaaaaaaa\n"));
+        assert!(err.response_body.as_deref().unwrap().contains("[truncated 90 bytes]"));
+    }
+}
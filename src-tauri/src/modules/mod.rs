@@ -10,6 +10,8 @@ pub mod migration;
 pub mod tray;
 pub mod i18n;
 pub mod proxy_db;
+pub mod log_retention;
+pub mod log_export;
 pub mod device;
 pub mod update_checker;
 pub mod scheduler;
@@ -23,6 +25,10 @@ pub mod cache;
 pub mod log_bridge;
 pub mod security_db;
 pub mod user_token_db;
+pub mod admin_user_db;
+pub mod audit_log;
+pub mod image_store;
+pub mod gemini_store;
 pub mod version;
 
 use crate::models;
@@ -1,7 +1,19 @@
-use rusqlite::{params, Connection};
+use rusqlite::{params, Connection, OptionalExtension};
+use rusqlite::types::Value as SqlValue;
+use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 use crate::proxy::monitor::ProxyRequestLog;
 
+/// Columns selected for list views (bodies are never loaded here)
+const SUMMARY_COLUMNS: &str = "id, timestamp, method, url, status, duration, model, error,
+                NULL as request_body, NULL as response_body,
+                input_tokens, output_tokens, account_email, mapped_model, protocol, client_ip, username, retries, resumes, image_bytes_saved, image_tokens_saved";
+
+/// Columns selected when bodies are needed (detail view, export)
+const FULL_COLUMNS: &str = "id, timestamp, method, url, status, duration, model, error,
+                request_body, response_body, input_tokens, output_tokens,
+                account_email, mapped_model, protocol, client_ip, username, retries, resumes, image_bytes_saved, image_tokens_saved";
+
 pub fn get_proxy_db_path() -> Result<PathBuf, String> {
     let data_dir = crate::modules::account::get_data_dir()?;
     Ok(data_dir.join("proxy_logs.db"))
@@ -26,7 +38,10 @@ fn connect_db() -> Result<Connection, String> {
 pub fn init_db() -> Result<(), String> {
     // connect_db will initialize WAL mode and other pragmas
     let conn = connect_db()?;
-    
+    init_schema(&conn)
+}
+
+fn init_schema(conn: &Connection) -> Result<(), String> {
     conn.execute(
         "CREATE TABLE IF NOT EXISTS request_logs (
             id TEXT PRIMARY KEY,
@@ -51,6 +66,10 @@ pub fn init_db() -> Result<(), String> {
     let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN protocol TEXT", []);
     let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN client_ip TEXT", []);
     let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN username TEXT", []);
+    let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN retries INTEGER", []);
+    let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN resumes INTEGER", []);
+    let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN image_bytes_saved INTEGER", []);
+    let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN image_tokens_saved INTEGER", []);
 
     conn.execute(
         "CREATE INDEX IF NOT EXISTS idx_timestamp ON request_logs (timestamp DESC)",
@@ -63,15 +82,112 @@ pub fn init_db() -> Result<(), String> {
         [],
     ).map_err(|e| e.to_string())?;
 
+    init_fts(conn)?;
+
+    Ok(())
+}
+
+/// Full-text index over request/response bodies and error text.
+/// Encrypted bodies (see LogRetentionConfig::encrypt_bodies) are never indexed.
+///
+/// The index is contentless (`content=''`): bodies are stored only once, in request_logs,
+/// and FTS rows are keyed by request_logs.rowid. Triggers keep both tables in sync.
+fn init_fts(conn: &Connection) -> Result<(), String> {
+    let fts_sql: Option<String> = conn.query_row(
+        "SELECT sql FROM sqlite_master WHERE type = 'table' AND name = 'request_logs_fts'",
+        [],
+        |row| row.get(0),
+    ).optional().map_err(|e| e.to_string())?;
+
+    // Older versions stored a full copy of every body in the index: rebuild it contentless
+    let needs_backfill = match fts_sql {
+        Some(sql) if sql.contains("content=''") => false,
+        Some(_) => {
+            conn.execute_batch(
+                "DROP TRIGGER IF EXISTS request_logs_fts_insert;
+                 DROP TABLE request_logs_fts;",
+            ).map_err(|e| e.to_string())?;
+            true
+        }
+        None => true,
+    };
+
+    conn.execute(
+        "CREATE VIRTUAL TABLE IF NOT EXISTS request_logs_fts USING fts5(
+            request_body,
+            response_body,
+            error,
+            content='',
+            contentless_delete=1
+        )",
+        [],
+    ).map_err(|e| e.to_string())?;
+
+    conn.execute(
+        "CREATE TRIGGER IF NOT EXISTS request_logs_fts_insert AFTER INSERT ON request_logs BEGIN
+            INSERT INTO request_logs_fts (rowid, request_body, response_body, error) VALUES (
+                new.rowid,
+                CASE WHEN new.request_body LIKE 'ag_blob_%' THEN NULL ELSE new.request_body END,
+                CASE WHEN new.response_body LIKE 'ag_blob_%' THEN NULL ELSE new.response_body END,
+                new.error
+            );
+        END",
+        [],
+    ).map_err(|e| e.to_string())?;
+
+    conn.execute(
+        "CREATE TRIGGER IF NOT EXISTS request_logs_fts_delete AFTER DELETE ON request_logs BEGIN
+            DELETE FROM request_logs_fts WHERE rowid = old.rowid;
+        END",
+        [],
+    ).map_err(|e| e.to_string())?;
+
+    // Backfill rows written before the index existed
+    if needs_backfill {
+        let indexed = backfill_fts(conn)?;
+        if indexed > 0 {
+            tracing::info!("[ProxyDB] Built full-text index for {} existing logs", indexed);
+        }
+    }
+
+    Ok(())
+}
+
+fn backfill_fts(conn: &Connection) -> Result<usize, String> {
+    conn.execute(
+        "INSERT INTO request_logs_fts (rowid, request_body, response_body, error)
+         SELECT rowid,
+                CASE WHEN request_body LIKE 'ag_blob_%' THEN NULL ELSE request_body END,
+                CASE WHEN response_body LIKE 'ag_blob_%' THEN NULL ELSE response_body END,
+                error
+         FROM request_logs",
+        [],
+    ).map_err(|e| e.to_string())
+}
+
+/// VACUUM may renumber the rowids of request_logs (its primary key is TEXT),
+/// so the index is rebuilt afterwards to keep FTS rowids pointing at the right logs
+fn vacuum_and_reindex(conn: &Connection) -> Result<(), String> {
+    conn.execute("VACUUM", []).map_err(|e| e.to_string())?;
+    conn.execute("INSERT INTO request_logs_fts (request_logs_fts) VALUES ('delete-all')", [])
+        .map_err(|e| e.to_string())?;
+    backfill_fts(conn)?;
     Ok(())
 }
 
 pub fn save_log(log: &ProxyRequestLog) -> Result<(), String> {
     let conn = connect_db()?;
+    let encrypt = crate::proxy::config::get_log_retention_config().encrypt_bodies;
+    insert_log(&conn, log, encrypt)
+}
+
+fn insert_log(conn: &Connection, log: &ProxyRequestLog, encrypt: bool) -> Result<(), String> {
+    let request_body = encode_body(log.request_body.as_deref(), encrypt)?;
+    let response_body = encode_body(log.response_body.as_deref(), encrypt)?;
 
     conn.execute(
-        "INSERT INTO request_logs (id, timestamp, method, url, status, duration, model, error, request_body, response_body, input_tokens, output_tokens, account_email, mapped_model, protocol, client_ip, username)
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17)",
+        "INSERT INTO request_logs (id, timestamp, method, url, status, duration, model, error, request_body, response_body, input_tokens, output_tokens, account_email, mapped_model, protocol, client_ip, username, retries, resumes, image_bytes_saved, image_tokens_saved)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21)",
         params![
             log.id,
             log.timestamp,
@@ -81,8 +197,8 @@ pub fn save_log(log: &ProxyRequestLog) -> Result<(), String> {
             log.duration,
             log.model,
             log.error,
-            log.request_body,
-            log.response_body,
+            request_body,
+            response_body,
             log.input_tokens,
             log.output_tokens,
             log.account_email,
@@ -90,12 +206,64 @@ pub fn save_log(log: &ProxyRequestLog) -> Result<(), String> {
             log.protocol,
             log.client_ip,
             log.username,
+            log.retries,
+            log.resumes,
+            log.image_bytes_saved,
+            log.image_tokens_saved,
         ],
     ).map_err(|e| e.to_string())?;
 
     Ok(())
 }
 
+/// Encrypt a body column value when at-rest encryption is enabled
+fn encode_body(body: Option<&str>, encrypt: bool) -> Result<Option<String>, String> {
+    match body {
+        Some(b) if encrypt => crate::utils::crypto::encrypt_blob(b).map(Some),
+        Some(b) => Ok(Some(b.to_string())),
+        None => Ok(None),
+    }
+}
+
+/// Decrypt a body column value read from DB (plaintext rows pass through unchanged)
+fn decode_body(body: Option<String>) -> Option<String> {
+    body.map(|b| {
+        if crate::utils::crypto::is_encrypted_blob(&b) {
+            crate::utils::crypto::decrypt_blob(&b)
+                .unwrap_or_else(|e| format!("[encrypted body: {}]", e))
+        } else {
+            b
+        }
+    })
+}
+
+/// Map a row selected with SUMMARY_COLUMNS
+fn row_to_summary(row: &rusqlite::Row) -> rusqlite::Result<ProxyRequestLog> {
+    Ok(ProxyRequestLog {
+        id: row.get(0)?,
+        timestamp: row.get(1)?,
+        method: row.get(2)?,
+        url: row.get(3)?,
+        status: row.get(4)?,
+        duration: row.get(5)?,
+        model: row.get(6)?,
+        mapped_model: row.get(13).unwrap_or(None),
+        account_email: row.get(12).unwrap_or(None),
+        error: row.get(7)?,
+        request_body: None,  // Don't query large fields for list view
+        response_body: None, // Don't query large fields for list view
+        input_tokens: row.get(10).unwrap_or(None),
+        output_tokens: row.get(11).unwrap_or(None),
+        protocol: row.get(14).unwrap_or(None),
+        client_ip: row.get(15).unwrap_or(None),
+        username: row.get(16).unwrap_or(None),
+        retries: row.get(17).unwrap_or(None),
+        resumes: row.get(18).unwrap_or(None),
+        image_bytes_saved: row.get(19).unwrap_or(None),
+        image_tokens_saved: row.get(20).unwrap_or(None),
+    })
+}
+
 /// Get logs summary (without large request_body and response_body fields) with pagination
 pub fn get_logs_summary(limit: usize, offset: usize) -> Result<Vec<ProxyRequestLog>, String> {
     let conn = connect_db()?;
@@ -128,6 +296,10 @@ pub fn get_logs_summary(limit: usize, offset: usize) -> Result<Vec<ProxyRequestL
             protocol: row.get(14).unwrap_or(None),
             client_ip: row.get(15).unwrap_or(None),
             username: row.get(16).unwrap_or(None),
+            retries: row.get(17).unwrap_or(None),
+            resumes: row.get(18).unwrap_or(None),
+            image_bytes_saved: row.get(19).unwrap_or(None),
+            image_tokens_saved: row.get(20).unwrap_or(None),
         })
 
     }).map_err(|e| e.to_string())?;
@@ -170,54 +342,44 @@ pub fn get_stats() -> Result<crate::proxy::monitor::ProxyStats, String> {
 pub fn get_log_detail(log_id: &str) -> Result<ProxyRequestLog, String> {
     let conn = connect_db()?;
 
-    let mut stmt = conn.prepare(
-        "SELECT id, timestamp, method, url, status, duration, model, error,
-                request_body, response_body, input_tokens, output_tokens,
-                account_email, mapped_model, protocol, client_ip, username
-         FROM request_logs
-         WHERE id = ?1"
-    ).map_err(|e| e.to_string())?;
+    let sql = format!("SELECT {} FROM request_logs WHERE id = ?1", FULL_COLUMNS);
+    let mut stmt = conn.prepare(&sql).map_err(|e| e.to_string())?;
 
-    stmt.query_row([log_id], |row| {
-        Ok(ProxyRequestLog {
-            id: row.get(0)?,
-            timestamp: row.get(1)?,
-            method: row.get(2)?,
-            url: row.get(3)?,
-            status: row.get(4)?,
-            duration: row.get(5)?,
-            model: row.get(6)?,
-            mapped_model: row.get(13).unwrap_or(None),
-            account_email: row.get(12).unwrap_or(None),
-            error: row.get(7)?,
-            request_body: row.get(8).unwrap_or(None),
-            response_body: row.get(9).unwrap_or(None),
-            input_tokens: row.get(10).unwrap_or(None),
-            output_tokens: row.get(11).unwrap_or(None),
-            protocol: row.get(14).unwrap_or(None),
-            client_ip: row.get(15).unwrap_or(None),
-            username: row.get(16).unwrap_or(None),
-        })
-    }).map_err(|e| e.to_string())
+    stmt.query_row([log_id], row_to_full).map_err(|e| e.to_string())
+}
+
+/// Map a row selected with FULL_COLUMNS (bodies are decrypted if needed)
+fn row_to_full(row: &rusqlite::Row) -> rusqlite::Result<ProxyRequestLog> {
+    let mut log = row_to_summary(row)?;
+    log.request_body = decode_body(row.get(8).unwrap_or(None));
+    log.response_body = decode_body(row.get(9).unwrap_or(None));
+    Ok(log)
 }
 
 /// Cleanup old logs (keep last N days)
 pub fn cleanup_old_logs(days: i64) -> Result<usize, String> {
     let conn = connect_db()?;
     
-    let cutoff_timestamp = chrono::Utc::now().timestamp() - (days * 24 * 3600);
+    // request_logs.timestamp is stored in milliseconds
+    let cutoff_timestamp = chrono::Utc::now().timestamp_millis() - (days * 24 * 3600 * 1000);
     
     let deleted = conn.execute(
         "DELETE FROM request_logs WHERE timestamp < ?1",
         [cutoff_timestamp],
     ).map_err(|e| e.to_string())?;
-    
-    // Execute VACUUM to reclaim disk space
-    conn.execute("VACUUM", []).map_err(|e| e.to_string())?;
-    
+
     Ok(deleted)
 }
 
+/// Reclaim disk space (VACUUM + WAL checkpoint)
+pub fn vacuum_db() -> Result<(), String> {
+    let conn = connect_db()?;
+    vacuum_and_reindex(&conn)?;
+    conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
 /// Limit maximum log count (keep newest N records)
 #[allow(dead_code)]
 pub fn limit_max_logs(max_count: usize) -> Result<usize, String> {
@@ -229,8 +391,8 @@ pub fn limit_max_logs(max_count: usize) -> Result<usize, String> {
         )",
         [max_count],
     ).map_err(|e| e.to_string())?;
-    
-    conn.execute("VACUUM", []).map_err(|e| e.to_string())?;
+
+    vacuum_and_reindex(&conn)?;
     
     Ok(deleted)
 }
@@ -254,187 +416,365 @@ pub fn get_logs_count() -> Result<u64, String> {
     Ok(count)
 }
 
-/// Get count of logs matching search filter
-/// filter: search text to match in url, method, model, or status
-/// errors_only: if true, only count logs with status < 200 or >= 400
-pub fn get_logs_count_filtered(filter: &str, errors_only: bool) -> Result<u64, String> {
-    let conn = connect_db()?;
-    
-    let filter_pattern = format!("%{}%", filter);
-    
-    let sql = if errors_only {
-        "SELECT COUNT(*) FROM request_logs WHERE (status < 200 OR status >= 400)"
-    } else if filter.is_empty() {
-        "SELECT COUNT(*) FROM request_logs"
-    } else {
-        "SELECT COUNT(*) FROM request_logs WHERE
-            (url LIKE ?1 OR method LIKE ?1 OR model LIKE ?1 OR CAST(status AS TEXT) LIKE ?1 OR account_email LIKE ?1)"
-    };
-    
-    let count: u64 = if filter.is_empty() && !errors_only {
-        conn.query_row(sql, [], |row| row.get(0))
-    } else if errors_only {
-        conn.query_row(sql, [], |row| row.get(0))
-    } else {
-        conn.query_row(sql, [&filter_pattern], |row| row.get(0))
-    }.map_err(|e| e.to_string())?;
-    
-    Ok(count)
+/// Sort order for log queries
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogSort {
+    #[default]
+    TimestampDesc,
+    TimestampAsc,
+    DurationDesc,
+    TokensDesc,
 }
 
-/// Get logs with search filter and pagination
-/// filter: search text to match in url, method, model, or status
-/// errors_only: if true, only return logs with status < 200 or >= 400
-pub fn get_logs_filtered(filter: &str, errors_only: bool, limit: usize, offset: usize) -> Result<Vec<ProxyRequestLog>, String> {
-    let conn = connect_db()?;
+impl LogSort {
+    fn key_expr(&self) -> &'static str {
+        match self {
+            LogSort::TimestampDesc | LogSort::TimestampAsc => "timestamp",
+            LogSort::DurationDesc => "duration",
+            LogSort::TokensDesc => "(COALESCE(input_tokens, 0) + COALESCE(output_tokens, 0))",
+        }
+    }
 
-    let filter_pattern = format!("%{}%", filter);
-    
-    let sql = if errors_only {
-        "SELECT id, timestamp, method, url, status, duration, model, error,
-                NULL as request_body, NULL as response_body,
-                input_tokens, output_tokens, account_email, mapped_model, protocol, client_ip, username
-         FROM request_logs
-         WHERE (status < 200 OR status >= 400)
-         ORDER BY timestamp DESC
-         LIMIT ?1 OFFSET ?2"
-    } else if filter.is_empty() {
-        "SELECT id, timestamp, method, url, status, duration, model, error,
-                NULL as request_body, NULL as response_body,
-                input_tokens, output_tokens, account_email, mapped_model, protocol, client_ip, username
-         FROM request_logs
-         ORDER BY timestamp DESC
-         LIMIT ?1 OFFSET ?2"
+    fn is_desc(&self) -> bool {
+        !matches!(self, LogSort::TimestampAsc)
+    }
+}
+
+/// Structured log query
+/// - filter: legacy free-text LIKE match on url/method/model/status/account/ip
+/// - text: FTS5 match on request/response bodies and error text
+/// - cursor: opaque value from a previous LogPage (takes precedence over offset)
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct LogQuery {
+    pub filter: String,
+    #[serde(alias = "errors_only")]
+    pub errors_only: bool,
+    pub text: Option<String>,
+    /// Inclusive lower bound (ms)
+    pub start_time: Option<i64>,
+    /// Exclusive upper bound (ms)
+    pub end_time: Option<i64>,
+    pub model: Option<String>,
+    pub mapped_model: Option<String>,
+    pub account: Option<String>,
+    pub username: Option<String>,
+    pub protocol: Option<String>,
+    /// 2 / 4 / 5 (or 200 / 400 / 500) for 2xx / 4xx / 5xx
+    pub status_class: Option<u16>,
+    /// Minimum duration (ms)
+    pub min_latency: Option<u64>,
+    /// Minimum input + output tokens
+    pub min_tokens: Option<u64>,
+    pub sort: LogSort,
+    pub cursor: Option<String>,
+    pub limit: usize,
+    pub offset: usize,
+}
+
+impl LogQuery {
+    /// Check client-supplied parameters that are parsed after deserialization,
+    /// so callers can tell a bad request apart from a database failure
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(cursor) = self.cursor.as_deref().filter(|c| !c.is_empty()) {
+            decode_cursor(cursor)?;
+        }
+        Ok(())
+    }
+}
+
+/// One page of query results
+#[derive(Debug, Clone, Serialize)]
+pub struct LogPage {
+    pub logs: Vec<ProxyRequestLog>,
+    pub next_cursor: Option<String>,
+}
+
+/// Build an FTS5 query from free text: every whitespace-separated term is
+/// quoted so user input can never be parsed as FTS syntax (terms are ANDed).
+fn to_fts_query(text: &str) -> Option<String> {
+    let terms: Vec<String> = text
+        .split_whitespace()
+        .map(|t| format!("\"{}\"", t.replace('"', "\"\"")))
+        .collect();
+    if terms.is_empty() {
+        None
     } else {
-        "SELECT id, timestamp, method, url, status, duration, model, error,
-                NULL as request_body, NULL as response_body,
-                input_tokens, output_tokens, account_email, mapped_model, protocol, client_ip, username
-         FROM request_logs
-         WHERE (url LIKE ?3 OR method LIKE ?3 OR model LIKE ?3 OR CAST(status AS TEXT) LIKE ?3 OR account_email LIKE ?3 OR client_ip LIKE ?3)
-         ORDER BY timestamp DESC
-         LIMIT ?1 OFFSET ?2"
+        Some(terms.join(" "))
+    }
+}
+
+fn encode_cursor(key: i64, id: &str) -> String {
+    use base64::Engine as _;
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(format!("{}|{}", key, id))
+}
+
+fn decode_cursor(cursor: &str) -> Result<(i64, String), String> {
+    use base64::Engine as _;
+    let raw = base64::engine::general_purpose::URL_SAFE_NO_PAD
+        .decode(cursor)
+        .ok()
+        .and_then(|b| String::from_utf8(b).ok())
+        .ok_or_else(|| "Invalid cursor".to_string())?;
+    let (key, id) = raw.split_once('|').ok_or_else(|| "Invalid cursor".to_string())?;
+    let key = key.parse::<i64>().map_err(|_| "Invalid cursor".to_string())?;
+    Ok((key, id.to_string()))
+}
+
+/// Build the WHERE clause (without cursor) and its positional parameters
+fn build_where(query: &LogQuery) -> (String, Vec<SqlValue>) {
+    let mut clauses: Vec<String> = Vec::new();
+    let mut values: Vec<SqlValue> = Vec::new();
+
+    // Placeholders are anonymous `?`, bound in order of appearance
+    let mut push = |clause: &str, vals: Vec<SqlValue>, clauses: &mut Vec<String>| {
+        clauses.push(clause.to_string());
+        values.extend(vals);
     };
 
-    let logs: Vec<ProxyRequestLog> = if filter.is_empty() && !errors_only {
-        let mut stmt = conn.prepare(sql).map_err(|e| e.to_string())?;
-        let logs_iter = stmt.query_map([limit, offset], |row| {
-            Ok(ProxyRequestLog {
-                id: row.get(0)?,
-                timestamp: row.get(1)?,
-                method: row.get(2)?,
-                url: row.get(3)?,
-                status: row.get(4)?,
-                duration: row.get(5)?,
-                model: row.get(6)?,
-                mapped_model: row.get(13).unwrap_or(None),
-                account_email: row.get(12).unwrap_or(None),
-                error: row.get(7)?,
-                request_body: None,
-                response_body: None,
-                input_tokens: row.get(10).unwrap_or(None),
-                output_tokens: row.get(11).unwrap_or(None),
-                protocol: row.get(14).unwrap_or(None),
-                client_ip: row.get(15).unwrap_or(None),
-                username: row.get(16).unwrap_or(None),
-            })
-
-        }).map_err(|e| e.to_string())?;
-        logs_iter.filter_map(|r| r.ok()).collect()
-    } else if errors_only {
-        let mut stmt = conn.prepare(sql).map_err(|e| e.to_string())?;
-        let logs_iter = stmt.query_map([limit, offset], |row| {
-            Ok(ProxyRequestLog {
-                id: row.get(0)?,
-                timestamp: row.get(1)?,
-                method: row.get(2)?,
-                url: row.get(3)?,
-                status: row.get(4)?,
-                duration: row.get(5)?,
-                model: row.get(6)?,
-                mapped_model: row.get(13).unwrap_or(None),
-                account_email: row.get(12).unwrap_or(None),
-                error: row.get(7)?,
-                request_body: None,
-                response_body: None,
-                input_tokens: row.get(10).unwrap_or(None),
-                output_tokens: row.get(11).unwrap_or(None),
-                protocol: row.get(14).unwrap_or(None),
-                client_ip: row.get(15).unwrap_or(None),
-                username: row.get(16).unwrap_or(None),
-            })
-
-        }).map_err(|e| e.to_string())?;
-        logs_iter.filter_map(|r| r.ok()).collect()
+    if query.errors_only {
+        push("(status < 200 OR status >= 400)", vec![], &mut clauses);
+    }
+    if !query.filter.is_empty() {
+        let pattern = SqlValue::Text(format!("%{}%", query.filter));
+        push(
+            "(url LIKE ? OR method LIKE ? OR model LIKE ? OR CAST(status AS TEXT) LIKE ? OR account_email LIKE ? OR client_ip LIKE ?)",
+            vec![pattern; 6],
+            &mut clauses,
+        );
+    }
+    if let Some(fts) = query.text.as_deref().and_then(to_fts_query) {
+        push(
+            "rowid IN (SELECT rowid FROM request_logs_fts WHERE request_logs_fts MATCH ?)",
+            vec![SqlValue::Text(fts)],
+            &mut clauses,
+        );
+    }
+    if let Some(start) = query.start_time {
+        push("timestamp >= ?", vec![SqlValue::Integer(start)], &mut clauses);
+    }
+    if let Some(end) = query.end_time {
+        push("timestamp < ?", vec![SqlValue::Integer(end)], &mut clauses);
+    }
+    for (column, value) in [
+        ("model", &query.model),
+        ("mapped_model", &query.mapped_model),
+        ("account_email", &query.account),
+        ("username", &query.username),
+        ("protocol", &query.protocol),
+    ] {
+        if let Some(v) = value.as_ref().filter(|v| !v.is_empty()) {
+            push(
+                &format!("{} = ?", column),
+                vec![SqlValue::Text(v.clone())],
+                &mut clauses,
+            );
+        }
+    }
+    if let Some(class) = query.status_class {
+        let class = if class >= 100 { class / 100 } else { class } as i64;
+        push(
+            "(status >= ? AND status < ?)",
+            vec![SqlValue::Integer(class * 100), SqlValue::Integer((class + 1) * 100)],
+            &mut clauses,
+        );
+    }
+    if let Some(min) = query.min_latency {
+        push("duration >= ?", vec![SqlValue::Integer(min as i64)], &mut clauses);
+    }
+    if let Some(min) = query.min_tokens {
+        push(
+            "(COALESCE(input_tokens, 0) + COALESCE(output_tokens, 0)) >= ?",
+            vec![SqlValue::Integer(min as i64)],
+            &mut clauses,
+        );
+    }
+
+    let sql = if clauses.is_empty() {
+        String::new()
     } else {
-        let mut stmt = conn.prepare(sql).map_err(|e| e.to_string())?;
-        let logs_iter = stmt.query_map(rusqlite::params![limit, offset, filter_pattern], |row| {
-            Ok(ProxyRequestLog {
-                id: row.get(0)?,
-                timestamp: row.get(1)?,
-                method: row.get(2)?,
-                url: row.get(3)?,
-                status: row.get(4)?,
-                duration: row.get(5)?,
-                model: row.get(6)?,
-                mapped_model: row.get(13).unwrap_or(None),
-                account_email: row.get(12).unwrap_or(None),
-                error: row.get(7)?,
-                request_body: None,
-                response_body: None,
-                input_tokens: row.get(10).unwrap_or(None),
-                output_tokens: row.get(11).unwrap_or(None),
-                protocol: row.get(14).unwrap_or(None),
-                client_ip: row.get(15).unwrap_or(None),
-                username: row.get(16).unwrap_or(None),
-            })
-
-        }).map_err(|e| e.to_string())?;
-        logs_iter.filter_map(|r| r.ok()).collect()
+        format!("WHERE {}", clauses.join(" AND "))
     };
+    (sql, values)
+}
 
-    Ok(logs)
+/// Count logs matching a structured query (cursor/limit/offset are ignored)
+pub fn count_logs(query: &LogQuery) -> Result<u64, String> {
+    let conn = connect_db()?;
+    let (where_sql, values) = build_where(query);
+    let sql = format!("SELECT COUNT(*) FROM request_logs {}", where_sql);
+    conn.query_row(&sql, rusqlite::params_from_iter(values.iter()), |row| row.get(0))
+        .map_err(|e| e.to_string())
 }
 
-/// Get all logs with full details for export
-pub fn get_all_logs_for_export() -> Result<Vec<ProxyRequestLog>, String> {
+/// Query logs with structured filters, sorting and cursor-based pagination
+pub fn query_logs(query: &LogQuery) -> Result<LogPage, String> {
     let conn = connect_db()?;
+    query_logs_on(&conn, query)
+}
 
-    let mut stmt = conn.prepare(
-        "SELECT id, timestamp, method, url, status, duration, model, error,
-                request_body, response_body, input_tokens, output_tokens,
-                account_email, mapped_model, protocol, client_ip, username
-         FROM request_logs
-         ORDER BY timestamp DESC"
-    ).map_err(|e| e.to_string())?;
+fn query_logs_on(conn: &Connection, query: &LogQuery) -> Result<LogPage, String> {
+    let limit = if query.limit == 0 { 50 } else { query.limit };
+    let key = query.sort.key_expr();
+    let (cmp, dir) = if query.sort.is_desc() { ("<", "DESC") } else { (">", "ASC") };
+
+    let (mut where_sql, mut values) = build_where(query);
+
+    let cursor = query.cursor.as_deref().filter(|c| !c.is_empty());
+    if let Some(cursor) = cursor {
+        let (cursor_key, cursor_id) = decode_cursor(cursor)?;
+        let cursor_clause = format!("({key} {cmp} ? OR ({key} = ? AND id {cmp} ?))", key = key, cmp = cmp);
+        values.push(SqlValue::Integer(cursor_key));
+        values.push(SqlValue::Integer(cursor_key));
+        values.push(SqlValue::Text(cursor_id));
+        where_sql = if where_sql.is_empty() {
+            format!("WHERE {}", cursor_clause)
+        } else {
+            format!("{} AND {}", where_sql, cursor_clause)
+        };
+    }
 
-    let logs_iter = stmt.query_map([], |row| {
-        Ok(ProxyRequestLog {
-            id: row.get(0)?,
-            timestamp: row.get(1)?,
-            method: row.get(2)?,
-            url: row.get(3)?,
-            status: row.get(4)?,
-            duration: row.get(5)?,
-            model: row.get(6)?,
-            mapped_model: row.get(13).unwrap_or(None),
-            account_email: row.get(12).unwrap_or(None),
-            error: row.get(7)?,
-            request_body: row.get(8).unwrap_or(None),
-            response_body: row.get(9).unwrap_or(None),
-            input_tokens: row.get(10).unwrap_or(None),
-            output_tokens: row.get(11).unwrap_or(None),
-            protocol: row.get(14).unwrap_or(None),
-            client_ip: row.get(15).unwrap_or(None),
-            username: row.get(16).unwrap_or(None),
-        })
+    // Offset only applies to legacy page-based pagination
+    let offset = if cursor.is_some() { 0 } else { query.offset };
+    values.push(SqlValue::Integer(limit as i64));
+    values.push(SqlValue::Integer(offset as i64));
 
-    }).map_err(|e| e.to_string())?;
+    let sql = format!(
+        "SELECT {columns}, {key} AS sort_key
+         FROM request_logs
+         {where_sql}
+         ORDER BY {key} {dir}, id {dir}
+         LIMIT ? OFFSET ?",
+        columns = SUMMARY_COLUMNS,
+        key = key,
+        where_sql = where_sql,
+        dir = dir,
+    );
+
+    let mut stmt = conn.prepare(&sql).map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(rusqlite::params_from_iter(values.iter()), |row| {
+            Ok((row_to_summary(row)?, row.get::<_, i64>("sort_key")?))
+        })
+        .map_err(|e| e.to_string())?;
 
     let mut logs = Vec::new();
-    for log in logs_iter {
-        logs.push(log.map_err(|e| e.to_string())?);
+    let mut last_key = None;
+    for row in rows {
+        let (log, sort_key) = row.map_err(|e| e.to_string())?;
+        last_key = Some(sort_key);
+        logs.push(log);
     }
-    Ok(logs)
+
+    let next_cursor = match (logs.len() == limit, last_key, logs.last()) {
+        (true, Some(k), Some(last)) => Some(encode_cursor(k, &last.id)),
+        _ => None,
+    };
+
+    Ok(LogPage { logs, next_cursor })
+}
+
+/// Get count of logs matching search filter
+/// filter: search text to match in url, method, model, or status
+/// errors_only: if true, only count logs with status < 200 or >= 400
+pub fn get_logs_count_filtered(filter: &str, errors_only: bool) -> Result<u64, String> {
+    count_logs(&LogQuery {
+        filter: filter.to_string(),
+        errors_only,
+        ..Default::default()
+    })
+}
+
+/// Get logs with search filter and pagination
+/// filter: search text to match in url, method, model, or status
+/// errors_only: if true, only return logs with status < 200 or >= 400
+pub fn get_logs_filtered(filter: &str, errors_only: bool, limit: usize, offset: usize) -> Result<Vec<ProxyRequestLog>, String> {
+    query_logs(&LogQuery {
+        filter: filter.to_string(),
+        errors_only,
+        limit,
+        offset,
+        ..Default::default()
+    })
+    .map(|page| page.logs)
+}
+
+/// Stream every log matching `query` (with bodies) to `f` row by row,
+/// without loading the whole result set into memory. limit/offset/cursor are ignored.
+pub fn for_each_log<F>(query: &LogQuery, mut f: F) -> Result<usize, String>
+where
+    F: FnMut(ProxyRequestLog) -> Result<(), String>,
+{
+    let conn = connect_db()?;
+    let (where_sql, values) = build_where(query);
+    let dir = if query.sort.is_desc() { "DESC" } else { "ASC" };
+    let sql = format!(
+        "SELECT {columns} FROM request_logs {where_sql} ORDER BY {key} {dir}, id {dir}",
+        columns = FULL_COLUMNS,
+        where_sql = where_sql,
+        key = query.sort.key_expr(),
+        dir = dir,
+    );
+
+    let mut stmt = conn.prepare(&sql).map_err(|e| e.to_string())?;
+    let mut rows = stmt
+        .query(rusqlite::params_from_iter(values.iter()))
+        .map_err(|e| e.to_string())?;
+
+    let mut count = 0;
+    while let Some(row) = rows.next().map_err(|e| e.to_string())? {
+        f(row_to_full(row).map_err(|e| e.to_string())?)?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// Insert logs (e.g. from another instance's export) in one transaction.
+/// Rows whose id already exists are skipped. Returns (imported, duplicates).
+pub fn import_logs(logs: &[ProxyRequestLog]) -> Result<(usize, usize), String> {
+    let mut conn = connect_db()?;
+    let encrypt = crate::proxy::config::get_log_retention_config().encrypt_bodies;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+
+    let mut imported = 0;
+    {
+        let mut stmt = tx.prepare(
+            "INSERT OR IGNORE INTO request_logs (id, timestamp, method, url, status, duration, model, error, request_body, response_body, input_tokens, output_tokens, account_email, mapped_model, protocol, client_ip, username, retries, resumes, image_bytes_saved, image_tokens_saved)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21)"
+        ).map_err(|e| e.to_string())?;
+
+        for log in logs {
+            let request_body = encode_body(log.request_body.as_deref(), encrypt)?;
+            let response_body = encode_body(log.response_body.as_deref(), encrypt)?;
+            imported += stmt.execute(params![
+                log.id,
+                log.timestamp,
+                log.method,
+                log.url,
+                log.status,
+                log.duration,
+                log.model,
+                log.error,
+                request_body,
+                response_body,
+                log.input_tokens,
+                log.output_tokens,
+                log.account_email,
+                log.mapped_model,
+                log.protocol,
+                log.client_ip,
+                log.username,
+                log.retries,
+                log.resumes,
+                log.image_bytes_saved,
+                log.image_tokens_saved,
+            ]).map_err(|e| e.to_string())?;
+        }
+    }
+
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok((imported, logs.len() - imported))
 }
 
 // ... existing code ...
@@ -504,3 +844,171 @@ pub fn get_token_usage_by_ip(limit: usize, hours: i64) -> Result<Vec<IpTokenStat
     Ok(stats)
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fts_query_quotes_terms() {
+        assert_eq!(to_fts_query("  "), None);
+        assert_eq!(
+            to_fts_query("rate limit"),
+            Some("\"rate\" \"limit\"".to_string())
+        );
+        // FTS operators and quotes are treated as literal text
+        assert_eq!(
+            to_fts_query("a\"b OR"),
+            Some("\"a\"\"b\" \"OR\"".to_string())
+        );
+    }
+
+    #[test]
+    fn test_cursor_roundtrip() {
+        let cursor = encode_cursor(1_700_000_000_000, "abc|def");
+        assert_eq!(
+            decode_cursor(&cursor).unwrap(),
+            (1_700_000_000_000, "abc|def".to_string())
+        );
+        assert!(decode_cursor("not-a-cursor").is_err());
+
+        let mut query = LogQuery {
+            cursor: Some(cursor),
+            ..Default::default()
+        };
+        assert!(query.validate().is_ok());
+        query.cursor = Some("not-a-cursor".to_string());
+        assert!(query.validate().is_err());
+    }
+
+    fn sample_log(id: &str, timestamp: i64) -> ProxyRequestLog {
+        ProxyRequestLog {
+            id: id.to_string(),
+            timestamp,
+            method: "POST".to_string(),
+            url: "/v1/messages".to_string(),
+            status: 200,
+            duration: 100,
+            model: None,
+            mapped_model: None,
+            account_email: None,
+            client_ip: None,
+            error: None,
+            request_body: None,
+            response_body: None,
+            input_tokens: None,
+            output_tokens: None,
+            protocol: None,
+            username: None,
+            retries: Some(0),
+            resumes: None,
+            image_bytes_saved: None,
+            image_tokens_saved: None,
+        }
+    }
+
+    #[test]
+    fn test_cursor_pagination_walks_all_rows() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_schema(&conn).unwrap();
+        for i in 0..5 {
+            insert_log(&conn, &sample_log(&format!("log-{}", i), 1_000 + i), false).unwrap();
+        }
+        // Row written before the retries column existed
+        let mut legacy = sample_log("legacy", 999);
+        legacy.retries = None;
+        insert_log(&conn, &legacy, false).unwrap();
+
+        let mut query = LogQuery {
+            limit: 2,
+            ..Default::default()
+        };
+        let mut seen = Vec::new();
+        loop {
+            let page = query_logs_on(&conn, &query).unwrap();
+            seen.extend(page.logs.into_iter().map(|l| l.id));
+            match page.next_cursor {
+                Some(cursor) => query.cursor = Some(cursor),
+                None => break,
+            }
+        }
+        assert_eq!(
+            seen,
+            vec!["log-4", "log-3", "log-2", "log-1", "log-0", "legacy"]
+        );
+    }
+
+    #[test]
+    fn test_fts_follows_deletes_and_vacuum() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_schema(&conn).unwrap();
+        for (i, body) in ["rate limit exceeded", "hello world", "quota exceeded"].iter().enumerate() {
+            let mut log = sample_log(&format!("log-{}", i), 1_000 + i as i64);
+            log.response_body = Some(body.to_string());
+            insert_log(&conn, &log, false).unwrap();
+        }
+        let search = |conn: &Connection, text: &str| -> Vec<String> {
+            let query = LogQuery {
+                text: Some(text.to_string()),
+                limit: 10,
+                ..Default::default()
+            };
+            query_logs_on(conn, &query).unwrap().logs.into_iter().map(|l| l.id).collect()
+        };
+        let fts_rows = |conn: &Connection| -> i64 {
+            conn.query_row("SELECT COUNT(*) FROM request_logs_fts", [], |row| row.get(0))
+                .unwrap()
+        };
+        assert_eq!(search(&conn, "exceeded"), vec!["log-2", "log-0"]);
+
+        conn.execute("DELETE FROM request_logs WHERE id = 'log-0'", []).unwrap();
+        assert_eq!(fts_rows(&conn), 2);
+        assert_eq!(search(&conn, "exceeded"), vec!["log-2"]);
+
+        vacuum_and_reindex(&conn).unwrap();
+        assert_eq!(fts_rows(&conn), 2);
+        assert_eq!(search(&conn, "hello"), vec!["log-1"]);
+    }
+
+    #[test]
+    fn test_fts_migrates_to_contentless_index() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE request_logs (id TEXT PRIMARY KEY, timestamp INTEGER, method TEXT, url TEXT, status INTEGER, duration INTEGER, model TEXT, error TEXT);
+             CREATE VIRTUAL TABLE request_logs_fts USING fts5(id UNINDEXED, request_body, response_body, error);
+             INSERT INTO request_logs (id, timestamp, method, url, status, duration, error) VALUES ('old', 1, 'POST', '/v1/messages', 500, 10, 'upstream overloaded');",
+        ).unwrap();
+        init_schema(&conn).unwrap();
+
+        let sql: String = conn
+            .query_row(
+                "SELECT sql FROM sqlite_master WHERE name = 'request_logs_fts'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert!(sql.contains("content=''"));
+        let query = LogQuery {
+            text: Some("overloaded".to_string()),
+            limit: 10,
+            ..Default::default()
+        };
+        let ids: Vec<String> = query_logs_on(&conn, &query).unwrap().logs.into_iter().map(|l| l.id).collect();
+        assert_eq!(ids, vec!["old"]);
+    }
+
+    #[test]
+    fn test_build_where_binds_in_order() {
+        let query = LogQuery {
+            filter: "gemini".to_string(),
+            model: Some("claude-sonnet-4-5".to_string()),
+            status_class: Some(429),
+            min_latency: Some(1000),
+            ..Default::default()
+        };
+        let (sql, values) = build_where(&query);
+        assert_eq!(sql.matches('?').count(), values.len());
+        assert_eq!(values.len(), 6 + 1 + 2 + 1);
+        assert_eq!(values[7], SqlValue::Integer(400));
+        assert_eq!(values[8], SqlValue::Integer(500));
+    }
+}
@@ -371,12 +371,16 @@ pub fn cleanup_old_ip_logs(days: i64) -> Result<usize, String> {
         )
         .map_err(|e| e.to_string())?;
 
-    // VACUUM to reclaim space
-    conn.execute("VACUUM", []).map_err(|e| e.to_string())?;
-
     Ok(deleted)
 }
 
+/// 压缩数据库，回收已删除日志占用的磁盘空间
+pub fn vacuum_db() -> Result<(), String> {
+    let conn = connect_db()?;
+    conn.execute("VACUUM", []).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
 // ============================================================================
 // 黑名单操作
 // ============================================================================
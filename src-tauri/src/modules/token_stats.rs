@@ -553,6 +553,28 @@ pub fn get_account_trend_daily(days: i64) -> Result<Vec<AccountTrendPoint>, Stri
         .collect())
 }
 
+/// Delete raw usage rows older than N days (hourly aggregates are kept)
+pub fn cleanup_old_usage(days: i64) -> Result<usize, String> {
+    let conn = connect_db()?;
+    let cutoff = chrono::Utc::now().timestamp() - (days * 24 * 3600);
+    conn.execute("DELETE FROM token_usage WHERE timestamp < ?1", [cutoff])
+        .map_err(|e| e.to_string())
+}
+
+/// Get total count of raw usage rows
+pub fn get_usage_count() -> Result<u64, String> {
+    let conn = connect_db()?;
+    conn.query_row("SELECT COUNT(*) FROM token_usage", [], |row| row.get(0))
+        .map_err(|e| e.to_string())
+}
+
+/// Reclaim disk space
+pub fn vacuum_db() -> Result<(), String> {
+    let conn = connect_db()?;
+    conn.execute("VACUUM", []).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -28,6 +28,8 @@ pub struct UserToken {
     pub last_used_at: Option<i64>,
     pub total_requests: i64,
     pub total_tokens_used: i64,
+    #[serde(default)]
+    pub priority: i32,             // 排队优先级，越大越优先
 }
 
 /// 令牌 IP 绑定结构体
@@ -91,7 +93,8 @@ pub fn init_db() -> Result<(), String> {
             total_requests INTEGER NOT NULL DEFAULT 0,
             total_tokens_used INTEGER NOT NULL DEFAULT 0,
             curfew_start TEXT,
-            curfew_end TEXT
+            curfew_end TEXT,
+            priority INTEGER NOT NULL DEFAULT 0
         )",
         [],
     ).map_err(|e| format!("Failed to create user_tokens table: {}", e))?;
@@ -105,6 +108,7 @@ pub fn init_db() -> Result<(), String> {
     let _ = conn.execute("ALTER TABLE user_tokens ADD COLUMN last_used_at INTEGER", []);
     let _ = conn.execute("ALTER TABLE user_tokens ADD COLUMN curfew_start TEXT", []);
     let _ = conn.execute("ALTER TABLE user_tokens ADD COLUMN curfew_end TEXT", []);
+    let _ = conn.execute("ALTER TABLE user_tokens ADD COLUMN priority INTEGER DEFAULT 0", []);
 
     // 创建 token_ip_bindings 表
     conn.execute(
@@ -149,6 +153,7 @@ pub fn init_db() -> Result<(), String> {
     let _ = conn.execute("UPDATE user_tokens SET total_requests = 0 WHERE total_requests IS NULL", []);
     let _ = conn.execute("UPDATE user_tokens SET total_tokens_used = 0 WHERE total_tokens_used IS NULL", []);
     let _ = conn.execute("UPDATE user_tokens SET enabled = 1 WHERE enabled IS NULL", []);
+    let _ = conn.execute("UPDATE user_tokens SET priority = 0 WHERE priority IS NULL", []);
 
     Ok(())
 }
@@ -161,7 +166,8 @@ pub fn create_token(
     max_ips: i32,
     curfew_start: Option<String>,
     curfew_end: Option<String>,
-    custom_expires_at: Option<i64>  // 自定义过期时间戳 (秒)
+    custom_expires_at: Option<i64>, // 自定义过期时间戳 (秒)
+    priority: i32,
 ) -> Result<UserToken, String> {
     let conn = connect_db()?;
     let id = Uuid::new_v4().to_string();
@@ -192,14 +198,15 @@ pub fn create_token(
         last_used_at: None,
         total_requests: 0,
         total_tokens_used: 0,
+        priority,
     };
 
     conn.execute(
         "INSERT INTO user_tokens (
             id, token, username, description, enabled, expires_type, expires_at, max_ips,
             curfew_start, curfew_end,
-            created_at, updated_at, total_requests, total_tokens_used
-        ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+            created_at, updated_at, total_requests, total_tokens_used, priority
+        ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)",
         params![
             user_token.id,
             user_token.token,
@@ -215,6 +222,7 @@ pub fn create_token(
             user_token.updated_at,
             user_token.total_requests,
             user_token.total_tokens_used,
+            user_token.priority,
         ],
     ).map_err(|e| format!("Failed to insert user token: {}", e))?;
 
@@ -244,6 +252,7 @@ pub fn list_tokens() -> Result<Vec<UserToken>, String> {
             last_used_at: row.get("last_used_at").unwrap_or(None),
             total_requests: row.get("total_requests").unwrap_or(0),
             total_tokens_used: row.get("total_tokens_used").unwrap_or(0),
+            priority: row.get("priority").unwrap_or(0),
         })
     }).map_err(|e| format!("Failed to query tokens: {}", e))?;
 
@@ -278,6 +287,7 @@ pub fn get_token_by_id(id: &str) -> Result<Option<UserToken>, String> {
             last_used_at: row.get("last_used_at")?,
             total_requests: row.get("total_requests")?,
             total_tokens_used: row.get("total_tokens_used")?,
+            priority: row.get("priority").unwrap_or(0),
         })
     }).optional().map_err(|e| format!("Failed to query token: {}", e))?;
     
@@ -307,6 +317,7 @@ pub fn get_token_by_value(token: &str) -> Result<Option<UserToken>, String> {
             last_used_at: row.get("last_used_at")?,
             total_requests: row.get("total_requests")?,
             total_tokens_used: row.get("total_tokens_used")?,
+            priority: row.get("priority").unwrap_or(0),
         })
     }).optional().map_err(|e| format!("Failed to query token: {}", e))?;
     
@@ -321,7 +332,8 @@ pub fn update_token(
     enabled: Option<bool>,
     max_ips: Option<i32>,
     curfew_start: Option<Option<String>>,
-    curfew_end: Option<Option<String>>
+    curfew_end: Option<Option<String>>,
+    priority: Option<i32>,
 ) -> Result<(), String> {
     let conn = connect_db()?;
     let now = Utc::now().timestamp();
@@ -366,6 +378,12 @@ pub fn update_token(
         param_idx += 1;
     }
 
+    if let Some(p) = priority {
+        query.push_str(&format!(", priority = ?{}", param_idx));
+        params_vec.push(Box::new(p));
+        param_idx += 1;
+    }
+
     query.push_str(&format!(" WHERE id = ?{}", param_idx));
     params_vec.push(Box::new(id.to_string()));
 
@@ -585,6 +603,31 @@ pub fn get_username_for_ip(ip: &str) -> Result<Option<String>, String> {
     Ok(result)
 }
 
+/// 清理旧的令牌使用日志 (保留最近 N 天)
+pub fn cleanup_old_usage_logs(days: i64) -> Result<usize, String> {
+    let conn = connect_db()?;
+    let cutoff = Utc::now().timestamp() - (days * 24 * 3600);
+    conn.execute(
+        "DELETE FROM token_usage_logs WHERE request_time < ?1",
+        params![cutoff],
+    ).map_err(|e| format!("Failed to cleanup usage logs: {}", e))
+}
+
+/// 获取令牌使用日志总数
+pub fn get_usage_logs_count() -> Result<u64, String> {
+    let conn = connect_db()?;
+    conn.query_row("SELECT COUNT(*) FROM token_usage_logs", [], |row| row.get(0))
+        .map_err(|e| format!("Failed to count usage logs: {}", e))
+}
+
+/// 压缩数据库
+pub fn vacuum_db() -> Result<(), String> {
+    let conn = connect_db()?;
+    conn.execute("VACUUM", [])
+        .map_err(|e| format!("Failed to vacuum database: {}", e))?;
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -595,7 +638,7 @@ mod tests {
         
         // Use a random username to avoid collisions in existing DB runs during dev
         let username = format!("TestUser_{}", Uuid::new_v4());
-        let token_res = create_token(username.clone(), "day".to_string(), Some("Test token".to_string()), 0, None, None, None);
+        let token_res = create_token(username.clone(), "day".to_string(), Some("Test token".to_string()), 0, None, None, None, 0);
         assert!(token_res.is_ok());
 
         let token = token_res.unwrap();
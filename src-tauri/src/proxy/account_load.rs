@@ -0,0 +1,401 @@
+// 账号负载跟踪
+// 记录每个账号的在途请求数与上游首字节延迟 EWMA，供加权调度使用。
+//
+// 在途计数通过请求级租约 (AccountLease) 维护：TokenManager::get_token 成功后把租约挂到
+// 当前请求上 (同一请求重试换号时旧租约被替换并释放)，monitor 中间件持有租约直到响应体
+// (包括 SSE 流) 结束，因此计数覆盖整个上游流的生命周期。
+//
+// 租约同时充当并发许可：配置了账号 / 账号+模型的在途上限时，只有未饱和的账号才能发放租约，
+// 租约释放时唤醒等待许可的请求。
+
+use dashmap::DashMap;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+use tokio::sync::futures::Notified;
+use tokio::sync::Notify;
+
+tokio::task_local! {
+    static REQUEST_LEASE: LeaseSlot;
+}
+
+/// 请求级租约槽位 (由 monitor 中间件创建并持有)
+pub type LeaseSlot = Arc<Mutex<Option<AccountLease>>>;
+
+/// 在途并发上限 (0 = 不限制)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct ConcurrencyLimits {
+    pub per_account: usize,
+    pub per_account_model: usize,
+}
+
+impl ConcurrencyLimits {
+    pub fn is_unlimited(&self) -> bool {
+        self.per_account == 0 && self.per_account_model == 0
+    }
+}
+
+#[derive(Debug, Default)]
+struct InFlightCounts {
+    accounts: HashMap<String, usize>,
+    /// (account_id, model) -> 在途数
+    models: HashMap<(String, String), usize>,
+}
+
+impl InFlightCounts {
+    fn is_saturated(&self, account_id: &str, model: &str, limits: ConcurrencyLimits) -> bool {
+        if limits.per_account > 0
+            && self.accounts.get(account_id).copied().unwrap_or(0) >= limits.per_account
+        {
+            return true;
+        }
+        limits.per_account_model > 0
+            && self
+                .models
+                .get(&(account_id.to_string(), model.to_string()))
+                .copied()
+                .unwrap_or(0)
+                >= limits.per_account_model
+    }
+}
+
+/// 单个账号的负载快照 (供 Admin API 展示)
+#[derive(Debug, Clone, Serialize)]
+pub struct AccountLoadSnapshot {
+    pub account_id: String,
+    pub email: String,
+    pub in_flight: usize,
+    /// 按模型拆分的在途数
+    pub in_flight_by_model: BTreeMap<String, usize>,
+    pub latency_ewma_ms: Option<f64>,
+    pub saturated: bool,
+}
+
+#[derive(Debug)]
+pub struct AccountLoadTracker {
+    counts: Mutex<InFlightCounts>,
+    latency_ms: DashMap<String, f64>,
+    alpha_bits: AtomicU64,
+    /// 租约释放通知 (唤醒等待并发许可的请求)
+    released: Notify,
+}
+
+impl Default for AccountLoadTracker {
+    fn default() -> Self {
+        Self {
+            counts: Mutex::new(InFlightCounts::default()),
+            latency_ms: DashMap::new(),
+            alpha_bits: AtomicU64::new(0.2f64.to_bits()),
+            released: Notify::new(),
+        }
+    }
+}
+
+impl AccountLoadTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 更新延迟 EWMA 平滑系数
+    pub fn set_latency_alpha(&self, alpha: f32) {
+        let alpha = (alpha as f64).clamp(0.01, 1.0);
+        self.alpha_bits.store(alpha.to_bits(), Ordering::Relaxed);
+    }
+
+    fn counts(&self) -> MutexGuard<'_, InFlightCounts> {
+        self.counts.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// 账号当前在途请求数
+    pub fn in_flight(&self, account_id: &str) -> usize {
+        self.counts().accounts.get(account_id).copied().unwrap_or(0)
+    }
+
+    /// 账号 (或账号+模型) 是否已达到在途上限
+    pub fn is_saturated(&self, account_id: &str, model: &str, limits: ConcurrencyLimits) -> bool {
+        !limits.is_unlimited() && self.counts().is_saturated(account_id, model, limits)
+    }
+
+    /// 是否存在任何已饱和的账号 (此时等待租约释放可能换来可用账号)
+    pub fn any_saturated(&self, limits: ConcurrencyLimits) -> bool {
+        if limits.is_unlimited() {
+            return false;
+        }
+        let counts = self.counts();
+        (limits.per_account > 0 && counts.accounts.values().any(|n| *n >= limits.per_account))
+            || (limits.per_account_model > 0
+                && counts.models.values().any(|n| *n >= limits.per_account_model))
+    }
+
+    /// 账号上游首字节延迟 EWMA (毫秒)，无样本时为 None
+    pub fn latency_ms(&self, account_id: &str) -> Option<f64> {
+        self.latency_ms.get(account_id).map(|v| *v)
+    }
+
+    pub fn record_latency(&self, account_id: &str, latency: Duration) {
+        let alpha = f64::from_bits(self.alpha_bits.load(Ordering::Relaxed));
+        let ms = latency.as_secs_f64() * 1000.0;
+        self.latency_ms
+            .entry(account_id.to_string())
+            .and_modify(|v| *v = alpha * ms + (1.0 - alpha) * *v)
+            .or_insert(ms);
+    }
+
+    /// 尝试为该账号发放并发许可；已饱和时返回 None
+    /// 返回的租约释放时在途计数自动减一
+    pub fn try_begin(
+        self: &Arc<Self>,
+        account_id: &str,
+        model: &str,
+        limits: ConcurrencyLimits,
+    ) -> Option<AccountLease> {
+        let mut counts = self.counts();
+        if counts.is_saturated(account_id, model, limits) {
+            return None;
+        }
+        *counts.accounts.entry(account_id.to_string()).or_insert(0) += 1;
+        *counts
+            .models
+            .entry((account_id.to_string(), model.to_string()))
+            .or_insert(0) += 1;
+        drop(counts);
+
+        Some(AccountLease {
+            tracker: self.clone(),
+            account_id: account_id.to_string(),
+            model: model.to_string(),
+            started: Instant::now(),
+            first_byte_recorded: false,
+        })
+    }
+
+    /// 等待任意租约释放；需在检查饱和状态之前调用 `enable()` 以免错过通知
+    pub fn released(&self) -> Notified<'_> {
+        self.released.notified()
+    }
+
+    fn release(&self, account_id: &str, model: &str) {
+        {
+            let mut counts = self.counts();
+            if let Some(count) = counts.accounts.get_mut(account_id) {
+                *count = count.saturating_sub(1);
+                if *count == 0 {
+                    counts.accounts.remove(account_id);
+                }
+            }
+            let key = (account_id.to_string(), model.to_string());
+            if let Some(count) = counts.models.get_mut(&key) {
+                *count = count.saturating_sub(1);
+                if *count == 0 {
+                    counts.models.remove(&key);
+                }
+            }
+        }
+        self.released.notify_waiters();
+    }
+
+    /// 账号负载快照 (email 与饱和状态由调用方补充)
+    pub fn snapshot(&self, account_id: &str) -> (usize, BTreeMap<String, usize>, Option<f64>) {
+        let counts = self.counts();
+        let in_flight = counts.accounts.get(account_id).copied().unwrap_or(0);
+        let by_model = counts
+            .models
+            .iter()
+            .filter(|((id, _), _)| id == account_id)
+            .map(|((_, model), n)| (model.clone(), *n))
+            .collect();
+        (in_flight, by_model, self.latency_ms(account_id))
+    }
+
+    /// 清理已删除账号的统计
+    pub fn remove(&self, account_id: &str) {
+        self.latency_ms.remove(account_id);
+    }
+}
+
+/// 账号在途请求租约 (Drop 时释放)
+#[derive(Debug)]
+pub struct AccountLease {
+    tracker: Arc<AccountLoadTracker>,
+    account_id: String,
+    model: String,
+    started: Instant,
+    first_byte_recorded: bool,
+}
+
+impl AccountLease {
+    fn mark_first_byte(&mut self) {
+        if !self.first_byte_recorded {
+            self.first_byte_recorded = true;
+            self.tracker
+                .record_latency(&self.account_id, self.started.elapsed());
+        }
+    }
+}
+
+impl Drop for AccountLease {
+    fn drop(&mut self) {
+        self.tracker.release(&self.account_id, &self.model);
+    }
+}
+
+pub fn new_slot() -> LeaseSlot {
+    Arc::new(Mutex::new(None))
+}
+
+/// 在请求作用域内执行 future，期间获取的账号租约存放到 slot
+pub async fn scope_request<F: Future>(slot: LeaseSlot, fut: F) -> F::Output {
+    REQUEST_LEASE.scope(slot, fut).await
+}
+
+/// 当前请求的租约槽位 (流在请求作用域外被消费，需在创建时捕获)
+pub fn current_slot() -> Option<LeaseSlot> {
+    REQUEST_LEASE.try_with(|slot| slot.clone()).ok()
+}
+
+/// 在捕获的槽位内为同一请求重新获取账号：先释放已结束的上游流占用的租约，
+/// fut 中获取的新租约存入该槽位；没有槽位时直接执行
+pub async fn reacquire<F: Future>(slot: Option<LeaseSlot>, fut: F) -> F::Output {
+    let Some(slot) = slot else {
+        return fut.await;
+    };
+    let previous = slot.lock().unwrap_or_else(|e| e.into_inner()).take();
+    drop(previous);
+    scope_request(slot, fut).await
+}
+
+/// 将租约挂到当前请求 (替换并释放之前的租约)；不在请求作用域内时租约立即释放
+pub fn attach(lease: AccountLease) {
+    let _ = REQUEST_LEASE.try_with(|slot| {
+        if let Ok(mut guard) = slot.lock() {
+            *guard = Some(lease);
+        }
+    });
+}
+
+/// 从当前请求取出租约 (对冲请求获取第二个账号前暂存主请求的租约)
+pub fn detach() -> Option<AccountLease> {
+    REQUEST_LEASE
+        .try_with(|slot| slot.lock().ok().and_then(|mut guard| guard.take()))
+        .ok()
+        .flatten()
+}
+
+/// 上游已返回响应头：记录当前租约账号的首字节延迟
+pub fn record_first_byte() {
+    let _ = REQUEST_LEASE.try_with(|slot| {
+        if let Ok(mut guard) = slot.lock() {
+            if let Some(lease) = guard.as_mut() {
+                lease.mark_first_byte();
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UNLIMITED: ConcurrencyLimits = ConcurrencyLimits {
+        per_account: 0,
+        per_account_model: 0,
+    };
+
+    #[test]
+    fn test_lease_tracks_in_flight() {
+        let tracker = Arc::new(AccountLoadTracker::new());
+        let a = tracker.try_begin("acc-1", "m", UNLIMITED).unwrap();
+        let b = tracker.try_begin("acc-1", "m", UNLIMITED).unwrap();
+        assert_eq!(tracker.in_flight("acc-1"), 2);
+        drop(a);
+        assert_eq!(tracker.in_flight("acc-1"), 1);
+        drop(b);
+        assert_eq!(tracker.in_flight("acc-1"), 0);
+    }
+
+    #[test]
+    fn test_latency_ewma() {
+        let tracker = AccountLoadTracker::new();
+        tracker.set_latency_alpha(0.5);
+        tracker.record_latency("acc-1", Duration::from_millis(100));
+        tracker.record_latency("acc-1", Duration::from_millis(300));
+        assert_eq!(tracker.latency_ms("acc-1"), Some(200.0));
+        assert_eq!(tracker.latency_ms("acc-2"), None);
+    }
+
+    #[tokio::test]
+    async fn test_attach_replaces_previous_lease() {
+        let tracker = Arc::new(AccountLoadTracker::new());
+        let slot = new_slot();
+
+        scope_request(slot.clone(), async {
+            attach(tracker.try_begin("acc-1", "m", UNLIMITED).unwrap());
+            // 重试换号：旧租约被释放
+            attach(tracker.try_begin("acc-2", "m", UNLIMITED).unwrap());
+            record_first_byte();
+        })
+        .await;
+
+        assert_eq!(tracker.in_flight("acc-1"), 0);
+        // 作用域结束后租约仍由 slot 持有，直到响应结束
+        assert_eq!(tracker.in_flight("acc-2"), 1);
+        assert!(tracker.latency_ms("acc-2").is_some());
+
+        slot.lock().unwrap().take();
+        assert_eq!(tracker.in_flight("acc-2"), 0);
+    }
+
+    #[test]
+    fn test_permits_respect_limits() {
+        let tracker = Arc::new(AccountLoadTracker::new());
+        let limits = ConcurrencyLimits {
+            per_account: 2,
+            per_account_model: 1,
+        };
+
+        let a = tracker.try_begin("acc-1", "m1", limits).unwrap();
+        // 同账号同模型已达上限
+        assert!(tracker.try_begin("acc-1", "m1", limits).is_none());
+        assert!(tracker.is_saturated("acc-1", "m1", limits));
+        assert!(!tracker.is_saturated("acc-1", "m2", limits));
+
+        let b = tracker.try_begin("acc-1", "m2", limits).unwrap();
+        // 账号总数已达上限
+        assert!(tracker.try_begin("acc-1", "m3", limits).is_none());
+        assert!(tracker.any_saturated(limits));
+
+        let (in_flight, by_model, _) = tracker.snapshot("acc-1");
+        assert_eq!(in_flight, 2);
+        assert_eq!(by_model.get("m1"), Some(&1));
+
+        drop(a);
+        assert!(tracker.try_begin("acc-1", "m1", limits).is_some());
+        drop(b);
+        assert!(!tracker.any_saturated(limits));
+        assert_eq!(tracker.in_flight("acc-1"), 0);
+    }
+
+    #[tokio::test]
+    async fn test_release_wakes_waiters() {
+        let tracker = Arc::new(AccountLoadTracker::new());
+        let limits = ConcurrencyLimits {
+            per_account: 1,
+            per_account_model: 0,
+        };
+        let lease = tracker.try_begin("acc-1", "m", limits).unwrap();
+
+        let released = tracker.released();
+        tokio::pin!(released);
+        released.as_mut().enable();
+
+        drop(lease);
+
+        tokio::time::timeout(Duration::from_secs(1), released)
+            .await
+            .expect("lease release should notify waiters");
+        assert!(tracker.try_begin("acc-1", "m", limits).is_some());
+    }
+}
@@ -0,0 +1,287 @@
+// 全局准入队列
+// 所有账号暂不可用 (并发饱和或限流) 时，请求在此排队等待，而不是立即返回错误。
+//
+// 同一模型的等待者按优先级 (用户令牌配置，越大越优先) 降序、入队顺序 FIFO 排列，
+// 只有队首请求会尝试获取账号；获取成功、超时或客户端断开后唤醒下一位。
+// 不同模型的等待者互不阻塞，避免某个模型的账号耗尽拖住其他模型的请求。
+
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::time::Instant;
+use tokio::sync::Notify;
+
+tokio::task_local! {
+    static REQUEST_PRIORITY: i32;
+}
+
+/// 队列已满时返回的错误前缀 (handler 据此返回 429 / 529)
+pub const QUEUE_FULL_ERROR: &str = "Admission queue is full";
+
+type QueueKey = (Reverse<i32>, u64);
+
+#[derive(Debug)]
+struct Waiter {
+    model: String,
+    enqueued_at: Instant,
+}
+
+/// 准入队列指标 (供 Admin API 展示)
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct AdmissionQueueMetrics {
+    /// 当前排队数
+    pub depth: usize,
+    /// 按模型拆分的排队数
+    pub depth_by_model: BTreeMap<String, usize>,
+    /// 当前最久等待者已等待的时间 (毫秒)
+    pub oldest_wait_ms: u64,
+    pub total_enqueued: u64,
+    pub total_admitted: u64,
+    pub total_timed_out: u64,
+    /// 因队列已满被拒绝的请求数
+    pub total_rejected: u64,
+    /// 成功出队请求的平均 / 最大等待时间 (毫秒)
+    pub avg_wait_ms: u64,
+    pub max_wait_ms: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct AdmissionQueue {
+    waiting: Mutex<BTreeMap<QueueKey, Waiter>>,
+    /// 队首变化通知
+    turn: Notify,
+    next_seq: AtomicU64,
+    total_enqueued: AtomicU64,
+    total_admitted: AtomicU64,
+    total_timed_out: AtomicU64,
+    total_rejected: AtomicU64,
+    total_wait_ms: AtomicU64,
+    max_wait_ms: AtomicU64,
+}
+
+impl AdmissionQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn waiting(&self) -> MutexGuard<'_, BTreeMap<QueueKey, Waiter>> {
+        self.waiting.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// 该模型是否已有请求在排队 (新请求需排到其后)
+    pub fn has_waiters(&self, model: &str) -> bool {
+        self.waiting().values().any(|w| w.model == model)
+    }
+
+    /// 入队；队列已满时返回错误
+    pub fn enqueue(
+        &self,
+        model: &str,
+        priority: i32,
+        max_len: usize,
+    ) -> Result<QueueTicket<'_>, String> {
+        let mut waiting = self.waiting();
+        if waiting.len() >= max_len {
+            drop(waiting);
+            self.total_rejected.fetch_add(1, Ordering::Relaxed);
+            return Err(format!(
+                "{} ({} requests waiting), please retry later",
+                QUEUE_FULL_ERROR, max_len
+            ));
+        }
+        let key = (
+            Reverse(priority),
+            self.next_seq.fetch_add(1, Ordering::Relaxed),
+        );
+        let enqueued_at = Instant::now();
+        waiting.insert(
+            key,
+            Waiter {
+                model: model.to_string(),
+                enqueued_at,
+            },
+        );
+        drop(waiting);
+        self.total_enqueued.fetch_add(1, Ordering::Relaxed);
+
+        Ok(QueueTicket {
+            queue: self,
+            key,
+            enqueued_at,
+            finished: false,
+        })
+    }
+
+    /// 同模型中是否没有排在该票据之前的等待者
+    fn is_head(&self, key: &QueueKey) -> bool {
+        let waiting = self.waiting();
+        let Some(model) = waiting.get(key).map(|w| w.model.as_str()) else {
+            return true;
+        };
+        !waiting.range(..*key).any(|(_, w)| w.model == model)
+    }
+
+    /// 等待轮到该票据 (成为同模型队首)；到达 deadline 仍未轮到时返回 false
+    pub async fn wait_turn(&self, ticket: &QueueTicket<'_>, deadline: tokio::time::Instant) -> bool {
+        loop {
+            let turn = self.turn.notified();
+            tokio::pin!(turn);
+            turn.as_mut().enable();
+
+            if self.is_head(&ticket.key) {
+                return true;
+            }
+            if tokio::time::timeout_at(deadline, turn).await.is_err() {
+                return self.is_head(&ticket.key);
+            }
+        }
+    }
+
+    pub fn metrics(&self) -> AdmissionQueueMetrics {
+        let waiting = self.waiting();
+        let mut depth_by_model = BTreeMap::new();
+        for waiter in waiting.values() {
+            *depth_by_model.entry(waiter.model.clone()).or_insert(0) += 1;
+        }
+        let oldest_wait_ms = waiting
+            .values()
+            .map(|w| w.enqueued_at.elapsed().as_millis() as u64)
+            .max()
+            .unwrap_or(0);
+        let depth = waiting.len();
+        drop(waiting);
+
+        let admitted = self.total_admitted.load(Ordering::Relaxed);
+        AdmissionQueueMetrics {
+            depth,
+            depth_by_model,
+            oldest_wait_ms,
+            total_enqueued: self.total_enqueued.load(Ordering::Relaxed),
+            total_admitted: admitted,
+            total_timed_out: self.total_timed_out.load(Ordering::Relaxed),
+            total_rejected: self.total_rejected.load(Ordering::Relaxed),
+            avg_wait_ms: self
+                .total_wait_ms
+                .load(Ordering::Relaxed)
+                .checked_div(admitted)
+                .unwrap_or(0),
+            max_wait_ms: self.max_wait_ms.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// 排队票据；Drop 时离开队列并唤醒后续等待者
+#[derive(Debug)]
+pub struct QueueTicket<'a> {
+    queue: &'a AdmissionQueue,
+    key: QueueKey,
+    enqueued_at: Instant,
+    finished: bool,
+}
+
+impl QueueTicket<'_> {
+    /// 已获取到账号，记录等待时间
+    pub fn admit(mut self) {
+        let waited = self.enqueued_at.elapsed().as_millis() as u64;
+        self.queue.total_admitted.fetch_add(1, Ordering::Relaxed);
+        self.queue.total_wait_ms.fetch_add(waited, Ordering::Relaxed);
+        self.queue.max_wait_ms.fetch_max(waited, Ordering::Relaxed);
+        self.finished = true;
+    }
+
+    /// 等待超时放弃
+    pub fn time_out(mut self) {
+        self.queue.total_timed_out.fetch_add(1, Ordering::Relaxed);
+        self.finished = true;
+    }
+}
+
+impl Drop for QueueTicket<'_> {
+    fn drop(&mut self) {
+        if !self.finished {
+            tracing::debug!("[Admission] Queued request abandoned before admission");
+        }
+        self.queue.waiting().remove(&self.key);
+        self.queue.turn.notify_waiters();
+    }
+}
+
+/// 在请求作用域内执行 future，期间排队使用给定优先级
+pub async fn scope_priority<F: Future>(priority: i32, fut: F) -> F::Output {
+    REQUEST_PRIORITY.scope(priority, fut).await
+}
+
+/// 当前请求的排队优先级 (不在请求作用域内时为 0)
+pub fn current_priority() -> i32 {
+    REQUEST_PRIORITY.try_with(|p| *p).unwrap_or(0)
+}
+
+pub fn is_queue_full_error(error: &str) -> bool {
+    error.starts_with(QUEUE_FULL_ERROR)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_priority_then_fifo_per_model() {
+        let queue = AdmissionQueue::new();
+        let low = queue.enqueue("m1", 0, 10).unwrap();
+        let other_model = queue.enqueue("m2", 0, 10).unwrap();
+        let high = queue.enqueue("m1", 5, 10).unwrap();
+        let low_later = queue.enqueue("m1", 0, 10).unwrap();
+
+        // 高优先级排在前面；不同模型互不阻塞
+        assert!(queue.is_head(&high.key));
+        assert!(queue.is_head(&other_model.key));
+        assert!(!queue.is_head(&low.key));
+
+        high.admit();
+        assert!(queue.is_head(&low.key));
+        assert!(!queue.is_head(&low_later.key));
+
+        drop(low);
+        assert!(queue.is_head(&low_later.key));
+
+        let metrics = queue.metrics();
+        assert_eq!(metrics.depth, 2);
+        assert_eq!(metrics.total_admitted, 1);
+        assert_eq!(metrics.depth_by_model.get("m1"), Some(&1));
+    }
+
+    #[test]
+    fn test_rejects_when_full() {
+        let queue = AdmissionQueue::new();
+        let _a = queue.enqueue("m", 0, 1).unwrap();
+        let err = queue.enqueue("m", 0, 1).unwrap_err();
+        assert!(is_queue_full_error(&err));
+        assert_eq!(queue.metrics().total_rejected, 1);
+        assert!(queue.has_waiters("m"));
+        assert!(!queue.has_waiters("other"));
+    }
+
+    #[tokio::test]
+    async fn test_wait_turn_wakes_when_head_leaves() {
+        let queue = AdmissionQueue::new();
+        let first = queue.enqueue("m", 0, 10).unwrap();
+        let second = queue.enqueue("m", 0, 10).unwrap();
+
+        let deadline = tokio::time::Instant::now() + Duration::from_millis(20);
+        assert!(!queue.wait_turn(&second, deadline).await);
+
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(1);
+        let (turn, _) = tokio::join!(queue.wait_turn(&second, deadline), async {
+            tokio::task::yield_now().await;
+            first.time_out();
+        });
+        assert!(turn);
+        assert_eq!(queue.metrics().total_timed_out, 1);
+        assert_eq!(scope_priority(3, async { current_priority() }).await, 3);
+        assert_eq!(current_priority(), 0);
+    }
+}
@@ -0,0 +1,1210 @@
+// 长音频切分
+// 单次 inlineData 请求有大小上限，会议录音等长音频需要切成多段分别转录再拼接。
+// 没有音频解码器可用，只能处理无需解码即可切分的格式：
+// - WAV (PCM): 在目标边界前的一段范围内寻找能量最低的位置 (静音) 切分
+// - MP3: 按帧边界定长切分 (无法解码，不做静音检测)
+// - Ogg (Opus/Vorbis): 按页切分，每段带上头部页，重排页序号与 granule 并重算校验和
+// - WebM: 按 Cluster 切分，每段带上 Info/Tracks，Cluster 时间码改为相对本段开头
+// - 分片 MP4 (moof + mdat): 按片段切分，每段带上 ftyp/moov，tfdt 改为相对本段开头
+// 其他格式 (ADTS AAC/FLAC/AIFF/非分片 MP4) 只能整段发送
+
+use super::AudioProcessor;
+
+/// 每段最长时长 (秒)
+pub const MAX_CHUNK_SECS: f64 = 300.0;
+/// 每段最大字节数 (base64 后仍在上游 inline 请求上限内)
+pub const MAX_CHUNK_BYTES: usize = 12 * 1024 * 1024;
+/// 在目标边界前多长范围内寻找静音 (秒)
+const SILENCE_SEARCH_SECS: f64 = 10.0;
+/// 静音检测窗口 (秒)
+const SILENCE_WINDOW_SECS: f64 = 0.05;
+
+/// 切分后的音频片段
+#[derive(Debug, Clone)]
+pub struct AudioChunk {
+    pub data: Vec<u8>,
+    pub mime_type: String,
+    /// 片段在原音频中的起始时间 (秒)
+    pub offset_secs: f64,
+    /// 片段时长 (秒)；无法解析时长的格式为 None
+    pub duration_secs: Option<f64>,
+}
+
+/// 按格式切分音频；无法切分且超过单次上限时返回错误
+pub fn split_audio(
+    data: &[u8],
+    mime_type: &str,
+    max_chunk_secs: f64,
+    max_chunk_bytes: usize,
+) -> Result<Vec<AudioChunk>, String> {
+    let chunks = match mime_type {
+        "audio/wav" => split_wav(data, max_chunk_secs, max_chunk_bytes),
+        "audio/mp3" => split_mp3(data, max_chunk_secs, max_chunk_bytes),
+        "audio/ogg" => split_ogg(data, max_chunk_secs, max_chunk_bytes),
+        "audio/webm" => split_webm(data, max_chunk_secs, max_chunk_bytes),
+        "audio/mp4" | "audio/aac" => split_mp4(data, mime_type, max_chunk_secs, max_chunk_bytes),
+        _ => None,
+    };
+    if let Some(chunks) = chunks {
+        return Ok(chunks);
+    }
+
+    if AudioProcessor::exceeds_size_limit(data.len()) {
+        return Err(format!(
+            "音频文件过大 ({:.1} MB)。{} 文件无法自动切分 (支持 WAV、MP3、Ogg、WebM 与分片 MP4)，最大支持 15 MB；请转换为 WAV 或 MP3 后上传",
+            data.len() as f64 / (1024.0 * 1024.0),
+            mime_type
+        ));
+    }
+    Ok(vec![AudioChunk {
+        data: data.to_vec(),
+        mime_type: mime_type.to_string(),
+        offset_secs: 0.0,
+        duration_secs: None,
+    }])
+}
+
+// ===== WAV =====
+
+struct WavInfo<'a> {
+    fmt: &'a [u8],
+    format_tag: u16,
+    sample_rate: u32,
+    bits_per_sample: u16,
+    block_align: usize,
+    pcm: &'a [u8],
+}
+
+fn le_u16(b: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([b[at], b[at + 1]])
+}
+
+fn le_u32(b: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([b[at], b[at + 1], b[at + 2], b[at + 3]])
+}
+
+fn parse_wav(data: &[u8]) -> Option<WavInfo<'_>> {
+    if data.len() < 12 || &data[0..4] != b"RIFF" || &data[8..12] != b"WAVE" {
+        return None;
+    }
+    let mut fmt = None;
+    let mut pcm = None;
+    let mut pos = 12usize;
+    while pos + 8 <= data.len() {
+        let id = &data[pos..pos + 4];
+        let size = le_u32(data, pos + 4) as usize;
+        let start = pos + 8;
+        // 流式写入的 WAV 可能把 data 长度写成 0xFFFFFFFF
+        let end = start.saturating_add(size).min(data.len());
+        match id {
+            b"fmt " => fmt = Some(&data[start..end]),
+            b"data" => pcm = Some(&data[start..end]),
+            _ => {}
+        }
+        pos = start.saturating_add(size).saturating_add(size & 1);
+    }
+
+    let fmt = fmt.filter(|f| f.len() >= 16)?;
+    let block_align = le_u16(fmt, 12) as usize;
+    if block_align == 0 {
+        return None;
+    }
+    Some(WavInfo {
+        fmt,
+        format_tag: le_u16(fmt, 0),
+        sample_rate: le_u32(fmt, 4),
+        bits_per_sample: le_u16(fmt, 14),
+        block_align,
+        pcm: pcm?,
+    })
+}
+
+/// 用原始 fmt 块重新封装 PCM 数据
+fn build_wav(fmt: &[u8], pcm: &[u8]) -> Vec<u8> {
+    let fmt_padded = fmt.len() + (fmt.len() & 1);
+    let mut wav = Vec::with_capacity(20 + fmt_padded + 8 + pcm.len());
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&((4 + 8 + fmt_padded + 8 + pcm.len()) as u32).to_le_bytes());
+    wav.extend_from_slice(b"WAVE");
+    wav.extend_from_slice(b"fmt ");
+    wav.extend_from_slice(&(fmt.len() as u32).to_le_bytes());
+    wav.extend_from_slice(fmt);
+    if fmt.len() & 1 == 1 {
+        wav.push(0);
+    }
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&(pcm.len() as u32).to_le_bytes());
+    wav.extend_from_slice(pcm);
+    wav
+}
+
+/// 在 [from, to) 帧范围内寻找能量最低的窗口，返回窗口中点 (仅支持 16-bit PCM)
+fn quietest_frame(info: &WavInfo, from: usize, to: usize) -> Option<usize> {
+    if info.format_tag != 1 || info.bits_per_sample != 16 {
+        return None;
+    }
+    let window = ((info.sample_rate as f64 * SILENCE_WINDOW_SECS) as usize).max(1);
+    let mut best: Option<(u64, usize)> = None;
+    let mut frame = from;
+    while frame + window <= to {
+        let bytes = &info.pcm[frame * info.block_align..(frame + window) * info.block_align];
+        let energy: u64 = bytes
+            .chunks_exact(2)
+            .map(|s| {
+                let v = i16::from_le_bytes([s[0], s[1]]) as i64;
+                (v * v) as u64
+            })
+            .sum();
+        if best.map_or(true, |(e, _)| energy < e) {
+            best = Some((energy, frame + window / 2));
+        }
+        frame += window;
+    }
+    best.map(|(_, f)| f)
+}
+
+fn split_wav(data: &[u8], max_secs: f64, max_bytes: usize) -> Option<Vec<AudioChunk>> {
+    let info = parse_wav(data)?;
+    if info.sample_rate == 0 {
+        return None;
+    }
+    let total_frames = info.pcm.len() / info.block_align;
+    let rate = info.sample_rate as f64;
+    let header_len = 28 + info.fmt.len();
+    let max_frames = ((max_secs * rate) as usize)
+        .min(max_bytes.saturating_sub(header_len) / info.block_align)
+        .max(1);
+
+    if total_frames <= max_frames && data.len() <= max_bytes {
+        return Some(vec![AudioChunk {
+            data: data.to_vec(),
+            mime_type: "audio/wav".to_string(),
+            offset_secs: 0.0,
+            duration_secs: Some(total_frames as f64 / rate),
+        }]);
+    }
+
+    let search_frames = (SILENCE_SEARCH_SECS * rate) as usize;
+    let mut chunks = Vec::new();
+    let mut start = 0usize;
+    while start < total_frames {
+        let target = start + max_frames;
+        let end = if target >= total_frames {
+            total_frames
+        } else {
+            let from = target.saturating_sub(search_frames).max(start + 1);
+            quietest_frame(&info, from, target)
+                .filter(|f| *f > start)
+                .unwrap_or(target)
+        };
+        let pcm = &info.pcm[start * info.block_align..end * info.block_align];
+        chunks.push(AudioChunk {
+            data: build_wav(info.fmt, pcm),
+            mime_type: "audio/wav".to_string(),
+            offset_secs: start as f64 / rate,
+            duration_secs: Some((end - start) as f64 / rate),
+        });
+        start = end;
+    }
+    Some(chunks)
+}
+
+// ===== MP3 =====
+
+const MP3_BITRATES_V1: [u32; 15] = [
+    0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320,
+];
+const MP3_BITRATES_V2: [u32; 15] = [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160];
+
+/// 解析 MPEG Layer III 帧头，返回 (帧长度, 帧时长秒)
+fn mp3_frame(h: &[u8]) -> Option<(usize, f64)> {
+    if h.len() < 4 || h[0] != 0xFF || h[1] & 0xE0 != 0xE0 {
+        return None;
+    }
+    let version = (h[1] >> 3) & 0x03; // 0 = 2.5, 2 = 2, 3 = 1
+    let layer = (h[1] >> 1) & 0x03; // 1 = Layer III
+    let bitrate_idx = (h[2] >> 4) as usize;
+    let rate_idx = ((h[2] >> 2) & 0x03) as usize;
+    let padding = ((h[2] >> 1) & 0x01) as usize;
+    if version == 1 || layer != 1 || bitrate_idx == 0 || bitrate_idx == 15 || rate_idx == 3 {
+        return None;
+    }
+
+    let mpeg1 = version == 3;
+    let table = if mpeg1 {
+        &MP3_BITRATES_V1
+    } else {
+        &MP3_BITRATES_V2
+    };
+    let bitrate = table[bitrate_idx] * 1000;
+    // MPEG2 采样率减半，MPEG2.5 再减半
+    let shift = match version {
+        3 => 0,
+        2 => 1,
+        _ => 2,
+    };
+    let sample_rate = [44_100u32, 48_000, 32_000][rate_idx] >> shift;
+    let (coef, samples) = if mpeg1 { (144, 1152) } else { (72, 576) };
+    let len = (coef * bitrate / sample_rate) as usize + padding;
+    Some((len, samples as f64 / sample_rate as f64))
+}
+
+/// ID3v2 标签长度
+fn id3v2_len(data: &[u8]) -> usize {
+    if data.len() < 10 || &data[0..3] != b"ID3" {
+        return 0;
+    }
+    let size = data[6..10]
+        .iter()
+        .fold(0usize, |acc, b| (acc << 7) | (*b as usize & 0x7F));
+    let footer = if data[5] & 0x10 != 0 { 10 } else { 0 };
+    10 + size + footer
+}
+
+fn split_mp3(data: &[u8], max_secs: f64, max_bytes: usize) -> Option<Vec<AudioChunk>> {
+    // (偏移, 长度, 时长)
+    let mut frames: Vec<(usize, usize, f64)> = Vec::new();
+    let mut pos = id3v2_len(data);
+    while pos + 4 <= data.len() {
+        match mp3_frame(&data[pos..pos + 4]) {
+            Some((len, secs)) if len > 4 && pos + len <= data.len() => {
+                frames.push((pos, len, secs));
+                pos += len;
+            }
+            _ => pos += 1, // 重新同步
+        }
+    }
+    if frames.is_empty() {
+        return None;
+    }
+
+    let total_secs: f64 = frames.iter().map(|f| f.2).sum();
+    if total_secs <= max_secs && data.len() <= max_bytes {
+        return Some(vec![AudioChunk {
+            data: data.to_vec(),
+            mime_type: "audio/mp3".to_string(),
+            offset_secs: 0.0,
+            duration_secs: Some(total_secs),
+        }]);
+    }
+
+    let mut chunks = Vec::new();
+    let mut offset_secs = 0.0;
+    let mut i = 0;
+    while i < frames.len() {
+        let first = i;
+        let mut secs = 0.0;
+        let mut bytes = 0usize;
+        while i < frames.len() {
+            let (_, len, frame_secs) = frames[i];
+            if i > first && (secs + frame_secs > max_secs || bytes + len > max_bytes) {
+                break;
+            }
+            secs += frame_secs;
+            bytes += len;
+            i += 1;
+        }
+        let start = frames[first].0;
+        let (last_pos, last_len, _) = frames[i - 1];
+        chunks.push(AudioChunk {
+            data: data[start..last_pos + last_len].to_vec(),
+            mime_type: "audio/mp3".to_string(),
+            offset_secs,
+            duration_secs: Some(secs),
+        });
+        offset_secs += secs;
+    }
+    Some(chunks)
+}
+
+// ===== 容器格式通用 =====
+
+/// 可独立拼接的最小单元 (Ogg 页 / WebM Cluster / MP4 片段)
+struct Unit {
+    /// 起止时间 (秒)
+    start: f64,
+    end: f64,
+    /// 写入分段后的字节数
+    len: usize,
+    /// 是否可以从该单元开始新的分段
+    cut: bool,
+}
+
+/// 按时长与字节上限把单元划分为若干段；head_len 为每段都要带上的头部长度
+fn group_units(
+    units: &[Unit],
+    head_len: usize,
+    max_secs: f64,
+    max_bytes: usize,
+) -> Vec<std::ops::Range<usize>> {
+    let mut ranges = Vec::new();
+    let mut first = 0;
+    while first < units.len() {
+        let mut bytes = head_len;
+        let mut i = first;
+        while i < units.len() {
+            let unit = &units[i];
+            let over = unit.end - units[first].start > max_secs || bytes + unit.len > max_bytes;
+            if i > first && unit.cut && over {
+                break;
+            }
+            bytes += unit.len;
+            i += 1;
+        }
+        ranges.push(first..i);
+        first = i;
+    }
+    ranges
+}
+
+/// 未超过上限时整段返回
+fn fits_whole(data: &[u8], units: &[Unit], max_secs: f64, max_bytes: usize) -> bool {
+    let span = match (units.first(), units.last()) {
+        (Some(first), Some(last)) => last.end - first.start,
+        _ => 0.0,
+    };
+    data.len() <= max_bytes && span <= max_secs
+}
+
+fn whole_chunk(data: &[u8], mime_type: &str, duration_secs: Option<f64>) -> Vec<AudioChunk> {
+    vec![AudioChunk {
+        data: data.to_vec(),
+        mime_type: mime_type.to_string(),
+        offset_secs: 0.0,
+        duration_secs,
+    }]
+}
+
+fn be_u32(b: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(b.get(at..at + 4)?.try_into().ok()?))
+}
+
+fn be_u64(b: &[u8], at: usize) -> Option<u64> {
+    Some(u64::from_be_bytes(b.get(at..at + 8)?.try_into().ok()?))
+}
+
+// ===== Ogg =====
+
+const OGG_HEADER_LEN: usize = 27;
+
+/// Ogg 页校验和 (多项式 0x04C11DB7，不反转)
+const OGG_CRC_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut r = (i as u32) << 24;
+        let mut j = 0;
+        while j < 8 {
+            r = if r & 0x8000_0000 != 0 {
+                (r << 1) ^ 0x04C1_1DB7
+            } else {
+                r << 1
+            };
+            j += 1;
+        }
+        table[i] = r;
+        i += 1;
+    }
+    table
+};
+
+fn ogg_crc(page: &[u8]) -> u32 {
+    page.iter().fold(0u32, |crc, &b| {
+        (crc << 8) ^ OGG_CRC_TABLE[((crc >> 24) as u8 ^ b) as usize]
+    })
+}
+
+struct OggPage {
+    start: usize,
+    len: usize,
+    body_start: usize,
+    /// 页内最后一个完整包结束时的采样位置；-1 表示没有包在本页结束
+    granule: i64,
+    /// 本页以上一页未完成的包开头
+    continued: bool,
+}
+
+/// 解析单一逻辑流的 Ogg 页 (多路复用的流不处理)
+fn ogg_pages(data: &[u8]) -> Option<Vec<OggPage>> {
+    let mut pages: Vec<OggPage> = Vec::new();
+    let mut serial = None;
+    let mut pos = 0usize;
+    while pos + OGG_HEADER_LEN <= data.len() && &data[pos..pos + 4] == b"OggS" {
+        let segments = data[pos + 26] as usize;
+        let body_start = pos + OGG_HEADER_LEN + segments;
+        let Some(lacing) = data.get(pos + OGG_HEADER_LEN..body_start) else {
+            break;
+        };
+        let end = body_start + lacing.iter().map(|&b| b as usize).sum::<usize>();
+        if end > data.len() {
+            break;
+        }
+        let page_serial = le_u32(data, pos + 14);
+        if *serial.get_or_insert(page_serial) != page_serial {
+            return None;
+        }
+        pages.push(OggPage {
+            start: pos,
+            len: end - pos,
+            body_start,
+            granule: i64::from_le_bytes(data[pos + 6..pos + 14].try_into().ok()?),
+            continued: data[pos + 5] & 0x01 != 0,
+        });
+        pos = end;
+    }
+    (!pages.is_empty()).then_some(pages)
+}
+
+/// 由首个头部包得到 granule 的时间单位
+fn ogg_sample_rate(first_packet: &[u8]) -> Option<u32> {
+    if first_packet.starts_with(b"OpusHead") {
+        Some(48_000)
+    } else if first_packet.starts_with(b"\x01vorbis") && first_packet.len() >= 16 {
+        Some(le_u32(first_packet, 12)).filter(|r| *r > 0)
+    } else {
+        None
+    }
+}
+
+/// 追加一页，改写序号、granule 与结束标记后重算校验和
+fn push_ogg_page(out: &mut Vec<u8>, page: &[u8], sequence: u32, granule: i64, last: bool) {
+    let at = out.len();
+    out.extend_from_slice(page);
+    if last {
+        out[at + 5] |= 0x04;
+    } else {
+        out[at + 5] &= !0x04;
+    }
+    out[at + 6..at + 14].copy_from_slice(&granule.to_le_bytes());
+    out[at + 18..at + 22].copy_from_slice(&sequence.to_le_bytes());
+    out[at + 22..at + 26].fill(0);
+    let crc = ogg_crc(&out[at..]);
+    out[at + 22..at + 26].copy_from_slice(&crc.to_le_bytes());
+}
+
+fn split_ogg(data: &[u8], max_secs: f64, max_bytes: usize) -> Option<Vec<AudioChunk>> {
+    let pages = ogg_pages(data)?;
+    let rate = ogg_sample_rate(&data[pages[0].body_start..pages[0].start + pages[0].len])? as f64;
+    // 头部页 (Opus: OpusHead/OpusTags，Vorbis: 三个头部包) 的 granule 均为 0
+    let header_count = pages.iter().take_while(|p| p.granule == 0).count();
+    if header_count == 0 || header_count == pages.len() {
+        return None;
+    }
+    let (headers, audio) = pages.split_at(header_count);
+
+    // 每页的起止 granule
+    let mut bounds = Vec::with_capacity(audio.len());
+    let mut prev = 0i64;
+    for page in audio {
+        let end = if page.granule >= 0 { page.granule.max(prev) } else { prev };
+        bounds.push((prev, end));
+        prev = end;
+    }
+    let units: Vec<Unit> = audio
+        .iter()
+        .zip(&bounds)
+        .map(|(page, (start, end))| Unit {
+            start: *start as f64 / rate,
+            end: *end as f64 / rate,
+            len: page.len,
+            cut: !page.continued,
+        })
+        .collect();
+    if fits_whole(data, &units, max_secs, max_bytes) {
+        return Some(whole_chunk(data, "audio/ogg", Some(prev as f64 / rate)));
+    }
+
+    let head_len: usize = headers.iter().map(|p| p.len).sum();
+    let chunks = group_units(&units, head_len, max_secs, max_bytes)
+        .into_iter()
+        .map(|range| {
+            let base = bounds[range.start].0;
+            let end = bounds[range.end - 1].1;
+            let mut out = Vec::with_capacity(head_len + units[range.clone()].iter().map(|u| u.len).sum::<usize>());
+            let mut sequence = 0u32;
+            for page in headers {
+                push_ogg_page(&mut out, &data[page.start..page.start + page.len], sequence, 0, false);
+                sequence += 1;
+            }
+            for i in range.clone() {
+                let page = &audio[i];
+                let granule = if page.granule >= 0 { page.granule - base } else { -1 };
+                push_ogg_page(
+                    &mut out,
+                    &data[page.start..page.start + page.len],
+                    sequence,
+                    granule,
+                    i + 1 == range.end,
+                );
+                sequence += 1;
+            }
+            AudioChunk {
+                data: out,
+                mime_type: "audio/ogg".to_string(),
+                offset_secs: base as f64 / rate,
+                duration_secs: Some((end - base) as f64 / rate),
+            }
+        })
+        .collect();
+    Some(chunks)
+}
+
+// ===== WebM (Matroska) =====
+
+const EBML_HEADER_ID: u32 = 0x1A45_DFA3;
+const SEGMENT_ID: u32 = 0x1853_8067;
+const INFO_ID: u32 = 0x1549_A966;
+const TRACKS_ID: u32 = 0x1654_AE6B;
+const CLUSTER_ID: u32 = 0x1F43_B675;
+const TIMECODE_SCALE_ID: u32 = 0x2A_D7B1;
+const DURATION_ID: u32 = 0x4489;
+const CLUSTER_TIMECODE_ID: u32 = 0xE7;
+/// Segment 的直接子元素；未知长度的 Cluster 在遇到其中任一元素时结束
+const SEGMENT_CHILD_IDS: &[u32] = &[
+    0x114D_9B74, // SeekHead
+    INFO_ID,
+    TRACKS_ID,
+    0x1C53_BB6B, // Cues
+    CLUSTER_ID,
+    0x1254_C367, // Tags
+    0x1043_A770, // Chapters
+    0x1941_A469, // Attachments
+];
+/// 8 字节的“未知长度”
+const EBML_UNKNOWN_SIZE: [u8; 8] = [0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF];
+
+struct EbmlElement {
+    id: u32,
+    start: usize,
+    data_start: usize,
+    /// None 表示未知长度 (流式录制的 Segment / Cluster)
+    size: Option<usize>,
+}
+
+/// 读取变长整数，返回 (值, 字节数, 是否为全 1 的“未知”值)；keep_marker 用于读取元素 ID
+fn ebml_vint(data: &[u8], pos: usize, keep_marker: bool) -> Option<(u64, usize, bool)> {
+    let first = *data.get(pos)?;
+    if first == 0 {
+        return None;
+    }
+    let len = first.leading_zeros() as usize + 1;
+    let rest = data.get(pos + 1..pos + len)?;
+    let head = if keep_marker {
+        first as u64
+    } else {
+        (first & (0xFFu16 >> len) as u8) as u64
+    };
+    let value = rest.iter().fold(head, |v, &b| (v << 8) | b as u64);
+    let unknown = !keep_marker && value == (1u64 << (7 * len)) - 1;
+    Some((value, len, unknown))
+}
+
+fn ebml_element(data: &[u8], pos: usize) -> Option<EbmlElement> {
+    let (id, id_len, _) = ebml_vint(data, pos, true)?;
+    if id_len > 4 {
+        return None;
+    }
+    let (size, size_len, unknown) = ebml_vint(data, pos + id_len, false)?;
+    Some(EbmlElement {
+        id: id as u32,
+        start: pos,
+        data_start: pos + id_len + size_len,
+        size: (!unknown).then_some(size as usize),
+    })
+}
+
+/// 元素结束位置；未知长度时扫描子元素直到下一个 Segment 级元素
+fn ebml_end(data: &[u8], element: &EbmlElement, limit: usize) -> usize {
+    if let Some(size) = element.size {
+        return element.data_start.saturating_add(size).min(limit);
+    }
+    let mut pos = element.data_start;
+    while pos < limit {
+        let Some(child) = ebml_element(data, pos) else {
+            break;
+        };
+        let Some(size) = child.size.filter(|_| !SEGMENT_CHILD_IDS.contains(&child.id)) else {
+            break;
+        };
+        pos = child.data_start.saturating_add(size);
+    }
+    pos.min(limit)
+}
+
+/// 已知长度的子元素及其结束位置
+fn ebml_children(data: &[u8], start: usize, end: usize) -> Vec<(EbmlElement, usize)> {
+    let mut children = Vec::new();
+    let mut pos = start;
+    while pos < end {
+        let Some(child) = ebml_element(data, pos) else {
+            break;
+        };
+        let Some(size) = child.size else {
+            break;
+        };
+        let child_end = child.data_start.saturating_add(size);
+        if child_end > end {
+            break;
+        }
+        pos = child_end;
+        children.push((child, child_end));
+    }
+    children
+}
+
+fn ebml_uint(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0u64, |v, &b| (v << 8) | b as u64)
+}
+
+fn ebml_float(bytes: &[u8]) -> Option<f64> {
+    match bytes.len() {
+        4 => Some(f32::from_be_bytes(bytes.try_into().ok()?) as f64),
+        8 => Some(f64::from_be_bytes(bytes.try_into().ok()?)),
+        _ => None,
+    }
+}
+
+/// 以 8 字节长度写出一个元素
+fn push_ebml(out: &mut Vec<u8>, id: u32, body: &[u8]) {
+    out.extend_from_slice(&id.to_be_bytes()[(id.leading_zeros() / 8) as usize..]);
+    out.push(0x01);
+    out.extend_from_slice(&(body.len() as u64).to_be_bytes()[1..]);
+    out.extend_from_slice(body);
+}
+
+struct WebmCluster {
+    timecode: u64,
+    /// Timecode 以外的子元素
+    children: Vec<(usize, usize)>,
+}
+
+impl WebmCluster {
+    /// 重新封装后的长度：ID + 未知长度 + 8 字节 Timecode + 子元素
+    fn encoded_len(&self) -> usize {
+        4 + 8 + 10 + self.children.iter().map(|(a, b)| b - a).sum::<usize>()
+    }
+}
+
+fn split_webm(data: &[u8], max_secs: f64, max_bytes: usize) -> Option<Vec<AudioChunk>> {
+    let header = ebml_element(data, 0).filter(|e| e.id == EBML_HEADER_ID)?;
+    let header_end = header.data_start.checked_add(header.size?)?;
+    let segment = ebml_element(data, header_end).filter(|e| e.id == SEGMENT_ID)?;
+    let segment_end = match segment.size {
+        Some(size) => segment.data_start.saturating_add(size).min(data.len()),
+        None => data.len(),
+    };
+
+    let mut info: Option<Vec<u8>> = None;
+    let mut tracks: Option<&[u8]> = None;
+    let mut scale = 1_000_000u64;
+    let mut duration: Option<f64> = None;
+    let mut clusters: Vec<WebmCluster> = Vec::new();
+    let mut pos = segment.data_start;
+    while pos < segment_end {
+        let Some(element) = ebml_element(data, pos) else {
+            break;
+        };
+        let end = ebml_end(data, &element, segment_end);
+        match element.id {
+            INFO_ID => {
+                // 分段时长不同，去掉 Duration
+                let mut body = Vec::new();
+                for (child, child_end) in ebml_children(data, element.data_start, end) {
+                    let value = &data[child.data_start..child_end];
+                    match child.id {
+                        TIMECODE_SCALE_ID => scale = ebml_uint(value).max(1),
+                        DURATION_ID => {
+                            duration = ebml_float(value);
+                            continue;
+                        }
+                        _ => {}
+                    }
+                    body.extend_from_slice(&data[child.start..child_end]);
+                }
+                info = Some(body);
+            }
+            TRACKS_ID => tracks = Some(&data[element.start..end]),
+            CLUSTER_ID => {
+                let mut timecode = None;
+                let mut children = Vec::new();
+                for (child, child_end) in ebml_children(data, element.data_start, end) {
+                    if child.id == CLUSTER_TIMECODE_ID {
+                        timecode = Some(ebml_uint(&data[child.data_start..child_end]));
+                    } else {
+                        children.push((child.start, child_end));
+                    }
+                }
+                clusters.push(WebmCluster {
+                    timecode: timecode?,
+                    children,
+                });
+            }
+            _ => {}
+        }
+        if end <= pos {
+            break;
+        }
+        pos = end;
+    }
+    let (info, tracks) = (info?, tracks?);
+    if clusters.is_empty() {
+        return None;
+    }
+
+    let secs = |units: f64| units * scale as f64 / 1e9;
+    let origin = secs(clusters[0].timecode as f64);
+    let total = duration.map(|d| secs(d) - origin);
+    let units: Vec<Unit> = clusters
+        .iter()
+        .enumerate()
+        .map(|(i, cluster)| {
+            let start = secs(cluster.timecode as f64) - origin;
+            let end = match clusters.get(i + 1) {
+                Some(next) => secs(next.timecode as f64) - origin,
+                None => total.unwrap_or(start).max(start),
+            };
+            Unit {
+                start,
+                end,
+                len: cluster.encoded_len(),
+                cut: true,
+            }
+        })
+        .collect();
+    if fits_whole(data, &units, max_secs, max_bytes) {
+        return Some(whole_chunk(data, "audio/webm", total));
+    }
+
+    let head_len = header_end + 12 + 12 + info.len() + tracks.len();
+    let chunks = group_units(&units, head_len, max_secs, max_bytes)
+        .into_iter()
+        .map(|range| {
+            let base = clusters[range.start].timecode;
+            let mut out = Vec::with_capacity(head_len + units[range.clone()].iter().map(|u| u.len).sum::<usize>());
+            out.extend_from_slice(&data[..header_end]);
+            out.extend_from_slice(&SEGMENT_ID.to_be_bytes());
+            out.extend_from_slice(&EBML_UNKNOWN_SIZE);
+            push_ebml(&mut out, INFO_ID, &info);
+            out.extend_from_slice(tracks);
+            for cluster in &clusters[range.clone()] {
+                out.extend_from_slice(&CLUSTER_ID.to_be_bytes());
+                out.extend_from_slice(&EBML_UNKNOWN_SIZE);
+                out.extend_from_slice(&[CLUSTER_TIMECODE_ID as u8, 0x88]);
+                out.extend_from_slice(&cluster.timecode.saturating_sub(base).to_be_bytes());
+                for (a, b) in &cluster.children {
+                    out.extend_from_slice(&data[*a..*b]);
+                }
+            }
+            let offset_secs = units[range.start].start;
+            let duration_secs = match units.get(range.end) {
+                Some(next) => Some(next.start - offset_secs),
+                None => total.map(|t| t - offset_secs).filter(|d| *d > 0.0),
+            };
+            AudioChunk {
+                data: out,
+                mime_type: "audio/webm".to_string(),
+                offset_secs,
+                duration_secs,
+            }
+        })
+        .collect();
+    Some(chunks)
+}
+
+// ===== 分片 MP4 =====
+
+struct Mp4Box {
+    kind: [u8; 4],
+    start: usize,
+    body: usize,
+    end: usize,
+}
+
+fn mp4_boxes(data: &[u8], start: usize, end: usize) -> Option<Vec<Mp4Box>> {
+    let mut boxes = Vec::new();
+    let mut pos = start;
+    while pos + 8 <= end {
+        let size = be_u32(data, pos)? as usize;
+        let kind: [u8; 4] = data[pos + 4..pos + 8].try_into().ok()?;
+        let (body, box_end) = match size {
+            0 => (pos + 8, end),
+            1 => (pos + 16, pos.checked_add(be_u64(data, pos + 8)? as usize)?),
+            size => (pos + 8, pos.checked_add(size)?),
+        };
+        if box_end < body || box_end > end {
+            return None;
+        }
+        boxes.push(Mp4Box {
+            kind,
+            start: pos,
+            body,
+            end: box_end,
+        });
+        pos = box_end;
+    }
+    Some(boxes)
+}
+
+fn child_box<'a>(boxes: &'a [Mp4Box], kind: &[u8; 4]) -> Option<&'a Mp4Box> {
+    boxes.iter().find(|b| &b.kind == kind)
+}
+
+/// moov 中各轨道的 track_ID → 时间刻度
+fn mp4_timescales(data: &[u8], moov: &Mp4Box) -> Option<std::collections::HashMap<u32, u32>> {
+    let mut timescales = std::collections::HashMap::new();
+    for trak in mp4_boxes(data, moov.body, moov.end)?
+        .iter()
+        .filter(|b| &b.kind == b"trak")
+    {
+        let children = mp4_boxes(data, trak.body, trak.end)?;
+        let tkhd = child_box(&children, b"tkhd")?;
+        let v1 = *data.get(tkhd.body)? == 1;
+        let track_id = be_u32(data, tkhd.body + if v1 { 20 } else { 12 })?;
+        let mdia = child_box(&children, b"mdia")?;
+        let mdia_children = mp4_boxes(data, mdia.body, mdia.end)?;
+        let mdhd = child_box(&mdia_children, b"mdhd")?;
+        let v1 = *data.get(mdhd.body)? == 1;
+        let timescale = be_u32(data, mdhd.body + if v1 { 20 } else { 12 })?;
+        if timescale > 0 {
+            timescales.insert(track_id, timescale);
+        }
+    }
+    Some(timescales)
+}
+
+struct Tfdt {
+    /// 解码时间在文件中的位置
+    pos: usize,
+    v1: bool,
+    track_id: u32,
+    value: u64,
+}
+
+/// moof + mdat 片段
+struct Mp4Fragment {
+    start: usize,
+    end: usize,
+    tfdts: Vec<Tfdt>,
+}
+
+fn mp4_fragment_tfdts(data: &[u8], moof: &Mp4Box) -> Option<Vec<Tfdt>> {
+    let mut tfdts = Vec::new();
+    for traf in mp4_boxes(data, moof.body, moof.end)?
+        .iter()
+        .filter(|b| &b.kind == b"traf")
+    {
+        let children = mp4_boxes(data, traf.body, traf.end)?;
+        let tfhd = child_box(&children, b"tfhd")?;
+        // base-data-offset 为文件绝对偏移，切分后失效
+        if be_u32(data, tfhd.body)? & 0x01 != 0 {
+            return None;
+        }
+        let track_id = be_u32(data, tfhd.body + 4)?;
+        let tfdt = child_box(&children, b"tfdt")?;
+        let v1 = *data.get(tfdt.body)? == 1;
+        let pos = tfdt.body + 4;
+        let value = if v1 {
+            be_u64(data, pos)?
+        } else {
+            be_u32(data, pos)? as u64
+        };
+        tfdts.push(Tfdt {
+            pos,
+            v1,
+            track_id,
+            value,
+        });
+    }
+    (!tfdts.is_empty()).then_some(tfdts)
+}
+
+fn split_mp4(data: &[u8], mime_type: &str, max_secs: f64, max_bytes: usize) -> Option<Vec<AudioChunk>> {
+    let boxes = mp4_boxes(data, 0, data.len())?;
+    let ftyp = child_box(&boxes, b"ftyp")?;
+    let moov = child_box(&boxes, b"moov")?;
+    let timescales = mp4_timescales(data, moov)?;
+
+    let mut fragments: Vec<Mp4Fragment> = Vec::new();
+    for b in &boxes {
+        match &b.kind {
+            b"moof" => fragments.push(Mp4Fragment {
+                start: b.start,
+                end: b.end,
+                tfdts: mp4_fragment_tfdts(data, b)?,
+            }),
+            b"mdat" => {
+                if let Some(fragment) = fragments.last_mut().filter(|f| f.end == b.start) {
+                    fragment.end = b.end;
+                }
+            }
+            _ => {}
+        }
+    }
+    // 非分片 MP4 需要重写整张采样表，不做处理
+    if fragments.is_empty() {
+        return None;
+    }
+
+    let mut starts = Vec::with_capacity(fragments.len());
+    for fragment in &fragments {
+        let tfdt = &fragment.tfdts[0];
+        let timescale = *timescales.get(&tfdt.track_id)?;
+        starts.push(tfdt.value as f64 / timescale as f64);
+    }
+    let origin = starts[0];
+    let units: Vec<Unit> = fragments
+        .iter()
+        .enumerate()
+        .map(|(i, fragment)| {
+            let start = starts[i] - origin;
+            Unit {
+                start,
+                end: starts.get(i + 1).map_or(start, |next| next - origin),
+                len: fragment.end - fragment.start,
+                cut: true,
+            }
+        })
+        .collect();
+    if fits_whole(data, &units, max_secs, max_bytes) {
+        return Some(whole_chunk(data, mime_type, None));
+    }
+
+    let head = [&data[ftyp.start..ftyp.end], &data[moov.start..moov.end]].concat();
+    let chunks = group_units(&units, head.len(), max_secs, max_bytes)
+        .into_iter()
+        .map(|range| {
+            let mut out = head.clone();
+            let mut bases = std::collections::HashMap::new();
+            for fragment in &fragments[range.clone()] {
+                let at = out.len();
+                out.extend_from_slice(&data[fragment.start..fragment.end]);
+                for tfdt in &fragment.tfdts {
+                    let base = *bases.entry(tfdt.track_id).or_insert(tfdt.value);
+                    let value = tfdt.value.saturating_sub(base);
+                    let pos = at + tfdt.pos - fragment.start;
+                    if tfdt.v1 {
+                        out[pos..pos + 8].copy_from_slice(&value.to_be_bytes());
+                    } else {
+                        out[pos..pos + 4].copy_from_slice(&(value as u32).to_be_bytes());
+                    }
+                }
+            }
+            let offset_secs = units[range.start].start;
+            AudioChunk {
+                data: out,
+                mime_type: mime_type.to_string(),
+                offset_secs,
+                duration_secs: units.get(range.end).map(|next| next.start - offset_secs),
+            }
+        })
+        .collect();
+    Some(chunks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wav_with(samples: &[i16], sample_rate: u32) -> Vec<u8> {
+        let pcm: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+        AudioProcessor::pcm_to_wav(&pcm, sample_rate, 1)
+    }
+
+    #[test]
+    fn test_split_wav_at_silence() {
+        // 1kHz 采样率 30 秒：第 17 秒附近静音，其余为满幅
+        let rate = 1000;
+        let samples: Vec<i16> = (0..30 * rate)
+            .map(|i| {
+                if (17_000..17_200).contains(&i) {
+                    0
+                } else {
+                    12_000
+                }
+            })
+            .collect();
+        let wav = wav_with(&samples, rate as u32);
+
+        let chunks = split_audio(&wav, "audio/wav", 20.0, MAX_CHUNK_BYTES).unwrap();
+        assert_eq!(chunks.len(), 2);
+        let cut = chunks[1].offset_secs;
+        assert!(cut > 17.0 && cut < 17.2, "cut at {}", cut);
+        let total: f64 = chunks.iter().map(|c| c.duration_secs.unwrap()).sum();
+        assert!((total - 30.0).abs() < 1e-9);
+        // 每段都是合法 WAV
+        for chunk in &chunks {
+            let info = parse_wav(&chunk.data).unwrap();
+            assert_eq!(info.sample_rate, rate as u32);
+        }
+    }
+
+    #[test]
+    fn test_split_mp3_on_frame_boundaries() {
+        // MPEG1 Layer III, 128kbps, 44.1kHz, 无 padding: 帧长 417 字节
+        let mut frame = vec![0xFF, 0xFB, 0x90, 0x00];
+        frame.resize(417, 0);
+        let mut mp3 = b"ID3\x03\x00\x00\x00\x00\x00\x05hello".to_vec();
+        for _ in 0..100 {
+            mp3.extend_from_slice(&frame);
+        }
+
+        let frame_secs = 1152.0 / 44_100.0;
+        let chunks = split_audio(&mp3, "audio/mp3", frame_secs * 40.5, MAX_CHUNK_BYTES).unwrap();
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[0].data.len(), 40 * 417);
+        assert_eq!(chunks[2].data.len(), 20 * 417);
+        assert!((chunks[1].offset_secs - frame_secs * 40.0).abs() < 1e-9);
+        assert!(chunks.iter().all(|c| c.data[0] == 0xFF));
+    }
+
+    fn ogg_page(header_type: u8, granule: i64, sequence: u32, body: &[u8]) -> Vec<u8> {
+        let mut lacing = vec![255u8; body.len() / 255];
+        lacing.push((body.len() % 255) as u8);
+        let mut page = b"OggS\0".to_vec();
+        page.push(header_type);
+        page.extend_from_slice(&granule.to_le_bytes());
+        page.extend_from_slice(&7u32.to_le_bytes());
+        page.extend_from_slice(&sequence.to_le_bytes());
+        page.extend_from_slice(&[0; 4]);
+        page.push(lacing.len() as u8);
+        page.extend_from_slice(&lacing);
+        page.extend_from_slice(body);
+        let crc = ogg_crc(&page);
+        page[22..26].copy_from_slice(&crc.to_le_bytes());
+        page
+    }
+
+    #[test]
+    fn test_split_ogg_opus_on_pages() {
+        // OpusHead + OpusTags 两个头部页，之后 10 页音频，每页 1 秒 (48kHz)
+        let mut ogg = ogg_page(0x02, 0, 0, b"OpusHead\x01\x01\x38\x01\x80\xbb\0\0\0\0\0");
+        ogg.extend(ogg_page(0, 0, 1, b"OpusTags\0\0\0\0\0\0\0\0"));
+        for i in 0..10 {
+            let flags = if i == 9 { 0x04 } else { 0 };
+            ogg.extend(ogg_page(flags, 48_000 * (i + 1), i as u32 + 2, &[i as u8; 300]));
+        }
+
+        let chunks = split_audio(&ogg, "audio/ogg", 4.0, MAX_CHUNK_BYTES).unwrap();
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[1].offset_secs, 4.0);
+        assert_eq!(chunks[2].duration_secs, Some(2.0));
+        for chunk in &chunks {
+            let pages = ogg_pages(&chunk.data).unwrap();
+            assert!(chunk.data[pages[0].body_start..].starts_with(b"OpusHead"));
+            // 序号连续、granule 从本段开头计、校验和有效、末页带结束标记
+            for (i, page) in pages.iter().enumerate() {
+                let mut raw = chunk.data[page.start..page.start + page.len].to_vec();
+                assert_eq!(le_u32(&raw, 18), i as u32);
+                let crc = le_u32(&raw, 22);
+                raw[22..26].fill(0);
+                assert_eq!(ogg_crc(&raw), crc);
+            }
+            assert_eq!(pages[2].granule, 48_000);
+            assert_eq!(chunk.data[pages.last().unwrap().start + 5] & 0x04, 0x04);
+        }
+    }
+
+    fn ebml(id: u32, body: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        push_ebml(&mut out, id, body);
+        out
+    }
+
+    fn webm_cluster(timecode: u16, payload: &[u8]) -> Vec<u8> {
+        let mut body = vec![0xE7, 0x82];
+        body.extend_from_slice(&timecode.to_be_bytes());
+        body.extend(ebml(0xA3, payload));
+        ebml(CLUSTER_ID, &body)
+    }
+
+    #[test]
+    fn test_split_webm_on_clusters() {
+        let mut webm = ebml(EBML_HEADER_ID, &ebml(0x4282, b"webm"));
+        let mut segment = ebml(
+            INFO_ID,
+            &[ebml(0x2AD7B1, &[0x0F, 0x42, 0x40]), ebml(DURATION_ID, &10_000f64.to_be_bytes())].concat(),
+        );
+        segment.extend(ebml(0x1C53BB6B, b"cues"));
+        segment.extend(ebml(TRACKS_ID, b"tracks"));
+        // 10 个 Cluster，每个 1 秒 (时间刻度 1ms)
+        for i in 0..10u16 {
+            segment.extend(webm_cluster(i * 1000, &[i as u8; 200]));
+        }
+        webm.extend(ebml(SEGMENT_ID, &segment));
+
+        let chunks = split_audio(&webm, "audio/webm", 4.0, MAX_CHUNK_BYTES).unwrap();
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[1].offset_secs, 4.0);
+        assert_eq!(chunks[1].duration_secs, Some(4.0));
+        assert_eq!(chunks[2].duration_secs, Some(2.0));
+
+        // 每段都能重新解析：Cluster 时间码从 0 开始，Info 中不再有 Duration
+        let reparsed = split_webm(&chunks[1].data, 1e9, usize::MAX).unwrap();
+        assert_eq!(reparsed[0].duration_secs, None);
+        let header = ebml_element(&chunks[1].data, 0).unwrap();
+        let segment = ebml_element(&chunks[1].data, header.data_start + header.size.unwrap()).unwrap();
+        assert_eq!(segment.id, SEGMENT_ID);
+        let mut pos = segment.data_start;
+        let mut timecodes = Vec::new();
+        while let Some(element) = ebml_element(&chunks[1].data, pos) {
+            let end = ebml_end(&chunks[1].data, &element, chunks[1].data.len());
+            if element.id == CLUSTER_ID {
+                let (timecode, _) = &ebml_children(&chunks[1].data, element.data_start, end)[0];
+                timecodes.push(ebml_uint(&chunks[1].data[timecode.data_start..timecode.data_start + 8]));
+            }
+            pos = end;
+        }
+        assert_eq!(timecodes, vec![0, 1000, 2000, 3000]);
+    }
+
+    fn mp4_box(kind: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut out = ((body.len() + 8) as u32).to_be_bytes().to_vec();
+        out.extend_from_slice(kind);
+        out.extend_from_slice(body);
+        out
+    }
+
+    #[test]
+    fn test_split_fragmented_mp4() {
+        let mut tkhd = vec![0u8; 84];
+        tkhd[12..16].copy_from_slice(&1u32.to_be_bytes());
+        let mut mdhd = vec![0u8; 24];
+        mdhd[12..16].copy_from_slice(&1000u32.to_be_bytes());
+        let trak = mp4_box(b"trak", &[mp4_box(b"tkhd", &tkhd), mp4_box(b"mdia", &mp4_box(b"mdhd", &mdhd))].concat());
+        let mut mp4 = mp4_box(b"ftyp", b"iso6\0\0\0\0");
+        mp4.extend(mp4_box(b"moov", &trak));
+        // 10 个片段，每个 1 秒，tfdt 为 version 1
+        for i in 0..10u64 {
+            let tfhd = mp4_box(b"tfhd", &[0, 0x02, 0, 0, 0, 0, 0, 1]);
+            let tfdt = mp4_box(b"tfdt", &[&[1, 0, 0, 0][..], &(i * 1000).to_be_bytes()].concat());
+            mp4.extend(mp4_box(b"moof", &mp4_box(b"traf", &[tfhd, tfdt].concat())));
+            mp4.extend(mp4_box(b"mdat", &[i as u8; 100]));
+        }
+
+        let chunks = split_audio(&mp4, "audio/mp4", 4.0, MAX_CHUNK_BYTES).unwrap();
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[2].offset_secs, 8.0);
+        assert_eq!(chunks[0].duration_secs, Some(4.0));
+        for chunk in &chunks {
+            let boxes = mp4_boxes(&chunk.data, 0, chunk.data.len()).unwrap();
+            assert_eq!(&boxes[0].kind, b"ftyp");
+            let moof = child_box(&boxes, b"moof").unwrap();
+            assert_eq!(mp4_fragment_tfdts(&chunk.data, moof).unwrap()[0].value, 0);
+        }
+
+        // 非分片 MP4 无法切分
+        let plain = [mp4_box(b"ftyp", b"isom"), mp4_box(b"moov", &trak), mp4_box(b"mdat", &[0; 64])].concat();
+        assert!(split_mp4(&plain, "audio/mp4", 1.0, MAX_CHUNK_BYTES).is_none());
+    }
+
+    #[test]
+    fn test_unsplittable_formats() {
+        let small = b"OggS-small".to_vec();
+        let chunks = split_audio(&small, "audio/ogg", MAX_CHUNK_SECS, MAX_CHUNK_BYTES).unwrap();
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].duration_secs, None);
+
+        let large = vec![0u8; 16 * 1024 * 1024];
+        assert!(split_audio(&large, "audio/webm", MAX_CHUNK_SECS, MAX_CHUNK_BYTES).is_err());
+    }
+}
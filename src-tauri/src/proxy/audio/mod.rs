@@ -1,6 +1,90 @@
+pub mod chunker;
+pub mod transcript;
+
 use base64::{engine::general_purpose, Engine as _};
 use std::path::Path;
 
+/// 未指定或指定 OpenAI TTS 模型 (tts-1 等) 时使用的 Gemini TTS 模型
+pub const DEFAULT_TTS_MODEL: &str = "gemini-2.5-flash-preview-tts";
+
+/// Gemini TTS 返回 audio/L16 未标注采样率时的默认值
+const DEFAULT_PCM_SAMPLE_RATE: u32 = 24_000;
+
+/// Gemini 预置音色 (原样透传)
+const GEMINI_VOICES: &[&str] = &[
+    "Zephyr",
+    "Puck",
+    "Charon",
+    "Kore",
+    "Fenrir",
+    "Leda",
+    "Orus",
+    "Aoede",
+    "Callirrhoe",
+    "Autonoe",
+    "Enceladus",
+    "Iapetus",
+    "Umbriel",
+    "Algieba",
+    "Despina",
+    "Erinome",
+    "Algenib",
+    "Rasalgethi",
+    "Laomedeia",
+    "Achernar",
+    "Alnilam",
+    "Schedar",
+    "Gacrux",
+    "Pulcherrima",
+    "Achird",
+    "Zubenelgenubi",
+    "Vindemiatrix",
+    "Sadachbia",
+    "Sadaltager",
+    "Sulafat",
+];
+
+/// 语音合成输出格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpeechFormat {
+    /// OpenAI 默认格式，由 LAME 编码
+    Mp3,
+    Wav,
+    /// 原始 16-bit 小端 PCM
+    Pcm,
+}
+
+/// 可输出的 `response_format` (未内置 Opus/AAC/FLAC 编码器)
+pub const SUPPORTED_SPEECH_FORMATS: &[&str] = &["mp3", "wav", "pcm"];
+
+/// MP3 输出码率 (单声道语音足够)
+const MP3_BITRATE: mp3lame_encoder::Bitrate = mp3lame_encoder::Bitrate::Kbps64;
+
+impl SpeechFormat {
+    /// 解析 OpenAI `response_format`
+    /// 未指定时与 OpenAI 一致输出 MP3；指定了不支持的格式时返回错误，不以其他封装格式代替
+    pub fn parse(response_format: Option<&str>) -> Result<Self, String> {
+        match response_format.map(|f| f.to_ascii_lowercase()).as_deref() {
+            None | Some("mp3") => Ok(Self::Mp3),
+            Some("wav") => Ok(Self::Wav),
+            Some("pcm") => Ok(Self::Pcm),
+            Some(other) => Err(format!(
+                "不支持的 response_format: {}，可用格式: {}",
+                other,
+                SUPPORTED_SPEECH_FORMATS.join(", ")
+            )),
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Self::Mp3 => "audio/mpeg",
+            Self::Wav => "audio/wav",
+            Self::Pcm => "audio/pcm",
+        }
+    }
+}
+
 pub struct AudioProcessor;
 
 impl AudioProcessor {
@@ -18,10 +102,53 @@ impl AudioProcessor {
             "ogg" => Ok("audio/ogg".to_string()),
             "flac" => Ok("audio/flac".to_string()),
             "aiff" | "aif" => Ok("audio/aiff".to_string()),
+            "webm" => Ok("audio/webm".to_string()),
+            "mp4" => Ok("audio/mp4".to_string()),
+            "opus" => Ok("audio/ogg".to_string()),
+            "mpga" | "mpeg" => Ok("audio/mp3".to_string()),
             _ => Err(format!("不支持的音频格式: {}", ext)),
         }
     }
 
+    /// 按文件头魔数识别音频格式 (Opus 封装在 Ogg 中，按 audio/ogg 处理)
+    pub fn sniff_mime_type(data: &[u8]) -> Option<&'static str> {
+        let starts = |magic: &[u8]| data.starts_with(magic);
+        if starts(b"RIFF") && matches!(data.get(8..12), Some(b"WAVE")) {
+            Some("audio/wav")
+        } else if starts(b"ID3") {
+            Some("audio/mp3")
+        } else if starts(b"fLaC") {
+            Some("audio/flac")
+        } else if starts(b"OggS") {
+            Some("audio/ogg")
+        } else if starts(b"FORM") && matches!(data.get(8..12), Some(b"AIFF") | Some(b"AIFC")) {
+            Some("audio/aiff")
+        } else if starts(&[0x1A, 0x45, 0xDF, 0xA3]) {
+            Some("audio/webm")
+        } else if matches!(data.get(4..8), Some(b"ftyp")) {
+            // M4A/M4B 为纯音频 AAC，其余品牌按 MP4 处理
+            match data.get(8..12) {
+                Some(b"M4A ") | Some(b"M4B ") => Some("audio/aac"),
+                _ => Some("audio/mp4"),
+            }
+        } else if data.len() >= 2 && data[0] == 0xFF && data[1] & 0xF6 == 0xF0 {
+            // ADTS AAC (layer 位为 00)
+            Some("audio/aac")
+        } else if data.len() >= 2 && data[0] == 0xFF && data[1] & 0xE0 == 0xE0 {
+            Some("audio/mp3")
+        } else {
+            None
+        }
+    }
+
+    /// 识别音频格式：优先文件头魔数，无法识别时回退到扩展名
+    pub fn detect_audio_mime(filename: &str, data: &[u8]) -> Result<String, String> {
+        match Self::sniff_mime_type(data) {
+            Some(mime) => Ok(mime.to_string()),
+            None => Self::detect_mime_type(filename),
+        }
+    }
+
     /// 将音频数据编码为 Base64
     pub fn encode_to_base64(audio_data: &[u8]) -> String {
         general_purpose::STANDARD.encode(audio_data)
@@ -32,6 +159,107 @@ impl AudioProcessor {
         const MAX_SIZE: usize = 15 * 1024 * 1024; // 15MB
         size_bytes > MAX_SIZE
     }
+
+    /// 解析 TTS 模型：Gemini TTS 模型原样使用，其余 (tts-1、gpt-4o-mini-tts 等) 映射到默认模型
+    pub fn resolve_tts_model(model: Option<&str>) -> String {
+        match model {
+            Some(m) if m.starts_with("gemini") && m.contains("tts") => m.to_string(),
+            _ => DEFAULT_TTS_MODEL.to_string(),
+        }
+    }
+
+    /// 将 OpenAI 音色映射为 Gemini 预置音色；Gemini 音色名 (不区分大小写) 原样透传
+    pub fn map_voice(voice: Option<&str>) -> &'static str {
+        let voice = voice.unwrap_or("").trim();
+        if let Some(v) = GEMINI_VOICES.iter().find(|v| v.eq_ignore_ascii_case(voice)) {
+            return v;
+        }
+        match voice.to_ascii_lowercase().as_str() {
+            "alloy" => "Kore",
+            "echo" => "Puck",
+            "fable" => "Aoede",
+            "onyx" => "Charon",
+            "nova" => "Leda",
+            "shimmer" => "Zephyr",
+            "ash" => "Fenrir",
+            "coral" => "Callirrhoe",
+            "sage" => "Orus",
+            "ballad" => "Enceladus",
+            "verse" => "Iapetus",
+            _ => "Kore",
+        }
+    }
+
+    /// 从 `audio/L16;codec=pcm;rate=24000` 形式的 MIME 中解析采样率
+    pub fn parse_pcm_sample_rate(mime_type: &str) -> u32 {
+        mime_type
+            .split(';')
+            .filter_map(|p| p.trim().strip_prefix("rate="))
+            .find_map(|r| r.parse().ok())
+            .unwrap_or(DEFAULT_PCM_SAMPLE_RATE)
+    }
+
+    /// 为 16-bit 小端 PCM 加上 WAV (RIFF) 头
+    pub fn pcm_to_wav(pcm: &[u8], sample_rate: u32, channels: u16) -> Vec<u8> {
+        const BITS_PER_SAMPLE: u16 = 16;
+        let block_align = channels * BITS_PER_SAMPLE / 8;
+        let byte_rate = sample_rate * block_align as u32;
+        let data_len = pcm.len() as u32;
+
+        let mut wav = Vec::with_capacity(44 + pcm.len());
+        wav.extend_from_slice(b"RIFF");
+        wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+        wav.extend_from_slice(b"WAVE");
+        wav.extend_from_slice(b"fmt ");
+        wav.extend_from_slice(&16u32.to_le_bytes());
+        wav.extend_from_slice(&1u16.to_le_bytes()); // PCM
+        wav.extend_from_slice(&channels.to_le_bytes());
+        wav.extend_from_slice(&sample_rate.to_le_bytes());
+        wav.extend_from_slice(&byte_rate.to_le_bytes());
+        wav.extend_from_slice(&block_align.to_le_bytes());
+        wav.extend_from_slice(&BITS_PER_SAMPLE.to_le_bytes());
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&data_len.to_le_bytes());
+        wav.extend_from_slice(pcm);
+        wav
+    }
+
+    /// 将 16-bit 小端 PCM 编码为 MP3
+    pub fn pcm_to_mp3(pcm: &[u8], sample_rate: u32, channels: u8) -> Result<Vec<u8>, String> {
+        use mp3lame_encoder::{Builder, FlushNoGap, InterleavedPcm, MonoPcm};
+
+        let mut builder = Builder::new().ok_or("MP3 编码器初始化失败")?;
+        builder
+            .set_num_channels(channels)
+            .map_err(|e| format!("MP3 声道数设置失败: {}", e))?;
+        builder
+            .set_sample_rate(sample_rate)
+            .map_err(|e| format!("MP3 采样率设置失败: {}", e))?;
+        builder
+            .set_brate(MP3_BITRATE)
+            .map_err(|e| format!("MP3 码率设置失败: {}", e))?;
+        let mut encoder = builder
+            .build()
+            .map_err(|e| format!("MP3 编码器初始化失败: {}", e))?;
+
+        let samples: Vec<i16> = pcm
+            .chunks_exact(2)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]))
+            .collect();
+        let mut mp3 = Vec::with_capacity(mp3lame_encoder::max_required_buffer_size(samples.len()));
+        let encoded = if channels == 1 {
+            encoder.encode_to_vec(MonoPcm(&samples), &mut mp3)
+        } else {
+            encoder.encode_to_vec(InterleavedPcm(&samples), &mut mp3)
+        };
+        encoded.map_err(|e| format!("MP3 编码失败: {}", e))?;
+        // flush 至少需要 7200 字节空间
+        mp3.reserve(7200);
+        encoder
+            .flush_to_vec::<FlushNoGap>(&mut mp3)
+            .map_err(|e| format!("MP3 编码失败: {}", e))?;
+        Ok(mp3)
+    }
 }
 
 #[cfg(test)]
@@ -51,6 +279,37 @@ mod tests {
         assert!(AudioProcessor::detect_mime_type("audio.txt").is_err());
     }
 
+    #[test]
+    fn test_sniff_mime_type() {
+        let wav = AudioProcessor::pcm_to_wav(&[0; 4], 16_000, 1);
+        assert_eq!(AudioProcessor::sniff_mime_type(&wav), Some("audio/wav"));
+        assert_eq!(
+            AudioProcessor::sniff_mime_type(&[0x1A, 0x45, 0xDF, 0xA3, 0x01]),
+            Some("audio/webm")
+        );
+        assert_eq!(
+            AudioProcessor::sniff_mime_type(b"\0\0\0\x20ftypisom"),
+            Some("audio/mp4")
+        );
+        assert_eq!(
+            AudioProcessor::sniff_mime_type(&[0xFF, 0xF1, 0x50]),
+            Some("audio/aac")
+        );
+        assert_eq!(
+            AudioProcessor::sniff_mime_type(&[0xFF, 0xFB, 0x90]),
+            Some("audio/mp3")
+        );
+        // 内容优先于扩展名
+        assert_eq!(
+            AudioProcessor::detect_audio_mime("voice.mp3", b"OggS\0\x02").unwrap(),
+            "audio/ogg"
+        );
+        assert_eq!(
+            AudioProcessor::detect_audio_mime("meeting.webm", b"????").unwrap(),
+            "audio/webm"
+        );
+    }
+
     #[test]
     fn test_exceeds_size_limit() {
         assert!(!AudioProcessor::exceeds_size_limit(10 * 1024 * 1024)); // 10MB
@@ -65,4 +324,54 @@ mod tests {
         let encoded = AudioProcessor::encode_to_base64(data);
         assert!(!encoded.is_empty());
     }
+
+    #[test]
+    fn test_pcm_to_wav_header() {
+        let pcm = vec![0u8; 480];
+        let rate = AudioProcessor::parse_pcm_sample_rate("audio/L16;codec=pcm;rate=24000");
+        let wav = AudioProcessor::pcm_to_wav(&pcm, rate, 1);
+        assert_eq!(wav.len(), 44 + 480);
+        assert_eq!(&wav[0..4], b"RIFF");
+        assert_eq!(&wav[8..12], b"WAVE");
+        assert_eq!(u32::from_le_bytes(wav[4..8].try_into().unwrap()), 36 + 480);
+        assert_eq!(u32::from_le_bytes(wav[24..28].try_into().unwrap()), 24_000);
+        assert_eq!(u32::from_le_bytes(wav[28..32].try_into().unwrap()), 48_000);
+        assert_eq!(u32::from_le_bytes(wav[40..44].try_into().unwrap()), 480);
+        assert_eq!(AudioProcessor::parse_pcm_sample_rate("audio/L16"), 24_000);
+    }
+
+    #[test]
+    fn test_voice_and_format_mapping() {
+        assert_eq!(AudioProcessor::map_voice(Some("alloy")), "Kore");
+        assert_eq!(AudioProcessor::map_voice(Some("puck")), "Puck");
+        assert_eq!(AudioProcessor::map_voice(None), "Kore");
+        assert_eq!(
+            AudioProcessor::resolve_tts_model(Some("tts-1-hd")),
+            DEFAULT_TTS_MODEL
+        );
+        assert_eq!(
+            AudioProcessor::resolve_tts_model(Some("gemini-2.5-pro-preview-tts")),
+            "gemini-2.5-pro-preview-tts"
+        );
+        assert_eq!(SpeechFormat::parse(Some("PCM")), Ok(SpeechFormat::Pcm));
+        assert_eq!(SpeechFormat::parse(None), Ok(SpeechFormat::Mp3));
+        assert_eq!(SpeechFormat::parse(Some("mp3")), Ok(SpeechFormat::Mp3));
+        assert_eq!(SpeechFormat::Mp3.content_type(), "audio/mpeg");
+        for unsupported in ["opus", "aac", "flac"] {
+            let err = SpeechFormat::parse(Some(unsupported)).unwrap_err();
+            assert!(err.contains(unsupported) && err.contains("mp3, wav, pcm"));
+        }
+    }
+
+    #[test]
+    fn test_pcm_to_mp3() {
+        // 0.5 秒 24kHz 单声道正弦波
+        let pcm: Vec<u8> = (0..12_000)
+            .map(|i| ((i as f32 * 0.05).sin() * 8000.0) as i16)
+            .flat_map(|s| s.to_le_bytes())
+            .collect();
+        let mp3 = AudioProcessor::pcm_to_mp3(&pcm, 24_000, 1).unwrap();
+        assert!(!mp3.is_empty());
+        assert_eq!(AudioProcessor::sniff_mime_type(&mp3), Some("audio/mp3"));
+    }
 }
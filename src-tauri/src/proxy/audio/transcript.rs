@@ -0,0 +1,233 @@
+// 转录结果格式化 (OpenAI response_format: json / text / verbose_json / srt / vtt)
+
+use serde_json::{json, Value};
+
+/// 转录输出格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TranscriptFormat {
+    Json,
+    Text,
+    VerboseJson,
+    Srt,
+    Vtt,
+}
+
+impl TranscriptFormat {
+    pub fn parse(value: Option<&str>) -> Result<Self, String> {
+        match value.map(|v| v.trim().to_ascii_lowercase()).as_deref() {
+            None | Some("") | Some("json") => Ok(Self::Json),
+            Some("text") => Ok(Self::Text),
+            Some("verbose_json") => Ok(Self::VerboseJson),
+            Some("srt") => Ok(Self::Srt),
+            Some("vtt") => Ok(Self::Vtt),
+            Some(other) => Err(format!("不支持的 response_format: {}", other)),
+        }
+    }
+
+    /// 是否需要分段时间戳
+    pub fn needs_segments(&self) -> bool {
+        matches!(self, Self::VerboseJson | Self::Srt | Self::Vtt)
+    }
+}
+
+/// 带时间戳的转录片段 (秒，相对整段音频)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Segment {
+    pub start: f64,
+    pub end: f64,
+    pub text: String,
+}
+
+/// 要求模型按分段输出时使用的 responseSchema
+pub fn segments_schema() -> Value {
+    json!({
+        "type": "OBJECT",
+        "properties": {
+            "language": {"type": "STRING"},
+            "segments": {
+                "type": "ARRAY",
+                "items": {
+                    "type": "OBJECT",
+                    "properties": {
+                        "start": {"type": "NUMBER"},
+                        "end": {"type": "NUMBER"},
+                        "text": {"type": "STRING"}
+                    },
+                    "required": ["start", "end", "text"]
+                }
+            }
+        },
+        "required": ["segments"]
+    })
+}
+
+/// 解析 "12.5" / "01:02.5" / "1:02:03" 形式的时间
+fn parse_time(value: &Value) -> Option<f64> {
+    if let Some(n) = value.as_f64() {
+        return Some(n);
+    }
+    value
+        .as_str()?
+        .trim()
+        .split(':')
+        .try_fold(0.0, |acc, part| {
+            part.trim().parse::<f64>().ok().map(|v| acc * 60.0 + v)
+        })
+}
+
+/// 解析模型返回的分段 JSON，时间戳平移到整段音频上并裁剪到片段时长内
+/// 返回 (语言, 分段)
+pub fn parse_segments(
+    raw: &str,
+    offset_secs: f64,
+    duration_secs: Option<f64>,
+) -> Option<(Option<String>, Vec<Segment>)> {
+    let raw = raw.trim();
+    let raw = raw
+        .strip_prefix("```json")
+        .or_else(|| raw.strip_prefix("```"))
+        .map(|r| r.trim_end().trim_end_matches("```"))
+        .unwrap_or(raw);
+    let value: Value = serde_json::from_str(raw.trim()).ok()?;
+    let items = value["segments"].as_array().or_else(|| value.as_array())?;
+    let limit = duration_secs.unwrap_or(f64::MAX);
+
+    let mut segments = Vec::new();
+    for item in items {
+        let text = item["text"].as_str().unwrap_or("").trim();
+        if text.is_empty() {
+            continue;
+        }
+        let start = parse_time(&item["start"]).unwrap_or(0.0).clamp(0.0, limit);
+        let end = parse_time(&item["end"])
+            .unwrap_or(start)
+            .clamp(start, limit);
+        segments.push(Segment {
+            start: offset_secs + start,
+            end: offset_secs + end,
+            text: text.to_string(),
+        });
+    }
+    let language = value["language"]
+        .as_str()
+        .filter(|l| !l.is_empty())
+        .map(|l| l.to_lowercase());
+    Some((language, segments))
+}
+
+/// 格式化时间戳；`sep` 为毫秒分隔符 (SRT 用逗号，VTT 用点)
+fn format_timestamp(secs: f64, sep: char) -> String {
+    let total_ms = (secs.max(0.0) * 1000.0).round() as u64;
+    format!(
+        "{:02}:{:02}:{:02}{}{:03}",
+        total_ms / 3_600_000,
+        total_ms / 60_000 % 60,
+        total_ms / 1000 % 60,
+        sep,
+        total_ms % 1000
+    )
+}
+
+pub fn to_srt(segments: &[Segment]) -> String {
+    segments
+        .iter()
+        .enumerate()
+        .map(|(i, s)| {
+            format!(
+                "{}\n{} --> {}\n{}\n\n",
+                i + 1,
+                format_timestamp(s.start, ','),
+                format_timestamp(s.end, ','),
+                s.text
+            )
+        })
+        .collect()
+}
+
+pub fn to_vtt(segments: &[Segment]) -> String {
+    let mut out = String::from("WEBVTT\n\n");
+    for s in segments {
+        out.push_str(&format!(
+            "{} --> {}\n{}\n\n",
+            format_timestamp(s.start, '.'),
+            format_timestamp(s.end, '.'),
+            s.text
+        ));
+    }
+    out
+}
+
+/// OpenAI verbose_json 的 segments 字段
+pub fn to_verbose_segments(segments: &[Segment]) -> Value {
+    Value::Array(
+        segments
+            .iter()
+            .enumerate()
+            .map(|(i, s)| {
+                json!({
+                    "id": i,
+                    "seek": 0,
+                    "start": s.start,
+                    "end": s.end,
+                    "text": s.text,
+                    "tokens": [],
+                    "temperature": 0.0,
+                    "avg_logprob": 0.0,
+                    "compression_ratio": 0.0,
+                    "no_speech_prob": 0.0
+                })
+            })
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_segments_with_offset() {
+        let raw = r#"```json
+{"language":"English","segments":[
+  {"start":0,"end":4.5,"text":"Hello there."},
+  {"start":"00:05.0","end":"1:30","text":"Second line"},
+  {"start":6,"end":7,"text":"  "}
+]}
+```"#;
+        let (language, segments) = parse_segments(raw, 300.0, Some(60.0)).unwrap();
+        assert_eq!(language.as_deref(), Some("english"));
+        assert_eq!(segments.len(), 2);
+        assert_eq!(segments[0].start, 300.0);
+        assert_eq!(segments[1].start, 305.0);
+        // 结束时间裁剪到片段时长内
+        assert_eq!(segments[1].end, 360.0);
+        assert!(parse_segments("not json", 0.0, None).is_none());
+    }
+
+    #[test]
+    fn test_render_srt_and_vtt() {
+        let segments = vec![
+            Segment {
+                start: 0.0,
+                end: 1.5,
+                text: "Hi".to_string(),
+            },
+            Segment {
+                start: 3661.25,
+                end: 3662.0,
+                text: "Bye".to_string(),
+            },
+        ];
+        assert_eq!(
+            to_srt(&segments),
+            "1\n00:00:00,000 --> 00:00:01,500\nHi\n\n2\n01:01:01,250 --> 01:01:02,000\nBye\n\n"
+        );
+        assert!(to_vtt(&segments).starts_with("WEBVTT\n\n00:00:00.000 --> 00:00:01.500\nHi\n"));
+        assert_eq!(
+            TranscriptFormat::parse(Some("SRT")),
+            Ok(TranscriptFormat::Srt)
+        );
+        assert!(TranscriptFormat::parse(Some("xml")).is_err());
+        assert!(!TranscriptFormat::parse(None).unwrap().needs_segments());
+    }
+}
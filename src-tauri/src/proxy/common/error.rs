@@ -1,41 +1,325 @@
 // 错误处理
-use thiserror::Error;
-use axum::{http::StatusCode, Json, response::IntoResponse};
+// 各协议 SDK 只认自己的错误信封，纯文本或其他协议格式的错误体会导致客户端解析失败：
+// - OpenAI:    { "error": { "message", "type", "code", "param" } }
+// - Anthropic: { "type": "error", "error": { "type", "message" } }
+// - Google:    { "error": { "code", "status", "message" } }
+// handler 统一返回 `ProxyError`，由 `error_envelope_middleware` 按入口路由渲染为对应协议的信封；
+// 中间件同时把其他来源的纯文本 / 非标准错误体 (鉴权、准入、上游透传等) 规范化。
 
-#[derive(Debug, Error)]
-pub enum ProxyError {
-    #[error("Upstream API error: {0}")]
-    UpstreamError(String),
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::{json, Value};
+use std::fmt;
 
-    #[error("Transform error: {0}")]
-    TransformError(String),
+/// 入口路由对应的客户端协议
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiProtocol {
+    OpenAI,
+    Anthropic,
+    Gemini,
+}
 
-    #[error("Account error: {0}")]
-    AccountError(String),
+impl ApiProtocol {
+    /// 按请求路径判断协议；非 AI 协议路由 (健康检查、MCP、内部端点) 返回 None
+    pub fn from_path(path: &str) -> Option<Self> {
+        if path.starts_with("/v1/messages") || path.starts_with("/v1/models/claude") {
+            Some(Self::Anthropic)
+        } else if path.starts_with("/v1beta/")
+            || path == "/v1beta"
+            || path.starts_with("/upload/v1beta/")
+        {
+            Some(Self::Gemini)
+        } else if path.starts_with("/v1/") && !path.starts_with("/v1/api/") {
+            Some(Self::OpenAI)
+        } else {
+            None
+        }
+    }
+}
 
-    #[error("Rate limit exceeded")]
-    RateLimitExceeded,
+/// 错误类别，决定各协议中的 type / code / status 取值
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    InvalidRequest,
+    Authentication,
+    PermissionDenied,
+    NotFound,
+    RequestTooLarge,
+    RateLimited,
+    Timeout,
+    Overloaded,
+    Api,
+}
 
-    #[error("Invalid request: {0}")]
-    InvalidRequest(String),
+impl ErrorKind {
+    pub fn from_status(status: StatusCode) -> Self {
+        match status.as_u16() {
+            401 => Self::Authentication,
+            403 => Self::PermissionDenied,
+            404 => Self::NotFound,
+            413 => Self::RequestTooLarge,
+            429 => Self::RateLimited,
+            408 | 504 => Self::Timeout,
+            503 | 529 => Self::Overloaded,
+            s if s >= 500 => Self::Api,
+            _ => Self::InvalidRequest,
+        }
+    }
+
+    fn anthropic_type(&self) -> &'static str {
+        match self {
+            Self::InvalidRequest => "invalid_request_error",
+            Self::Authentication => "authentication_error",
+            Self::PermissionDenied => "permission_error",
+            Self::NotFound => "not_found_error",
+            Self::RequestTooLarge => "request_too_large",
+            Self::RateLimited => "rate_limit_error",
+            Self::Timeout => "timeout_error",
+            Self::Overloaded => "overloaded_error",
+            Self::Api => "api_error",
+        }
+    }
+
+    /// (type, code)
+    fn openai_type_code(&self) -> (&'static str, Option<&'static str>) {
+        match self {
+            Self::InvalidRequest => ("invalid_request_error", None),
+            Self::Authentication => ("invalid_request_error", Some("invalid_api_key")),
+            Self::PermissionDenied => ("permission_error", Some("permission_denied")),
+            Self::NotFound => ("invalid_request_error", Some("not_found")),
+            Self::RequestTooLarge => ("invalid_request_error", Some("request_too_large")),
+            Self::RateLimited => ("rate_limit_error", Some("rate_limit_exceeded")),
+            Self::Timeout => ("server_error", Some("timeout")),
+            Self::Overloaded => ("server_error", Some("service_unavailable")),
+            Self::Api => ("server_error", None),
+        }
+    }
+
+    fn google_status(&self) -> &'static str {
+        match self {
+            Self::InvalidRequest | Self::RequestTooLarge => "INVALID_ARGUMENT",
+            Self::Authentication => "UNAUTHENTICATED",
+            Self::PermissionDenied => "PERMISSION_DENIED",
+            Self::NotFound => "NOT_FOUND",
+            Self::RateLimited => "RESOURCE_EXHAUSTED",
+            Self::Timeout => "DEADLINE_EXCEEDED",
+            Self::Overloaded => "UNAVAILABLE",
+            Self::Api => "INTERNAL",
+        }
+    }
 }
 
-impl IntoResponse for ProxyError {
-    fn into_response(self) -> axum::response::Response {
-        let status = match &self {
-            ProxyError::InvalidRequest(_) => StatusCode::BAD_REQUEST,
-            ProxyError::RateLimitExceeded => StatusCode::TOO_MANY_REQUESTS,
-            ProxyError::AccountError(_) => StatusCode::UNAUTHORIZED,
-            _ => StatusCode::INTERNAL_SERVER_ERROR,
-        };
-
-        let body = serde_json::json!({
-            "error": {
-                "message": self.to_string(),
-                "type": format!("{:?}", self)
+/// 代理统一错误
+#[derive(Debug, Clone)]
+pub struct ProxyError {
+    pub status: StatusCode,
+    pub kind: ErrorKind,
+    pub message: String,
+}
+
+impl ProxyError {
+    pub fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            kind: ErrorKind::from_status(status),
+            message: message.into(),
+        }
+    }
+
+    pub fn invalid_request(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, message)
+    }
+
+    pub fn internal(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::INTERNAL_SERVER_ERROR, message)
+    }
+
+    pub fn bad_gateway(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_GATEWAY, message)
+    }
+
+    /// 上游返回的错误：保留状态码，从 Google 错误体中提取 message
+    pub fn upstream(status: StatusCode, body: &str) -> Self {
+        Self::new(
+            status,
+            extract_message(body.as_bytes()).unwrap_or_else(|| body.to_string()),
+        )
+    }
+
+    /// 按协议渲染错误体
+    pub fn to_body(&self, protocol: ApiProtocol) -> Value {
+        match protocol {
+            ApiProtocol::OpenAI => {
+                let (error_type, code) = self.kind.openai_type_code();
+                json!({
+                    "error": {
+                        "message": self.message,
+                        "type": error_type,
+                        "code": code,
+                        "param": null
+                    }
+                })
             }
-        });
+            ApiProtocol::Anthropic => json!({
+                "type": "error",
+                "error": {
+                    "type": self.kind.anthropic_type(),
+                    "message": self.message
+                }
+            }),
+            ApiProtocol::Gemini => json!({
+                "error": {
+                    "code": self.status.as_u16(),
+                    "status": self.kind.google_status(),
+                    "message": self.message
+                }
+            }),
+        }
+    }
+}
+
+impl fmt::Display for ProxyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.message, self.status)
+    }
+}
+
+impl std::error::Error for ProxyError {}
+
+impl From<(StatusCode, String)> for ProxyError {
+    fn from((status, message): (StatusCode, String)) -> Self {
+        Self::new(status, message)
+    }
+}
+
+impl IntoResponse for ProxyError {
+    /// 不知道入口路由时先按 OpenAI 信封渲染，并把错误挂到扩展上，由中间件按路由重新渲染
+    fn into_response(self) -> Response {
+        let mut response = (self.status, Json(self.to_body(ApiProtocol::OpenAI))).into_response();
+        response.extensions_mut().insert(self);
+        response
+    }
+}
+
+/// 错误体是否已经是该协议的标准信封 (例如 z.ai 的 Anthropic 错误、Google 上游错误直接透传)
+pub fn is_protocol_envelope(body: &Value, protocol: ApiProtocol) -> bool {
+    let error = &body["error"];
+    let has_message = error["message"].is_string();
+    match protocol {
+        ApiProtocol::OpenAI => {
+            has_message && error["type"].is_string() && body.get("type").is_none()
+        }
+        ApiProtocol::Anthropic => {
+            body["type"] == "error" && has_message && error["type"].is_string()
+        }
+        ApiProtocol::Gemini => has_message && error["code"].is_u64() && error["status"].is_string(),
+    }
+}
+
+/// 从任意错误体中提取可读的错误信息 (各协议信封、{message}、{detail} 或纯文本)
+pub fn extract_message(body: &[u8]) -> Option<String> {
+    let text = String::from_utf8_lossy(body);
+    let text = text.trim();
+    if text.is_empty() {
+        return None;
+    }
+    let Ok(value) = serde_json::from_str::<Value>(text) else {
+        return Some(text.to_string());
+    };
+    // Google 错误有时包在数组里
+    let value = match value {
+        Value::Array(mut items) if !items.is_empty() => items.swap_remove(0),
+        other => other,
+    };
+    let message = [
+        value["error"]["message"].as_str(),
+        value["error"].as_str(),
+        value["message"].as_str(),
+        value["detail"].as_str(),
+    ]
+    .into_iter()
+    .flatten()
+    .next()
+    .unwrap_or(text)
+    .to_string();
+    Some(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_protocol_from_path() {
+        assert_eq!(
+            ApiProtocol::from_path("/v1/messages"),
+            Some(ApiProtocol::Anthropic)
+        );
+        assert_eq!(
+            ApiProtocol::from_path("/v1/messages/count_tokens"),
+            Some(ApiProtocol::Anthropic)
+        );
+        assert_eq!(
+            ApiProtocol::from_path("/v1beta/models/gemini-2.5-pro:generateContent"),
+            Some(ApiProtocol::Gemini)
+        );
+        assert_eq!(
+            ApiProtocol::from_path("/upload/v1beta/files"),
+            Some(ApiProtocol::Gemini)
+        );
+        assert_eq!(
+            ApiProtocol::from_path("/v1/chat/completions"),
+            Some(ApiProtocol::OpenAI)
+        );
+        assert_eq!(
+            ApiProtocol::from_path("/v1/audio/transcriptions"),
+            Some(ApiProtocol::OpenAI)
+        );
+        assert_eq!(ApiProtocol::from_path("/mcp/web_reader/mcp"), None);
+        assert_eq!(ApiProtocol::from_path("/healthz"), None);
+    }
+
+    #[test]
+    fn test_render_envelopes() {
+        let err = ProxyError::new(StatusCode::TOO_MANY_REQUESTS, "All accounts exhausted");
+
+        let openai = err.to_body(ApiProtocol::OpenAI);
+        assert_eq!(openai["error"]["type"], "rate_limit_error");
+        assert_eq!(openai["error"]["code"], "rate_limit_exceeded");
+        assert!(is_protocol_envelope(&openai, ApiProtocol::OpenAI));
+
+        let anthropic = err.to_body(ApiProtocol::Anthropic);
+        assert_eq!(anthropic["type"], "error");
+        assert_eq!(anthropic["error"]["type"], "rate_limit_error");
+        assert!(is_protocol_envelope(&anthropic, ApiProtocol::Anthropic));
+        assert!(!is_protocol_envelope(&anthropic, ApiProtocol::OpenAI));
+
+        let gemini = err.to_body(ApiProtocol::Gemini);
+        assert_eq!(gemini["error"]["code"], 429);
+        assert_eq!(gemini["error"]["status"], "RESOURCE_EXHAUSTED");
+        assert!(is_protocol_envelope(&gemini, ApiProtocol::Gemini));
+    }
+
+    #[test]
+    fn test_extract_message() {
+        let google = br#"{"error":{"code":400,"message":"Request contains an invalid argument.","status":"INVALID_ARGUMENT"}}"#;
+        assert_eq!(
+            extract_message(google).unwrap(),
+            "Request contains an invalid argument."
+        );
+        assert_eq!(
+            extract_message(b"All accounts exhausted").unwrap(),
+            "All accounts exhausted"
+        );
+        assert_eq!(extract_message(br#"{"error":"denied"}"#).unwrap(), "denied");
+        assert_eq!(extract_message(b"  "), None);
 
-        (status, Json(body)).into_response()
+        let upstream =
+            ProxyError::upstream(StatusCode::NOT_FOUND, std::str::from_utf8(google).unwrap());
+        assert_eq!(upstream.message, "Request contains an invalid argument.");
+        assert_eq!(upstream.kind, ErrorKind::NotFound);
     }
 }
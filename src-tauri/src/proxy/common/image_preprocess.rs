@@ -0,0 +1,417 @@
+// 图片预处理
+// 客户端常以 base64 直接发送全分辨率截图，既放大请求体也增加图片 token 消耗，偶尔还会超出上游限制。
+// 转发前对内联图片 (Claude `image` 块、OpenAI `image_url` data URL、Gemini `inlineData`)
+// 按配置限制最大尺寸并以目标格式/质量重新编码 (默认 JPEG)，上游不支持的格式 (BMP/ICO) 一并转换。
+
+use crate::proxy::config::{get_image_preprocess_config, ImageOutputFormat, ImagePreprocessConfig};
+use base64::Engine as _;
+use image::{imageops::FilterType, DynamicImage, ImageFormat};
+use serde_json::Value;
+use std::future::Future;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+tokio::task_local! {
+    /// 当前请求的图片节省统计 (由 monitor 中间件设置作用域)
+    static REQUEST_IMAGE_SAVINGS: Arc<ImageSavings>;
+}
+
+/// 单个请求的图片预处理节省量
+#[derive(Debug, Default)]
+pub struct ImageSavings {
+    images: AtomicU32,
+    bytes: AtomicU32,
+    tokens: AtomicU32,
+}
+
+impl ImageSavings {
+    /// 被重新编码的图片数
+    pub fn images(&self) -> u32 {
+        self.images.load(Ordering::Relaxed)
+    }
+
+    /// 节省的 (解码后) 字节数
+    pub fn bytes(&self) -> u32 {
+        self.bytes.load(Ordering::Relaxed)
+    }
+
+    /// 按上游图片计费规则估算节省的 token 数
+    pub fn tokens(&self) -> u32 {
+        self.tokens.load(Ordering::Relaxed)
+    }
+
+    fn add(&self, other: &ImageSavings) {
+        self.images.fetch_add(other.images(), Ordering::Relaxed);
+        self.bytes.fetch_add(other.bytes(), Ordering::Relaxed);
+        self.tokens.fetch_add(other.tokens(), Ordering::Relaxed);
+    }
+}
+
+/// 在请求作用域内执行 future，期间的图片预处理节省量会累计到 savings
+pub async fn scope_request<F: Future>(savings: Arc<ImageSavings>, fut: F) -> F::Output {
+    REQUEST_IMAGE_SAVINGS.scope(savings, fut).await
+}
+
+/// 按全局配置预处理请求体中的全部内联图片 (三种协议的请求格式均可直接传入)
+pub fn preprocess_request_images(body: &mut Value, trace_id: &str) {
+    let cfg = get_image_preprocess_config();
+    if !cfg.enabled {
+        return;
+    }
+    let savings = run_cpu_bound(|| preprocess_images(body, &cfg));
+    if savings.images() == 0 {
+        return;
+    }
+    tracing::info!(
+        "[{}] [ImagePreprocess] Re-encoded {} image(s), saved {} bytes / ~{} tokens",
+        trace_id,
+        savings.images(),
+        savings.bytes(),
+        savings.tokens()
+    );
+    let _ = REQUEST_IMAGE_SAVINGS.try_with(|total| total.add(&savings));
+}
+
+/// 解码与编码是 CPU 密集操作，多线程运行时中让出当前 worker，避免阻塞其他请求
+fn run_cpu_bound<T>(f: impl FnOnce() -> T) -> T {
+    let multi_thread = tokio::runtime::Handle::try_current()
+        .map(|h| h.runtime_flavor() == tokio::runtime::RuntimeFlavor::MultiThread)
+        .unwrap_or(false);
+    if multi_thread {
+        tokio::task::block_in_place(f)
+    } else {
+        f()
+    }
+}
+
+/// 递归遍历 JSON，原地替换可处理的内联图片
+pub fn preprocess_images(body: &mut Value, cfg: &ImagePreprocessConfig) -> ImageSavings {
+    let savings = ImageSavings::default();
+    walk(body, cfg, &savings);
+    savings
+}
+
+fn walk(value: &mut Value, cfg: &ImagePreprocessConfig, savings: &ImageSavings) {
+    match value {
+        Value::Object(map) => {
+            // Gemini: { "inlineData": { "mimeType", "data" } }
+            for key in ["inlineData", "inline_data"] {
+                if let Some(inline) = map.get_mut(key).and_then(|v| v.as_object_mut()) {
+                    let mime_key = if inline.contains_key("mimeType") { "mimeType" } else { "mime_type" };
+                    replace_base64_fields(inline, mime_key, "data", cfg, savings);
+                }
+            }
+
+            // Claude: { "type": "image", "source": { "type": "base64", "media_type", "data" } }
+            if map.get("type").and_then(|t| t.as_str()) == Some("image") {
+                if let Some(source) = map.get_mut("source").and_then(|v| v.as_object_mut()) {
+                    if source.get("type").and_then(|t| t.as_str()) == Some("base64") {
+                        replace_base64_fields(source, "media_type", "data", cfg, savings);
+                    }
+                }
+            }
+
+            // OpenAI: { "image_url": { "url": "data:..." } } 或 Responses 格式 { "image_url": "data:..." }
+            if let Some(image_url) = map.get_mut("image_url") {
+                let url = match image_url {
+                    Value::Object(obj) => obj.get_mut("url"),
+                    other => Some(other),
+                };
+                if let Some(url) = url {
+                    if let Some(new_url) = url.as_str().and_then(|u| process_data_url(u, cfg, savings)) {
+                        *url = Value::String(new_url);
+                    }
+                }
+            }
+
+            for child in map.values_mut() {
+                walk(child, cfg, savings);
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                walk(item, cfg, savings);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn replace_base64_fields(
+    obj: &mut serde_json::Map<String, Value>,
+    mime_key: &str,
+    data_key: &str,
+    cfg: &ImagePreprocessConfig,
+    savings: &ImageSavings,
+) {
+    let Some(data) = obj.get(data_key).and_then(|d| d.as_str()) else {
+        return;
+    };
+    if let Some(out) = process_image(data, cfg, savings) {
+        obj.insert(mime_key.to_string(), Value::String(out.mime_type.to_string()));
+        obj.insert(data_key.to_string(), Value::String(out.data));
+    }
+}
+
+fn process_data_url(url: &str, cfg: &ImagePreprocessConfig, savings: &ImageSavings) -> Option<String> {
+    let rest = url.strip_prefix("data:image/")?;
+    let (_, data) = rest.split_once(";base64,")?;
+    let out = process_image(data, cfg, savings)?;
+    Some(format!("data:{};base64,{}", out.mime_type, out.data))
+}
+
+struct ProcessedImage {
+    mime_type: &'static str,
+    data: String,
+}
+
+/// 处理单张 base64 图片；无需改动或无法解码时返回 None
+fn process_image(data: &str, cfg: &ImagePreprocessConfig, savings: &ImageSavings) -> Option<ProcessedImage> {
+    let raw = base64::engine::general_purpose::STANDARD.decode(data.trim()).ok()?;
+    let format = image::guess_format(&raw).ok()?;
+    // 仅处理已启用解码器的格式，其余 (GIF 等) 原样转发
+    let needs_convert = match format {
+        ImageFormat::Png | ImageFormat::Jpeg | ImageFormat::WebP => false,
+        ImageFormat::Bmp | ImageFormat::Ico => true,
+        _ => return None,
+    };
+    if !needs_convert && raw.len() < cfg.min_bytes && !exceeds_limits_hint(&raw, format, cfg) {
+        return None;
+    }
+
+    let img = match image::load_from_memory_with_format(&raw, format) {
+        Ok(img) => img,
+        Err(e) => {
+            tracing::debug!("[ImagePreprocess] Failed to decode {:?} image: {}", format, e);
+            return None;
+        }
+    };
+    let (orig_w, orig_h) = (img.width(), img.height());
+    let resized = orig_w > cfg.max_width || orig_h > cfg.max_height;
+    let img = if resized {
+        img.resize(cfg.max_width.max(1), cfg.max_height.max(1), FilterType::Triangle)
+    } else {
+        img
+    };
+
+    let (encoded, mime_type) = match encode(&img, cfg.output_format, cfg.quality) {
+        Ok(out) => out,
+        Err(e) => {
+            tracing::warn!("[ImagePreprocess] Failed to encode image: {}", e);
+            return None;
+        }
+    };
+    // 仅重新编码但没有变小时保留原图
+    if !resized && !needs_convert && encoded.len() >= raw.len() {
+        return None;
+    }
+
+    let saved_tokens = estimate_image_tokens(orig_w, orig_h)
+        .saturating_sub(estimate_image_tokens(img.width(), img.height()));
+    let saved_bytes = raw.len().saturating_sub(encoded.len());
+    tracing::debug!(
+        "[ImagePreprocess] {:?} {}x{} ({} bytes) -> {} {}x{} ({} bytes)",
+        format,
+        orig_w,
+        orig_h,
+        raw.len(),
+        mime_type,
+        img.width(),
+        img.height(),
+        encoded.len()
+    );
+    savings.images.fetch_add(1, Ordering::Relaxed);
+    savings.bytes.fetch_add(saved_bytes.min(u32::MAX as usize) as u32, Ordering::Relaxed);
+    savings.tokens.fetch_add(saved_tokens, Ordering::Relaxed);
+
+    Some(ProcessedImage {
+        mime_type,
+        data: base64::engine::general_purpose::STANDARD.encode(encoded),
+    })
+}
+
+/// 小图片只有在尺寸超限时才需要解码；先读取文件头中的尺寸，避免对每张小图完整解码
+fn exceeds_limits_hint(raw: &[u8], format: ImageFormat, cfg: &ImagePreprocessConfig) -> bool {
+    image::ImageReader::with_format(std::io::Cursor::new(raw), format)
+        .into_dimensions()
+        .map(|(w, h)| w > cfg.max_width || h > cfg.max_height)
+        .unwrap_or(false)
+}
+
+fn encode(img: &DynamicImage, format: ImageOutputFormat, quality: u8) -> Result<(Vec<u8>, &'static str), String> {
+    // 编码器仅接受 RGB8 / RGBA8；带 alpha 通道但全部不透明的图片 (常见于截图) 按 RGB 处理
+    let transparent = img.color().has_alpha() && img.to_rgba8().pixels().any(|p| p[3] != u8::MAX);
+    let img = if transparent {
+        DynamicImage::ImageRgba8(img.to_rgba8())
+    } else {
+        DynamicImage::ImageRgb8(img.to_rgb8())
+    };
+    // JPEG 不支持透明度，改用无损 WebP
+    let format = match format {
+        ImageOutputFormat::Jpeg if transparent => ImageOutputFormat::Webp,
+        other => other,
+    };
+    let mut out = Vec::new();
+    match format {
+        ImageOutputFormat::Jpeg => {
+            img.write_with_encoder(image::codecs::jpeg::JpegEncoder::new_with_quality(
+                &mut out,
+                quality.clamp(1, 100),
+            ))
+            .map_err(|e| e.to_string())?;
+            Ok((out, "image/jpeg"))
+        }
+        ImageOutputFormat::Webp => {
+            img.write_with_encoder(image::codecs::webp::WebPEncoder::new_lossless(&mut out))
+                .map_err(|e| e.to_string())?;
+            Ok((out, "image/webp"))
+        }
+        ImageOutputFormat::Png => {
+            img.write_with_encoder(image::codecs::png::PngEncoder::new(&mut out))
+                .map_err(|e| e.to_string())?;
+            Ok((out, "image/png"))
+        }
+    }
+}
+
+/// 估算上游对单张图片收取的 token 数
+/// 两边均不超过 384 像素时固定 258；否则按短边 / 1.5 (限制在 256-768) 切块，每块 258
+pub fn estimate_image_tokens(width: u32, height: u32) -> u32 {
+    if width == 0 || height == 0 {
+        return 0;
+    }
+    if width <= 384 && height <= 384 {
+        return 258;
+    }
+    let unit = ((width.min(height) as f64 / 1.5) as u32).clamp(256, 768);
+    258 * width.div_ceil(unit) * height.div_ceil(unit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn encode_b64(img: DynamicImage, format: ImageFormat) -> String {
+        let mut out = std::io::Cursor::new(Vec::new());
+        img.write_to(&mut out, format).unwrap();
+        base64::engine::general_purpose::STANDARD.encode(out.into_inner())
+    }
+
+    fn decode_dims(data: &str) -> (u32, u32) {
+        let raw = base64::engine::general_purpose::STANDARD.decode(data).unwrap();
+        let img = image::load_from_memory(&raw).unwrap();
+        (img.width(), img.height())
+    }
+
+    fn enabled_cfg() -> ImagePreprocessConfig {
+        ImagePreprocessConfig {
+            enabled: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_claude_image_is_downscaled() {
+        let png = encode_b64(DynamicImage::new_rgb8(4000, 3000), ImageFormat::Png);
+        let mut body = json!({
+            "messages": [{"role": "user", "content": [
+                {"type": "image", "source": {"type": "base64", "media_type": "image/png", "data": png}}
+            ]}]
+        });
+        let savings = preprocess_images(&mut body, &enabled_cfg());
+
+        let source = &body["messages"][0]["content"][0]["source"];
+        assert_eq!(source["media_type"], "image/jpeg");
+        assert_eq!(decode_dims(source["data"].as_str().unwrap()), (2048, 1536));
+        assert_eq!(savings.images(), 1);
+        // 4000x3000 => 6x4 块，2048x1536 => 3x2 块
+        assert_eq!(savings.tokens(), 258 * 18);
+    }
+
+    #[test]
+    fn test_openai_bmp_data_url_is_converted() {
+        let bmp = encode_b64(DynamicImage::new_rgb8(8, 8), ImageFormat::Bmp);
+        let mut body = json!({
+            "messages": [{"role": "user", "content": [
+                {"type": "image_url", "image_url": {"url": format!("data:image/bmp;base64,{}", bmp)}}
+            ]}]
+        });
+        let cfg = ImagePreprocessConfig {
+            output_format: ImageOutputFormat::Png,
+            ..enabled_cfg()
+        };
+        let savings = preprocess_images(&mut body, &cfg);
+
+        let url = body["messages"][0]["content"][0]["image_url"]["url"].as_str().unwrap();
+        let data = url.strip_prefix("data:image/png;base64,").expect("converted to png");
+        assert_eq!(decode_dims(data), (8, 8));
+        assert_eq!(savings.images(), 1);
+        assert_eq!(savings.tokens(), 0);
+    }
+
+    #[test]
+    fn test_jpeg_input_is_recompressed_at_quality() {
+        // 噪声图片在高质量下体积较大，低质量重新编码后应明显变小
+        let mut img = image::RgbImage::new(512, 512);
+        for (x, y, p) in img.enumerate_pixels_mut() {
+            let v = (x.wrapping_mul(31) ^ y.wrapping_mul(17)).wrapping_mul(2654435761) >> 24;
+            *p = image::Rgb([v as u8, (v >> 1) as u8, (v >> 2) as u8]);
+        }
+        let mut raw = Vec::new();
+        DynamicImage::ImageRgb8(img)
+            .write_with_encoder(image::codecs::jpeg::JpegEncoder::new_with_quality(&mut raw, 100))
+            .unwrap();
+        let jpeg = base64::engine::general_purpose::STANDARD.encode(&raw);
+        let mut body = json!({
+            "contents": [{"role": "user", "parts": [{"inlineData": {"mimeType": "image/jpeg", "data": jpeg}}]}]
+        });
+        let cfg = ImagePreprocessConfig {
+            quality: 40,
+            min_bytes: 0,
+            ..enabled_cfg()
+        };
+        let savings = preprocess_images(&mut body, &cfg);
+
+        let inline = &body["contents"][0]["parts"][0]["inlineData"];
+        assert_eq!(inline["mimeType"], "image/jpeg");
+        assert_eq!(decode_dims(inline["data"].as_str().unwrap()), (512, 512));
+        assert_eq!(savings.images(), 1);
+        assert!(savings.bytes() > 0);
+    }
+
+    #[test]
+    fn test_transparent_image_is_not_encoded_as_jpeg() {
+        let png = encode_b64(DynamicImage::new_rgba8(3000, 100), ImageFormat::Png);
+        let mut body = json!({
+            "contents": [{"role": "user", "parts": [{"inlineData": {"mimeType": "image/png", "data": png}}]}]
+        });
+        preprocess_images(&mut body, &enabled_cfg());
+
+        let inline = &body["contents"][0]["parts"][0]["inlineData"];
+        assert_eq!(inline["mimeType"], "image/webp");
+        assert_eq!(decode_dims(inline["data"].as_str().unwrap()), (2048, 68));
+    }
+
+    #[test]
+    fn test_small_gemini_image_is_untouched() {
+        let png = encode_b64(DynamicImage::new_rgb8(64, 64), ImageFormat::Png);
+        let mut body = json!({
+            "contents": [{"role": "user", "parts": [{"inlineData": {"mimeType": "image/png", "data": png.clone()}}]}]
+        });
+        let savings = preprocess_images(&mut body, &enabled_cfg());
+
+        assert_eq!(body["contents"][0]["parts"][0]["inlineData"]["data"], png);
+        assert_eq!(savings.images(), 0);
+    }
+
+    #[test]
+    fn test_estimate_image_tokens() {
+        assert_eq!(estimate_image_tokens(300, 200), 258);
+        // 短边 1000 -> 切块 666，3000x1000 => 5x2 块
+        assert_eq!(estimate_image_tokens(3000, 1000), 258 * 10);
+        // 短边 683 -> 切块 455，2048x683 => 5x2 块
+        assert_eq!(estimate_image_tokens(2048, 683), 258 * 10);
+        assert_eq!(estimate_image_tokens(4000, 4000), 258 * 36);
+    }
+}
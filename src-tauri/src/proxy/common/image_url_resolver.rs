@@ -0,0 +1,501 @@
+// 图片 URL 解析
+// OpenAI `image_url` 可以是 http(s) 地址或本地文件路径。转发前统一解析为 data URL：
+// - 远程图片限制大小与超时，按 URL 缓存，并拒绝解析到内网/回环地址的目标 (SSRF)
+// - 本地文件仅允许白名单目录，且默认只对本机客户端开放
+// - MIME 类型按文件头魔数识别，而不是信任扩展名或响应头
+
+use crate::proxy::config::{get_image_url_config, ImageUrlConfig};
+use axum::http::HeaderMap;
+use base64::Engine as _;
+use once_cell::sync::Lazy;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const MAX_REDIRECTS: usize = 3;
+
+struct CachedImage {
+    data_url: Arc<String>,
+    fetched_at: Instant,
+}
+
+/// 远程图片缓存 (key: URL)
+static REMOTE_CACHE: Lazy<Mutex<HashMap<String, CachedImage>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// 请求是否来自本机：TCP 对端为回环地址且没有经过反向代理 (cloudflared、nginx 等会从本机转发外部请求)
+pub fn is_local_client(peer: Option<SocketAddr>, headers: &HeaderMap) -> bool {
+    let forwarded = ["x-forwarded-for", "x-real-ip", "forwarded", "cf-connecting-ip"]
+        .iter()
+        .any(|h| headers.contains_key(*h));
+    !forwarded && peer.map_or(false, |addr| addr.ip().is_loopback())
+}
+
+/// 把请求体中所有非 data: 的 `image_url` 解析为 data URL；任意一张失败时返回错误信息
+pub async fn resolve_image_urls(body: &mut Value, local_client: bool, trace_id: &str) -> Result<(), String> {
+    let mut urls = HashSet::new();
+    collect_urls(body, &mut urls);
+    if urls.is_empty() {
+        return Ok(());
+    }
+
+    let cfg = get_image_url_config();
+    let mut resolved = HashMap::new();
+    for url in urls {
+        let data_url = resolve_url(&url, &cfg, local_client)
+            .await
+            .map_err(|e| format!("Failed to load image_url '{}': {}", truncate_url(&url), e))?;
+        tracing::debug!(
+            "[{}] [ImageUrl] Resolved {} ({} chars)",
+            trace_id,
+            truncate_url(&url),
+            data_url.len()
+        );
+        resolved.insert(url, data_url);
+    }
+    replace_urls(body, &resolved);
+    Ok(())
+}
+
+fn needs_resolution(url: &str) -> bool {
+    !url.is_empty() && !url.starts_with("data:")
+}
+
+/// `image_url` 字段可能是 { "url": ... } 对象 (Chat) 或直接是字符串 (Responses input_image)
+fn image_url_slot(map: &mut serde_json::Map<String, Value>) -> Option<&mut Value> {
+    match map.get_mut("image_url")? {
+        Value::Object(obj) => obj.get_mut("url"),
+        other => Some(other),
+    }
+}
+
+fn collect_urls(value: &Value, urls: &mut HashSet<String>) {
+    match value {
+        Value::Object(map) => {
+            let url = match map.get("image_url") {
+                Some(Value::Object(obj)) => obj.get("url").and_then(|u| u.as_str()),
+                Some(other) => other.as_str(),
+                None => None,
+            };
+            if let Some(url) = url.filter(|u| needs_resolution(u)) {
+                urls.insert(url.to_string());
+            }
+            map.values().for_each(|child| collect_urls(child, urls));
+        }
+        Value::Array(items) => items.iter().for_each(|item| collect_urls(item, urls)),
+        _ => {}
+    }
+}
+
+fn replace_urls(value: &mut Value, resolved: &HashMap<String, Arc<String>>) {
+    match value {
+        Value::Object(map) => {
+            if let Some(slot) = image_url_slot(map) {
+                if let Some(data_url) = slot.as_str().and_then(|u| resolved.get(u)) {
+                    *slot = Value::String(data_url.to_string());
+                }
+            }
+            map.values_mut().for_each(|child| replace_urls(child, resolved));
+        }
+        Value::Array(items) => items.iter_mut().for_each(|item| replace_urls(item, resolved)),
+        _ => {}
+    }
+}
+
+async fn resolve_url(url: &str, cfg: &ImageUrlConfig, local_client: bool) -> Result<Arc<String>, String> {
+    let lower = url.to_ascii_lowercase();
+    if lower.starts_with("http://") || lower.starts_with("https://") {
+        fetch_remote(url, cfg).await
+    } else {
+        read_local(url, cfg, local_client).await.map(Arc::new)
+    }
+}
+
+// ===== 远程图片 =====
+
+async fn fetch_remote(url: &str, cfg: &ImageUrlConfig) -> Result<Arc<String>, String> {
+    if let Some(hit) = cache_get(url, cfg) {
+        return Ok(hit);
+    }
+
+    let timeout = Duration::from_secs(cfg.timeout_secs.max(1));
+    let bytes = tokio::time::timeout(timeout, download(url, cfg, timeout))
+        .await
+        .map_err(|_| format!("download timed out after {}s", timeout.as_secs()))??;
+    let mime = sniff_image_mime(&bytes).ok_or("content is not a supported image format")?;
+    let data_url = Arc::new(to_data_url(mime, &bytes));
+
+    cache_put(url, data_url.clone(), cfg);
+    Ok(data_url)
+}
+
+/// 手动跟随重定向，每一跳都重新做地址检查并把连接固定到检查过的 IP (防止 DNS rebinding)
+async fn download(url: &str, cfg: &ImageUrlConfig, timeout: Duration) -> Result<Vec<u8>, String> {
+    let mut current = reqwest::Url::parse(url).map_err(|e| format!("invalid URL: {}", e))?;
+
+    for _ in 0..=MAX_REDIRECTS {
+        let host = current.host_str().ok_or("URL has no host")?.to_string();
+        let port = current.port_or_known_default().ok_or("URL has no port")?;
+        let addrs = resolve_allowed_addrs(&host, port, cfg.allow_private_networks).await?;
+
+        let client = reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .no_proxy()
+            .timeout(timeout)
+            .resolve_to_addrs(host.trim_start_matches('[').trim_end_matches(']'), &addrs)
+            .build()
+            .map_err(|e| e.to_string())?;
+        let mut resp = client
+            .get(current.clone())
+            .header(reqwest::header::ACCEPT, "image/*")
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        if resp.status().is_redirection() {
+            let location = resp
+                .headers()
+                .get(reqwest::header::LOCATION)
+                .and_then(|v| v.to_str().ok())
+                .ok_or("redirect without Location header")?;
+            current = current.join(location).map_err(|e| format!("invalid redirect: {}", e))?;
+            if !matches!(current.scheme(), "http" | "https") {
+                return Err(format!("redirect to unsupported scheme '{}'", current.scheme()));
+            }
+            continue;
+        }
+        if !resp.status().is_success() {
+            return Err(format!("upstream returned HTTP {}", resp.status()));
+        }
+        if resp.content_length().map_or(false, |len| len as usize > cfg.max_bytes) {
+            return Err(format!("image exceeds {} bytes", cfg.max_bytes));
+        }
+
+        let mut body = Vec::new();
+        while let Some(chunk) = resp.chunk().await.map_err(|e| e.to_string())? {
+            if body.len() + chunk.len() > cfg.max_bytes {
+                return Err(format!("image exceeds {} bytes", cfg.max_bytes));
+            }
+            body.extend_from_slice(&chunk);
+        }
+        return Ok(body);
+    }
+    Err(format!("too many redirects (> {})", MAX_REDIRECTS))
+}
+
+async fn resolve_allowed_addrs(host: &str, port: u16, allow_private: bool) -> Result<Vec<SocketAddr>, String> {
+    let bare = host.trim_start_matches('[').trim_end_matches(']');
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host((bare, port))
+        .await
+        .map_err(|e| format!("DNS lookup failed: {}", e))?
+        .collect();
+    if addrs.is_empty() {
+        return Err("DNS lookup returned no addresses".to_string());
+    }
+    // 任一解析结果指向内网即拒绝，避免客户端借助多 A 记录绕过检查
+    if !allow_private {
+        if let Some(blocked) = addrs.iter().find(|addr| !is_public_ip(addr.ip())) {
+            return Err(format!("target address {} is not publicly routable", blocked.ip()));
+        }
+    }
+    Ok(addrs)
+}
+
+/// 是否为公网可路由地址
+pub fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => {
+            let [a, b, ..] = v4.octets();
+            !(v4.is_private()
+                || v4.is_loopback()
+                || v4.is_link_local()
+                || v4.is_broadcast()
+                || v4.is_documentation()
+                || v4.is_unspecified()
+                || v4.is_multicast()
+                || a == 0
+                || (a == 100 && (b & 0xc0) == 64) // 100.64.0.0/10 运营商级 NAT
+                || (a == 198 && (b & 0xfe) == 18) // 198.18.0.0/15 基准测试
+                || a >= 240)
+        }
+        IpAddr::V6(v6) => {
+            if let Some(v4) = embedded_ipv4(v6) {
+                return is_public_ip(IpAddr::V4(v4));
+            }
+            let seg = v6.segments();
+            !(v6.is_loopback()
+                || v6.is_unspecified()
+                || v6.is_multicast()
+                || (seg[0] & 0xfe00) == 0xfc00 // fc00::/7 唯一本地地址
+                || (seg[0] & 0xffc0) == 0xfe80 // fe80::/10 链路本地
+                || (seg[0] == 0x2001 && seg[1] == 0x0db8)) // 文档地址
+        }
+    }
+}
+
+/// 提取 IPv6 地址中内嵌的 IPv4 地址，这些地址最终会被转发到对应的 IPv4 目标，需要按 IPv4 规则检查
+fn embedded_ipv4(v6: Ipv6Addr) -> Option<Ipv4Addr> {
+    let seg = v6.segments();
+    let from_segments = |hi: u16, lo: u16| Ipv4Addr::from(((hi as u32) << 16) | lo as u32);
+    match seg {
+        // ::ffff:a.b.c.d 映射地址 与 ::a.b.c.d 兼容地址 (:: 与 ::1 由 IPv6 规则处理)
+        [0, 0, 0, 0, 0, 0xffff, ..] => v6.to_ipv4_mapped(),
+        [0, 0, 0, 0, 0, 0, hi, lo] if hi != 0 => Some(from_segments(hi, lo)),
+        // 64:ff9b::/96 NAT64 知名前缀
+        [0x64, 0xff9b, 0, 0, 0, 0, hi, lo] => Some(from_segments(hi, lo)),
+        // 2002::/16 6to4
+        [0x2002, hi, lo, ..] => Some(from_segments(hi, lo)),
+        // 2001:0::/32 Teredo，客户端地址按位取反存放在末尾
+        [0x2001, 0, _, _, _, _, hi, lo] => Some(from_segments(!hi, !lo)),
+        _ => None,
+    }
+}
+
+fn cache_get(url: &str, cfg: &ImageUrlConfig) -> Option<Arc<String>> {
+    if cfg.cache_ttl_secs == 0 {
+        return None;
+    }
+    let cache = REMOTE_CACHE.lock().ok()?;
+    cache
+        .get(url)
+        .filter(|entry| entry.fetched_at.elapsed() < Duration::from_secs(cfg.cache_ttl_secs))
+        .map(|entry| entry.data_url.clone())
+}
+
+fn cache_put(url: &str, data_url: Arc<String>, cfg: &ImageUrlConfig) {
+    if cfg.cache_ttl_secs == 0 || cfg.cache_max_entries == 0 {
+        return;
+    }
+    let Ok(mut cache) = REMOTE_CACHE.lock() else {
+        return;
+    };
+    let ttl = Duration::from_secs(cfg.cache_ttl_secs);
+    cache.retain(|_, entry| entry.fetched_at.elapsed() < ttl);
+    while cache.len() >= cfg.cache_max_entries {
+        let oldest = cache
+            .iter()
+            .min_by_key(|(_, entry)| entry.fetched_at)
+            .map(|(key, _)| key.clone());
+        match oldest {
+            Some(key) => cache.remove(&key),
+            None => break,
+        };
+    }
+    cache.insert(
+        url.to_string(),
+        CachedImage {
+            data_url,
+            fetched_at: Instant::now(),
+        },
+    );
+}
+
+// ===== 本地文件 =====
+
+async fn read_local(url: &str, cfg: &ImageUrlConfig, local_client: bool) -> Result<String, String> {
+    if cfg.local_file_dirs.is_empty() {
+        return Err("local image files are disabled (no allowed directories configured)".to_string());
+    }
+    if !local_client && !cfg.allow_remote_local_files {
+        return Err("local image files are only available to clients on this machine".to_string());
+    }
+
+    let path = local_path(url)?;
+    let canonical = tokio::fs::canonicalize(&path)
+        .await
+        .map_err(|e| format!("cannot access file: {}", e))?;
+    if !is_within_allowed_dirs(&canonical, &cfg.local_file_dirs) {
+        return Err("file is outside the allowed directories".to_string());
+    }
+
+    let meta = tokio::fs::metadata(&canonical).await.map_err(|e| e.to_string())?;
+    if !meta.is_file() {
+        return Err("not a regular file".to_string());
+    }
+    if meta.len() as usize > cfg.max_bytes {
+        return Err(format!("image exceeds {} bytes", cfg.max_bytes));
+    }
+    let bytes = tokio::fs::read(&canonical).await.map_err(|e| e.to_string())?;
+    let mime = sniff_image_mime(&bytes).ok_or("content is not a supported image format")?;
+    Ok(to_data_url(mime, &bytes))
+}
+
+fn local_path(url: &str) -> Result<PathBuf, String> {
+    if url.to_ascii_lowercase().starts_with("file:") {
+        let parsed = url::Url::parse(url).map_err(|e| format!("invalid file URL: {}", e))?;
+        parsed.to_file_path().map_err(|_| "invalid file URL".to_string())
+    } else {
+        Ok(PathBuf::from(url))
+    }
+}
+
+/// 路径需已规范化 (解析符号链接与 ..)，白名单目录同样规范化后比较
+fn is_within_allowed_dirs(canonical: &Path, dirs: &[String]) -> bool {
+    dirs.iter()
+        .filter_map(|dir| std::fs::canonicalize(dir).ok())
+        .any(|dir| canonical.starts_with(dir))
+}
+
+// ===== 工具函数 =====
+
+/// 按文件头魔数识别图片 MIME 类型
+pub fn sniff_image_mime(bytes: &[u8]) -> Option<&'static str> {
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if bytes.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some("image/jpeg")
+    } else if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        Some("image/gif")
+    } else if bytes.len() >= 12 && &bytes[0..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+        Some("image/webp")
+    } else if bytes.starts_with(b"BM") && bytes.len() >= 26 {
+        Some("image/bmp")
+    } else if bytes.starts_with(&[0x00, 0x00, 0x01, 0x00]) {
+        Some("image/x-icon")
+    } else if bytes.len() >= 12 && &bytes[4..8] == b"ftyp" {
+        match &bytes[8..12] {
+            b"heic" | b"heix" | b"hevc" | b"heim" | b"heis" => Some("image/heic"),
+            b"mif1" | b"msf1" | b"heif" => Some("image/heif"),
+            b"avif" | b"avis" => Some("image/avif"),
+            _ => None,
+        }
+    } else {
+        None
+    }
+}
+
+fn to_data_url(mime: &str, bytes: &[u8]) -> String {
+    format!(
+        "data:{};base64,{}",
+        mime,
+        base64::engine::general_purpose::STANDARD.encode(bytes)
+    )
+}
+
+/// 错误信息中只保留 URL 前缀，避免回显超长或带签名的地址
+fn truncate_url(url: &str) -> String {
+    const MAX: usize = 120;
+    match url.char_indices().nth(MAX) {
+        Some((idx, _)) => format!("{}...", &url[..idx]),
+        None => url.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_is_public_ip() {
+        for blocked in ["127.0.0.1", "10.1.2.3", "172.16.0.1", "192.168.1.1", "169.254.169.254", "100.64.0.1", "0.0.0.0", "::1", "fd00::1", "fe80::1", "::ffff:127.0.0.1"] {
+            assert!(!is_public_ip(blocked.parse().unwrap()), "{} should be blocked", blocked);
+        }
+        for allowed in ["8.8.8.8", "142.250.72.14", "2606:4700:4700::1111"] {
+            assert!(is_public_ip(allowed.parse().unwrap()), "{} should be allowed", allowed);
+        }
+    }
+
+    #[test]
+    fn test_is_public_ip_checks_embedded_ipv4() {
+        for blocked in [
+            "64:ff9b::7f00:1",        // NAT64 -> 127.0.0.1
+            "64:ff9b::a9fe:a9fe",     // NAT64 -> 169.254.169.254
+            "2002:a00:1::1",          // 6to4 -> 10.0.0.1
+            "2002:c0a8:101::",        // 6to4 -> 192.168.1.1
+            "::127.0.0.1",            // IPv4 兼容地址
+            "::a9fe:a9fe",            // IPv4 兼容地址 -> 169.254.169.254
+            "2001:0:4136:e378:8000:63bf:f5ff:fffe", // Teredo -> 10.0.0.1
+        ] {
+            assert!(!is_public_ip(blocked.parse().unwrap()), "{} should be blocked", blocked);
+        }
+        for allowed in ["64:ff9b::808:808", "2002:808:808::1", "::8.8.8.8"] {
+            assert!(is_public_ip(allowed.parse().unwrap()), "{} should be allowed", allowed);
+        }
+    }
+
+    #[test]
+    fn test_sniff_image_mime() {
+        assert_eq!(sniff_image_mime(b"\x89PNG\r\n\x1a\n\0\0"), Some("image/png"));
+        assert_eq!(sniff_image_mime(&[0xFF, 0xD8, 0xFF, 0xE0]), Some("image/jpeg"));
+        assert_eq!(sniff_image_mime(b"RIFF\0\0\0\0WEBPVP8 "), Some("image/webp"));
+        assert_eq!(sniff_image_mime(b"\0\0\0\x18ftypheic\0\0"), Some("image/heic"));
+        assert_eq!(sniff_image_mime(b"<html>not an image</html>"), None);
+    }
+
+    #[test]
+    fn test_local_client_detection() {
+        let loopback: SocketAddr = "127.0.0.1:50000".parse().unwrap();
+        let lan: SocketAddr = "192.168.1.20:50000".parse().unwrap();
+        let mut headers = HeaderMap::new();
+        assert!(is_local_client(Some(loopback), &headers));
+        assert!(!is_local_client(Some(lan), &headers));
+        assert!(!is_local_client(None, &headers));
+
+        // 经本机反向代理转发的外部请求不算本机
+        headers.insert("x-forwarded-for", "203.0.113.9".parse().unwrap());
+        assert!(!is_local_client(Some(loopback), &headers));
+    }
+
+    #[tokio::test]
+    async fn test_private_targets_are_rejected() {
+        let cfg = ImageUrlConfig::default();
+        let err = fetch_remote("http://127.0.0.1:9/secret.png", &cfg).await.unwrap_err();
+        assert!(err.contains("not publicly routable"), "{}", err);
+        let err = fetch_remote("http://169.254.169.254/latest/meta-data", &cfg).await.unwrap_err();
+        assert!(err.contains("not publicly routable"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_local_files_respect_allowlist_and_client() {
+        let dir = std::env::temp_dir().join(format!("ag-image-url-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("shot.bin");
+        std::fs::write(&file, b"\x89PNG\r\n\x1a\nrest").unwrap();
+        let url = url::Url::from_file_path(&file).unwrap().to_string();
+
+        let disabled = ImageUrlConfig::default();
+        assert!(read_local(&url, &disabled, true).await.unwrap_err().contains("disabled"));
+
+        let cfg = ImageUrlConfig {
+            local_file_dirs: vec![dir.to_string_lossy().to_string()],
+            ..Default::default()
+        };
+        assert!(read_local(&url, &cfg, false).await.is_err());
+        let data_url = read_local(&url, &cfg, true).await.unwrap();
+        assert!(data_url.starts_with("data:image/png;base64,"));
+
+        let outside = ImageUrlConfig {
+            local_file_dirs: vec![dir.join("sub").to_string_lossy().to_string()],
+            ..Default::default()
+        };
+        assert!(read_local(&url, &outside, true).await.is_err());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_replace_urls_handles_chat_and_responses_shapes() {
+        let mut body = json!({
+            "messages": [{"role": "user", "content": [
+                {"type": "image_url", "image_url": {"url": "https://example.com/a.png"}},
+                {"type": "input_image", "image_url": "https://example.com/a.png"},
+                {"type": "image_url", "image_url": {"url": "data:image/png;base64,AAAA"}}
+            ]}]
+        });
+        let mut urls = HashSet::new();
+        collect_urls(&body, &mut urls);
+        assert_eq!(urls.len(), 1);
+
+        let resolved = HashMap::from([(
+            "https://example.com/a.png".to_string(),
+            Arc::new("data:image/png;base64,BBBB".to_string()),
+        )]);
+        replace_urls(&mut body, &resolved);
+        let content = &body["messages"][0]["content"];
+        assert_eq!(content[0]["image_url"]["url"], "data:image/png;base64,BBBB");
+        assert_eq!(content[1]["image_url"], "data:image/png;base64,BBBB");
+        assert_eq!(content[2]["image_url"]["url"], "data:image/png;base64,AAAA");
+    }
+}
@@ -1,6 +1,6 @@
 // Common 模块 - 公共工具
 
-// pub mod error;
+pub mod error;
 // pub mod rate_limiter;
 pub mod model_mapping;
 pub mod utils;
@@ -10,3 +10,6 @@ pub mod tool_adapters;
 pub mod schema_cache;
 pub mod client_adapter;
 pub mod client_adapters;
+pub mod retry_budget;
+pub mod image_preprocess;
+pub mod image_url_resolver;
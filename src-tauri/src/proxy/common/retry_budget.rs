@@ -0,0 +1,156 @@
+// Retry Budget
+// 全局重试令牌桶 + 单请求重试计数
+//
+// 上游故障时所有在途请求各自重试会放大流量 (retry storm)。
+// 这里用一个进程级令牌桶限制重试总量：每个请求存入 ratio 个令牌，
+// 每秒保底补充 min_retries_per_sec 个，每次重试消耗 1 个。
+
+use crate::proxy::config::RetryBudgetConfig;
+use once_cell::sync::Lazy;
+use std::future::Future;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+tokio::task_local! {
+    /// 当前请求的重试次数 (由 monitor 中间件设置作用域)
+    static REQUEST_RETRIES: Arc<AtomicU32>;
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn new(initial: f64) -> Self {
+        Self {
+            tokens: initial,
+            last_refill: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self, cfg: &RetryBudgetConfig, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last_refill).as_secs_f64();
+        self.last_refill = now;
+        self.tokens = (self.tokens + elapsed * cfg.min_retries_per_sec as f64)
+            .min(cfg.max_tokens as f64);
+    }
+
+    fn deposit(&mut self, cfg: &RetryBudgetConfig, now: Instant) {
+        self.refill(cfg, now);
+        self.tokens = (self.tokens + cfg.ratio as f64).min(cfg.max_tokens as f64);
+    }
+
+    fn try_withdraw(&mut self, cfg: &RetryBudgetConfig, now: Instant) -> bool {
+        self.refill(cfg, now);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+static BUDGET: Lazy<Mutex<TokenBucket>> = Lazy::new(|| {
+    let cfg = crate::proxy::config::get_retry_policy_config().budget;
+    Mutex::new(TokenBucket::new(cfg.max_tokens as f64))
+});
+
+/// 记录一个新请求 (向预算存入令牌)
+pub fn record_request() {
+    let cfg = crate::proxy::config::get_retry_policy_config().budget;
+    if !cfg.enabled {
+        return;
+    }
+    if let Ok(mut bucket) = BUDGET.lock() {
+        bucket.deposit(&cfg, Instant::now());
+    }
+}
+
+/// 尝试为一次重试申请预算；预算关闭时总是成功
+pub fn try_acquire_retry() -> bool {
+    let cfg = crate::proxy::config::get_retry_policy_config().budget;
+    if !cfg.enabled {
+        return true;
+    }
+    BUDGET
+        .lock()
+        .map(|mut bucket| bucket.try_withdraw(&cfg, Instant::now()))
+        .unwrap_or(true)
+}
+
+/// 在请求作用域内执行 future，期间发生的重试会累计到 counter
+pub async fn scope_request<F: Future>(counter: Arc<AtomicU32>, fut: F) -> F::Output {
+    REQUEST_RETRIES.scope(counter, fut).await
+}
+
+/// 为当前请求累计一次重试 (不在请求作用域内时忽略)
+pub fn count_retry() {
+    let _ = REQUEST_RETRIES.try_with(|c| c.fetch_add(1, Ordering::Relaxed));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn cfg() -> RetryBudgetConfig {
+        RetryBudgetConfig {
+            enabled: true,
+            ratio: 0.5,
+            min_retries_per_sec: 0.0,
+            max_tokens: 2.0,
+        }
+    }
+
+    #[test]
+    fn test_bucket_caps_retries_to_ratio() {
+        let cfg = cfg();
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(0.0);
+
+        // 1 个请求只存入 0.5 个令牌，不足以重试
+        bucket.deposit(&cfg, now);
+        assert!(!bucket.try_withdraw(&cfg, now));
+
+        // 第 2 个请求后可以重试一次
+        bucket.deposit(&cfg, now);
+        assert!(bucket.try_withdraw(&cfg, now));
+        assert!(!bucket.try_withdraw(&cfg, now));
+
+        // 容量上限
+        for _ in 0..10 {
+            bucket.deposit(&cfg, now);
+        }
+        assert_eq!(bucket.tokens, 2.0);
+    }
+
+    #[test]
+    fn test_bucket_time_refill() {
+        let cfg = RetryBudgetConfig {
+            min_retries_per_sec: 2.0,
+            ..cfg()
+        };
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(0.0);
+        bucket.last_refill = start;
+        assert!(bucket.try_withdraw(&cfg, start + Duration::from_millis(600)));
+        assert!(!bucket.try_withdraw(&cfg, start + Duration::from_millis(700)));
+    }
+
+    #[tokio::test]
+    async fn test_retry_counter_scope() {
+        let counter = Arc::new(AtomicU32::new(0));
+        scope_request(counter.clone(), async {
+            count_retry();
+            count_retry();
+        })
+        .await;
+        // 作用域外调用不影响计数
+        count_retry();
+        assert_eq!(counter.load(Ordering::Relaxed), 2);
+    }
+}
@@ -122,6 +122,426 @@ pub fn update_image_thinking_mode(mode: Option<String>) {
     }
 }
 
+// ============================================================================
+// 全局日志保留策略配置存储
+// 用于在 monitor / proxy_db 中访问配置（无需层层传递）
+// ============================================================================
+static GLOBAL_LOG_RETENTION_CONFIG: OnceLock<RwLock<LogRetentionConfig>> = OnceLock::new();
+
+/// 获取当前日志保留策略配置
+pub fn get_log_retention_config() -> LogRetentionConfig {
+    GLOBAL_LOG_RETENTION_CONFIG
+        .get()
+        .and_then(|lock| lock.read().ok())
+        .map(|cfg| cfg.clone())
+        .unwrap_or_default()
+}
+
+/// 更新全局日志保留策略配置
+pub fn update_log_retention_config(config: LogRetentionConfig) {
+    if let Some(lock) = GLOBAL_LOG_RETENTION_CONFIG.get() {
+        if let Ok(mut cfg) = lock.write() {
+            *cfg = config.clone();
+            tracing::info!(
+                "[Log-Retention] Global config updated: request_logs_days={}, max_body_bytes={}, encrypt_bodies={}",
+                config.request_logs_days,
+                config.max_body_bytes,
+                config.encrypt_bodies
+            );
+        }
+    } else {
+        // 首次初始化
+        let _ = GLOBAL_LOG_RETENTION_CONFIG.set(RwLock::new(config.clone()));
+        tracing::info!(
+            "[Log-Retention] Global config initialized: request_logs_days={}, max_body_bytes={}, encrypt_bodies={}",
+            config.request_logs_days,
+            config.max_body_bytes,
+            config.encrypt_bodies
+        );
+    }
+}
+
+// ============================================================================
+// 全局上游端点配置存储
+// 用于在 upstream::client 中访问配置（无需层层传递）
+// ============================================================================
+static GLOBAL_UPSTREAM_ENDPOINTS_CONFIG: OnceLock<RwLock<UpstreamEndpointsConfig>> =
+    OnceLock::new();
+
+/// 获取当前上游端点配置
+pub fn get_upstream_endpoints_config() -> UpstreamEndpointsConfig {
+    GLOBAL_UPSTREAM_ENDPOINTS_CONFIG
+        .get()
+        .and_then(|lock| lock.read().ok())
+        .map(|cfg| cfg.clone())
+        .unwrap_or_default()
+}
+
+/// 更新全局上游端点配置
+pub fn update_upstream_endpoints_config(config: UpstreamEndpointsConfig) {
+    if let Some(lock) = GLOBAL_UPSTREAM_ENDPOINTS_CONFIG.get() {
+        if let Ok(mut cfg) = lock.write() {
+            *cfg = config.clone();
+            tracing::info!(
+                "[Upstream-Endpoints] Global config updated: endpoints={}, cooldown={}s",
+                config.endpoints.len(),
+                config.cooldown_secs
+            );
+        }
+    } else {
+        // 首次初始化
+        let _ = GLOBAL_UPSTREAM_ENDPOINTS_CONFIG.set(RwLock::new(config.clone()));
+        tracing::info!(
+            "[Upstream-Endpoints] Global config initialized: endpoints={}, cooldown={}s",
+            config.endpoints.len(),
+            config.cooldown_secs
+        );
+    }
+}
+
+// ============================================================================
+// 全局公平调度配置存储
+// 用于在 monitor 中间件中访问配置（无需层层传递）
+// ============================================================================
+static GLOBAL_FAIR_SCHEDULING_CONFIG: OnceLock<RwLock<FairSchedulingConfig>> = OnceLock::new();
+
+/// 获取当前公平调度配置
+pub fn get_fair_scheduling_config() -> FairSchedulingConfig {
+    GLOBAL_FAIR_SCHEDULING_CONFIG
+        .get()
+        .and_then(|lock| lock.read().ok())
+        .map(|cfg| cfg.clone())
+        .unwrap_or_default()
+}
+
+/// 更新全局公平调度配置
+pub fn update_fair_scheduling_config(config: FairSchedulingConfig) {
+    if let Some(lock) = GLOBAL_FAIR_SCHEDULING_CONFIG.get() {
+        if let Ok(mut cfg) = lock.write() {
+            *cfg = config.clone();
+            tracing::info!(
+                "[Fair-Share] Global config updated: enabled={}, max_concurrent_requests={}",
+                config.enabled,
+                config.max_concurrent_requests
+            );
+        }
+    } else {
+        // 首次初始化
+        let _ = GLOBAL_FAIR_SCHEDULING_CONFIG.set(RwLock::new(config.clone()));
+        tracing::info!(
+            "[Fair-Share] Global config initialized: enabled={}, max_concurrent_requests={}",
+            config.enabled,
+            config.max_concurrent_requests
+        );
+    }
+}
+
+// ============================================================================
+// 全局请求对冲配置存储
+// 用于在 handler 中访问配置（无需层层传递）
+// ============================================================================
+static GLOBAL_HEDGING_CONFIG: OnceLock<RwLock<HedgingConfig>> = OnceLock::new();
+
+/// 获取当前请求对冲配置
+pub fn get_hedging_config() -> HedgingConfig {
+    GLOBAL_HEDGING_CONFIG
+        .get()
+        .and_then(|lock| lock.read().ok())
+        .map(|cfg| cfg.clone())
+        .unwrap_or_default()
+}
+
+/// 更新全局请求对冲配置
+pub fn update_hedging_config(config: HedgingConfig) {
+    if let Some(lock) = GLOBAL_HEDGING_CONFIG.get() {
+        if let Ok(mut cfg) = lock.write() {
+            *cfg = config.clone();
+            tracing::info!(
+                "[Hedging] Global config updated: enabled={}, threshold_ms={}",
+                config.enabled,
+                config.threshold_ms
+            );
+        }
+    } else {
+        // 首次初始化
+        let _ = GLOBAL_HEDGING_CONFIG.set(RwLock::new(config.clone()));
+        tracing::info!(
+            "[Hedging] Global config initialized: enabled={}, threshold_ms={}",
+            config.enabled,
+            config.threshold_ms
+        );
+    }
+}
+
+// ============================================================================
+// 全局 SSE 心跳配置存储
+// 用于在流式 mapper 中访问配置（无需层层传递）
+// ============================================================================
+static GLOBAL_STREAM_HEARTBEAT_CONFIG: OnceLock<RwLock<StreamHeartbeatConfig>> = OnceLock::new();
+
+/// 获取当前 SSE 心跳配置
+pub fn get_stream_heartbeat_config() -> StreamHeartbeatConfig {
+    GLOBAL_STREAM_HEARTBEAT_CONFIG
+        .get()
+        .and_then(|lock| lock.read().ok())
+        .map(|cfg| cfg.clone())
+        .unwrap_or_default()
+}
+
+/// 更新全局 SSE 心跳配置
+pub fn update_stream_heartbeat_config(config: StreamHeartbeatConfig) {
+    if let Some(lock) = GLOBAL_STREAM_HEARTBEAT_CONFIG.get() {
+        if let Ok(mut cfg) = lock.write() {
+            *cfg = config.clone();
+            tracing::info!(
+                "[Heartbeat] Global config updated: enabled={}, idle_seconds={}",
+                config.enabled,
+                config.idle_seconds
+            );
+        }
+    } else {
+        // 首次初始化
+        let _ = GLOBAL_STREAM_HEARTBEAT_CONFIG.set(RwLock::new(config.clone()));
+        tracing::info!(
+            "[Heartbeat] Global config initialized: enabled={}, idle_seconds={}",
+            config.enabled,
+            config.idle_seconds
+        );
+    }
+}
+
+// ============================================================================
+// 全局 MAX_TOKENS 自动续写配置存储
+// 用于在流式处理中访问配置（无需层层传递）
+// ============================================================================
+static GLOBAL_AUTO_CONTINUE_CONFIG: OnceLock<RwLock<AutoContinueConfig>> = OnceLock::new();
+
+/// 获取当前自动续写配置
+pub fn get_auto_continue_config() -> AutoContinueConfig {
+    GLOBAL_AUTO_CONTINUE_CONFIG
+        .get()
+        .and_then(|lock| lock.read().ok())
+        .map(|cfg| cfg.clone())
+        .unwrap_or_default()
+}
+
+/// 更新全局自动续写配置
+pub fn update_auto_continue_config(config: AutoContinueConfig) {
+    if let Some(lock) = GLOBAL_AUTO_CONTINUE_CONFIG.get() {
+        if let Ok(mut cfg) = lock.write() {
+            *cfg = config.clone();
+            tracing::info!(
+                "[Auto-Continue] Global config updated: enabled={}, max_continuations={}, max_total_tokens={}",
+                config.enabled,
+                config.max_continuations,
+                config.max_total_tokens
+            );
+        }
+    } else {
+        let _ = GLOBAL_AUTO_CONTINUE_CONFIG.set(RwLock::new(config.clone()));
+        tracing::info!(
+            "[Auto-Continue] Global config initialized: enabled={}, max_continuations={}, max_total_tokens={}",
+            config.enabled,
+            config.max_continuations,
+            config.max_total_tokens
+        );
+    }
+}
+
+// ============================================================================
+// 全局工具结果压缩配置存储
+// 用于在协议转换 (mapper) 中访问配置（无需层层传递）
+// ============================================================================
+static GLOBAL_TOOL_RESULT_COMPRESSION_CONFIG: OnceLock<RwLock<ToolResultCompressionConfig>> =
+    OnceLock::new();
+
+/// 获取当前工具结果压缩配置
+pub fn get_tool_result_compression_config() -> ToolResultCompressionConfig {
+    GLOBAL_TOOL_RESULT_COMPRESSION_CONFIG
+        .get()
+        .and_then(|lock| lock.read().ok())
+        .map(|cfg| cfg.clone())
+        .unwrap_or_default()
+}
+
+/// 更新全局工具结果压缩配置
+pub fn update_tool_result_compression_config(config: ToolResultCompressionConfig) {
+    if let Some(lock) = GLOBAL_TOOL_RESULT_COMPRESSION_CONFIG.get() {
+        if let Ok(mut cfg) = lock.write() {
+            *cfg = config.clone();
+            tracing::info!(
+                "[ToolCompressor] Global config updated: {} rules, default_max_chars={}",
+                config.rules.len(),
+                config.default_max_chars
+            );
+        }
+    } else {
+        let _ = GLOBAL_TOOL_RESULT_COMPRESSION_CONFIG.set(RwLock::new(config.clone()));
+        tracing::info!(
+            "[ToolCompressor] Global config initialized: {} rules, default_max_chars={}",
+            config.rules.len(),
+            config.default_max_chars
+        );
+    }
+}
+
+// ============================================================================
+// 全局图片预处理配置存储
+// 用于在 handler 转发前访问配置（无需层层传递）
+// ============================================================================
+static GLOBAL_IMAGE_PREPROCESS_CONFIG: OnceLock<RwLock<ImagePreprocessConfig>> = OnceLock::new();
+
+/// 获取当前图片预处理配置
+pub fn get_image_preprocess_config() -> ImagePreprocessConfig {
+    GLOBAL_IMAGE_PREPROCESS_CONFIG
+        .get()
+        .and_then(|lock| lock.read().ok())
+        .map(|cfg| cfg.clone())
+        .unwrap_or_default()
+}
+
+/// 更新全局图片预处理配置
+pub fn update_image_preprocess_config(config: ImagePreprocessConfig) {
+    if let Some(lock) = GLOBAL_IMAGE_PREPROCESS_CONFIG.get() {
+        if let Ok(mut cfg) = lock.write() {
+            *cfg = config.clone();
+            tracing::info!(
+                "[ImagePreprocess] Global config updated: enabled={}, max={}x{}, format={:?}",
+                config.enabled,
+                config.max_width,
+                config.max_height,
+                config.output_format
+            );
+        }
+    } else {
+        let _ = GLOBAL_IMAGE_PREPROCESS_CONFIG.set(RwLock::new(config.clone()));
+        tracing::info!(
+            "[ImagePreprocess] Global config initialized: enabled={}, max={}x{}, format={:?}",
+            config.enabled,
+            config.max_width,
+            config.max_height,
+            config.output_format
+        );
+    }
+}
+
+// ============================================================================
+// 全局图片 URL 解析配置存储
+// 用于在 handler 下载远程图片/读取本地图片时访问配置（无需层层传递）
+// ============================================================================
+static GLOBAL_IMAGE_URL_CONFIG: OnceLock<RwLock<ImageUrlConfig>> = OnceLock::new();
+
+/// 获取当前图片 URL 解析配置
+pub fn get_image_url_config() -> ImageUrlConfig {
+    GLOBAL_IMAGE_URL_CONFIG
+        .get()
+        .and_then(|lock| lock.read().ok())
+        .map(|cfg| cfg.clone())
+        .unwrap_or_default()
+}
+
+/// 更新全局图片 URL 解析配置
+pub fn update_image_url_config(config: ImageUrlConfig) {
+    if let Some(lock) = GLOBAL_IMAGE_URL_CONFIG.get() {
+        if let Ok(mut cfg) = lock.write() {
+            *cfg = config.clone();
+            tracing::info!(
+                "[ImageUrl] Global config updated: max_bytes={}, allow_private_networks={}, local_dirs={}",
+                config.max_bytes,
+                config.allow_private_networks,
+                config.local_file_dirs.len()
+            );
+        }
+    } else {
+        let _ = GLOBAL_IMAGE_URL_CONFIG.set(RwLock::new(config.clone()));
+        tracing::info!(
+            "[ImageUrl] Global config initialized: max_bytes={}, allow_private_networks={}, local_dirs={}",
+            config.max_bytes,
+            config.allow_private_networks,
+            config.local_file_dirs.len()
+        );
+    }
+}
+
+// ============================================================================
+// 全局图片图库配置存储
+// 用于在图片生成 handler 保存结果时访问配置（无需层层传递）
+// ============================================================================
+static GLOBAL_IMAGE_STORE_CONFIG: OnceLock<RwLock<ImageStoreConfig>> = OnceLock::new();
+
+/// 获取当前图片图库配置
+pub fn get_image_store_config() -> ImageStoreConfig {
+    GLOBAL_IMAGE_STORE_CONFIG
+        .get()
+        .and_then(|lock| lock.read().ok())
+        .map(|cfg| cfg.clone())
+        .unwrap_or_default()
+}
+
+/// 更新全局图片图库配置
+pub fn update_image_store_config(config: ImageStoreConfig) {
+    if let Some(lock) = GLOBAL_IMAGE_STORE_CONFIG.get() {
+        if let Ok(mut cfg) = lock.write() {
+            *cfg = config.clone();
+            tracing::info!(
+                "[ImageStore] Global config updated: enabled={}, retention_days={}, max_images={}, max_total_mb={}",
+                config.enabled,
+                config.retention_days,
+                config.max_images,
+                config.max_total_mb
+            );
+        }
+    } else {
+        let _ = GLOBAL_IMAGE_STORE_CONFIG.set(RwLock::new(config.clone()));
+        tracing::info!(
+            "[ImageStore] Global config initialized: enabled={}, retention_days={}, max_images={}, max_total_mb={}",
+            config.enabled,
+            config.retention_days,
+            config.max_images,
+            config.max_total_mb
+        );
+    }
+}
+
+// ============================================================================
+// 全局重试策略配置存储
+// 用于在 handlers::common 中访问配置（无需层层传递）
+// ============================================================================
+static GLOBAL_RETRY_POLICY_CONFIG: OnceLock<RwLock<RetryPolicyConfig>> = OnceLock::new();
+
+/// 获取当前重试策略配置
+pub fn get_retry_policy_config() -> RetryPolicyConfig {
+    GLOBAL_RETRY_POLICY_CONFIG
+        .get()
+        .and_then(|lock| lock.read().ok())
+        .map(|cfg| cfg.clone())
+        .unwrap_or_default()
+}
+
+/// 更新全局重试策略配置
+pub fn update_retry_policy_config(config: RetryPolicyConfig) {
+    if let Some(lock) = GLOBAL_RETRY_POLICY_CONFIG.get() {
+        if let Ok(mut cfg) = lock.write() {
+            *cfg = config.clone();
+            tracing::info!(
+                "[Retry-Policy] Global config updated: rules={}, budget_enabled={}, budget_ratio={}",
+                config.rules.len(),
+                config.budget.enabled,
+                config.budget.ratio
+            );
+        }
+    } else {
+        // 首次初始化
+        let _ = GLOBAL_RETRY_POLICY_CONFIG.set(RwLock::new(config.clone()));
+        tracing::info!(
+            "[Retry-Policy] Global config initialized: rules={}, budget_enabled={}, budget_ratio={}",
+            config.rules.len(),
+            config.budget.enabled,
+            config.budget.ratio
+        );
+    }
+}
+
 /// 全局系统提示词配置
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GlobalSystemPromptConfig {
@@ -388,19 +808,842 @@ impl Default for DebugLoggingConfig {
     }
 }
 
-/// IP 黑名单配置
+/// 请求日志保留与存储策略
+/// 保留天数为 0 表示永久保留
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct IpBlacklistConfig {
-    /// 是否启用黑名单
+pub struct LogRetentionConfig {
+    /// request_logs (proxy_logs.db) 保留天数
+    #[serde(default = "default_request_logs_days")]
+    pub request_logs_days: u32,
+
+    /// ip_access_logs (security.db) 保留天数
     #[serde(default)]
-    pub enabled: bool,
+    pub ip_access_logs_days: u32,
 
-    /// 自定义封禁消息
-    #[serde(default = "default_block_message")]
-    pub block_message: String,
+    /// token_usage_logs (user_tokens.db) 保留天数
+    #[serde(default)]
+    pub token_usage_logs_days: u32,
+
+    /// token_usage (token_stats.db) 明细保留天数 (小时聚合表不受影响)
+    #[serde(default)]
+    pub token_usage_days: u32,
+
+    /// 单个请求/响应体的最大存储字节数，超出部分截断 (0 = 不限制)
+    #[serde(default = "default_max_body_bytes")]
+    pub max_body_bytes: usize,
+
+    /// 成功请求的 body 采样率 (0.0 - 1.0)
+    #[serde(default = "default_body_sample_rate")]
+    pub success_body_sample_rate: f32,
+
+    /// 失败请求的 body 采样率 (0.0 - 1.0)
+    #[serde(default = "default_body_sample_rate")]
+    pub error_body_sample_rate: f32,
+
+    /// 是否加密存储 request_body / response_body
+    #[serde(default)]
+    pub encrypt_bodies: bool,
+
+    /// VACUUM 压缩间隔 (小时, 0 = 禁用)
+    #[serde(default = "default_vacuum_interval_hours")]
+    pub vacuum_interval_hours: u32,
 }
 
-impl Default for IpBlacklistConfig {
+impl Default for LogRetentionConfig {
+    fn default() -> Self {
+        Self {
+            request_logs_days: default_request_logs_days(),
+            ip_access_logs_days: 0,
+            token_usage_logs_days: 0,
+            token_usage_days: 0,
+            max_body_bytes: default_max_body_bytes(),
+            success_body_sample_rate: default_body_sample_rate(),
+            error_body_sample_rate: default_body_sample_rate(),
+            encrypt_bodies: false,
+            vacuum_interval_hours: default_vacuum_interval_hours(),
+        }
+    }
+}
+
+fn default_request_logs_days() -> u32 {
+    30
+}
+
+fn default_max_body_bytes() -> usize {
+    0
+}
+
+fn default_body_sample_rate() -> f32 {
+    1.0
+}
+
+fn default_vacuum_interval_hours() -> u32 {
+    24
+}
+
+/// 重试退避方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackoffKind {
+    /// 不重试
+    None,
+    /// 固定延迟: base_ms
+    Fixed,
+    /// 线性退避: base_ms * (attempt + 1)
+    Linear,
+    /// 指数退避: base_ms * 2^attempt，上限 max_ms
+    Exponential,
+}
+
+/// 上游错误分类 (由状态码和错误信息推断)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RetryErrorClass {
+    /// Thinking 签名失效 (400)
+    ThinkingSignature,
+    /// 配额耗尽 (QUOTA_EXHAUSTED)
+    QuotaExhausted,
+    /// 限流 (429)
+    RateLimited,
+    /// 服务过载 (503 / 529)
+    Overloaded,
+    /// 其他 5xx
+    ServerError,
+    /// 认证 / 权限 (401 / 403)
+    Auth,
+    /// 404
+    NotFound,
+    /// 其他
+    Other,
+}
+
+/// 单条重试规则 (按顺序匹配，第一条命中生效)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetryRule {
+    /// 匹配的状态码 (为空表示任意状态码)
+    #[serde(default)]
+    pub statuses: Vec<u16>,
+
+    /// 匹配的错误分类 (为空表示任意分类)
+    #[serde(default)]
+    pub error_class: Option<RetryErrorClass>,
+
+    /// 该类错误最多尝试次数 (含首次请求, 0 = 仅受请求级上限约束)
+    #[serde(default)]
+    pub max_attempts: usize,
+
+    pub backoff: BackoffKind,
+
+    #[serde(default)]
+    pub base_ms: u64,
+
+    /// 指数退避上限 / Retry-After 上限 (0 = 不限制)
+    #[serde(default)]
+    pub max_ms: u64,
+
+    /// 抖动比例 (0.0 - 1.0)，实际延迟在 delay * (1 ± jitter) 之间
+    #[serde(default)]
+    pub jitter: f32,
+
+    /// 重试时是否轮换账号
+    #[serde(default)]
+    pub rotate_account: bool,
+
+    /// 是否优先使用上游返回的 retryDelay / Retry-After
+    #[serde(default)]
+    pub respect_retry_after: bool,
+}
+
+impl RetryRule {
+    fn new(statuses: &[u16], backoff: BackoffKind, base_ms: u64, rotate_account: bool) -> Self {
+        Self {
+            statuses: statuses.to_vec(),
+            error_class: None,
+            max_attempts: 0,
+            backoff,
+            base_ms,
+            max_ms: 0,
+            jitter: 0.0,
+            rotate_account,
+            respect_retry_after: false,
+        }
+    }
+}
+
+/// 全局重试预算 (令牌桶)
+/// 每个请求存入 ratio 个令牌，每次重试消耗 1 个，令牌不足时不再重试，
+/// 从而在上游故障期间把重试量限制在总流量的一定比例内
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetryBudgetConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+
+    /// 每个请求存入的令牌数 (0.2 = 重试量最多约为请求量的 20%)
+    #[serde(default = "default_retry_budget_ratio")]
+    pub ratio: f32,
+
+    /// 每秒保底补充的令牌数 (保证低流量时仍可重试)
+    #[serde(default = "default_retry_budget_min_per_sec")]
+    pub min_retries_per_sec: f32,
+
+    /// 令牌桶容量
+    #[serde(default = "default_retry_budget_max_tokens")]
+    pub max_tokens: f32,
+}
+
+impl Default for RetryBudgetConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            ratio: default_retry_budget_ratio(),
+            min_retries_per_sec: default_retry_budget_min_per_sec(),
+            max_tokens: default_retry_budget_max_tokens(),
+        }
+    }
+}
+
+fn default_retry_budget_ratio() -> f32 {
+    0.2
+}
+
+fn default_retry_budget_min_per_sec() -> f32 {
+    1.0
+}
+
+fn default_retry_budget_max_tokens() -> f32 {
+    20.0
+}
+
+/// 全局准入队列配置
+/// 所有账号均饱和或限流时请求排队等待，直到有账号释放或限流重置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AdmissionQueueConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+
+    /// 最大排队请求数，超出时直接返回 429 / 529
+    #[serde(default = "default_admission_max_queue_length")]
+    pub max_queue_length: usize,
+
+    /// 单个请求的最长排队时间 (秒)
+    #[serde(default = "default_admission_max_wait_seconds")]
+    pub max_wait_seconds: u64,
+}
+
+impl Default for AdmissionQueueConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_queue_length: default_admission_max_queue_length(),
+            max_wait_seconds: default_admission_max_wait_seconds(),
+        }
+    }
+}
+
+fn default_admission_max_queue_length() -> usize {
+    100
+}
+
+fn default_admission_max_wait_seconds() -> u64 {
+    30
+}
+
+/// SSE 心跳配置
+/// 上游静默超过 idle_seconds 秒后向客户端发送心跳，防止客户端或反向代理因空闲断开连接
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StreamHeartbeatConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+
+    /// 上游静默多少秒后发送心跳
+    #[serde(default = "default_heartbeat_idle_seconds")]
+    pub idle_seconds: u64,
+}
+
+impl Default for StreamHeartbeatConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            idle_seconds: default_heartbeat_idle_seconds(),
+        }
+    }
+}
+
+fn default_heartbeat_idle_seconds() -> u64 {
+    15
+}
+
+/// 工具结果压缩策略
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ToolCompressionStrategy {
+    /// 通用启发式 (大文件提示、浏览器快照、HTML 清理、截断)
+    Default,
+    /// 保留头部与尾部 (命令行输出)
+    HeadTail,
+    /// 折叠堆栈帧 (测试运行器)
+    StackTrace,
+    /// 丢弃未变更的上下文行 (diff 工具)
+    Diff,
+    /// 概括 JSON 数组 (搜索、列表类工具)
+    JsonArray,
+}
+
+/// 按工具名匹配的压缩规则
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolCompressionRule {
+    /// 工具名匹配模式 (支持 * 通配符，不区分大小写)
+    pub patterns: Vec<String>,
+    pub strategy: ToolCompressionStrategy,
+    /// 该工具结果的最大字符数
+    pub max_chars: usize,
+}
+
+impl ToolCompressionRule {
+    fn new(patterns: &[&str], strategy: ToolCompressionStrategy, max_chars: usize) -> Self {
+        Self {
+            patterns: patterns.iter().map(|p| p.to_string()).collect(),
+            strategy,
+            max_chars,
+        }
+    }
+}
+
+/// 工具结果压缩配置
+/// 按工具名选择压缩策略与字符预算，作用于 Claude tool_result、OpenAI tool 消息与 Gemini functionResponse
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolResultCompressionConfig {
+    /// 按顺序匹配，命中第一条规则即停止
+    #[serde(default = "default_tool_compression_rules")]
+    pub rules: Vec<ToolCompressionRule>,
+
+    /// 未命中任何规则时的最大字符数
+    #[serde(default = "default_tool_result_max_chars")]
+    pub default_max_chars: usize,
+}
+
+impl Default for ToolResultCompressionConfig {
+    fn default() -> Self {
+        Self {
+            rules: default_tool_compression_rules(),
+            default_max_chars: default_tool_result_max_chars(),
+        }
+    }
+}
+
+fn default_tool_result_max_chars() -> usize {
+    200_000
+}
+
+/// 默认规则
+pub fn default_tool_compression_rules() -> Vec<ToolCompressionRule> {
+    use ToolCompressionStrategy::*;
+    vec![
+        ToolCompressionRule::new(&["*diff*"], Diff, 40_000),
+        ToolCompressionRule::new(&["*test*", "pytest*", "jest*", "vitest*"], StackTrace, 30_000),
+        ToolCompressionRule::new(
+            &["bash", "shell*", "*exec*", "run_command", "*terminal*", "powershell"],
+            HeadTail,
+            30_000,
+        ),
+        ToolCompressionRule::new(&["*search*", "*list*", "*query*"], JsonArray, 40_000),
+    ]
+}
+
+/// 图片预处理输出格式
+/// JPEG 按 quality 有损编码 (含透明像素的图片改用无损 WebP)；image crate 的 WebP 编码器仅支持无损
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ImageOutputFormat {
+    #[default]
+    Jpeg,
+    Webp,
+    Png,
+}
+
+/// 图片预处理配置
+/// 转发前对内联 base64 图片限制最大尺寸、重新编码，并把上游不支持的格式 (BMP/ICO) 转为 JPEG/WebP/PNG
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImagePreprocessConfig {
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// 最大宽度 (像素)，超出时等比缩放
+    #[serde(default = "default_image_max_dimension")]
+    pub max_width: u32,
+
+    /// 最大高度 (像素)，超出时等比缩放
+    #[serde(default = "default_image_max_dimension")]
+    pub max_height: u32,
+
+    #[serde(default)]
+    pub output_format: ImageOutputFormat,
+
+    /// 有损编码质量 (1-100，仅 JPEG 输出使用)
+    #[serde(default = "default_image_quality")]
+    pub quality: u8,
+
+    /// 小于该字节数且无需缩放/转换的图片保持原样
+    #[serde(default = "default_image_min_bytes")]
+    pub min_bytes: usize,
+}
+
+impl Default for ImagePreprocessConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_width: default_image_max_dimension(),
+            max_height: default_image_max_dimension(),
+            output_format: ImageOutputFormat::default(),
+            quality: default_image_quality(),
+            min_bytes: default_image_min_bytes(),
+        }
+    }
+}
+
+fn default_image_max_dimension() -> u32 {
+    2048
+}
+
+fn default_image_quality() -> u8 {
+    85
+}
+
+fn default_image_min_bytes() -> usize {
+    100 * 1024
+}
+
+/// 图片 URL 解析配置
+/// OpenAI `image_url` 中的 http(s) 与本地文件地址在转发前下载/读取为内联数据
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImageUrlConfig {
+    /// 单张图片最大字节数
+    #[serde(default = "default_image_url_max_bytes")]
+    pub max_bytes: usize,
+
+    /// 单张远程图片的下载超时 (秒，含重定向)
+    #[serde(default = "default_image_url_timeout_secs")]
+    pub timeout_secs: u64,
+
+    /// 下载结果按 URL 缓存的时长 (秒，0 = 不缓存)
+    #[serde(default = "default_image_url_cache_ttl_secs")]
+    pub cache_ttl_secs: u64,
+
+    #[serde(default = "default_image_url_cache_max_entries")]
+    pub cache_max_entries: usize,
+
+    /// 允许下载内网 / 回环地址的图片 (默认拒绝，防止 SSRF)
+    #[serde(default)]
+    pub allow_private_networks: bool,
+
+    /// 允许读取 file:// 图片的目录白名单 (空 = 禁用本地文件)
+    #[serde(default)]
+    pub local_file_dirs: Vec<String>,
+
+    /// 允许非本机客户端读取本地文件 (默认仅回环地址且未经反向代理的请求可用)
+    #[serde(default)]
+    pub allow_remote_local_files: bool,
+}
+
+impl Default for ImageUrlConfig {
+    fn default() -> Self {
+        Self {
+            max_bytes: default_image_url_max_bytes(),
+            timeout_secs: default_image_url_timeout_secs(),
+            cache_ttl_secs: default_image_url_cache_ttl_secs(),
+            cache_max_entries: default_image_url_cache_max_entries(),
+            allow_private_networks: false,
+            local_file_dirs: Vec::new(),
+            allow_remote_local_files: false,
+        }
+    }
+}
+
+fn default_image_url_max_bytes() -> usize {
+    20 * 1024 * 1024
+}
+
+fn default_image_url_timeout_secs() -> u64 {
+    15
+}
+
+fn default_image_url_cache_ttl_secs() -> u64 {
+    600
+}
+
+fn default_image_url_cache_max_entries() -> usize {
+    64
+}
+
+/// 图片图库配置
+/// 生成 / 编辑得到的图片保存到本地，`response_format: "url"` 返回 `/v1/files/images/{id}` 地址。
+/// 图片地址使用随机 ID，无需 API Key 即可访问 (与 OpenAI 返回的临时链接一致)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImageStoreConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+
+    /// 保留天数 (0 = 不按时间清理)
+    #[serde(default = "default_image_store_retention_days")]
+    pub retention_days: u32,
+
+    /// 最多保留图片数 (0 = 不限)
+    #[serde(default = "default_image_store_max_images")]
+    pub max_images: usize,
+
+    /// 图片总大小上限 (MB，0 = 不限)
+    #[serde(default = "default_image_store_max_total_mb")]
+    pub max_total_mb: u64,
+
+    /// 生成 URL 使用的外部地址 (如 https://ai.example.com)；为空时按请求的 Host 推断
+    #[serde(default)]
+    pub public_base_url: String,
+}
+
+impl Default for ImageStoreConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            retention_days: default_image_store_retention_days(),
+            max_images: default_image_store_max_images(),
+            max_total_mb: default_image_store_max_total_mb(),
+            public_base_url: String::new(),
+        }
+    }
+}
+
+fn default_image_store_retention_days() -> u32 {
+    7
+}
+
+fn default_image_store_max_images() -> usize {
+    1000
+}
+
+fn default_image_store_max_total_mb() -> u64 {
+    1024
+}
+
+/// MAX_TOKENS 自动续写配置
+/// 上游因单次输出上限以 MAX_TOKENS 结束时，以累计输出作为 prefill 发起后续请求，并把续写内容拼接到同一客户端流中
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AutoContinueConfig {
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// 单个请求最多续写次数
+    #[serde(default = "default_auto_continue_max_continuations")]
+    pub max_continuations: u32,
+
+    /// 所有分段合计的输出 token 预算 (客户端 max_tokens 更小时以客户端为准)
+    #[serde(default = "default_auto_continue_max_total_tokens")]
+    pub max_total_tokens: u64,
+}
+
+impl Default for AutoContinueConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_continuations: default_auto_continue_max_continuations(),
+            max_total_tokens: default_auto_continue_max_total_tokens(),
+        }
+    }
+}
+
+fn default_auto_continue_max_continuations() -> u32 {
+    3
+}
+
+fn default_auto_continue_max_total_tokens() -> u64 {
+    131072
+}
+
+/// 请求对冲配置
+/// Claude 协议的后台任务 (标题生成、摘要等) 非流式请求超过阈值仍未响应时，在第二个账号上发出相同请求，取先完成者
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HedgingConfig {
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// 固定对冲阈值 (毫秒)，0 = 使用按模型学习的 p95 延迟
+    #[serde(default)]
+    pub threshold_ms: u64,
+
+    /// 学习阈值的下限 (毫秒)，避免对本就很快的模型频繁对冲
+    #[serde(default = "default_hedging_min_threshold_ms")]
+    pub min_threshold_ms: u64,
+
+    /// 样本不足以计算 p95 时使用的阈值 (毫秒)
+    #[serde(default = "default_hedging_cold_start_threshold_ms")]
+    pub cold_start_threshold_ms: u64,
+}
+
+impl Default for HedgingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            threshold_ms: 0,
+            min_threshold_ms: default_hedging_min_threshold_ms(),
+            cold_start_threshold_ms: default_hedging_cold_start_threshold_ms(),
+        }
+    }
+}
+
+fn default_hedging_min_threshold_ms() -> u64 {
+    1_000
+}
+
+fn default_hedging_cold_start_threshold_ms() -> u64 {
+    5_000
+}
+
+/// 用户令牌间的加权公平调度配置
+/// 全局并发达到上限时，按用户令牌 (无令牌时按客户端 IP) 分流排队，以赤字轮询按权重分配空闲容量
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FairSchedulingConfig {
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// 全局最大并发请求数 (含流式响应全程，0 = 不限制)
+    #[serde(default = "default_fair_max_concurrent_requests")]
+    pub max_concurrent_requests: usize,
+
+    /// 单个请求等待公平调度许可的最长时间 (秒)
+    #[serde(default = "default_fair_max_wait_seconds")]
+    pub max_wait_seconds: u64,
+
+    /// 未单独配置的流的权重
+    #[serde(default = "default_fair_weight")]
+    pub default_weight: u32,
+
+    /// 按用户令牌 ID 或用户名配置的权重，越大分得的容量越多
+    #[serde(default)]
+    pub weights: HashMap<String, u32>,
+}
+
+impl Default for FairSchedulingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_concurrent_requests: default_fair_max_concurrent_requests(),
+            max_wait_seconds: default_fair_max_wait_seconds(),
+            default_weight: default_fair_weight(),
+            weights: HashMap::new(),
+        }
+    }
+}
+
+impl FairSchedulingConfig {
+    /// 用户令牌的调度权重 (先按令牌 ID，再按用户名查找，至少为 1)
+    pub fn weight_for(&self, token_id: &str, username: Option<&str>) -> u32 {
+        self.weights
+            .get(token_id)
+            .or_else(|| username.and_then(|u| self.weights.get(u)))
+            .copied()
+            .unwrap_or(self.default_weight)
+            .max(1)
+    }
+}
+
+fn default_fair_max_concurrent_requests() -> usize {
+    32
+}
+
+fn default_fair_max_wait_seconds() -> u64 {
+    60
+}
+
+fn default_fair_weight() -> u32 {
+    1
+}
+
+/// 流式响应中断续传配置
+/// 上游 SSE 在输出途中断开时，以已输出的内容作为 prefill 重新发起请求，并把续写内容拼接到同一客户端流中
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StreamResumeConfig {
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// 单个请求最多续传次数
+    #[serde(default = "default_stream_resume_max_resumes")]
+    pub max_resumes: u32,
+
+    /// 续传时是否轮换到其他账号
+    #[serde(default = "default_true")]
+    pub rotate_account: bool,
+}
+
+impl Default for StreamResumeConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_resumes: default_stream_resume_max_resumes(),
+            rotate_account: true,
+        }
+    }
+}
+
+fn default_stream_resume_max_resumes() -> u32 {
+    2
+}
+
+/// 重试策略配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetryPolicyConfig {
+    #[serde(default = "default_retry_rules")]
+    pub rules: Vec<RetryRule>,
+
+    #[serde(default)]
+    pub budget: RetryBudgetConfig,
+
+    #[serde(default)]
+    pub stream_resume: StreamResumeConfig,
+}
+
+impl Default for RetryPolicyConfig {
+    fn default() -> Self {
+        Self {
+            rules: default_retry_rules(),
+            budget: RetryBudgetConfig::default(),
+            stream_resume: StreamResumeConfig::default(),
+        }
+    }
+}
+
+/// 默认规则 (与历史硬编码行为一致)
+pub fn default_retry_rules() -> Vec<RetryRule> {
+    vec![
+        // 400: 仅 Thinking 签名失败时重试一次
+        RetryRule {
+            error_class: Some(RetryErrorClass::ThinkingSignature),
+            ..RetryRule::new(&[400], BackoffKind::Fixed, 200, false)
+        },
+        // 429: 优先 Retry-After (上限 30s)，否则线性 5s
+        RetryRule {
+            max_ms: 30_000,
+            respect_retry_after: true,
+            ..RetryRule::new(&[429], BackoffKind::Linear, 5000, true)
+        },
+        // 503 / 529: 指数 10s 起，上限 60s (Google 边缘节点过载，轮换无意义)
+        RetryRule {
+            max_ms: 60_000,
+            ..RetryRule::new(&[503, 529], BackoffKind::Exponential, 10_000, false)
+        },
+        RetryRule::new(&[500], BackoffKind::Linear, 3000, true),
+        RetryRule::new(&[401, 403], BackoffKind::Fixed, 200, true),
+        // 404: Cloud Code API 的 404 通常是账号级别的间歇性问题，轮换账号往往能解决
+        RetryRule::new(&[404], BackoffKind::Fixed, 300, true),
+    ]
+}
+
+/// 单个 v1internal 上游端点
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct UpstreamEndpoint {
+    /// 显示名称 (如 "sandbox")
+    pub name: String,
+    /// v1internal 基础地址 (如 "https://cloudcode-pa.googleapis.com/v1internal")
+    pub base_url: String,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+}
+
+impl UpstreamEndpoint {
+    fn new(name: &str, base_url: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            base_url: base_url.to_string(),
+            enabled: true,
+        }
+    }
+}
+
+/// v1internal 端点列表与健康检查配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpstreamEndpointsConfig {
+    /// 按优先级排列的端点 (第一个健康的端点优先使用)
+    #[serde(default = "default_upstream_endpoints")]
+    pub endpoints: Vec<UpstreamEndpoint>,
+
+    /// 错误率 EWMA 超过该阈值时将端点标记为不健康 (0.0 - 1.0)
+    #[serde(default = "default_endpoint_error_threshold")]
+    pub error_rate_threshold: f64,
+
+    /// 判定不健康前所需的最少样本数
+    #[serde(default = "default_endpoint_min_samples")]
+    pub min_samples: u32,
+
+    /// 不健康端点的冷却时间 (秒)，期间会被跳过
+    #[serde(default = "default_endpoint_cooldown_secs")]
+    pub cooldown_secs: u64,
+
+    /// 后台探测不健康端点的间隔 (秒, 0 = 禁用)
+    #[serde(default = "default_endpoint_probe_interval_secs")]
+    pub probe_interval_secs: u64,
+
+    /// EWMA 平滑系数 (越大越偏向最近的样本)
+    #[serde(default = "default_endpoint_ewma_alpha")]
+    pub ewma_alpha: f64,
+}
+
+impl Default for UpstreamEndpointsConfig {
+    fn default() -> Self {
+        Self {
+            endpoints: default_upstream_endpoints(),
+            error_rate_threshold: default_endpoint_error_threshold(),
+            min_samples: default_endpoint_min_samples(),
+            cooldown_secs: default_endpoint_cooldown_secs(),
+            probe_interval_secs: default_endpoint_probe_interval_secs(),
+            ewma_alpha: default_endpoint_ewma_alpha(),
+        }
+    }
+}
+
+// Cloud Code v1internal endpoints (fallback order: Sandbox → Daily → Prod)
+// 优先使用 Sandbox/Daily 环境以避免 Prod环境的 429 错误 (Ref: Issue #1176)
+pub fn default_upstream_endpoints() -> Vec<UpstreamEndpoint> {
+    vec![
+        UpstreamEndpoint::new(
+            "sandbox",
+            "https://daily-cloudcode-pa.sandbox.googleapis.com/v1internal",
+        ),
+        UpstreamEndpoint::new("daily", "https://daily-cloudcode-pa.googleapis.com/v1internal"),
+        UpstreamEndpoint::new("prod", "https://cloudcode-pa.googleapis.com/v1internal"),
+    ]
+}
+
+fn default_endpoint_error_threshold() -> f64 {
+    0.5
+}
+
+fn default_endpoint_min_samples() -> u32 {
+    3
+}
+
+fn default_endpoint_cooldown_secs() -> u64 {
+    60
+}
+
+fn default_endpoint_probe_interval_secs() -> u64 {
+    30
+}
+
+fn default_endpoint_ewma_alpha() -> f64 {
+    0.3
+}
+
+/// IP 黑名单配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IpBlacklistConfig {
+    /// 是否启用黑名单
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// 自定义封禁消息
+    #[serde(default = "default_block_message")]
+    pub block_message: String,
+}
+
+impl Default for IpBlacklistConfig {
     fn default() -> Self {
         Self {
             enabled: false,
@@ -444,6 +1687,11 @@ pub struct SecurityMonitorConfig {
     /// IP 白名单配置
     #[serde(default)]
     pub whitelist: IpWhitelistConfig,
+
+    /// 服务部署在可信反向代理之后：管理员登录限速按 X-Forwarded-For / X-Real-IP 识别客户端
+    /// 关闭时仅使用 TCP 连接地址，避免客户端伪造请求头绕过限速
+    #[serde(default)]
+    pub trust_proxy_headers: bool,
 }
 
 impl Default for SecurityMonitorConfig {
@@ -451,6 +1699,7 @@ impl Default for SecurityMonitorConfig {
         Self {
             blacklist: IpBlacklistConfig::default(),
             whitelist: IpWhitelistConfig::default(),
+            trust_proxy_headers: false,
         }
     }
 }
@@ -556,6 +1805,54 @@ pub struct ProxyConfig {
     /// 代理池配置
     #[serde(default)]
     pub proxy_pool: ProxyPoolConfig,
+
+    /// 日志保留与存储策略
+    #[serde(default)]
+    pub log_retention: LogRetentionConfig,
+
+    /// 上游错误重试策略
+    #[serde(default)]
+    pub retry_policy: RetryPolicyConfig,
+
+    /// v1internal 上游端点列表与健康检查
+    #[serde(default)]
+    pub upstream_endpoints: UpstreamEndpointsConfig,
+
+    /// 账号全部不可用时的请求排队
+    #[serde(default)]
+    pub admission_queue: AdmissionQueueConfig,
+
+    /// 用户令牌间的加权公平调度
+    #[serde(default)]
+    pub fair_scheduling: FairSchedulingConfig,
+
+    /// 非流式短请求的对冲
+    #[serde(default)]
+    pub hedging: HedgingConfig,
+
+    /// 流式响应的 SSE 心跳
+    #[serde(default)]
+    pub stream_heartbeat: StreamHeartbeatConfig,
+
+    /// MAX_TOKENS 自动续写
+    #[serde(default)]
+    pub auto_continue: AutoContinueConfig,
+
+    /// 工具结果压缩
+    #[serde(default)]
+    pub tool_result_compression: ToolResultCompressionConfig,
+
+    /// 内联图片缩放与重新编码
+    #[serde(default)]
+    pub image_preprocess: ImagePreprocessConfig,
+
+    /// 远程 / 本地图片 URL 解析
+    #[serde(default)]
+    pub image_url: ImageUrlConfig,
+
+    /// 生成图片图库
+    #[serde(default)]
+    pub image_store: ImageStoreConfig,
 }
 
 /// 上游代理配置
@@ -593,6 +1890,18 @@ impl Default for ProxyConfig {
             global_system_prompt: GlobalSystemPromptConfig::default(),
             proxy_pool: ProxyPoolConfig::default(),
             image_thinking_mode: None,
+            log_retention: LogRetentionConfig::default(),
+            retry_policy: RetryPolicyConfig::default(),
+            upstream_endpoints: UpstreamEndpointsConfig::default(),
+            admission_queue: AdmissionQueueConfig::default(),
+            fair_scheduling: FairSchedulingConfig::default(),
+            hedging: HedgingConfig::default(),
+            stream_heartbeat: StreamHeartbeatConfig::default(),
+            auto_continue: AutoContinueConfig::default(),
+            tool_result_compression: ToolResultCompressionConfig::default(),
+            image_preprocess: ImagePreprocessConfig::default(),
+            image_url: ImageUrlConfig::default(),
+            image_store: ImageStoreConfig::default(),
         }
     }
 }
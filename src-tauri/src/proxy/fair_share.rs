@@ -0,0 +1,386 @@
+// 用户令牌间的加权公平调度
+// 在鉴权之后、获取账号之前为每个请求发放全局并发许可。空闲时直接放行；
+// 并发已满时请求按所属流 (用户令牌，无令牌时按客户端 IP) 排队，许可释放后按
+// 赤字轮询 (Deficit Round-Robin) 在有积压的流之间分配：每轮每个流获得与权重成正比的额度，
+// 因此轻量的交互式用户不会被批量任务的长队列阻塞，批量用户则吸收剩余容量。
+
+use crate::proxy::config::FairSchedulingConfig;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Mutex, MutexGuard};
+use tokio::sync::oneshot;
+
+static SCHEDULER: Lazy<FairScheduler> = Lazy::new(FairScheduler::new);
+
+#[derive(Debug, Default)]
+struct Flow {
+    weight: u32,
+    deficit: u32,
+    in_flight: usize,
+    queue: VecDeque<oneshot::Sender<()>>,
+}
+
+#[derive(Debug, Default)]
+struct SchedulerState {
+    flows: HashMap<String, Flow>,
+    /// 有积压请求的流 (DRR 轮询顺序)
+    active: VecDeque<String>,
+    /// 队首流本轮是否已补充过额度 (容量不足中断时下次继续消耗剩余额度)
+    front_topped_up: bool,
+    in_flight: usize,
+    capacity: usize,
+}
+
+/// 单个流的调度快照 (供 Admin API 展示)
+#[derive(Debug, Clone, Serialize)]
+pub struct FlowSnapshot {
+    pub flow: String,
+    pub weight: u32,
+    pub in_flight: usize,
+    pub queued: usize,
+    pub deficit: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FairShareSnapshot {
+    pub capacity: usize,
+    pub in_flight: usize,
+    pub flows: Vec<FlowSnapshot>,
+}
+
+#[derive(Debug, Default)]
+pub struct FairScheduler {
+    state: Mutex<SchedulerState>,
+}
+
+/// 并发许可；Drop 时释放并把容量分配给下一个排队请求
+#[derive(Debug)]
+pub struct FairPermit<'a> {
+    scheduler: Option<&'a FairScheduler>,
+    flow: String,
+}
+
+impl FairScheduler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn state(&self) -> MutexGuard<'_, SchedulerState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// 为指定流获取并发许可；未启用时直接放行，排队超时返回错误
+    pub async fn acquire(
+        &self,
+        flow: &str,
+        weight: u32,
+        cfg: &FairSchedulingConfig,
+    ) -> Result<FairPermit<'_>, String> {
+        if !cfg.enabled || cfg.max_concurrent_requests == 0 {
+            return Ok(FairPermit {
+                scheduler: None,
+                flow: flow.to_string(),
+            });
+        }
+
+        let rx = {
+            let mut state = self.state();
+            state.capacity = cfg.max_concurrent_requests;
+            state.flows.entry(flow.to_string()).or_default().weight = weight.max(1);
+
+            if state.active.is_empty() && state.in_flight < state.capacity {
+                state.in_flight += 1;
+                if let Some(entry) = state.flows.get_mut(flow) {
+                    entry.in_flight += 1;
+                }
+                return Ok(self.permit(flow));
+            }
+
+            let (tx, rx) = oneshot::channel();
+            if let Some(entry) = state.flows.get_mut(flow) {
+                entry.queue.push_back(tx);
+            }
+            if !state.active.iter().any(|f| f == flow) {
+                state.active.push_back(flow.to_string());
+            }
+            Self::dispatch(&mut state);
+            rx
+        };
+
+        // 排队期间 future 被丢弃 (客户端断开) 时由 Waiter 归还已分配的许可
+        let mut waiter = Waiter {
+            scheduler: self,
+            flow: flow.to_string(),
+            rx: Some(rx),
+        };
+        let wait = std::time::Duration::from_secs(cfg.max_wait_seconds);
+        if let Some(rx) = waiter.rx.as_mut() {
+            if let Ok(Ok(())) = tokio::time::timeout(wait, rx).await {
+                waiter.rx = None;
+                return Ok(self.permit(flow));
+            }
+        }
+
+        // 超时：若恰好已被分配许可则直接使用
+        if waiter.cancel() {
+            return Ok(self.permit(flow));
+        }
+        Err(format!(
+            "Too many concurrent requests: waited {}s for a fair-share slot",
+            cfg.max_wait_seconds
+        ))
+    }
+
+    fn permit(&self, flow: &str) -> FairPermit<'_> {
+        FairPermit {
+            scheduler: Some(self),
+            flow: flow.to_string(),
+        }
+    }
+
+    /// 按 DRR 把空闲容量分配给排队中的请求
+    fn dispatch(state: &mut SchedulerState) {
+        while state.in_flight < state.capacity {
+            let Some(flow_id) = state.active.front().cloned() else {
+                break;
+            };
+            let topped_up = state.front_topped_up;
+            let Some(flow) = state.flows.get_mut(&flow_id) else {
+                state.active.pop_front();
+                state.front_topped_up = false;
+                continue;
+            };
+            if !topped_up {
+                flow.deficit += flow.weight.max(1);
+                state.front_topped_up = true;
+            }
+
+            let mut granted = 0;
+            while flow.deficit > 0 && state.in_flight + granted < state.capacity {
+                let Some(tx) = flow.queue.pop_front() else {
+                    break;
+                };
+                // 接收方已放弃 (超时 / 客户端断开) 时不消耗额度
+                if tx.send(()).is_ok() {
+                    flow.deficit -= 1;
+                    flow.in_flight += 1;
+                    granted += 1;
+                }
+            }
+            state.in_flight += granted;
+
+            if flow.queue.is_empty() {
+                flow.deficit = 0;
+                state.active.pop_front();
+                state.front_topped_up = false;
+            } else if flow.deficit == 0 {
+                // 本轮额度用完，轮到下一个流
+                state.active.rotate_left(1);
+                state.front_topped_up = false;
+            } else {
+                // 容量用完但仍有额度：保留位置，下次释放时继续
+                break;
+            }
+        }
+    }
+
+    fn cleanup_flow(state: &mut SchedulerState, flow: &str) {
+        let idle = state
+            .flows
+            .get(flow)
+            .map_or(false, |f| f.in_flight == 0 && f.queue.is_empty());
+        if idle {
+            state.flows.remove(flow);
+            if state.active.front().map_or(false, |f| f == flow) {
+                state.front_topped_up = false;
+            }
+            state.active.retain(|f| f != flow);
+        }
+    }
+
+    fn release(&self, flow: &str) {
+        let mut state = self.state();
+        state.in_flight = state.in_flight.saturating_sub(1);
+        if let Some(entry) = state.flows.get_mut(flow) {
+            entry.in_flight = entry.in_flight.saturating_sub(1);
+        }
+        Self::cleanup_flow(&mut state, flow);
+        Self::dispatch(&mut state);
+    }
+
+    pub fn snapshot(&self) -> FairShareSnapshot {
+        let state = self.state();
+        let mut flows: Vec<FlowSnapshot> = state
+            .flows
+            .iter()
+            .map(|(flow, f)| FlowSnapshot {
+                flow: flow.clone(),
+                weight: f.weight,
+                in_flight: f.in_flight,
+                queued: f.queue.len(),
+                deficit: f.deficit,
+            })
+            .collect();
+        flows.sort_by(|a, b| b.in_flight.cmp(&a.in_flight).then_with(|| a.flow.cmp(&b.flow)));
+        FairShareSnapshot {
+            capacity: state.capacity,
+            in_flight: state.in_flight,
+            flows,
+        }
+    }
+}
+
+impl Drop for FairPermit<'_> {
+    fn drop(&mut self) {
+        if let Some(scheduler) = self.scheduler {
+            scheduler.release(&self.flow);
+        }
+    }
+}
+
+/// 排队中的请求；未拿到许可就被丢弃时退出队列，已被分配的许可则归还
+struct Waiter<'a> {
+    scheduler: &'a FairScheduler,
+    flow: String,
+    rx: Option<oneshot::Receiver<()>>,
+}
+
+impl Waiter<'_> {
+    /// 持锁关闭接收端，避免与 dispatch 竞争；返回是否已被分配许可
+    fn cancel(&mut self) -> bool {
+        let Some(mut rx) = self.rx.take() else {
+            return false;
+        };
+        let mut state = self.scheduler.state();
+        rx.close();
+        if rx.try_recv().is_ok() {
+            return true;
+        }
+        if let Some(entry) = state.flows.get_mut(&self.flow) {
+            entry.queue.retain(|tx| !tx.is_closed());
+        }
+        FairScheduler::cleanup_flow(&mut state, &self.flow);
+        false
+    }
+}
+
+impl Drop for Waiter<'_> {
+    fn drop(&mut self) {
+        if self.cancel() {
+            self.scheduler.release(&self.flow);
+        }
+    }
+}
+
+/// 使用全局调度器获取许可
+pub async fn acquire(
+    flow: &str,
+    weight: u32,
+    cfg: &FairSchedulingConfig,
+) -> Result<FairPermit<'static>, String> {
+    SCHEDULER.acquire(flow, weight, cfg).await
+}
+
+pub fn snapshot() -> FairShareSnapshot {
+    SCHEDULER.snapshot()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cfg(capacity: usize) -> FairSchedulingConfig {
+        FairSchedulingConfig {
+            enabled: true,
+            max_concurrent_requests: capacity,
+            max_wait_seconds: 5,
+            default_weight: 1,
+            weights: HashMap::from([("light".to_string(), 2)]),
+        }
+    }
+
+    fn weight(cfg: &FairSchedulingConfig, flow: &str) -> u32 {
+        cfg.weight_for(flow, None)
+    }
+
+    #[tokio::test]
+    async fn test_disabled_passes_through() {
+        let scheduler = FairScheduler::new();
+        let mut cfg = cfg(1);
+        cfg.enabled = false;
+        let _a = scheduler.acquire("batch", weight(&cfg, "batch"), &cfg).await.unwrap();
+        let _b = scheduler.acquire("batch", weight(&cfg, "batch"), &cfg).await.unwrap();
+        assert_eq!(scheduler.snapshot().in_flight, 0);
+    }
+
+    #[tokio::test]
+    async fn test_drr_interleaves_flows_by_weight() {
+        let scheduler = FairScheduler::new();
+        let cfg = cfg(1);
+        let holder = scheduler.acquire("batch", weight(&cfg, "batch"), &cfg).await.unwrap();
+
+        // batch 积压 4 个请求，light (权重 2) 随后到达 3 个
+        let order = std::sync::Arc::new(Mutex::new(Vec::new()));
+        let mut waiters = Vec::new();
+        for flow in ["batch", "batch", "batch", "batch", "light", "light", "light"] {
+            let order = order.clone();
+            let cfg = cfg.clone();
+            let scheduler = &scheduler;
+            waiters.push(async move {
+                let permit = scheduler.acquire(flow, weight(&cfg, flow), &cfg).await.unwrap();
+                order.lock().unwrap().push(flow);
+                tokio::task::yield_now().await;
+                drop(permit);
+            });
+        }
+
+        let snapshot_before = async {
+            tokio::task::yield_now().await;
+            let snap = scheduler.snapshot();
+            assert_eq!(snap.in_flight, 1);
+            assert_eq!(snap.flows.iter().map(|f| f.queued).sum::<usize>(), 7);
+            drop(holder);
+        };
+        tokio::join!(futures::future::join_all(waiters), snapshot_before);
+
+        // 每轮 batch 1 个、light 2 个
+        assert_eq!(
+            *order.lock().unwrap(),
+            vec!["batch", "light", "light", "batch", "light", "batch", "batch"]
+        );
+        let snap = scheduler.snapshot();
+        assert_eq!(snap.in_flight, 0);
+        assert!(snap.flows.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_wait_timeout_returns_error() {
+        let scheduler = FairScheduler::new();
+        let mut cfg = cfg(1);
+        cfg.max_wait_seconds = 0;
+        let _holder = scheduler.acquire("batch", weight(&cfg, "batch"), &cfg).await.unwrap();
+        assert!(scheduler.acquire("light", weight(&cfg, "light"), &cfg).await.is_err());
+        let snap = scheduler.snapshot();
+        assert_eq!(snap.flows.len(), 1);
+        assert_eq!(snap.flows[0].queued, 0);
+    }
+
+    #[tokio::test]
+    async fn test_dropped_waiter_returns_granted_permit() {
+        let scheduler = FairScheduler::new();
+        let cfg = cfg(1);
+        let holder = scheduler.acquire("batch", weight(&cfg, "batch"), &cfg).await.unwrap();
+        let mut waiter = Box::pin(scheduler.acquire("light", weight(&cfg, "light"), &cfg));
+        assert!(futures::poll!(&mut waiter).is_pending());
+
+        // 许可已分配给 light，但等待方在被唤醒前就被丢弃
+        drop(holder);
+        assert_eq!(scheduler.snapshot().in_flight, 1);
+        drop(waiter);
+
+        let snap = scheduler.snapshot();
+        assert_eq!(snap.in_flight, 0);
+        assert!(snap.flows.is_empty());
+    }
+}
@@ -1,30 +1,56 @@
 use axum::{
     extract::{Multipart, State},
-    http::StatusCode,
+    http::{header, StatusCode},
     response::IntoResponse,
     Json,
 };
+use base64::{engine::general_purpose, Engine as _};
+use futures::{StreamExt, TryStreamExt};
 use serde_json::{json, Value};
-use tracing::{debug, info};
+use tracing::{info, warn};
 use uuid::Uuid;
 
-use crate::proxy::{audio::AudioProcessor, server::AppState};
+use crate::proxy::{
+    audio::{
+        chunker,
+        transcript::{self, Segment, TranscriptFormat},
+        AudioProcessor, SpeechFormat,
+    },
+    common::error::ProxyError,
+    handlers::common::{
+        apply_retry_decision, resolve_retry_decision, token_error_status, RetryAttempts,
+    },
+    server::AppState,
+};
 
-/// 处理音频转录请求 (OpenAI Whisper API 兼容)
-pub async fn handle_audio_transcription(
-    State(state): State<AppState>,
-    mut multipart: Multipart,
-) -> Result<impl IntoResponse, (StatusCode, String)> {
+const MAX_RETRY_ATTEMPTS: usize = 3;
+const DEFAULT_AUDIO_MODEL: &str = "gemini-2.0-flash-exp";
+/// 长音频并行转录的最大并发片段数
+const MAX_PARALLEL_CHUNKS: usize = 4;
+
+/// multipart/form-data 音频表单 (transcriptions / translations 共用)
+struct AudioForm {
+    audio: Vec<u8>,
+    file_name: String,
+    model: String,
+    prompt: Option<String>,
+    response_format: Option<String>,
+    language: Option<String>,
+}
+
+/// 解析音频表单 (大小由切分逻辑处理)
+async fn parse_audio_form(mut multipart: Multipart) -> Result<AudioForm, ProxyError> {
     let mut audio_data: Option<Vec<u8>> = None;
     let mut filename: Option<String> = None;
-    let mut model = "gemini-2.0-flash-exp".to_string();
-    let mut prompt = "Generate a transcript of the speech.".to_string();
+    let mut model = DEFAULT_AUDIO_MODEL.to_string();
+    let mut prompt: Option<String> = None;
+    let mut response_format: Option<String> = None;
+    let mut language: Option<String> = None;
 
-    // 1. 解析 multipart/form-data
     while let Some(field) = multipart
         .next_field()
         .await
-        .map_err(|e| (StatusCode::BAD_REQUEST, format!("解析表单失败: {}", e)))?
+        .map_err(|e| ProxyError::invalid_request(format!("解析表单失败: {}", e)))?
     {
         let name = field.name().unwrap_or("").to_string();
 
@@ -35,7 +61,7 @@ pub async fn handle_audio_transcription(
                     field
                         .bytes()
                         .await
-                        .map_err(|e| (StatusCode::BAD_REQUEST, format!("读取文件失败: {}", e)))?
+                        .map_err(|e| ProxyError::invalid_request(format!("读取文件失败: {}", e)))?
                         .to_vec(),
                 );
             }
@@ -43,128 +69,500 @@ pub async fn handle_audio_transcription(
                 model = field.text().await.unwrap_or(model);
             }
             "prompt" => {
-                prompt = field.text().await.unwrap_or(prompt);
+                prompt = field.text().await.ok().filter(|p| !p.trim().is_empty());
+            }
+            "response_format" => {
+                response_format = field.text().await.ok();
+            }
+            "language" => {
+                language = field.text().await.ok().filter(|l| !l.trim().is_empty());
             }
             _ => {}
         }
     }
 
-    let audio_bytes = audio_data.ok_or((StatusCode::BAD_REQUEST, "缺少音频文件".to_string()))?;
+    let audio = audio_data.ok_or_else(|| ProxyError::invalid_request("缺少音频文件"))?;
+    let file_name = filename.ok_or_else(|| ProxyError::invalid_request("无法获取文件名"))?;
+
+    Ok(AudioForm {
+        audio,
+        file_name,
+        model,
+        prompt,
+        response_format,
+        language,
+    })
+}
+
+/// 发送 Gemini 请求 (带账号轮换与重试)，返回解包后的响应与所用账号
+async fn call_gemini_audio(
+    state: &AppState,
+    model: &str,
+    gemini_request: Value,
+    trace_id: &str,
+) -> Result<(Value, String), ProxyError> {
+    let token_manager = state.token_manager.clone();
+    let max_attempts = MAX_RETRY_ATTEMPTS.min(token_manager.len()).max(1);
+    let mut last_error = String::new();
+    // 上一次失败命中的重试规则是否要求轮换账号
+    let mut rotate_on_retry = true;
+    // 各重试规则已命中的失败次数
+    let mut retry_attempts = RetryAttempts::default();
+
+    for attempt in 0..max_attempts {
+        let force_rotate = attempt > 0 && rotate_on_retry;
+        rotate_on_retry = true;
+        let (access_token, project_id, email, account_id, _wait_ms) = token_manager
+            .get_token("text", force_rotate, None, model)
+            .await
+            .map_err(|e| ProxyError::new(token_error_status(&e), format!("Token error: {}", e)))?;
+
+        info!("[{}] 使用账号: {}", trace_id, email);
+
+        // 包装请求为 v1internal 格式
+        let wrapped_body = json!({
+            "project": project_id,
+            "requestId": format!("audio-{}", Uuid::new_v4()),
+            "request": gemini_request.clone(),
+            "model": model,
+            "userAgent": "antigravity",
+            "requestType": "text"
+        });
+
+        let response = match state
+            .upstream
+            .call_v1_internal(
+                "generateContent",
+                &access_token,
+                wrapped_body,
+                None,
+                Some(account_id.as_str()),
+            )
+            .await
+        {
+            Ok(r) => r.response,
+            Err(e) => {
+                last_error = e.clone();
+                warn!(
+                    "[{}] Audio request failed on attempt {}/{}: {}",
+                    trace_id,
+                    attempt + 1,
+                    max_attempts,
+                    e
+                );
+                continue;
+            }
+        };
 
-    let file_name = filename.ok_or((StatusCode::BAD_REQUEST, "无法获取文件名".to_string()))?;
+        let status = response.status();
+        if status.is_success() {
+            let result: Value = response
+                .json()
+                .await
+                .map_err(|e| ProxyError::bad_gateway(format!("解析响应失败: {}", e)))?;
+            // 解包 v1internal 响应
+            let inner = match result {
+                Value::Object(mut map) if map.contains_key("response") => {
+                    map.remove("response").unwrap_or(Value::Null)
+                }
+                other => other,
+            };
+            return Ok((inner, email));
+        }
+
+        let status_code = status.as_u16();
+        let error_text = response
+            .text()
+            .await
+            .unwrap_or_else(|_| format!("HTTP {}", status_code));
+        last_error = format!("HTTP {}: {}", status_code, error_text);
+
+        let decision = resolve_retry_decision(status_code, &error_text, false);
+        if apply_retry_decision(&decision, &mut retry_attempts, attempt, max_attempts, status_code, trace_id).await {
+            rotate_on_retry = decision.rotate_account;
+            continue;
+        }
+
+        return Err(ProxyError::upstream(status, &error_text));
+    }
+
+    Err(ProxyError::new(
+        StatusCode::TOO_MANY_REQUESTS,
+        format!("All accounts exhausted. Last error: {}", last_error),
+    ))
+}
+
+/// 拼接 candidates[0] 中的文本
+fn extract_text(response: &Value) -> String {
+    response["candidates"][0]["content"]["parts"]
+        .as_array()
+        .map(|parts| {
+            parts
+                .iter()
+                .filter(|p| !p["thought"].as_bool().unwrap_or(false))
+                .filter_map(|p| p["text"].as_str())
+                .collect::<String>()
+        })
+        .unwrap_or_default()
+}
+
+/// 从 usageMetadata 提取 (input, output) token 数
+fn extract_usage(response: &Value) -> (u64, u64) {
+    let usage = &response["usageMetadata"];
+    (
+        usage["promptTokenCount"].as_u64().unwrap_or(0),
+        usage["candidatesTokenCount"].as_u64().unwrap_or(0),
+    )
+}
+
+/// 转录 / 翻译的公共流程：长音频切分后并行发送到不同账号，再按时间顺序拼接
+async fn run_audio_to_text(
+    state: &AppState,
+    form: AudioForm,
+    mut instruction: String,
+    task: &str,
+    trace_id: &str,
+) -> Result<axum::response::Response, ProxyError> {
+    let format = TranscriptFormat::parse(form.response_format.as_deref())
+        .map_err(ProxyError::invalid_request)?;
+    let mime_type = AudioProcessor::detect_audio_mime(&form.file_name, &form.audio)
+        .map_err(ProxyError::invalid_request)?;
+    let chunks = chunker::split_audio(
+        &form.audio,
+        &mime_type,
+        chunker::MAX_CHUNK_SECS,
+        chunker::MAX_CHUNK_BYTES,
+    )
+    .map_err(|e| ProxyError::new(StatusCode::PAYLOAD_TOO_LARGE, e))?;
+    if chunks.len() > 1 {
+        info!(
+            "[{}] 长音频切分为 {} 段 ({})",
+            trace_id,
+            chunks.len(),
+            mime_type
+        );
+    }
+
+    if let Some(language) = &form.language {
+        instruction.push_str(&format!("\nThe spoken language is {}.", language));
+    }
+    let mut generation_config = json!({});
+    if format.needs_segments() {
+        instruction.push_str(
+            "\nSplit the result into segments of consecutive speech. For each segment give start \
+            and end times in seconds from the beginning of this audio clip. Also report the spoken \
+            language as a lowercase English name (e.g. \"english\").",
+        );
+        generation_config = json!({
+            "responseMimeType": "application/json",
+            "responseSchema": transcript::segments_schema()
+        });
+    }
+
+    let model = form.model.as_str();
+    let instruction = instruction.as_str();
+    let generation_config = &generation_config;
+    let results: Vec<(f64, Option<f64>, Value, String)> =
+        futures::stream::iter(chunks.into_iter().enumerate())
+            .map(|(i, chunk)| {
+                let gemini_request = json!({
+                    "contents": [{
+                        "parts": [
+                            {"text": instruction},
+                            {
+                                "inlineData": {
+                                    "mimeType": chunk.mime_type,
+                                    "data": AudioProcessor::encode_to_base64(&chunk.data)
+                                }
+                            }
+                        ]
+                    }],
+                    "generationConfig": generation_config
+                });
+                let chunk_trace = format!("{}#{}", trace_id, i);
+                async move {
+                    let (response, email) =
+                        call_gemini_audio(state, model, gemini_request, &chunk_trace).await?;
+                    Ok::<_, ProxyError>((chunk.offset_secs, chunk.duration_secs, response, email))
+                }
+            })
+            .buffered(MAX_PARALLEL_CHUNKS)
+            .try_collect()
+            .await?;
+
+    // 按片段顺序拼接文本与分段
+    let mut texts: Vec<String> = Vec::new();
+    let mut segments: Vec<Segment> = Vec::new();
+    let mut language: Option<String> = None;
+    let mut emails: Vec<String> = Vec::new();
+    let (mut input_tokens, mut output_tokens) = (0u64, 0u64);
+    let mut duration = 0.0f64;
+    for (offset, chunk_duration, response, email) in results {
+        let (input, output) = extract_usage(&response);
+        input_tokens += input;
+        output_tokens += output;
+        if !emails.contains(&email) {
+            emails.push(email);
+        }
+        if let Some(d) = chunk_duration {
+            duration = duration.max(offset + d);
+        }
+
+        let text = extract_text(&response);
+        if !format.needs_segments() {
+            if !text.trim().is_empty() {
+                texts.push(text.trim().to_string());
+            }
+            continue;
+        }
+        match transcript::parse_segments(&text, offset, chunk_duration) {
+            Some((lang, parsed)) => {
+                language = language.or(lang);
+                texts.extend(parsed.iter().map(|s| s.text.clone()));
+                segments.extend(parsed);
+            }
+            None if !text.trim().is_empty() => {
+                // 模型未按 schema 返回：整个片段作为一个分段
+                warn!("[{}] 分段结果解析失败，按整段处理", trace_id);
+                segments.push(Segment {
+                    start: offset,
+                    end: offset + chunk_duration.unwrap_or(0.0),
+                    text: text.trim().to_string(),
+                });
+                texts.push(text.trim().to_string());
+            }
+            None => {}
+        }
+    }
+    if duration == 0.0 {
+        duration = segments.last().map_or(0.0, |s| s.end);
+    }
+    let text = texts.join(" ");
+
+    info!(
+        "[{}] 音频处理完成，返回 {} 字符 ({:?})",
+        trace_id,
+        text.len(),
+        format
+    );
+
+    let usage = json!({
+        "type": "tokens",
+        "input_tokens": input_tokens,
+        "output_tokens": output_tokens,
+        "total_tokens": input_tokens + output_tokens
+    });
+    let (content_type, body) = match format {
+        TranscriptFormat::Json => (
+            "application/json",
+            json!({"text": text, "usage": usage}).to_string(),
+        ),
+        TranscriptFormat::VerboseJson => {
+            let language = if task == "translate" {
+                Some("english".to_string())
+            } else {
+                language.or(form.language.clone())
+            };
+            (
+                "application/json",
+                json!({
+                    "task": task,
+                    "language": language.unwrap_or_default(),
+                    "duration": duration,
+                    "text": text,
+                    "segments": transcript::to_verbose_segments(&segments),
+                    "usage": usage
+                })
+                .to_string(),
+            )
+        }
+        TranscriptFormat::Text => ("text/plain; charset=utf-8", text),
+        TranscriptFormat::Srt => ("text/plain; charset=utf-8", transcript::to_srt(&segments)),
+        TranscriptFormat::Vtt => ("text/vtt; charset=utf-8", transcript::to_vtt(&segments)),
+    };
+
+    Ok((
+        StatusCode::OK,
+        [
+            (header::CONTENT_TYPE.as_str(), content_type.to_string()),
+            ("X-Account-Email", emails.join(", ")),
+            ("X-Mapped-Model", form.model.clone()),
+            // 纯文本格式没有 usage 字段，通过响应头交给监控中间件记账
+            ("X-Usage-Input-Tokens", input_tokens.to_string()),
+            ("X-Usage-Output-Tokens", output_tokens.to_string()),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+/// 处理音频转录请求 (OpenAI Whisper API 兼容)
+pub async fn handle_audio_transcription(
+    State(state): State<AppState>,
+    multipart: Multipart,
+) -> Result<impl IntoResponse, ProxyError> {
+    let trace_id = format!("audio_{}", chrono::Utc::now().timestamp_subsec_millis());
+    let form = parse_audio_form(multipart).await?;
 
     info!(
         "收到音频转录请求: 文件={}, 大小={} bytes, 模型={}",
-        file_name,
-        audio_bytes.len(),
-        model
+        form.file_name,
+        form.audio.len(),
+        form.model
     );
 
-    // 2. 检测 MIME 类型
-    let mime_type =
-        AudioProcessor::detect_mime_type(&file_name).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
-
-    // 3. 验证文件大小
-    if AudioProcessor::exceeds_size_limit(audio_bytes.len()) {
-        let size_mb = audio_bytes.len() as f64 / (1024.0 * 1024.0);
-        return Err((
-            StatusCode::PAYLOAD_TOO_LARGE,
-            format!(
-                "音频文件过大 ({:.1} MB)。最大支持 15 MB (约 16 分钟 MP3)。建议: 1) 压缩音频质量 2) 分段上传",
-                size_mb
-            ),
-        ));
+    let instruction = form
+        .prompt
+        .clone()
+        .unwrap_or_else(|| "Generate a transcript of the speech.".to_string());
+    run_audio_to_text(&state, form, instruction, "transcribe", &trace_id).await
+}
+
+/// 处理音频翻译请求：转录并翻译为英文 (OpenAI /v1/audio/translations 兼容)
+pub async fn handle_audio_translation(
+    State(state): State<AppState>,
+    multipart: Multipart,
+) -> Result<impl IntoResponse, ProxyError> {
+    let trace_id = format!("audio_{}", chrono::Utc::now().timestamp_subsec_millis());
+    let form = parse_audio_form(multipart).await?;
+
+    info!(
+        "收到音频翻译请求: 文件={}, 大小={} bytes, 模型={}",
+        form.file_name,
+        form.audio.len(),
+        form.model
+    );
+
+    // prompt 在 OpenAI 语义中是风格/上下文提示，不替换翻译指令
+    let mut instruction = "Transcribe the speech and translate it into English. \
+        Output only the English translation, without any commentary."
+        .to_string();
+    if let Some(prompt) = &form.prompt {
+        instruction.push_str("\nContext: ");
+        instruction.push_str(prompt);
+    }
+    run_audio_to_text(&state, form, instruction, "translate", &trace_id).await
+}
+
+/// 处理语音合成请求 (OpenAI /v1/audio/speech 兼容，使用 Gemini TTS 模型)
+pub async fn handle_audio_speech(
+    State(state): State<AppState>,
+    Json(body): Json<Value>,
+) -> Result<impl IntoResponse, ProxyError> {
+    let trace_id = format!("tts_{}", chrono::Utc::now().timestamp_subsec_millis());
+
+    let input = body["input"]
+        .as_str()
+        .filter(|s| !s.trim().is_empty())
+        .ok_or_else(|| ProxyError::invalid_request("缺少 input 文本"))?;
+    let model = AudioProcessor::resolve_tts_model(body["model"].as_str());
+    let voice = AudioProcessor::map_voice(body["voice"].as_str());
+    let format =
+        SpeechFormat::parse(body["response_format"].as_str()).map_err(ProxyError::invalid_request)?;
+
+    // Gemini TTS 通过自然语言控制风格与语速
+    let mut style: Vec<String> = Vec::new();
+    if let Some(instructions) = body["instructions"]
+        .as_str()
+        .filter(|s| !s.trim().is_empty())
+    {
+        style.push(instructions.trim().to_string());
+    }
+    if let Some(speed) = body["speed"]
+        .as_f64()
+        .filter(|s| (*s - 1.0).abs() > f64::EPSILON)
+    {
+        if !(0.25..=4.0).contains(&speed) {
+            return Err(ProxyError::invalid_request("speed 取值范围为 0.25 - 4.0"));
+        }
+        style.push(format!("Speak at {:.2}x normal speed", speed));
     }
+    let prompt = if style.is_empty() {
+        input.to_string()
+    } else {
+        format!("{}: {}", style.join(". "), input)
+    };
 
-    // 4. 使用 Inline Data 方式
-    debug!("使用 Inline Data 方式处理");
-    let base64_audio = AudioProcessor::encode_to_base64(&audio_bytes);
+    info!(
+        "[{}] 收到语音合成请求: 模型={}, 音色={}, 格式={:?}, 长度={} 字符",
+        trace_id,
+        model,
+        voice,
+        format,
+        input.chars().count()
+    );
 
-    // 5. 构建 Gemini 请求
     let gemini_request = json!({
         "contents": [{
-            "parts": [
-                {"text": prompt},
-                {
-                    "inlineData": {
-                        "mimeType": mime_type,
-                        "data": base64_audio
-                    }
+            "role": "user",
+            "parts": [{"text": prompt}]
+        }],
+        "generationConfig": {
+            "responseModalities": ["AUDIO"],
+            "speechConfig": {
+                "voiceConfig": {
+                    "prebuiltVoiceConfig": {"voiceName": voice}
                 }
-            ]
-        }]
+            }
+        }
     });
 
-    // 6. 获取 Token 和上游客户端
-    let token_manager = state.token_manager;
-    let (access_token, project_id, email, account_id, _wait_ms) = token_manager
-        .get_token("text", false, None, &model)
-        .await
-        .map_err(|e| (StatusCode::SERVICE_UNAVAILABLE, e))?;
-
-    info!("使用账号: {}", email);
-
-    // 7. 包装请求为 v1internal 格式
-    let wrapped_body = json!({
-        "project": project_id,
-        "requestId": format!("audio-{}", Uuid::new_v4()),
-        "request": gemini_request,
-        "model": model,
-        "userAgent": "antigravity",
-        "requestType": "text"
-    });
+    let (response, email) = call_gemini_audio(&state, &model, gemini_request, &trace_id).await?;
 
-    // 8. 发送请求到 Gemini
-    let upstream = state.upstream.clone();
-    let response = upstream
-        .call_v1_internal(
-            "generateContent",
-            &access_token,
-            wrapped_body,
-            None,
-            Some(account_id.as_str()),
-        )
-        .await
-        .map_err(|e| (StatusCode::BAD_GATEWAY, format!("上游请求失败: {}", e)))?
-        .response;
+    let inline = response["candidates"][0]["content"]["parts"]
+        .as_array()
+        .and_then(|parts| parts.iter().find_map(|p| p.get("inlineData")))
+        .ok_or_else(|| ProxyError::bad_gateway("上游未返回音频数据"))?;
+    let upstream_mime = inline["mimeType"].as_str().unwrap_or("audio/L16");
+    let audio = general_purpose::STANDARD
+        .decode(inline["data"].as_str().unwrap_or(""))
+        .map_err(|e| ProxyError::bad_gateway(format!("音频数据解码失败: {}", e)))?;
 
-    if !response.status().is_success() {
-        let error_text = response
-            .text()
-            .await
-            .unwrap_or_else(|_| "Unknown error".to_string());
-        return Err((
-            StatusCode::BAD_GATEWAY,
-            format!("Gemini API 错误: {}", error_text),
-        ));
-    }
+    // Gemini TTS 输出 16-bit 单声道 PCM；若上游已是所请求的封装格式则原样返回
+    let is_pcm = upstream_mime.starts_with("audio/L16") || upstream_mime.contains("pcm");
+    let audio = match (is_pcm, format) {
+        (true, SpeechFormat::Wav) => {
+            let rate = AudioProcessor::parse_pcm_sample_rate(upstream_mime);
+            AudioProcessor::pcm_to_wav(&audio, rate, 1)
+        }
+        (true, SpeechFormat::Mp3) => {
+            let rate = AudioProcessor::parse_pcm_sample_rate(upstream_mime);
+            tokio::task::spawn_blocking(move || AudioProcessor::pcm_to_mp3(&audio, rate, 1))
+                .await
+                .map_err(|e| ProxyError::internal(format!("MP3 编码任务失败: {}", e)))?
+                .map_err(ProxyError::internal)?
+        }
+        (true, SpeechFormat::Pcm) => audio,
+        (false, _) if upstream_mime.starts_with(format.content_type()) => audio,
+        (false, _) => {
+            return Err(ProxyError::bad_gateway(format!(
+                "上游返回的音频格式 {} 无法转换为 {}",
+                upstream_mime,
+                format.content_type()
+            )))
+        }
+    };
+    let content_type = format.content_type().to_string();
+
+    let (input_tokens, output_tokens) = extract_usage(&response);
+    info!(
+        "[{}] 语音合成完成: {} bytes ({})",
+        trace_id,
+        audio.len(),
+        content_type
+    );
 
-    let result: Value = response
-        .json()
-        .await
-        .map_err(|e| (StatusCode::BAD_GATEWAY, format!("解析响应失败: {}", e)))?;
-
-    // 9. 提取文本响应（解包 v1internal 响应）
-    let inner_response = result.get("response").unwrap_or(&result);
-    let text = inner_response
-        .get("candidates")
-        .and_then(|c| c.get(0))
-        .and_then(|c| c.get("content"))
-        .and_then(|c| c.get("parts"))
-        .and_then(|p| p.get(0))
-        .and_then(|p| p.get("text"))
-        .and_then(|t| t.as_str())
-        .unwrap_or("");
-
-    info!("音频转录完成，返回 {} 字符", text.len());
-
-    // 10. 返回标准格式响应
     Ok((
         StatusCode::OK,
-        [("X-Account-Email", email.as_str())],
-        Json(json!({
-            "text": text
-        })),
+        [
+            (header::CONTENT_TYPE.as_str(), content_type),
+            ("X-Account-Email", email),
+            ("X-Mapped-Model", model),
+            // 二进制响应无法携带 usage 字段，通过响应头交给监控中间件记账
+            ("X-Usage-Input-Tokens", input_tokens.to_string()),
+            ("X-Usage-Output-Tokens", output_tokens.to_string()),
+        ],
+        audio,
     )
         .into_response())
 }
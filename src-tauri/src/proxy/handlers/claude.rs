@@ -20,6 +20,7 @@ use crate::proxy::mappers::claude::{
 };
 use crate::proxy::server::AppState;
 use crate::proxy::mappers::context_manager::ContextManager;
+use crate::proxy::mappers::context_compression::{compression_context_limit, CONTEXT_SUMMARY_PROMPT};
 use crate::proxy::mappers::estimation_calibrator::get_calibrator;
 use crate::proxy::debug_logger;
 use crate::proxy::upstream::client::mask_email;
@@ -160,66 +161,6 @@ const MAX_RETRY_ATTEMPTS: usize = 3;
 // These can be adjusted for performance/cost optimization or overridden by custom_mapping
 const INTERNAL_BACKGROUND_TASK: &str = "internal-background-task";  // Unified virtual ID for all background tasks
 
-// ===== Layer 3: XML Summary Prompt Template =====
-// Borrowed from Practical-Guide-to-Context-Engineering + Claude Code official practice
-// This prompt generates a structured 8-section XML summary for context compression
-const CONTEXT_SUMMARY_PROMPT: &str = r#"You are a context compression specialist. Your task is to create a structured XML snapshot of the conversation history.
-
-This snapshot will become the Agent's ONLY memory of the past. All key details, plans, errors, and user instructions MUST be preserved.
-
-First, think through the entire history in a private <scratchpad>. Review the user's overall goal, the agent's actions, tool outputs, file modifications, and any unresolved issues. Identify every piece of information critical for future actions.
-
-After reasoning, generate the final <state_snapshot> XML object. Information must be extremely dense. Omit any irrelevant conversational filler.
-
-The structure MUST be as follows:
-
-<state_snapshot>
-  <overall_goal>
-    <!-- Describe the user's high-level goal in one concise sentence -->
-  </overall_goal>
-  
-  <technical_context>
-    <!-- Tech stack: frameworks, languages, toolchain, dependency versions -->
-  </technical_context>
-  
-  <file_system_state>
-    <!-- List files that were created, read, modified, or deleted. Note their status -->
-  </file_system_state>
-  
-  <code_changes>
-    <!-- Key code snippets (preserve function signatures and important logic) -->
-  </code_changes>
-  
-  <debugging_history>
-    <!-- List all errors encountered, with stack traces, and how they were fixed -->
-  </debugging_history>
-  
-  <current_plan>
-    <!-- Step-by-step plan. Mark completed steps -->
-  </current_plan>
-  
-  <user_preferences>
-    <!-- User's work preferences for this project (test commands, code style, etc.) -->
-  </user_preferences>
-  
-  <key_decisions>
-    <!-- Critical architectural decisions and design choices -->
-  </key_decisions>
-  
-  <latest_thinking_signature>
-    <!-- [CRITICAL] Preserve the last valid thinking signature -->
-    <!-- Format: base64-encoded signature string -->
-    <!-- This MUST be copied exactly as-is, no modifications -->
-  </latest_thinking_signature>
-</state_snapshot>
-
-**IMPORTANT**:
-1. Code snippets must be complete, including function signatures and key logic
-2. Error messages must be preserved verbatim, including line numbers and stacks
-3. File paths must use absolute paths
-4. The thinking signature must be copied exactly, no modifications
-"#;
-
 // ===== Jitter Configuration (REMOVED) =====
 // Jitter was causing connection instability, reverted to fixed delays
 // const JITTER_FACTOR: f64 = 0.2;
@@ -232,7 +173,9 @@ The structure MUST be as follows:
 
 // ===== 统一退避策略模块 =====
 // 移除本地重复定义，使用 common 中的统一实现
-use super::common::{determine_retry_strategy, apply_retry_strategy, should_rotate_account, RetryStrategy};
+use super::common::{
+    apply_retry_decision, apply_retry_strategy, resolve_retry_decision, RetryAttempts, RetryStrategy,
+};
 
 // ===== 退避策略模块结束 =====
 
@@ -242,7 +185,7 @@ use super::common::{determine_retry_strategy, apply_retry_strategy, should_rotat
 pub async fn handle_messages(
     State(state): State<AppState>,
     headers: HeaderMap,
-    Json(body): Json<Value>,
+    Json(mut body): Json<Value>,
 ) -> Response {
     // [FIX] 保存原始请求体的完整副本，用于日志记录
     // 这确保了即使结构体定义遗漏字段，日志也能完整记录所有参数
@@ -256,6 +199,9 @@ pub async fn handle_messages(
         .map(char::from)
         .collect::<String>().to_lowercase();
     let debug_cfg = state.debug_logging.read().await.clone();
+
+    // 转发前缩放并重新编码内联图片
+    crate::proxy::common::image_preprocess::preprocess_request_images(&mut body, &trace_id);
     
     // [NEW] Detect Client Adapter
     // 检查是否有匹配的客户端适配器（如 opencode）
@@ -523,6 +469,10 @@ pub async fn handle_messages(
     let mut last_email: Option<String> = None;
     let mut last_mapped_model: Option<String> = None;
     let mut last_status = StatusCode::SERVICE_UNAVAILABLE; // Default to 503 if no response reached
+    // 上一次失败命中的重试规则是否要求轮换账号
+    let mut rotate_on_retry = true;
+    // 各重试规则已命中的失败次数
+    let mut retry_attempts = RetryAttempts::default();
     
     for attempt in 0..max_attempts {
         // 2. 模型路由解析
@@ -552,8 +502,9 @@ pub async fn handle_messages(
         let session_id_str = crate::proxy::session_manager::SessionManager::extract_session_id(&request_for_body);
         let session_id = Some(session_id_str.as_str());
 
-        let force_rotate_token = attempt > 0;
-        let (access_token, project_id, email, account_id, _wait_ms) = match token_manager.get_token(&config.request_type, force_rotate_token, session_id, &config.final_model).await {
+        let force_rotate_token = attempt > 0 && rotate_on_retry;
+        rotate_on_retry = true;
+        let (access_token, project_id, mut email, mut account_id, _wait_ms) = match token_manager.get_token(&config.request_type, force_rotate_token, session_id, &config.final_model).await {
             Ok(t) => t,
             Err(e) => {
                 let safe_message = if e.contains("invalid_grant") {
@@ -564,8 +515,14 @@ pub async fn handle_messages(
                 let headers = [
                     ("X-Mapped-Model", mapped_model.as_str()),
                 ];
+                // 准入队列已满时按 Anthropic 约定返回 529 overloaded
+                let status = if crate::proxy::admission::is_queue_full_error(&safe_message) {
+                    StatusCode::from_u16(529).unwrap_or(StatusCode::TOO_MANY_REQUESTS)
+                } else {
+                    StatusCode::SERVICE_UNAVAILABLE
+                };
                  return (
-                    StatusCode::SERVICE_UNAVAILABLE,
+                    status,
                     headers,
                     Json(json!({
                         "type": "error",
@@ -639,21 +596,18 @@ pub async fn handle_messages(
         
         if !retried_without_thinking && scaling_enabled {  // 新增 scaling_enabled 联动判断
             // 1. Determine context limit (Flash: ~1M, Pro: ~2M)
-            let context_limit = if mapped_model.contains("flash") {
-                1_000_000
-            } else {
-                2_000_000
-            };
+            let context_limit = compression_context_limit(&mapped_model);
 
             // 2. [ENHANCED] 使用校准器提高估算准确度 (PR #925)
-            let raw_estimated = ContextManager::estimate_token_usage(&request_with_mapped);
+            let raw_estimated = ContextManager::estimate_token_usage(&request_with_mapped, &mapped_model);
             let calibrator = get_calibrator();
-            let mut estimated_usage = calibrator.calibrate(raw_estimated);
+            let mut estimated_usage = calibrator.calibrate(&raw_estimated);
             let mut usage_ratio = estimated_usage as f32 / context_limit as f32;
             
             info!(
-                "[{}] [ContextManager] Context pressure: {:.1}% (raw: {}, calibrated: {} / {}), Calibration factor: {:.2}",
-                trace_id, usage_ratio * 100.0, raw_estimated, estimated_usage, context_limit, calibrator.get_factor()
+                "[{}] [ContextManager] Context pressure: {:.1}% (raw: {}, calibrated: {} / {}), Calibration factor ({}): {:.2}",
+                trace_id, usage_ratio * 100.0, raw_estimated.total(), estimated_usage, context_limit,
+                raw_estimated.family.as_str(), calibrator.get_factor(raw_estimated.family)
             );
 
             // ===== Layer 1: Tool Message Trimming (L1 threshold) =====
@@ -668,8 +622,8 @@ pub async fn handle_messages(
                     compression_applied = true;
                     
                     // Re-estimate after trimming (with calibration)
-                    let new_raw = ContextManager::estimate_token_usage(&request_with_mapped);
-                    let new_usage = calibrator.calibrate(new_raw);
+                    let new_raw = ContextManager::estimate_token_usage(&request_with_mapped, &mapped_model);
+                    let new_usage = calibrator.calibrate(&new_raw);
                     let new_ratio = new_usage as f32 / context_limit as f32;
                     
                     info!(
@@ -707,8 +661,8 @@ pub async fn handle_messages(
                     is_purified = true; // Still breaks cache, but preserves signatures
                     compression_applied = true;
                     
-                    let new_raw = ContextManager::estimate_token_usage(&request_with_mapped);
-                    let new_usage = calibrator.calibrate(new_raw);
+                    let new_raw = ContextManager::estimate_token_usage(&request_with_mapped, &mapped_model);
+                    let new_usage = calibrator.calibrate(&new_raw);
                     let new_ratio = new_usage as f32 / context_limit as f32;
                     
                     info!(
@@ -745,8 +699,8 @@ pub async fn handle_messages(
                         is_purified = false; // Fork doesn't break cache!
                         
                         // Re-estimate after fork (with calibration)
-                        let new_raw = ContextManager::estimate_token_usage(&request_with_mapped);
-                        let new_usage = calibrator.calibrate(new_raw);
+                        let new_raw = ContextManager::estimate_token_usage(&request_with_mapped, &mapped_model);
+                        let new_usage = calibrator.calibrate(&new_raw);
                         let new_ratio = new_usage as f32 / context_limit as f32;
                         
                         info!(
@@ -780,9 +734,9 @@ pub async fn handle_messages(
         // [FIX] Estimate AFTER purification to get accurate token count for calibrator learning
         // Only estimate for calibrator when content was not purified, to avoid skewed learning
         let raw_estimated = if !is_purified {
-            ContextManager::estimate_token_usage(&request_with_mapped)
+            Some(ContextManager::estimate_token_usage(&request_with_mapped, &mapped_model))
         } else {
-            0 // Don't record calibration data when content was purified
+            None // Don't record calibration data when content was purified
         };
 
         request_with_mapped.model = mapped_model.clone();
@@ -863,14 +817,120 @@ pub async fn handle_messages(
 
         // Upstream call configuration continued...
 
-        let call_result = match upstream
-            .call_v1_internal_with_headers(method, &access_token, gemini_body, query, extra_headers.clone(), Some(account_id.as_str()))
-            .await {
-            Ok(r) => r,
-            Err(e) => {
-                last_error = e.clone();
-                debug!("Request failed on attempt {}/{}: {}", attempt + 1, max_attempts, e);
-                continue;
+        // [NEW] 流式中断续传需要保留原始请求体
+        let resume_cfg = crate::proxy::config::get_retry_policy_config().stream_resume;
+        let resume_body = (actual_stream && resume_cfg.enabled).then(|| gemini_body.clone());
+        let continue_cfg = crate::proxy::config::get_auto_continue_config();
+        let continue_body = (actual_stream && continue_cfg.enabled).then(|| gemini_body.clone());
+
+        // [NEW] 请求对冲：后台任务的非流式短请求超过阈值仍未返回时，在第二个账号上发出相同请求
+        // 只对冲上游调用本身，胜出者的响应继续走下方的正常处理 (状态码处理、签名重试、收集与用量记录)，
+        // 落败请求随 future 丢弃而取消
+        let hedge_threshold = if background_task_type.is_some() && !client_wants_stream {
+            crate::proxy::hedging::threshold(
+                &crate::proxy::config::get_hedging_config(),
+                &request_with_mapped.model,
+            )
+        } else {
+            None
+        };
+        let call_result = if let Some(threshold) = hedge_threshold {
+            // 暂存主请求的账号租约，避免对冲账号的租约将其替换释放
+            let primary_lease = crate::proxy::account_load::detach();
+            let hedge_body = gemini_body.clone();
+            // 竞速中两路都只以 2xx 作为成功；主请求的错误响应暂存于此，竞速失败后交给下方的状态码处理
+            let primary_failure: std::sync::Mutex<Option<crate::proxy::upstream::client::UpstreamCallResult>> =
+                std::sync::Mutex::new(None);
+            // 记录失败账号的限流状态并返回错误描述
+            let note_failure = |failed_email: String, result: crate::proxy::upstream::client::UpstreamCallResult| {
+                let token_manager = &token_manager;
+                let model = request_with_mapped.model.as_str();
+                async move {
+                    let status_code = result.response.status().as_u16();
+                    let retry_after = result.response.headers().get("Retry-After").and_then(|h| h.to_str().ok()).map(|s| s.to_string());
+                    let error_text = result.response.text().await.unwrap_or_default();
+                    if matches!(status_code, 429 | 529 | 503 | 500 | 404) {
+                        token_manager
+                            .mark_rate_limited_async(&failed_email, status_code, retry_after.as_deref(), &error_text, Some(model))
+                            .await;
+                    }
+                    format!("HTTP {}: {}", status_code, error_text)
+                }
+            };
+            let primary = async {
+                let result = upstream
+                    .call_v1_internal_with_headers(method, &access_token, gemini_body, query, extra_headers.clone(), Some(account_id.as_str()))
+                    .await?;
+                let status_code = result.response.status().as_u16();
+                if !result.response.status().is_success() {
+                    *primary_failure.lock().unwrap_or_else(|e| e.into_inner()) = Some(result);
+                    return Err(format!("HTTP {}", status_code));
+                }
+                Ok((result, email.clone(), account_id.clone()))
+            };
+            let hedge = || async {
+                let (hedge_token, hedge_project, hedge_email, hedge_account_id, _) = token_manager
+                    .get_token(&config.request_type, true, None, &config.final_model)
+                    .await?;
+                if hedge_account_id == account_id {
+                    return Err("No alternative account available for hedging".to_string());
+                }
+                let mut body = hedge_body;
+                body["project"] = json!(hedge_project);
+                body["requestId"] = json!(format!("agent-{}", uuid::Uuid::new_v4()));
+                let result = upstream
+                    .call_v1_internal_with_headers(method, &hedge_token, body, query, extra_headers.clone(), Some(hedge_account_id.as_str()))
+                    .await?;
+                if !result.response.status().is_success() {
+                    return Err(note_failure(hedge_email, result).await);
+                }
+                Ok((result, hedge_email, hedge_account_id))
+            };
+
+            let raced = crate::proxy::hedging::race(&request_with_mapped.model, threshold, primary, hedge).await;
+            let primary_failure = primary_failure.into_inner().unwrap_or_else(|e| e.into_inner());
+            match raced {
+                Ok(((result, winner_email, winner_account_id), hedge_won)) => {
+                    if hedge_won {
+                        info!("[{}] ✓ Hedge request won on account {}", trace_id, mask_email(&winner_email));
+                        if let Some(failed) = primary_failure {
+                            let error = note_failure(email.clone(), failed).await;
+                            debug!("[{}] Primary request failed before the hedge won: {}", trace_id, error);
+                        }
+                        drop(primary_lease);
+                        email = winner_email;
+                        account_id = winner_account_id;
+                        last_email = Some(email.clone());
+                    } else if let Some(lease) = primary_lease {
+                        crate::proxy::account_load::attach(lease);
+                    }
+                    result
+                }
+                Err(e) => {
+                    if let Some(lease) = primary_lease {
+                        crate::proxy::account_load::attach(lease);
+                    }
+                    match primary_failure {
+                        // 主请求的错误响应按未对冲时的流程处理 (限流标记、签名重试、账号轮换)
+                        Some(failed) => failed,
+                        None => {
+                            last_error = e;
+                            debug!("Hedged request failed on attempt {}/{}: {}", attempt + 1, max_attempts, last_error);
+                            continue;
+                        }
+                    }
+                }
+            }
+        } else {
+            match upstream
+                .call_v1_internal_with_headers(method, &access_token, gemini_body, query, extra_headers.clone(), Some(account_id.as_str()))
+                .await {
+                Ok(r) => r,
+                Err(e) => {
+                    last_error = e.clone();
+                    debug!("Request failed on attempt {}/{}: {}", attempt + 1, max_attempts, e);
+                    continue;
+                }
             }
         };
 
@@ -922,13 +982,44 @@ pub async fn handle_messages(
                     "status": status.as_u16(),
                     "upstream_url": upstream_url,
                 });
-                let gemini_stream = debug_logger::wrap_reqwest_stream_with_debug(
+                let mut gemini_stream = debug_logger::wrap_reqwest_stream_with_debug(
                     Box::pin(response.bytes_stream()),
                     debug_cfg.clone(),
                     trace_id.clone(),
                     "upstream_response",
                     meta,
                 );
+                if let Some(body) = continue_body {
+                    gemini_stream = crate::proxy::upstream::continuation::continuing_stream(
+                        gemini_stream,
+                        crate::proxy::upstream::resume::ResumeContext {
+                            token_manager: token_manager.clone(),
+                            upstream: upstream.clone(),
+                            body,
+                            request_type: config.request_type.clone(),
+                            model: config.final_model.clone(),
+                            extra_headers: extra_headers.clone(),
+                            trace_id: trace_id.clone(),
+                        },
+                        continue_cfg,
+                        request.max_tokens,
+                    );
+                }
+                if let Some(body) = resume_body {
+                    gemini_stream = crate::proxy::upstream::resume::resumable_stream(
+                        gemini_stream,
+                        crate::proxy::upstream::resume::ResumeContext {
+                            token_manager: token_manager.clone(),
+                            upstream: upstream.clone(),
+                            body,
+                            request_type: config.request_type.clone(),
+                            model: config.final_model.clone(),
+                            extra_headers: extra_headers.clone(),
+                            trace_id: trace_id.clone(),
+                        },
+                        resume_cfg,
+                    );
+                }
 
                 let current_message_count = request_with_mapped.messages.len();
 
@@ -942,7 +1033,7 @@ pub async fn handle_messages(
                     Some(session_id_str.clone()),
                     scaling_enabled,
                     context_limit,
-                    Some(raw_estimated), // [FIX] Pass estimated tokens for calibrator learning
+                    raw_estimated, // [FIX] Pass estimated tokens for calibrator learning
                     current_message_count, // [NEW v4.0.0] Pass message count for rewind detection
                     client_adapter.clone(), // [NEW] Pass client adapter
                 );
@@ -951,17 +1042,14 @@ pub async fn handle_messages(
                 let mut retry_this_account = false;
 
                 // Loop to skip heartbeats during peek
+                // 心跳会持续到达，因此使用总截止时间而非单次读取超时
+                let peek_deadline = tokio::time::Instant::now() + std::time::Duration::from_secs(60);
                 loop {
-                    match tokio::time::timeout(std::time::Duration::from_secs(60), claude_stream.next()).await {
+                    match tokio::time::timeout_at(peek_deadline, claude_stream.next()).await {
                         Ok(Some(Ok(bytes))) => {
-                            if bytes.is_empty() {
-                                continue;
-                            }
-                            
-                            let text = String::from_utf8_lossy(&bytes);
                             // Skip SSE comments/pings
-                            if text.trim().starts_with(":") {
-                                debug!("[{}] Skipping peek heartbeat: {}", trace_id, text.trim());
+                            if crate::proxy::mappers::heartbeat::is_heartbeat(&bytes) {
+                                debug!("[{}] Skipping peek heartbeat: {}", trace_id, String::from_utf8_lossy(&bytes).trim());
                                 continue;
                             }
 
@@ -1242,7 +1330,7 @@ pub async fn handle_messages(
             }
             
             // [FIX] 强制重试：因为我们已经清理了 thinking block，所以这是一个新的、可以重试的请求
-            // 不要使用 determine_retry_strategy，因为它会因为 retried_without_thinking=true 而返回 NoRetry
+            // 不要使用 resolve_retry_decision，因为它会因为 retried_without_thinking=true 而返回 NoRetry
             if apply_retry_strategy(
                 RetryStrategy::FixedDelay(Duration::from_millis(200)), 
                 attempt, 
@@ -1285,14 +1373,15 @@ pub async fn handle_messages(
         }
 
         // 确定重试策略
-        let strategy = determine_retry_strategy(status_code, &error_text, retried_without_thinking);
+        let decision = resolve_retry_decision(status_code, &error_text, retried_without_thinking);
         
         // 执行退避
-        if apply_retry_strategy(strategy, attempt, max_attempts, status_code, &trace_id).await {
+        if apply_retry_decision(&decision, &mut retry_attempts, attempt, max_attempts, status_code, &trace_id).await {
             // 判断是否需要轮换账号
-            if !should_rotate_account(status_code) {
+            if !decision.rotate_account {
                 debug!("[{}] Keeping same account for status {} (server-side issue)", trace_id, status_code);
             }
+            rotate_on_retry = decision.rotate_account;
             continue;
         } else {
             // 5. 增强的 400 错误处理: Prompt Too Long 友好提示
@@ -1415,7 +1504,7 @@ pub async fn handle_list_models(State(state): State<AppState>) -> impl IntoRespo
     }))
 }
 
-/// 计算 tokens (占位符)
+/// 计算 tokens (本地 BPE 分词，按上游模型族的规则与校准系数换算)
 pub async fn handle_count_tokens(
     State(state): State<AppState>,
     headers: HeaderMap,
@@ -1436,9 +1525,34 @@ pub async fn handle_count_tokens(
         .await;
     }
 
+    let mut request: ClaudeRequest = match serde_json::from_value(body) {
+        Ok(r) => r,
+        Err(e) => {
+            return crate::proxy::common::error::ProxyError::invalid_request(format!(
+                "Invalid request body: {}",
+                e
+            ))
+            .into_response();
+        }
+    };
+    // count_tokens 只统计输入，不预留思考预算
+    request.thinking = None;
+    let mapped_model = crate::proxy::common::model_mapping::resolve_model_route(
+        &request.model,
+        &*state.custom_mapping.read().await,
+    );
+    // 整个请求都要分词，放到阻塞线程池中执行
+    let estimate = match tokio::task::spawn_blocking(move || {
+        ContextManager::estimate_token_usage(&request, &mapped_model)
+    })
+    .await
+    {
+        Ok(estimate) => estimate,
+        Err(e) => return crate::proxy::common::error::ProxyError::internal(e.to_string()).into_response(),
+    };
+
     Json(json!({
-        "input_tokens": 0,
-        "output_tokens": 0
+        "input_tokens": get_calibrator().calibrate(&estimate),
     }))
     .into_response()
 }
@@ -1,9 +1,21 @@
+use std::collections::HashMap;
 use tokio::time::{sleep, Duration};
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
+use crate::proxy::common::retry_budget;
+use crate::proxy::config::{BackoffKind, RetryErrorClass, RetryRule};
 use axum::{http::StatusCode, response::{IntoResponse, Response}, Json, extract::State};
 use serde_json::{json, Value};
 use crate::proxy::server::AppState;
 
+/// 获取账号失败时的响应状态码：准入队列已满返回 429，其余情况为 503
+pub fn token_error_status(error: &str) -> StatusCode {
+    if crate::proxy::admission::is_queue_full_error(error) {
+        StatusCode::TOO_MANY_REQUESTS
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    }
+}
+
 // ===== 统一重试与退避策略 =====
 
 /// 重试策略枚举
@@ -19,130 +31,242 @@ pub enum RetryStrategy {
     ExponentialBackoff { base_ms: u64, max_ms: u64 },
 }
 
-/// 根据错误状态码和错误信息确定重试策略
-pub fn determine_retry_strategy(
+/// 根据状态码和错误信息推断错误分类
+pub fn classify_error(status_code: u16, error_text: &str) -> RetryErrorClass {
+    if status_code == 400
+        && (error_text.contains("Invalid `signature`")
+            || error_text.contains("thinking.signature")
+            || error_text.contains("thinking.thinking")
+            || error_text.contains("Corrupted thought signature"))
+    {
+        return RetryErrorClass::ThinkingSignature;
+    }
+    if error_text.contains("QUOTA_EXHAUSTED") {
+        return RetryErrorClass::QuotaExhausted;
+    }
+    match status_code {
+        429 => RetryErrorClass::RateLimited,
+        503 | 529 => RetryErrorClass::Overloaded,
+        500..=599 => RetryErrorClass::ServerError,
+        401 | 403 => RetryErrorClass::Auth,
+        404 => RetryErrorClass::NotFound,
+        _ => RetryErrorClass::Other,
+    }
+}
+
+/// 一次失败后的重试决策 (由命中的重试规则得出)
+#[derive(Debug, Clone)]
+pub struct RetryDecision {
+    pub strategy: RetryStrategy,
+    /// 该类错误最多尝试次数 (0 = 仅受请求级上限约束)
+    pub max_attempts: usize,
+    pub rotate_account: bool,
+    pub jitter: f32,
+    /// 命中的规则下标 (用于按规则统计尝试次数)
+    pub rule: Option<usize>,
+}
+
+impl RetryDecision {
+    fn no_retry() -> Self {
+        Self {
+            strategy: RetryStrategy::NoRetry,
+            max_attempts: 0,
+            rotate_account: false,
+            jitter: 0.0,
+            rule: None,
+        }
+    }
+}
+
+/// 单个请求内每条重试规则已命中的失败次数
+///
+/// 规则级 max_attempts 只统计命中该规则的失败，先前其他类型的失败不占用其次数
+#[derive(Debug, Default)]
+pub struct RetryAttempts {
+    by_rule: HashMap<usize, usize>,
+}
+
+impl RetryAttempts {
+    /// 记录一次命中 rule 的失败，返回该规则累计的失败次数
+    fn record(&mut self, rule: usize) -> usize {
+        let count = self.by_rule.entry(rule).or_insert(0);
+        *count += 1;
+        *count
+    }
+}
+
+fn rule_matches(rule: &RetryRule, status_code: u16, class: RetryErrorClass) -> bool {
+    (rule.statuses.is_empty() || rule.statuses.contains(&status_code))
+        && rule.error_class.map_or(true, |c| c == class)
+}
+
+/// 按给定规则列表计算重试决策 (第一条命中的规则生效)
+pub fn resolve_retry_decision_with(
+    rules: &[RetryRule],
     status_code: u16,
     error_text: &str,
     retried_without_thinking: bool,
-) -> RetryStrategy {
-    match status_code {
-        // 400 错误：仅在特定 Thinking 签名失败时重试一次
-        400 if !retried_without_thinking
-            && (error_text.contains("Invalid `signature`")
-                || error_text.contains("thinking.signature")
-                || error_text.contains("thinking.thinking")
-                || error_text.contains("Corrupted thought signature")) =>
-        {
-            RetryStrategy::FixedDelay(Duration::from_millis(200))
-        }
+) -> RetryDecision {
+    let class = classify_error(status_code, error_text);
+    let Some((index, rule)) = rules
+        .iter()
+        .enumerate()
+        .find(|(_, r)| rule_matches(r, status_code, class))
+    else {
+        return RetryDecision::no_retry();
+    };
 
-        // 429 限流错误
-        429 => {
-            // 优先使用服务端返回的 Retry-After
-            if let Some(delay_ms) = crate::proxy::upstream::retry::parse_retry_delay(error_text) {
-                let actual_delay = delay_ms.saturating_add(200).min(30_000); // 上限上调至 30s
-                RetryStrategy::FixedDelay(Duration::from_millis(actual_delay))
-            } else {
-                // 否则使用线性退避：起始 5s，逐步增加
-                RetryStrategy::LinearBackoff { base_ms: 5000 }
-            }
-        }
+    // Thinking 签名错误只重试一次 (去掉 thinking 后仍失败则放弃)
+    if class == RetryErrorClass::ThinkingSignature && retried_without_thinking {
+        return RetryDecision::no_retry();
+    }
 
-        // 503 服务不可用 / 529 服务器过载
-        503 | 529 => {
-            // 指数退避：起始 10s，上限 60s (针对 Google 边缘节点过载)
-            RetryStrategy::ExponentialBackoff {
-                base_ms: 10000,
-                max_ms: 60000,
-            }
-        }
+    let retry_after = if rule.respect_retry_after {
+        crate::proxy::upstream::retry::parse_retry_delay(error_text)
+    } else {
+        None
+    };
 
-        // 500 服务器内部错误
-        500 => {
-            // 线性退避：起始 3s
-            RetryStrategy::LinearBackoff { base_ms: 3000 }
+    let strategy = if let Some(delay_ms) = retry_after {
+        // 优先使用服务端返回的 Retry-After
+        let cap = if rule.max_ms > 0 { rule.max_ms } else { 30_000 };
+        RetryStrategy::FixedDelay(Duration::from_millis(delay_ms.saturating_add(200).min(cap)))
+    } else {
+        match rule.backoff {
+            BackoffKind::None => RetryStrategy::NoRetry,
+            BackoffKind::Fixed => RetryStrategy::FixedDelay(Duration::from_millis(rule.base_ms)),
+            BackoffKind::Linear => RetryStrategy::LinearBackoff { base_ms: rule.base_ms },
+            BackoffKind::Exponential => RetryStrategy::ExponentialBackoff {
+                base_ms: rule.base_ms,
+                max_ms: if rule.max_ms > 0 { rule.max_ms } else { u64::MAX },
+            },
         }
+    };
 
-        // 401/403 认证/权限错误：切换账号前给予极短缓冲
-        401 | 403 => RetryStrategy::FixedDelay(Duration::from_millis(200)),
+    RetryDecision {
+        strategy,
+        max_attempts: rule.max_attempts,
+        rotate_account: rule.rotate_account,
+        jitter: rule.jitter.clamp(0.0, 1.0),
+        rule: Some(index),
+    }
+}
 
-        // 404 资源未找到：Google Cloud Code API 的 404 通常是账号级别的间歇性问题
-        // (灰度发布、账号权限不同步等)，轮换账号往往能解决
-        404 => RetryStrategy::FixedDelay(Duration::from_millis(300)),
+/// 按全局重试策略配置计算重试决策
+pub fn resolve_retry_decision(
+    status_code: u16,
+    error_text: &str,
+    retried_without_thinking: bool,
+) -> RetryDecision {
+    let policy = crate::proxy::config::get_retry_policy_config();
+    resolve_retry_decision_with(&policy.rules, status_code, error_text, retried_without_thinking)
+}
 
-        // 其他错误：不重试
-        _ => RetryStrategy::NoRetry,
+fn backoff_delay_ms(strategy: &RetryStrategy, attempt: usize) -> Option<u64> {
+    match strategy {
+        RetryStrategy::NoRetry => None,
+        RetryStrategy::FixedDelay(duration) => Some(duration.as_millis() as u64),
+        RetryStrategy::LinearBackoff { base_ms } => Some(base_ms.saturating_mul(attempt as u64 + 1)),
+        RetryStrategy::ExponentialBackoff { base_ms, max_ms } => Some(
+            base_ms
+                .saturating_mul(2_u64.saturating_pow(attempt as u32))
+                .min(*max_ms),
+        ),
     }
 }
 
-/// 执行退避策略并返回是否应该继续重试
-pub async fn apply_retry_strategy(
-    strategy: RetryStrategy,
+fn with_jitter(delay_ms: u64, jitter: f32) -> u64 {
+    if jitter <= 0.0 || delay_ms == 0 {
+        return delay_ms;
+    }
+    use rand::Rng;
+    let factor = rand::thread_rng().gen_range((1.0 - jitter as f64)..=(1.0 + jitter as f64));
+    (delay_ms as f64 * factor).round() as u64
+}
+
+/// 执行重试决策 (规则次数上限 + 全局重试预算 + 退避) 并返回是否应该继续重试
+pub async fn apply_retry_decision(
+    decision: &RetryDecision,
+    attempts: &mut RetryAttempts,
     attempt: usize,
     max_attempts: usize,
     status_code: u16,
     trace_id: &str,
 ) -> bool {
-    match strategy {
-        RetryStrategy::NoRetry => {
-            debug!("[{}] Non-retryable error {}, stopping", trace_id, status_code);
-            false
-        }
+    let Some(base_delay) = backoff_delay_ms(&decision.strategy, attempt) else {
+        debug!("[{}] Non-retryable error {}, stopping", trace_id, status_code);
+        return false;
+    };
 
-        RetryStrategy::FixedDelay(duration) => {
-            let base_ms = duration.as_millis() as u64;
-            info!(
-                "[{}] ⏱️ Retry with fixed delay: status={}, attempt={}/{}, delay={}ms",
-                trace_id,
-                status_code,
-                attempt + 1,
-                max_attempts,
-                base_ms
+    // 规则级次数上限 (按命中该规则的失败次数计算)
+    if let Some(rule) = decision.rule {
+        let failures = attempts.record(rule);
+        if decision.max_attempts > 0 && failures >= decision.max_attempts {
+            debug!(
+                "[{}] Retry rule limit reached for status {} ({} attempts)",
+                trace_id, status_code, decision.max_attempts
             );
-            sleep(duration).await;
-            true
+            return false;
         }
+    }
 
-        RetryStrategy::LinearBackoff { base_ms } => {
-            let calculated_ms = base_ms * (attempt as u64 + 1);
-            info!(
-                "[{}] ⏱️ Retry with linear backoff: status={}, attempt={}/{}, delay={}ms",
-                trace_id,
-                status_code,
-                attempt + 1,
-                max_attempts,
-                calculated_ms
-            );
-            sleep(Duration::from_millis(calculated_ms)).await;
-            true
-        }
+    // 最后一次尝试后循环会自然结束，不必等待也不消耗预算
+    if attempt + 1 >= max_attempts {
+        return true;
+    }
 
-        RetryStrategy::ExponentialBackoff { base_ms, max_ms } => {
-            let calculated_ms = (base_ms * 2_u64.pow(attempt as u32)).min(max_ms);
-            info!(
-                "[{}] ⏱️ Retry with exponential backoff: status={}, attempt={}/{}, delay={}ms",
-                trace_id,
-                status_code,
-                attempt + 1,
-                max_attempts,
-                calculated_ms
-            );
-            sleep(Duration::from_millis(calculated_ms)).await;
-            true
-        }
+    if !retry_budget::try_acquire_retry() {
+        warn!(
+            "[{}] Retry budget exhausted, giving up on status {} (attempt {}/{})",
+            trace_id,
+            status_code,
+            attempt + 1,
+            max_attempts
+        );
+        return false;
     }
+    retry_budget::count_retry();
+
+    let delay_ms = with_jitter(base_delay, decision.jitter);
+    let kind = match decision.strategy {
+        RetryStrategy::FixedDelay(_) => "fixed delay",
+        RetryStrategy::LinearBackoff { .. } => "linear backoff",
+        _ => "exponential backoff",
+    };
+    info!(
+        "[{}] ⏱️ Retry with {}: status={}, attempt={}/{}, delay={}ms",
+        trace_id,
+        kind,
+        status_code,
+        attempt + 1,
+        max_attempts,
+        delay_ms
+    );
+    sleep(Duration::from_millis(delay_ms)).await;
+    true
 }
 
-/// 判断是否应该轮换账号
-pub fn should_rotate_account(status_code: u16) -> bool {
-    match status_code {
-        // 这些错误是账号级别或特定节点配额的，需要轮换
-        // 404: Google Cloud Code API 模型可用性因账号而异（灰度/权限）
-        429 | 401 | 403 | 404 | 500 => true,
-        // 这些错误通常是协议或服务端全局性、甚至参数错误的，轮换账号通常无意义
-        400 | 503 | 529 => false,
-        _ => false,
-    }
+/// 执行退避策略并返回是否应该继续重试
+pub async fn apply_retry_strategy(
+    strategy: RetryStrategy,
+    attempt: usize,
+    max_attempts: usize,
+    status_code: u16,
+    trace_id: &str,
+) -> bool {
+    let decision = RetryDecision {
+        strategy,
+        ..RetryDecision::no_retry()
+    };
+    apply_retry_decision(
+        &decision,
+        &mut RetryAttempts::default(),
+        attempt,
+        max_attempts,
+        status_code,
+        trace_id,
+    )
+    .await
 }
 
 /// Detects model capabilities and configuration
@@ -0,0 +1,138 @@
+// 本地图库文件服务
+// 生成 / 编辑的图片保存到图库后，通过 /v1/files/images/{id} 以 URL 形式返回给客户端
+
+use axum::{
+    extract::Path,
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use base64::Engine as _;
+use serde_json::{json, Value};
+
+use crate::modules::image_store::{self, NewImage};
+use crate::proxy::common::error::ProxyError;
+use crate::proxy::config::{get_image_store_config, ImageStoreConfig};
+
+/// GET /v1/files/images/:id
+pub async fn handle_get_image(Path(id): Path<String>) -> Result<Response, ProxyError> {
+    // 兼容带扩展名的地址 (xxx.png)
+    let id = id.split('.').next().unwrap_or("").to_string();
+    let image = tokio::task::spawn_blocking(move || image_store::get_image(&id))
+        .await
+        .map_err(|e| ProxyError::internal(e.to_string()))?
+        .map_err(ProxyError::internal)?;
+
+    match image {
+        Some((record, bytes)) => Ok((
+            StatusCode::OK,
+            [
+                (header::CONTENT_TYPE, record.mime_type),
+                (header::CACHE_CONTROL, "private, max-age=86400".to_string()),
+            ],
+            bytes,
+        )
+            .into_response()),
+        None => Err(ProxyError::new(StatusCode::NOT_FOUND, "Image not found")),
+    }
+}
+
+/// 代理对外的基础地址：优先使用配置值，其次按 (反向代理) 请求头推断；都没有时返回空串 (相对地址)
+pub fn request_base_url(headers: &HeaderMap, configured: &str) -> String {
+    let configured = configured.trim().trim_end_matches('/');
+    if !configured.is_empty() {
+        return configured.to_string();
+    }
+    let header_value = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.split(',').next())
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty())
+    };
+    match header_value("x-forwarded-host").or_else(|| header_value("host")) {
+        Some(host) => format!(
+            "{}://{}",
+            header_value("x-forwarded-proto").unwrap_or_else(|| "http".to_string()),
+            host
+        ),
+        None => String::new(),
+    }
+}
+
+/// 图片 URL 的外部地址
+pub fn image_base_url(headers: &HeaderMap, config: &ImageStoreConfig) -> String {
+    request_base_url(headers, &config.public_base_url)
+}
+
+/// 保存生成结果并按 response_format 构建 OpenAI `data` 条目
+/// `images` 为 (base64 数据, MIME)。未启用图库或保存失败时 url 格式回退为 data URL
+pub async fn build_image_entries(
+    images: Vec<(String, String)>,
+    meta: NewImage,
+    response_format: &str,
+    headers: &HeaderMap,
+) -> Vec<Value> {
+    let config = get_image_store_config();
+    let base_url = image_base_url(headers, &config);
+
+    let ids: Vec<Option<String>> = if config.enabled {
+        let to_save = images.clone();
+        tokio::task::spawn_blocking(move || {
+            to_save
+                .iter()
+                .map(|(data, mime_type)| {
+                    base64::engine::general_purpose::STANDARD
+                        .decode(data)
+                        .map_err(|e| format!("Invalid image data: {}", e))
+                        .and_then(|bytes| {
+                            image_store::save_image(&meta, mime_type, &bytes, &config)
+                        })
+                        .map(|record| record.id)
+                        .map_err(|e| tracing::warn!("[ImageStore] Failed to save image: {}", e))
+                        .ok()
+                })
+                .collect()
+        })
+        .await
+        .unwrap_or_default()
+    } else {
+        Vec::new()
+    };
+
+    images
+        .into_iter()
+        .enumerate()
+        .map(|(i, (data, mime_type))| {
+            if response_format != "url" {
+                return json!({ "b64_json": data });
+            }
+            match ids.get(i).cloned().flatten() {
+                Some(id) => json!({ "url": format!("{}/v1/files/images/{}", base_url, id) }),
+                None => json!({ "url": format!("data:{};base64,{}", mime_type, data) }),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_image_base_url() {
+        let mut config = ImageStoreConfig::default();
+        let mut headers = HeaderMap::new();
+        assert_eq!(image_base_url(&headers, &config), "");
+
+        headers.insert("host", "127.0.0.1:8045".parse().unwrap());
+        assert_eq!(image_base_url(&headers, &config), "http://127.0.0.1:8045");
+
+        headers.insert("x-forwarded-host", "ai.example.com".parse().unwrap());
+        headers.insert("x-forwarded-proto", "https, http".parse().unwrap());
+        assert_eq!(image_base_url(&headers, &config), "https://ai.example.com");
+
+        config.public_base_url = "https://cdn.example.com/".to_string();
+        assert_eq!(image_base_url(&headers, &config), "https://cdn.example.com");
+    }
+}
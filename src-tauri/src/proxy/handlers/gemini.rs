@@ -4,6 +4,7 @@ use axum::{
     extract::{Json, Path},
     http::StatusCode,
     response::IntoResponse,
+    Extension,
 };
 use serde_json::{json, Value};
 use tracing::{debug, error, info};
@@ -11,9 +12,11 @@ use tracing::{debug, error, info};
 use crate::proxy::common::client_adapter::CLIENT_ADAPTERS;
 use crate::proxy::debug_logger;
 use crate::proxy::handlers::common::{
-    apply_retry_strategy, determine_retry_strategy, should_rotate_account,
+    apply_retry_decision, resolve_retry_decision, token_error_status, RetryAttempts,
 };
 use crate::proxy::mappers::gemini::{unwrap_response, wrap_request};
+use crate::proxy::common::error::ProxyError;
+use crate::proxy::middleware::auth::UserTokenIdentity;
 use crate::proxy::server::AppState;
 use crate::proxy::session_manager::SessionManager;
 use crate::proxy::upstream::client::mask_email;
@@ -27,8 +30,9 @@ pub async fn handle_generate(
     State(state): State<AppState>,
     Path(model_action): Path<String>,
     headers: HeaderMap,          // [NEW] Extract headers for adapter detection
+    identity: Option<Extension<UserTokenIdentity>>,
     Json(mut body): Json<Value>, // 改为 mut 以支持修复提示词注入
-) -> Result<impl IntoResponse, (StatusCode, String)> {
+) -> Result<impl IntoResponse, ProxyError> {
     // 解析 model:method
     let (model_name, method) = if let Some((m, action)) = model_action.rsplit_once(':') {
         (m.to_string(), action.to_string())
@@ -53,11 +57,24 @@ pub async fn handle_generate(
     }
 
     // 1. 验证方法
+    if method == "embedContent" || method == "batchEmbedContents" {
+        return crate::proxy::handlers::gemini_resources::handle_embed(
+            &state,
+            &model_name,
+            &method,
+            body,
+            &trace_id,
+        )
+        .await;
+    }
+    if method == "countTokens" {
+        return count_gemini_tokens(&state, &model_name, body, &identity).await;
+    }
     if method != "generateContent" && method != "streamGenerateContent" {
-        return Err((
-            StatusCode::BAD_REQUEST,
-            format!("Unsupported method: {}", method),
-        ));
+        return Err(ProxyError::invalid_request(format!(
+            "Unsupported method: {}",
+            method
+        )));
     }
     if debug_logger::is_enabled(&debug_cfg) {
         let original_payload = json!({
@@ -76,6 +93,13 @@ pub async fn handle_generate(
         )
         .await;
     }
+    // 展开 cachedContent 并内联本地上传的文件
+    let cached_tokens =
+        crate::proxy::handlers::gemini_resources::apply_local_resources(&mut body, &identity)
+            .await?;
+    // 转发前缩放并重新编码内联图片
+    crate::proxy::common::image_preprocess::preprocess_request_images(&mut body, &trace_id);
+
     let client_wants_stream = method == "streamGenerateContent";
     // [AUTO-CONVERSION] 强制内部流式化
     let force_stream_internally = !client_wants_stream;
@@ -93,6 +117,10 @@ pub async fn handle_generate(
 
     let mut last_error = String::new();
     let mut last_email: Option<String> = None;
+    // 上一次失败命中的重试规则是否要求轮换账号
+    let mut rotate_on_retry = true;
+    // 各重试规则已命中的失败次数
+    let mut retry_attempts = RetryAttempts::default();
 
     for attempt in 0..max_attempts {
         // 3. 模型路由解析
@@ -131,11 +159,13 @@ pub async fn handle_generate(
         // 提取 SessionId (粘性指纹)
         let session_id = SessionManager::extract_gemini_session_id(&body, &model_name);
 
-        // 关键：在重试尝试 (attempt > 0) 时强制轮换账号
+        // 关键：在重试尝试 (attempt > 0) 时强制轮换账号 (除非重试规则要求保留当前账号)
+        let force_rotate = attempt > 0 && rotate_on_retry;
+        rotate_on_retry = true;
         let (access_token, project_id, email, account_id, _wait_ms) = match token_manager
             .get_token(
                 &config.request_type,
-                attempt > 0,
+                force_rotate,
                 Some(&session_id),
                 &config.final_model,
             )
@@ -143,8 +173,8 @@ pub async fn handle_generate(
         {
             Ok(t) => t,
             Err(e) => {
-                return Err((
-                    StatusCode::SERVICE_UNAVAILABLE,
+                return Err(ProxyError::new(
+                    token_error_status(&e),
                     format!("Token error: {}", e),
                 ));
             }
@@ -155,7 +185,34 @@ pub async fn handle_generate(
 
         // 5. 包装请求 (project injection)
         // [FIX #765] Pass session_id to wrap_request for signature injection
-        let wrapped_body = wrap_request(&body, &project_id, &mapped_model, Some(&session_id));
+        let mut wrapped_body = wrap_request(&body, &project_id, &mapped_model, Some(&session_id));
+
+        // [NEW] 上下文压力管理 (与 Claude 路径共用 L1/L2/L3 阈值)
+        let experimental = state.experimental.read().await.clone();
+        let summarizer = crate::proxy::mappers::context_compression::Summarizer {
+            upstream: &upstream,
+            access_token: &access_token,
+            project_id: &project_id,
+            account_id: &account_id,
+            model: crate::proxy::common::model_mapping::resolve_model_route(
+                "internal-background-task",
+                &*state.custom_mapping.read().await,
+            ),
+        };
+        if let Err(e) = crate::proxy::mappers::context_compression::compress_gemini_body(
+            &mut wrapped_body,
+            &mapped_model,
+            &experimental,
+            &summarizer,
+            &trace_id,
+        )
+        .await
+        {
+            return Err(ProxyError::invalid_request(format!(
+                "Context too long and automatic compression failed: {}",
+                e
+            )));
+        }
 
         if debug_logger::is_enabled(&debug_cfg) {
             let payload = json!({
@@ -324,14 +381,27 @@ pub async fn handle_generate(
 
                 let s_id_for_stream = s_id.clone();
                 let model_name_for_stream = mapped_model.clone();
+                let heartbeat_label = trace_id.clone();
                 let stream = async_stream::stream! {
                     let mut first_data = first_chunk;
+                    // 上游静默时发送空白行保活
+                    let mut heartbeat = crate::proxy::mappers::heartbeat::Heartbeat::new(
+                        crate::proxy::mappers::heartbeat::HeartbeatStyle::Gemini,
+                        heartbeat_label,
+                    );
                     loop {
                         let item = if let Some(fd) = first_data.take() {
                             Some(Ok(fd))
                         } else {
-                            response_stream.next().await
+                            tokio::select! {
+                                item = response_stream.next() => item,
+                                ping = heartbeat.tick() => {
+                                    yield Ok::<Bytes, String>(ping);
+                                    continue;
+                                }
+                            }
                         };
+                        heartbeat.reset();
 
                         let bytes = match item {
                             Some(Ok(b)) => b,
@@ -385,6 +455,7 @@ pub async fn handle_generate(
 
                                             // [FIX #1522] Inject Tool ID into Stream Response
                                             crate::proxy::mappers::gemini::wrapper::inject_ids_to_response(&mut json, &model_name_for_stream);
+                                            crate::proxy::handlers::gemini_resources::annotate_cached_usage(&mut json, cached_tokens);
 
                                             // Unwrap v1internal response wrapper
                                             if let Some(inner) = json.get_mut("response").map(|v| v.take()) {
@@ -446,11 +517,11 @@ pub async fn handle_generate(
                         }
                         Err(e) => {
                             error!("Stream collection error: {}", e);
-                            return Ok((
-                                StatusCode::INTERNAL_SERVER_ERROR,
-                                format!("Stream collection error: {}", e),
-                            )
-                                .into_response());
+                            return Ok(ProxyError::internal(format!(
+                                "Stream collection error: {}",
+                                e
+                            ))
+                            .into_response());
                         }
                     }
                 }
@@ -459,13 +530,17 @@ pub async fn handle_generate(
             let mut gemini_resp: Value = response
                 .json()
                 .await
-                .map_err(|e| (StatusCode::BAD_GATEWAY, format!("Parse error: {}", e)))?;
+                .map_err(|e| ProxyError::bad_gateway(format!("Parse error: {}", e)))?;
 
             // [FIX #1522] Inject Tool ID into Non-streaming Response
             crate::proxy::mappers::gemini::wrapper::inject_ids_to_response(
                 &mut gemini_resp,
                 &mapped_model,
             );
+            crate::proxy::handlers::gemini_resources::annotate_cached_usage(
+                &mut gemini_resp,
+                cached_tokens,
+            );
 
             // [FIX #765] Extract thoughtSignature from non-streaming response
             let inner_val = if gemini_resp.get("response").is_some() {
@@ -542,11 +617,11 @@ pub async fn handle_generate(
         }
 
         // 确定重试策略
-        let strategy = determine_retry_strategy(status_code, &error_text, false);
+        let decision = resolve_retry_decision(status_code, &error_text, false);
         let trace_id = format!("gemini_{}", session_id);
 
         // 执行退避
-        if apply_retry_strategy(strategy, attempt, max_attempts, status_code, &trace_id).await {
+        if apply_retry_decision(&decision, &mut retry_attempts, attempt, max_attempts, status_code, &trace_id).await {
             // [NEW] Apply Client Adapter "let_it_crash" strategy
             if let Some(adapter) = &client_adapter {
                 if adapter.let_it_crash() && attempt > 0 {
@@ -559,12 +634,13 @@ pub async fn handle_generate(
             }
 
             // 判断是否需要轮换账号
-            if !should_rotate_account(status_code) {
+            if !decision.rotate_account {
                 debug!(
                     "[{}] Keeping same account for status {} (Gemini server-side issue)",
                     trace_id, status_code
                 );
             }
+            rotate_on_retry = decision.rotate_account;
             continue;
         }
 
@@ -603,42 +679,36 @@ pub async fn handle_generate(
             status_code, error_text
         );
         return Ok((
-            status,
             [
                 ("X-Account-Email", email.as_str()),
                 ("X-Mapped-Model", mapped_model.as_str()),
             ],
-            // [FIX] Return JSON error
-            Json(json!({
-                "error": {
-                    "code": status_code,
-                    "message": error_text,
-                    "status": "UPSTREAM_ERROR"
-                }
-            })),
+            ProxyError::upstream(status, &error_text),
         )
             .into_response());
     }
 
     if let Some(email) = last_email {
         Ok((
-            StatusCode::TOO_MANY_REQUESTS,
             [("X-Account-Email", email)],
-            format!("All accounts exhausted. Last error: {}", last_error),
+            ProxyError::new(
+                StatusCode::TOO_MANY_REQUESTS,
+                format!("All accounts exhausted. Last error: {}", last_error),
+            ),
         )
             .into_response())
     } else {
-        Ok((
+        Ok(ProxyError::new(
             StatusCode::TOO_MANY_REQUESTS,
             format!("All accounts exhausted. Last error: {}", last_error),
         )
-            .into_response())
+        .into_response())
     }
 }
 
 pub async fn handle_list_models(
     State(state): State<AppState>,
-) -> Result<impl IntoResponse, (StatusCode, String)> {
+) -> Result<impl IntoResponse, ProxyError> {
     use crate::proxy::common::model_mapping::get_all_dynamic_models;
 
     // 获取所有动态模型列表（与 /v1/models 一致）
@@ -673,22 +743,52 @@ pub async fn handle_get_model(Path(model_name): Path<String>) -> impl IntoRespon
     }))
 }
 
+/// POST /v1beta/models/:model/countTokens
 pub async fn handle_count_tokens(
     State(state): State<AppState>,
-    Path(_model_name): Path<String>,
-    Json(_body): Json<Value>,
-) -> Result<impl IntoResponse, (StatusCode, String)> {
-    let model_group = "gemini";
-    let (_access_token, _project_id, _, _, _wait_ms) = state
-        .token_manager
-        .get_token(model_group, false, None, "gemini")
+    Path(model_name): Path<String>,
+    identity: Option<Extension<UserTokenIdentity>>,
+    Json(body): Json<Value>,
+) -> Result<impl IntoResponse, ProxyError> {
+    count_gemini_tokens(&state, &model_name, body, &identity).await
+}
+
+/// countTokens：本地 BPE 分词，按模型族的规则与校准系数换算
+async fn count_gemini_tokens(
+    state: &AppState,
+    model_name: &str,
+    body: Value,
+    identity: &Option<Extension<UserTokenIdentity>>,
+) -> Result<axum::response::Response, ProxyError> {
+    use crate::proxy::mappers::context_compression::estimate_gemini_tokens;
+    use crate::proxy::mappers::estimation_calibrator::get_calibrator;
+    use crate::proxy::mappers::token_counter::ModelFamily;
+
+    // 请求体为 {contents, ...} 或 {generateContentRequest: {...}}
+    let mut request = match body {
+        Value::Object(mut map) if map.contains_key("generateContentRequest") => {
+            map.remove("generateContentRequest").unwrap_or(Value::Null)
+        }
+        other => other,
+    };
+    let cached_tokens =
+        crate::proxy::handlers::gemini_resources::apply_local_resources(&mut request, identity)
+            .await?;
+
+    let mapped_model = crate::proxy::common::model_mapping::resolve_model_route(
+        model_name,
+        &*state.custom_mapping.read().await,
+    );
+    // 整个请求都要分词，放到阻塞线程池中执行
+    let family = ModelFamily::from_model(&mapped_model);
+    let estimate = tokio::task::spawn_blocking(move || estimate_gemini_tokens(&request, family))
         .await
-        .map_err(|e| {
-            (
-                StatusCode::SERVICE_UNAVAILABLE,
-                format!("Token error: {}", e),
-            )
-        })?;
+        .map_err(|e| ProxyError::internal(e.to_string()))?;
+    let total = get_calibrator().calibrate(&estimate);
 
-    Ok(Json(json!({"totalTokens": 0})))
+    let mut response = json!({ "totalTokens": total });
+    if cached_tokens > 0 {
+        response["cachedContentTokenCount"] = json!(cached_tokens);
+    }
+    Ok(Json(response).into_response())
 }
@@ -0,0 +1,980 @@
+// Gemini 原生资源端点 (files / cachedContents / embeddings)
+// 上游 v1internal 不提供这些资源，代理在本地实现：
+// - files: 上传内容保存到数据目录，请求中以 fileData.fileUri 引用时内联为 inlineData
+// - cachedContents: 保存 contents / systemInstruction / tools，使用时拼接到请求前部
+// - embedContent / batchEmbedContents: 逐条转发到上游 embedContent
+// files 与 cachedContents 按调用方的 User Token 隔离，只能访问自己创建的资源
+
+use axum::{
+    body::Bytes,
+    extract::{Path, Query},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use base64::Engine as _;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+use crate::modules::gemini_store::{self, CachedContentRecord, FileRecord};
+use crate::proxy::common::error::ProxyError;
+use crate::proxy::handlers::common::{
+    apply_retry_decision, resolve_retry_decision, token_error_status, RetryAttempts,
+};
+use crate::proxy::handlers::files::request_base_url;
+use crate::proxy::mappers::context_compression::estimate_gemini_tokens;
+use crate::proxy::mappers::estimation_calibrator::get_calibrator;
+use crate::proxy::mappers::token_counter::ModelFamily;
+use crate::proxy::middleware::auth::UserTokenIdentity;
+use crate::proxy::server::AppState;
+
+const MAX_RETRY_ATTEMPTS: usize = 3;
+/// cachedContents 未指定 ttl 时的默认有效期 (与官方一致)
+const DEFAULT_CACHE_TTL_SECS: i64 = 3600;
+/// 未完成的可续传上传会话保留时长
+const UPLOAD_SESSION_TTL: Duration = Duration::from_secs(3600);
+/// 单个文件的大小上限 (与默认请求体上限一致，文件使用时会内联进请求)
+const MAX_UPLOAD_BYTES: usize = 100 * 1024 * 1024;
+const LIST_LIMIT: usize = 1000;
+
+/// 资源归属：调用方的 User Token ID (未使用 User Token 时为 None)
+fn caller_owner(identity: &Option<Extension<UserTokenIdentity>>) -> Option<String> {
+    identity.as_ref().map(|Extension(id)| id.token_id.clone())
+}
+
+// ===== 上传 =====
+
+/// 进行中的可续传上传
+struct PendingUpload {
+    owner: Option<String>,
+    display_name: String,
+    mime_type: String,
+    data: Vec<u8>,
+    started_at: Instant,
+}
+
+impl PendingUpload {
+    /// 追加一个分片；offset 为客户端声明的分片起始位置 (x-goog-upload-offset)
+    /// 重传已收到的分片时不重复写入
+    fn append(&mut self, offset: Option<usize>, chunk: &[u8]) -> Result<(), ProxyError> {
+        let received = self.data.len();
+        let offset = offset.unwrap_or(received);
+        if offset < received && offset + chunk.len() <= received {
+            return Ok(());
+        }
+        if offset != received {
+            return Err(ProxyError::invalid_request(format!(
+                "Upload offset {} does not match received size {}",
+                offset, received
+            )));
+        }
+        if received + chunk.len() > MAX_UPLOAD_BYTES {
+            return Err(ProxyError::new(
+                StatusCode::PAYLOAD_TOO_LARGE,
+                format!("File exceeds the {} MB upload limit", MAX_UPLOAD_BYTES / 1024 / 1024),
+            ));
+        }
+        self.data.extend_from_slice(chunk);
+        Ok(())
+    }
+}
+
+static PENDING_UPLOADS: OnceLock<Mutex<HashMap<String, PendingUpload>>> = OnceLock::new();
+
+/// 锁定上传会话表并清理过期会话
+fn pending_uploads() -> std::sync::MutexGuard<'static, HashMap<String, PendingUpload>> {
+    let mut uploads = PENDING_UPLOADS
+        .get_or_init(|| Mutex::new(HashMap::new()))
+        .lock()
+        .unwrap_or_else(|e| e.into_inner());
+    uploads.retain(|_, u| u.started_at.elapsed() < UPLOAD_SESSION_TTL);
+    uploads
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UploadQuery {
+    upload_id: Option<String>,
+}
+
+fn header_str<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.trim())
+        .filter(|v| !v.is_empty())
+}
+
+/// 从上传元数据 `{"file": {"displayName", "mimeType"}}` 中读取字段
+fn metadata_field(metadata: &Value, key: &str) -> Option<String> {
+    metadata
+        .get("file")
+        .unwrap_or(metadata)
+        .get(key)
+        .and_then(|v| v.as_str())
+        .filter(|v| !v.is_empty())
+        .map(|v| v.to_string())
+}
+
+/// 解析 multipart/related 上传体：第一部分为 JSON 元数据，第二部分为文件内容
+/// 返回 (元数据, 内容 MIME, 内容)
+fn parse_multipart_related(
+    content_type: &str,
+    body: &[u8],
+) -> Result<(Value, Option<String>, Vec<u8>), String> {
+    let boundary = content_type
+        .split(';')
+        .filter_map(|p| p.trim().strip_prefix("boundary="))
+        .next()
+        .map(|b| b.trim_matches('"'))
+        .ok_or("multipart 请求缺少 boundary")?;
+    let delimiter = format!("--{}", boundary);
+
+    let mut parts = Vec::new();
+    let mut rest = body;
+    while let Some(start) = find_bytes(rest, delimiter.as_bytes()) {
+        rest = &rest[start + delimiter.len()..];
+        if rest.starts_with(b"--") {
+            break;
+        }
+        let end = find_bytes(rest, delimiter.as_bytes()).unwrap_or(rest.len());
+        parts.push(&rest[..end]);
+        rest = &rest[end..];
+    }
+
+    let mut parsed = parts.into_iter().map(|part| {
+        // 头部与内容之间以空行分隔；内容末尾的 CRLF 属于分隔符
+        let part = part.strip_prefix(b"\r\n").unwrap_or(part);
+        let (head, content) = match find_bytes(part, b"\r\n\r\n") {
+            Some(pos) => (&part[..pos], &part[pos + 4..]),
+            None => (&b""[..], part),
+        };
+        let content = content.strip_suffix(b"\r\n").unwrap_or(content);
+        let mime = String::from_utf8_lossy(head).lines().find_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            name.trim()
+                .eq_ignore_ascii_case("content-type")
+                .then(|| value.trim().to_string())
+        });
+        (mime, content)
+    });
+
+    let (_, meta_bytes) = parsed.next().ok_or("multipart 请求缺少元数据部分")?;
+    let metadata: Value =
+        serde_json::from_slice(meta_bytes).map_err(|e| format!("元数据不是合法 JSON: {}", e))?;
+    let (mime, content) = parsed.next().ok_or("multipart 请求缺少文件内容")?;
+    Ok((metadata, mime, content.to_vec()))
+}
+
+fn find_bytes(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+/// 保存上传的文件并返回 File 资源
+async fn store_file(
+    owner: Option<String>,
+    display_name: String,
+    mime_type: String,
+    data: Vec<u8>,
+) -> Result<FileRecord, ProxyError> {
+    if data.is_empty() {
+        return Err(ProxyError::invalid_request("Uploaded file is empty"));
+    }
+    if data.len() > MAX_UPLOAD_BYTES {
+        return Err(ProxyError::new(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!("File exceeds the {} MB upload limit", MAX_UPLOAD_BYTES / 1024 / 1024),
+        ));
+    }
+    tokio::task::spawn_blocking(move || {
+        gemini_store::create_file(owner.as_deref(), &display_name, &mime_type, &data)
+    })
+        .await
+        .map_err(|e| ProxyError::internal(e.to_string()))?
+        .map_err(ProxyError::internal)
+}
+
+/// POST /upload/v1beta/files
+/// 支持 resumable (start / upload / finalize)、multipart/related 与 raw 三种上传协议
+pub async fn handle_upload_file(
+    Query(query): Query<UploadQuery>,
+    identity: Option<Extension<UserTokenIdentity>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Response, ProxyError> {
+    let base_url = request_base_url(&headers, "");
+    let owner = caller_owner(&identity);
+
+    // 可续传上传的后续分片
+    if let Some(upload_id) = query.upload_id {
+        let command = header_str(&headers, "x-goog-upload-command")
+            .unwrap_or("upload, finalize")
+            .to_ascii_lowercase();
+        let finalize = command.contains("finalize");
+        let offset = match header_str(&headers, "x-goog-upload-offset") {
+            Some(v) => Some(v.parse::<usize>().map_err(|_| {
+                ProxyError::invalid_request(format!("Invalid x-goog-upload-offset: {}", v))
+            })?),
+            None => None,
+        };
+
+        let pending = {
+            let mut uploads = pending_uploads();
+            let session = uploads
+                .get_mut(&upload_id)
+                .filter(|u| u.owner == owner)
+                .ok_or_else(|| {
+                    ProxyError::new(StatusCode::NOT_FOUND, "Upload session not found")
+                })?;
+            if !command.contains("query") {
+                session.append(offset, &body)?;
+            }
+            if !finalize {
+                let received = session.data.len();
+                return Ok((
+                    StatusCode::OK,
+                    [
+                        ("x-goog-upload-status", "active".to_string()),
+                        ("x-goog-upload-size-received", received.to_string()),
+                    ],
+                )
+                    .into_response());
+            }
+            uploads.remove(&upload_id)
+        };
+        let pending = pending.ok_or_else(|| ProxyError::internal("Upload session vanished"))?;
+        let record = store_file(
+            pending.owner,
+            pending.display_name,
+            pending.mime_type,
+            pending.data,
+        )
+        .await?;
+        info!("[Gemini-Files] Upload finalized: {}", record.id);
+        return Ok((
+            StatusCode::OK,
+            [("x-goog-upload-status", "final")],
+            Json(json!({ "file": file_resource(&record, &base_url) })),
+        )
+            .into_response());
+    }
+
+    let protocol = header_str(&headers, "x-goog-upload-protocol")
+        .unwrap_or("")
+        .to_ascii_lowercase();
+    let content_type = header_str(&headers, "content-type")
+        .unwrap_or("")
+        .to_string();
+
+    if protocol == "resumable" {
+        let metadata: Value = serde_json::from_slice(&body).unwrap_or(Value::Null);
+        let mime_type = header_str(&headers, "x-goog-upload-header-content-type")
+            .map(|v| v.to_string())
+            .or_else(|| metadata_field(&metadata, "mimeType"))
+            .unwrap_or_else(|| "application/octet-stream".to_string());
+        let display_name = metadata_field(&metadata, "displayName").unwrap_or_default();
+        let declared_size = header_str(&headers, "x-goog-upload-header-content-length")
+            .and_then(|v| v.parse::<usize>().ok())
+            .unwrap_or(0);
+        if declared_size > MAX_UPLOAD_BYTES {
+            return Err(ProxyError::new(
+                StatusCode::PAYLOAD_TOO_LARGE,
+                format!("File exceeds the {} MB upload limit", MAX_UPLOAD_BYTES / 1024 / 1024),
+            ));
+        }
+        let upload_id = uuid::Uuid::new_v4().simple().to_string();
+
+        pending_uploads().insert(
+            upload_id.clone(),
+            PendingUpload {
+                owner,
+                display_name,
+                mime_type,
+                data: Vec::new(),
+                started_at: Instant::now(),
+            },
+        );
+        let upload_url = format!("{}/upload/v1beta/files?upload_id={}", base_url, upload_id);
+        return Ok((
+            StatusCode::OK,
+            [
+                ("x-goog-upload-url", upload_url),
+                ("x-goog-upload-status", "active".to_string()),
+                ("x-goog-upload-chunk-granularity", "8388608".to_string()),
+            ],
+        )
+            .into_response());
+    }
+
+    let (display_name, mime_type, data) =
+        if protocol == "multipart" || content_type.starts_with("multipart/related") {
+            let (metadata, part_mime, data) = parse_multipart_related(&content_type, &body)
+                .map_err(ProxyError::invalid_request)?;
+            let mime_type = part_mime
+                .or_else(|| metadata_field(&metadata, "mimeType"))
+                .unwrap_or_else(|| "application/octet-stream".to_string());
+            (
+                metadata_field(&metadata, "displayName").unwrap_or_default(),
+                mime_type,
+                data,
+            )
+        } else {
+            let mime_type = if content_type.is_empty() {
+                "application/octet-stream".to_string()
+            } else {
+                content_type
+            };
+            (String::new(), mime_type, body.to_vec())
+        };
+
+    let record = store_file(owner, display_name, mime_type, data).await?;
+    info!("[Gemini-Files] File uploaded: {}", record.id);
+    Ok(Json(json!({ "file": file_resource(&record, &base_url) })).into_response())
+}
+
+// ===== Files =====
+
+fn rfc3339(ms: i64) -> String {
+    chrono::DateTime::from_timestamp_millis(ms)
+        .unwrap_or_default()
+        .to_rfc3339_opts(chrono::SecondsFormat::Millis, true)
+}
+
+fn file_resource(record: &FileRecord, base_url: &str) -> Value {
+    json!({
+        "name": format!("files/{}", record.id),
+        "displayName": record.display_name,
+        "mimeType": record.mime_type,
+        "sizeBytes": record.size_bytes.to_string(),
+        "createTime": rfc3339(record.created_at),
+        "updateTime": rfc3339(record.created_at),
+        "expirationTime": rfc3339(record.expires_at),
+        "sha256Hash": record.sha256,
+        "uri": format!("{}/v1beta/files/{}", base_url, record.id),
+        "state": "ACTIVE",
+        "source": "UPLOADED"
+    })
+}
+
+async fn blocking<T: Send + 'static>(
+    f: impl FnOnce() -> Result<T, String> + Send + 'static,
+) -> Result<T, ProxyError> {
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|e| ProxyError::internal(e.to_string()))?
+        .map_err(ProxyError::internal)
+}
+
+/// GET /v1beta/files
+pub async fn handle_list_files(
+    identity: Option<Extension<UserTokenIdentity>>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, ProxyError> {
+    let base_url = request_base_url(&headers, "");
+    let owner = caller_owner(&identity);
+    let files = blocking(move || gemini_store::list_files(owner.as_deref(), LIST_LIMIT)).await?;
+    let files: Vec<Value> = files.iter().map(|f| file_resource(f, &base_url)).collect();
+    Ok(Json(json!({ "files": files })))
+}
+
+/// GET /v1beta/files/:id
+pub async fn handle_get_file(
+    Path(id): Path<String>,
+    identity: Option<Extension<UserTokenIdentity>>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, ProxyError> {
+    let base_url = request_base_url(&headers, "");
+    let owner = caller_owner(&identity);
+    match blocking(move || gemini_store::get_file(&id, owner.as_deref())).await? {
+        Some(record) => Ok(Json(file_resource(&record, &base_url))),
+        None => Err(ProxyError::new(StatusCode::NOT_FOUND, "File not found")),
+    }
+}
+
+/// DELETE /v1beta/files/:id
+pub async fn handle_delete_file(
+    Path(id): Path<String>,
+    identity: Option<Extension<UserTokenIdentity>>,
+) -> Result<impl IntoResponse, ProxyError> {
+    let owner = caller_owner(&identity);
+    if blocking(move || gemini_store::delete_file(&id, owner.as_deref())).await? {
+        Ok(Json(json!({})))
+    } else {
+        Err(ProxyError::new(StatusCode::NOT_FOUND, "File not found"))
+    }
+}
+
+// ===== Cached Contents =====
+
+/// 缓存保存的请求字段
+const CACHED_FIELDS: [&str; 4] = ["contents", "systemInstruction", "tools", "toolConfig"];
+
+/// 解析 "300s" / "1.5s" 形式的 Duration
+fn parse_duration_secs(value: &str) -> Option<f64> {
+    value
+        .trim()
+        .strip_suffix('s')?
+        .parse::<f64>()
+        .ok()
+        .filter(|v| v.is_finite() && *v >= 0.0)
+}
+
+/// 根据 ttl / expireTime 计算过期时间 (毫秒时间戳)
+fn resolve_expiry(body: &Value, now_ms: i64) -> Result<Option<i64>, ProxyError> {
+    if let Some(ttl) = body.get("ttl").and_then(|v| v.as_str()) {
+        let secs = parse_duration_secs(ttl)
+            .ok_or_else(|| ProxyError::invalid_request(format!("Invalid ttl: {}", ttl)))?;
+        return Ok(Some(now_ms + (secs * 1000.0) as i64));
+    }
+    if let Some(expire) = body.get("expireTime").and_then(|v| v.as_str()) {
+        let time = chrono::DateTime::parse_from_rfc3339(expire)
+            .map_err(|_| ProxyError::invalid_request(format!("Invalid expireTime: {}", expire)))?;
+        return Ok(Some(time.timestamp_millis()));
+    }
+    Ok(None)
+}
+
+fn cached_resource(record: &CachedContentRecord) -> Value {
+    json!({
+        "name": format!("cachedContents/{}", record.id),
+        "model": record.model,
+        "displayName": record.display_name,
+        "createTime": rfc3339(record.created_at),
+        "updateTime": rfc3339(record.updated_at),
+        "expireTime": rfc3339(record.expires_at),
+        "usageMetadata": { "totalTokenCount": record.token_count }
+    })
+}
+
+/// POST /v1beta/cachedContents
+pub async fn handle_create_cached_content(
+    identity: Option<Extension<UserTokenIdentity>>,
+    Json(body): Json<Value>,
+) -> Result<impl IntoResponse, ProxyError> {
+    let owner = caller_owner(&identity);
+    let model = body
+        .get("model")
+        .and_then(|v| v.as_str())
+        .filter(|v| !v.is_empty())
+        .ok_or_else(|| ProxyError::invalid_request("Missing model"))?;
+    let model = if model.starts_with("models/") {
+        model.to_string()
+    } else {
+        format!("models/{}", model)
+    };
+    let display_name = body
+        .get("displayName")
+        .and_then(|v| v.as_str())
+        .unwrap_or("")
+        .to_string();
+
+    let mut content = Map::new();
+    for field in CACHED_FIELDS {
+        if let Some(value) = body.get(field) {
+            content.insert(field.to_string(), value.clone());
+        }
+    }
+    if content.is_empty() {
+        return Err(ProxyError::invalid_request(
+            "Cached content must include contents or systemInstruction",
+        ));
+    }
+    let family = ModelFamily::from_model(&model);
+    let (content, token_count) = blocking(move || {
+        let content = Value::Object(content);
+        let token_count = get_calibrator().calibrate(&estimate_gemini_tokens(&content, family));
+        Ok((content, token_count))
+    })
+    .await?;
+
+    let now = chrono::Utc::now().timestamp_millis();
+    let expires_at = resolve_expiry(&body, now)?.unwrap_or(now + DEFAULT_CACHE_TTL_SECS * 1000);
+    let record = blocking(move || {
+        gemini_store::create_cached_content(
+            owner.as_deref(),
+            &model,
+            &display_name,
+            &content,
+            token_count,
+            expires_at,
+        )
+    })
+    .await?;
+    info!(
+        "[Gemini-Cache] Cached content created: {} ({} tokens)",
+        record.id, record.token_count
+    );
+    Ok(Json(cached_resource(&record)))
+}
+
+/// GET /v1beta/cachedContents
+pub async fn handle_list_cached_contents(
+    identity: Option<Extension<UserTokenIdentity>>,
+) -> Result<impl IntoResponse, ProxyError> {
+    let owner = caller_owner(&identity);
+    let records =
+        blocking(move || gemini_store::list_cached_contents(owner.as_deref(), LIST_LIMIT)).await?;
+    let items: Vec<Value> = records.iter().map(cached_resource).collect();
+    Ok(Json(json!({ "cachedContents": items })))
+}
+
+/// GET /v1beta/cachedContents/:id
+pub async fn handle_get_cached_content(
+    Path(id): Path<String>,
+    identity: Option<Extension<UserTokenIdentity>>,
+) -> Result<impl IntoResponse, ProxyError> {
+    let owner = caller_owner(&identity);
+    match blocking(move || gemini_store::get_cached_content(&id, owner.as_deref())).await? {
+        Some(record) => Ok(Json(cached_resource(&record))),
+        None => Err(ProxyError::new(
+            StatusCode::NOT_FOUND,
+            "Cached content not found",
+        )),
+    }
+}
+
+/// PATCH /v1beta/cachedContents/:id (仅支持更新 ttl / expireTime)
+pub async fn handle_update_cached_content(
+    Path(id): Path<String>,
+    identity: Option<Extension<UserTokenIdentity>>,
+    Json(body): Json<Value>,
+) -> Result<impl IntoResponse, ProxyError> {
+    let owner = caller_owner(&identity);
+    let now = chrono::Utc::now().timestamp_millis();
+    let expires_at = resolve_expiry(&body, now)?
+        .ok_or_else(|| ProxyError::invalid_request("Only ttl or expireTime can be updated"))?;
+    match blocking(move || {
+        gemini_store::update_cached_content_expiry(&id, owner.as_deref(), expires_at)
+    })
+    .await?
+    {
+        Some(record) => Ok(Json(cached_resource(&record))),
+        None => Err(ProxyError::new(
+            StatusCode::NOT_FOUND,
+            "Cached content not found",
+        )),
+    }
+}
+
+/// DELETE /v1beta/cachedContents/:id
+pub async fn handle_delete_cached_content(
+    Path(id): Path<String>,
+    identity: Option<Extension<UserTokenIdentity>>,
+) -> Result<impl IntoResponse, ProxyError> {
+    let owner = caller_owner(&identity);
+    if blocking(move || gemini_store::delete_cached_content(&id, owner.as_deref())).await? {
+        Ok(Json(json!({})))
+    } else {
+        Err(ProxyError::new(
+            StatusCode::NOT_FOUND,
+            "Cached content not found",
+        ))
+    }
+}
+
+// ===== 请求预处理 =====
+
+/// 从 "cachedContents/{id}" 或 "files/{id}" 形式的资源名 / URI 中提取本地 ID
+fn local_resource_id<'a>(value: &'a str, collection: &str) -> Option<&'a str> {
+    let marker = format!("{}/", collection);
+    let rest = match value.strip_prefix(&marker) {
+        Some(rest) => rest,
+        None => {
+            let pos = value.find(&format!("/v1beta/{}", marker))?;
+            &value[pos + "/v1beta/".len() + marker.len()..]
+        }
+    };
+    let id = rest.split(['?', '#', '/', ':']).next().unwrap_or("");
+    (!id.is_empty()).then_some(id)
+}
+
+/// 把缓存内容合并进请求：contents 前置，其余字段仅在请求未提供时补充
+fn merge_cached_content(body: &mut Value, cached: &Value) {
+    let Some(obj) = body.as_object_mut() else {
+        return;
+    };
+    if let Some(cached_contents) = cached.get("contents").and_then(|v| v.as_array()) {
+        let mut contents = cached_contents.clone();
+        if let Some(Value::Array(existing)) = obj.remove("contents") {
+            contents.extend(existing);
+        }
+        obj.insert("contents".to_string(), Value::Array(contents));
+    }
+    for field in ["systemInstruction", "tools", "toolConfig"] {
+        if let Some(value) = cached.get(field) {
+            obj.entry(field.to_string())
+                .or_insert_with(|| value.clone());
+        }
+    }
+}
+
+/// 收集请求中所有 fileData / file_data 节点的可变引用
+fn collect_file_parts<'a>(value: &'a mut Value, out: &mut Vec<&'a mut Value>) {
+    if value.get("fileData").is_some() || value.get("file_data").is_some() {
+        out.push(value);
+        return;
+    }
+    match value {
+        Value::Object(map) => {
+            for v in map.values_mut() {
+                collect_file_parts(v, out);
+            }
+        }
+        Value::Array(items) => {
+            for v in items.iter_mut() {
+                collect_file_parts(v, out);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// 解析请求中 owner 名下的本地资源 (阻塞)：展开 cachedContent，并把引用的本地文件内联为 inlineData
+/// 返回缓存贡献的 token 数
+fn resolve_local_resources(body: &mut Value, owner: Option<&str>) -> Result<u32, ProxyError> {
+    let mut cached_tokens = 0;
+
+    let cache_name = body
+        .as_object_mut()
+        .and_then(|obj| obj.remove("cachedContent"))
+        .and_then(|v| v.as_str().map(|s| s.to_string()));
+    if let Some(name) = cache_name {
+        let id = local_resource_id(&name, "cachedContents").unwrap_or(&name);
+        let record = gemini_store::get_cached_content(id, owner)
+            .map_err(ProxyError::internal)?
+            .ok_or_else(|| {
+                ProxyError::new(
+                    StatusCode::NOT_FOUND,
+                    format!("Cached content not found or expired: {}", name),
+                )
+            })?;
+        merge_cached_content(body, &record.content);
+        cached_tokens = record.token_count;
+    }
+
+    let mut parts = Vec::new();
+    collect_file_parts(body, &mut parts);
+    for part in parts {
+        let Some(map) = part.as_object_mut() else {
+            continue;
+        };
+        let key = if map.contains_key("fileData") {
+            "fileData"
+        } else {
+            "file_data"
+        };
+        let uri = map[key]
+            .get("fileUri")
+            .or_else(|| map[key].get("file_uri"))
+            .and_then(|v| v.as_str())
+            .unwrap_or("")
+            .to_string();
+        // 非本地文件 (如 gs:// 或外部 URL) 保持原样
+        let Some(id) = local_resource_id(&uri, "files") else {
+            continue;
+        };
+        let (record, bytes) = gemini_store::read_file(id, owner)
+            .map_err(ProxyError::internal)?
+            .ok_or_else(|| {
+                ProxyError::new(
+                    StatusCode::NOT_FOUND,
+                    format!("File not found or expired: {}", uri),
+                )
+            })?;
+        map.remove(key);
+        map.insert(
+            "inlineData".to_string(),
+            json!({
+                "mimeType": record.mime_type,
+                "data": base64::engine::general_purpose::STANDARD.encode(&bytes)
+            }),
+        );
+    }
+
+    Ok(cached_tokens)
+}
+
+/// 在转发前解析请求中的 cachedContent 与本地 files 引用 (仅限调用方自己的资源)，返回缓存 token 数
+pub async fn apply_local_resources(
+    body: &mut Value,
+    identity: &Option<Extension<UserTokenIdentity>>,
+) -> Result<u32, ProxyError> {
+    let owner = caller_owner(identity);
+    let mut owned = std::mem::take(body);
+    let (result, owned) = tokio::task::spawn_blocking(move || {
+        let result = resolve_local_resources(&mut owned, owner.as_deref());
+        (result, owned)
+    })
+    .await
+    .map_err(|e| ProxyError::internal(e.to_string()))?;
+    *body = owned;
+    result
+}
+
+/// 在响应的 usageMetadata 中标注缓存命中的 token 数
+pub fn annotate_cached_usage(response: &mut Value, cached_tokens: u32) {
+    if cached_tokens == 0 {
+        return;
+    }
+    let target = if response.get("response").is_some() {
+        &mut response["response"]
+    } else {
+        response
+    };
+    if let Some(usage) = target
+        .get_mut("usageMetadata")
+        .and_then(|u| u.as_object_mut())
+    {
+        usage.insert("cachedContentTokenCount".to_string(), json!(cached_tokens));
+    }
+}
+
+// ===== Embeddings =====
+
+/// 转发单条 embedContent 请求 (带账号轮换与重试)，返回 (embedding, 账号)
+async fn call_embed(
+    state: &AppState,
+    model: &str,
+    mut request: Value,
+    trace_id: &str,
+) -> Result<(Value, String), ProxyError> {
+    if let Some(obj) = request.as_object_mut() {
+        obj.remove("model");
+    }
+    let token_manager = state.token_manager.clone();
+    let max_attempts = MAX_RETRY_ATTEMPTS.min(token_manager.len()).max(1);
+    let mut last_error = String::new();
+    // 上一次失败命中的重试规则是否要求轮换账号
+    let mut rotate_on_retry = true;
+    // 各重试规则已命中的失败次数
+    let mut retry_attempts = RetryAttempts::default();
+
+    for attempt in 0..max_attempts {
+        let force_rotate = attempt > 0 && rotate_on_retry;
+        rotate_on_retry = true;
+        let (access_token, project_id, email, account_id, _wait_ms) = token_manager
+            .get_token("text", force_rotate, None, model)
+            .await
+            .map_err(|e| ProxyError::new(token_error_status(&e), format!("Token error: {}", e)))?;
+
+        let wrapped_body = json!({
+            "project": project_id,
+            "requestId": format!("embed-{}", uuid::Uuid::new_v4()),
+            "request": request.clone(),
+            "model": model,
+            "userAgent": "antigravity",
+            "requestType": "text"
+        });
+
+        let response = match state
+            .upstream
+            .call_v1_internal(
+                "embedContent",
+                &access_token,
+                wrapped_body,
+                None,
+                Some(account_id.as_str()),
+            )
+            .await
+        {
+            Ok(r) => r.response,
+            Err(e) => {
+                last_error = e.clone();
+                warn!(
+                    "[{}] Embedding request failed on attempt {}/{}: {}",
+                    trace_id,
+                    attempt + 1,
+                    max_attempts,
+                    e
+                );
+                continue;
+            }
+        };
+
+        let status = response.status();
+        if status.is_success() {
+            let result: Value = response
+                .json()
+                .await
+                .map_err(|e| ProxyError::bad_gateway(format!("Parse error: {}", e)))?;
+            let inner = result.get("response").unwrap_or(&result);
+            let embedding = inner.get("embedding").cloned().ok_or_else(|| {
+                ProxyError::bad_gateway("Upstream response contains no embedding")
+            })?;
+            return Ok((embedding, email));
+        }
+
+        let status_code = status.as_u16();
+        let error_text = response
+            .text()
+            .await
+            .unwrap_or_else(|_| format!("HTTP {}", status_code));
+        last_error = format!("HTTP {}: {}", status_code, error_text);
+
+        let decision = resolve_retry_decision(status_code, &error_text, false);
+        if apply_retry_decision(&decision, &mut retry_attempts, attempt, max_attempts, status_code, trace_id).await {
+            rotate_on_retry = decision.rotate_account;
+            continue;
+        }
+
+        return Err(ProxyError::upstream(status, &error_text));
+    }
+
+    Err(ProxyError::new(
+        StatusCode::TOO_MANY_REQUESTS,
+        format!("All accounts exhausted. Last error: {}", last_error),
+    ))
+}
+
+/// 处理 embedContent / batchEmbedContents
+/// batchEmbedContents 拆分为多条 embedContent 依次转发，结果按原顺序返回
+pub async fn handle_embed(
+    state: &AppState,
+    model_name: &str,
+    method: &str,
+    body: Value,
+    trace_id: &str,
+) -> Result<Response, ProxyError> {
+    let model = state
+        .custom_mapping
+        .read()
+        .await
+        .get(model_name)
+        .cloned()
+        .unwrap_or_else(|| model_name.to_string());
+
+    let (result, email) = if method == "batchEmbedContents" {
+        let requests = body
+            .get("requests")
+            .and_then(|r| r.as_array())
+            .filter(|r| !r.is_empty())
+            .ok_or_else(|| ProxyError::invalid_request("Missing requests"))?;
+        let mut embeddings = Vec::with_capacity(requests.len());
+        let mut email = String::new();
+        for request in requests {
+            let (embedding, used) = call_embed(state, &model, request.clone(), trace_id).await?;
+            embeddings.push(embedding);
+            email = used;
+        }
+        (json!({ "embeddings": embeddings }), email)
+    } else {
+        let (embedding, email) = call_embed(state, &model, body, trace_id).await?;
+        (json!({ "embedding": embedding }), email)
+    };
+
+    Ok((
+        StatusCode::OK,
+        [
+            ("X-Account-Email", email.as_str()),
+            ("X-Mapped-Model", model.as_str()),
+        ],
+        Json(result),
+    )
+        .into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_local_resource_id() {
+        assert_eq!(local_resource_id("files/abc123", "files"), Some("abc123"));
+        assert_eq!(
+            local_resource_id("http://127.0.0.1:8045/v1beta/files/abc123", "files"),
+            Some("abc123")
+        );
+        assert_eq!(
+            local_resource_id("cachedContents/xyz", "cachedContents"),
+            Some("xyz")
+        );
+        assert_eq!(local_resource_id("gs://bucket/video.mp4", "files"), None);
+        assert_eq!(local_resource_id("files/", "files"), None);
+    }
+
+    #[test]
+    fn test_merge_cached_content() {
+        let cached = json!({
+            "contents": [{"role": "user", "parts": [{"text": "long document"}]}],
+            "systemInstruction": {"parts": [{"text": "cached system"}]},
+            "tools": [{"functionDeclarations": []}]
+        });
+        let mut body = json!({
+            "contents": [{"role": "user", "parts": [{"text": "question"}]}],
+            "systemInstruction": {"parts": [{"text": "request system"}]}
+        });
+        merge_cached_content(&mut body, &cached);
+
+        let contents = body["contents"].as_array().unwrap();
+        assert_eq!(contents.len(), 2);
+        assert_eq!(contents[0]["parts"][0]["text"], "long document");
+        assert_eq!(contents[1]["parts"][0]["text"], "question");
+        // 请求自带的 systemInstruction 优先
+        assert_eq!(
+            body["systemInstruction"]["parts"][0]["text"],
+            "request system"
+        );
+        assert!(body["tools"].is_array());
+    }
+
+    #[test]
+    fn test_parse_multipart_related() {
+        let content_type = "multipart/related; boundary=\"sep\"";
+        let body = b"--sep\r\nContent-Type: application/json; charset=utf-8\r\n\r\n{\"file\":{\"displayName\":\"doc\"}}\r\n--sep\r\nContent-Type: application/pdf\r\n\r\n%PDF-1.4\r\n--sep--\r\n";
+        let (metadata, mime, data) = parse_multipart_related(content_type, body).unwrap();
+        assert_eq!(
+            metadata_field(&metadata, "displayName").as_deref(),
+            Some("doc")
+        );
+        assert_eq!(mime.as_deref(), Some("application/pdf"));
+        assert_eq!(data, b"%PDF-1.4");
+        assert!(parse_multipart_related("multipart/related", body).is_err());
+    }
+
+    #[test]
+    fn test_resumable_chunks_validate_offset_and_size() {
+        let mut upload = PendingUpload {
+            owner: None,
+            display_name: String::new(),
+            mime_type: "text/plain".to_string(),
+            data: Vec::new(),
+            started_at: Instant::now(),
+        };
+        upload.append(Some(0), b"hello ").unwrap();
+        // 重传已收到的分片不会重复写入
+        upload.append(Some(0), b"hello ").unwrap();
+        upload.append(None, b"world").unwrap();
+        assert_eq!(upload.data, b"hello world");
+        // 跳过或回退到中间位置的分片被拒绝
+        assert!(upload.append(Some(20), b"!").is_err());
+        assert!(upload.append(Some(3), b"lo there, world").is_err());
+
+        let oversized = vec![0u8; MAX_UPLOAD_BYTES];
+        let err = upload.append(Some(11), &oversized).unwrap_err();
+        assert_eq!(err.status, StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(upload.data.len(), 11);
+    }
+
+    #[test]
+    fn test_cache_expiry_and_usage() {
+        assert_eq!(parse_duration_secs("300s"), Some(300.0));
+        assert_eq!(parse_duration_secs("1.5s"), Some(1.5));
+        assert_eq!(parse_duration_secs("300"), None);
+        assert_eq!(
+            resolve_expiry(&json!({"ttl": "60s"}), 1_000).unwrap(),
+            Some(61_000)
+        );
+        assert!(resolve_expiry(&json!({"expireTime": "soon"}), 0).is_err());
+        assert_eq!(resolve_expiry(&json!({}), 0).unwrap(), None);
+
+        let mut response = json!({"response": {"usageMetadata": {"promptTokenCount": 10}}});
+        annotate_cached_usage(&mut response, 7);
+        assert_eq!(
+            response["response"]["usageMetadata"]["cachedContentTokenCount"],
+            7
+        );
+    }
+}
@@ -4,8 +4,10 @@
 pub mod claude;
 pub mod openai;
 pub mod gemini;
+pub mod gemini_resources; // Gemini files / cachedContents / embeddings
 pub mod mcp;
 pub mod common;
 pub mod audio;  // 音频转录处理器
+pub mod files;  // 图库文件服务
 pub mod warmup; // 预热处理器
 
@@ -12,23 +12,33 @@ use crate::proxy::mappers::openai::{
 };
 // use crate::proxy::upstream::client::UpstreamClient; // 通过 state 获取
 use crate::proxy::debug_logger;
+use crate::proxy::common::error::ProxyError;
 use crate::proxy::server::AppState;
 use crate::proxy::upstream::client::mask_email;
 
 const MAX_RETRY_ATTEMPTS: usize = 3;
 use super::common::{
-    apply_retry_strategy, determine_retry_strategy, should_rotate_account, RetryStrategy,
+    apply_retry_decision, apply_retry_strategy, resolve_retry_decision, token_error_status,
+    RetryAttempts, RetryStrategy,
 };
 use crate::proxy::common::client_adapter::CLIENT_ADAPTERS; // [NEW] Adapter Registry
+use crate::proxy::common::image_url_resolver;
 use crate::proxy::session_manager::SessionManager;
+use super::files;
+use crate::modules::image_store;
+use crate::proxy::middleware::auth::UserTokenIdentity;
+use axum::extract::ConnectInfo;
+use axum::Extension;
 use axum::http::HeaderMap;
+use std::net::SocketAddr;
 use tokio::time::Duration;
 
 pub async fn handle_chat_completions(
     State(state): State<AppState>,
     headers: HeaderMap, // [CHANGED] Extract headers
+    connect_info: Option<ConnectInfo<SocketAddr>>,
     Json(mut body): Json<Value>,
-) -> Result<impl IntoResponse, (StatusCode, String)> {
+) -> Result<impl IntoResponse, ProxyError> {
     // [FIX] 保存原始请求体的完整副本，用于日志记录
     // 这确保了即使结构体定义遗漏字段，日志也能完整记录所有参数
     let original_body = body.clone();
@@ -82,8 +92,17 @@ pub async fn handle_chat_completions(
         }
     }
 
+    let trace_id = format!("req_{}", chrono::Utc::now().timestamp_subsec_millis());
+
+    // 远程 / 本地图片地址解析为内联数据，再缩放并重新编码
+    let local_client = image_url_resolver::is_local_client(connect_info.map(|c| c.0), &headers);
+    image_url_resolver::resolve_image_urls(&mut body, local_client, &trace_id)
+        .await
+        .map_err(ProxyError::invalid_request)?;
+    crate::proxy::common::image_preprocess::preprocess_request_images(&mut body, &trace_id);
+
     let mut openai_req: OpenAIRequest = serde_json::from_value(body)
-        .map_err(|e| (StatusCode::BAD_REQUEST, format!("Invalid request: {}", e)))?;
+        .map_err(|e| ProxyError::invalid_request(format!("Invalid request: {}", e)))?;
 
     // Safety: Ensure messages is not empty
     if openai_req.messages.is_empty() {
@@ -102,7 +121,6 @@ pub async fn handle_chat_completions(
             });
     }
 
-    let trace_id = format!("req_{}", chrono::Utc::now().timestamp_subsec_millis());
     info!(
         "[{}] OpenAI Chat Request: {} | {} messages | stream: {}",
         trace_id,
@@ -154,6 +172,11 @@ pub async fn handle_chat_completions(
         &*state.custom_mapping.read().await,
     );
 
+    // 上一次失败命中的重试规则是否要求轮换账号
+    let mut rotate_on_retry = true;
+    // 各重试规则已命中的失败次数
+    let mut retry_attempts = RetryAttempts::default();
+
     for attempt in 0..max_attempts {
         // 将 OpenAI 工具转为 Value 数组以便探测联网
         let tools_val: Option<Vec<Value>> = openai_req
@@ -174,11 +197,13 @@ pub async fn handle_chat_completions(
         let session_id = SessionManager::extract_openai_session_id(&openai_req);
 
         // 4. 获取 Token (使用准确的 request_type)
-        // 关键：在重试尝试 (attempt > 0) 时强制轮换账号
+        // 关键：在重试尝试 (attempt > 0) 时强制轮换账号 (除非重试规则要求保留当前账号)
+        let force_rotate = attempt > 0 && rotate_on_retry;
+        rotate_on_retry = true;
         let (access_token, project_id, email, account_id, _wait_ms) = match token_manager
             .get_token(
                 &config.request_type,
-                attempt > 0,
+                force_rotate,
                 Some(&session_id),
                 &mapped_model,
             )
@@ -189,7 +214,7 @@ pub async fn handle_chat_completions(
                 // [FIX] Attach headers to error response for logging visibility
                 let headers = [("X-Mapped-Model", mapped_model.as_str())];
                 return Ok((
-                    StatusCode::SERVICE_UNAVAILABLE,
+                    token_error_status(&e),
                     headers,
                     format!("Token error: {}", e),
                 )
@@ -201,9 +226,40 @@ pub async fn handle_chat_completions(
         info!("✓ Using account: {} (type: {})", email, config.request_type);
 
         // 4. 转换请求 (返回内容包含 session_id 和 message_count)
-        let (gemini_body, session_id, message_count) =
+        let (mut gemini_body, session_id, message_count) =
             transform_openai_request(&openai_req, &project_id, &mapped_model);
 
+        // [NEW] 上下文压力管理 (与 Claude 路径共用 L1/L2/L3 阈值)
+        let experimental = state.experimental.read().await.clone();
+        let summarizer = crate::proxy::mappers::context_compression::Summarizer {
+            upstream: &upstream,
+            access_token: &access_token,
+            project_id: &project_id,
+            account_id: &account_id,
+            model: crate::proxy::common::model_mapping::resolve_model_route(
+                "internal-background-task",
+                &*state.custom_mapping.read().await,
+            ),
+        };
+        if let Err(e) = crate::proxy::mappers::context_compression::compress_gemini_body(
+            &mut gemini_body,
+            &mapped_model,
+            &experimental,
+            &summarizer,
+            &trace_id,
+        )
+        .await
+        {
+            return Ok((
+                [("X-Mapped-Model", mapped_model.as_str())],
+                ProxyError::invalid_request(format!(
+                    "Context too long and automatic compression failed: {}",
+                    e
+                )),
+            )
+                .into_response());
+        }
+
         if debug_logger::is_enabled(&debug_cfg) {
             let payload = json!({
                 "kind": "v1internal_request",
@@ -261,6 +317,12 @@ pub async fn handle_chat_completions(
             );
         }
 
+        // [NEW] 流式中断续传需要保留原始请求体
+        let resume_cfg = crate::proxy::config::get_retry_policy_config().stream_resume;
+        let resume_body = (actual_stream && resume_cfg.enabled).then(|| gemini_body.clone());
+        let continue_cfg = crate::proxy::config::get_auto_continue_config();
+        let continue_body = (actual_stream && continue_cfg.enabled).then(|| gemini_body.clone());
+
         let call_result = match upstream
             .call_v1_internal_with_headers(
                 method,
@@ -338,13 +400,44 @@ pub async fn handle_chat_completions(
                     "status": status.as_u16(),
                     "upstream_url": upstream_url,
                 });
-                let gemini_stream = debug_logger::wrap_reqwest_stream_with_debug(
+                let mut gemini_stream = debug_logger::wrap_reqwest_stream_with_debug(
                     Box::pin(response.bytes_stream()),
                     debug_cfg.clone(),
                     trace_id.clone(),
                     "upstream_response",
                     meta,
                 );
+                if let Some(body) = continue_body {
+                    gemini_stream = crate::proxy::upstream::continuation::continuing_stream(
+                        gemini_stream,
+                        crate::proxy::upstream::resume::ResumeContext {
+                            token_manager: token_manager.clone(),
+                            upstream: upstream.clone(),
+                            body,
+                            request_type: config.request_type.clone(),
+                            model: mapped_model.clone(),
+                            extra_headers: extra_headers.clone(),
+                            trace_id: trace_id.clone(),
+                        },
+                        continue_cfg,
+                        openai_req.max_tokens,
+                    );
+                }
+                if let Some(body) = resume_body {
+                    gemini_stream = crate::proxy::upstream::resume::resumable_stream(
+                        gemini_stream,
+                        crate::proxy::upstream::resume::ResumeContext {
+                            token_manager: token_manager.clone(),
+                            upstream: upstream.clone(),
+                            body,
+                            request_type: config.request_type.clone(),
+                            model: mapped_model.clone(),
+                            extra_headers: extra_headers.clone(),
+                            trace_id: trace_id.clone(),
+                        },
+                        resume_cfg,
+                    );
+                }
 
                 // [P1 FIX] Enhanced Peek logic to handle heartbeats and slow start
                 // Pre-read until we find meaningful content, skip heartbeats
@@ -360,25 +453,20 @@ pub async fn handle_chat_completions(
                 let mut retry_this_account = false;
 
                 // Loop to skip heartbeats during peek
+                // 心跳会持续到达，因此使用总截止时间而非单次读取超时
+                let peek_deadline =
+                    tokio::time::Instant::now() + std::time::Duration::from_secs(60);
                 loop {
-                    match tokio::time::timeout(
-                        std::time::Duration::from_secs(60),
-                        openai_stream.next(),
-                    )
-                    .await
-                    {
+                    match tokio::time::timeout_at(peek_deadline, openai_stream.next()).await {
                         Ok(Some(Ok(bytes))) => {
-                            if bytes.is_empty() {
-                                continue;
-                            }
-
-                            let text = String::from_utf8_lossy(&bytes);
                             // Skip SSE comments/pings (heartbeats)
-                            if text.trim().starts_with(":") || text.trim().starts_with("data: :") {
+                            if crate::proxy::mappers::heartbeat::is_heartbeat(&bytes) {
                                 tracing::debug!("[OpenAI] Skipping peek heartbeat");
                                 continue;
                             }
 
+                            let text = String::from_utf8_lossy(&bytes);
+
                             // Check for error events
                             if text.contains("\"error\"") {
                                 tracing::warn!("[OpenAI] Error detected during peek, retrying...");
@@ -460,11 +548,11 @@ pub async fn handle_chat_completions(
                         }
                         Err(e) => {
                             error!("[{}] Stream collection error: {}", trace_id, e);
-                            return Ok((
-                                StatusCode::INTERNAL_SERVER_ERROR,
-                                format!("Stream collection error: {}", e),
-                            )
-                                .into_response());
+                            return Ok(ProxyError::internal(format!(
+                                "Stream collection error: {}",
+                                e
+                            ))
+                            .into_response());
                         }
                     }
                 }
@@ -473,7 +561,7 @@ pub async fn handle_chat_completions(
             let gemini_resp: Value = response
                 .json()
                 .await
-                .map_err(|e| (StatusCode::BAD_GATEWAY, format!("Parse error: {}", e)))?;
+                .map_err(|e| ProxyError::bad_gateway(format!("Parse error: {}", e)))?;
 
             let openai_response =
                 transform_openai_response(&gemini_resp, Some(&session_id), message_count);
@@ -531,7 +619,7 @@ pub async fn handle_chat_completions(
         }
 
         // 确定重试策略
-        let strategy = determine_retry_strategy(status_code, &error_text, false);
+        let decision = resolve_retry_decision(status_code, &error_text, false);
 
         // 3. 标记限流状态(用于 UI 显示)
         if status_code == 429 || status_code == 529 || status_code == 503 || status_code == 500 {
@@ -548,7 +636,7 @@ pub async fn handle_chat_completions(
         }
 
         // 执行退避
-        if apply_retry_strategy(strategy, attempt, max_attempts, status_code, &trace_id).await {
+        if apply_retry_decision(&decision, &mut retry_attempts, attempt, max_attempts, status_code, &trace_id).await {
             // [NEW] Apply Client Adapter "let_it_crash" strategy
             if let Some(adapter) = &client_adapter {
                 if adapter.let_it_crash() && attempt > 0 {
@@ -567,12 +655,13 @@ pub async fn handle_chat_completions(
             }
 
             // 判断是否需要轮换账号
-            if !should_rotate_account(status_code) {
+            if !decision.rotate_account {
                 debug!(
                     "[{}] Keeping same account for status {} (server-side issue)",
                     trace_id, status_code
                 );
             }
+            rotate_on_retry = decision.rotate_account;
 
             // 2. [REMOVED] 不再特殊处理 QUOTA_EXHAUSTED，允许账号轮换
             // if error_text.contains("QUOTA_EXHAUSTED") { ... }
@@ -703,19 +792,11 @@ pub async fn handle_chat_completions(
             status_code, email, error_text
         );
         return Ok((
-            status,
             [
                 ("X-Account-Email", email.as_str()),
                 ("X-Mapped-Model", mapped_model.as_str()),
             ],
-            // [FIX] Return JSON error for better client compatibility
-            Json(json!({
-                "error": {
-                    "message": error_text,
-                    "type": "upstream_error",
-                    "code": status_code
-                }
-            })),
+            ProxyError::upstream(status, &error_text),
         )
             .into_response());
     }
@@ -723,16 +804,20 @@ pub async fn handle_chat_completions(
     // 所有尝试均失败
     if let Some(email) = last_email {
         Ok((
-            StatusCode::TOO_MANY_REQUESTS,
             [("X-Account-Email", email), ("X-Mapped-Model", mapped_model)],
-            format!("All accounts exhausted. Last error: {}", last_error),
+            ProxyError::new(
+                StatusCode::TOO_MANY_REQUESTS,
+                format!("All accounts exhausted. Last error: {}", last_error),
+            ),
         )
             .into_response())
     } else {
         Ok((
-            StatusCode::TOO_MANY_REQUESTS,
             [("X-Mapped-Model", mapped_model)],
-            format!("All accounts exhausted. Last error: {}", last_error),
+            ProxyError::new(
+                StatusCode::TOO_MANY_REQUESTS,
+                format!("All accounts exhausted. Last error: {}", last_error),
+            ),
         )
             .into_response())
     }
@@ -742,6 +827,8 @@ pub async fn handle_chat_completions(
 /// 将 Prompt 转换为 Chat Message 格式，复用 handle_chat_completions
 pub async fn handle_completions(
     State(state): State<AppState>,
+    headers: HeaderMap,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
     Json(mut body): Json<Value>,
 ) -> Response {
     debug!(
@@ -1093,10 +1180,20 @@ pub async fn handle_completions(
         );
     }
 
+    let trace_id = format!("req_{}", chrono::Utc::now().timestamp_subsec_millis());
+
+    // 远程 / 本地图片地址解析为内联数据，再缩放并重新编码
+    let local_client = image_url_resolver::is_local_client(connect_info.map(|c| c.0), &headers);
+    if let Err(e) = image_url_resolver::resolve_image_urls(&mut body, local_client, &trace_id).await
+    {
+        return ProxyError::invalid_request(e).into_response();
+    }
+    crate::proxy::common::image_preprocess::preprocess_request_images(&mut body, &trace_id);
+
     let mut openai_req: OpenAIRequest = match serde_json::from_value(body.clone()) {
         Ok(req) => req,
         Err(e) => {
-            return (StatusCode::BAD_REQUEST, format!("Invalid request: {}", e)).into_response();
+            return ProxyError::invalid_request(format!("Invalid request: {}", e)).into_response();
         }
     };
 
@@ -1130,7 +1227,11 @@ pub async fn handle_completions(
         &openai_req.model,
         &*state.custom_mapping.read().await,
     );
-    let trace_id = format!("req_{}", chrono::Utc::now().timestamp_subsec_millis());
+
+    // 上一次失败命中的重试规则是否要求轮换账号
+    let mut rotate_on_retry = true;
+    // 各重试规则已命中的失败次数
+    let mut retry_attempts = RetryAttempts::default();
 
     for attempt in 0..max_attempts {
         // 3. 模型配置解析
@@ -1154,8 +1255,9 @@ pub async fn handle_completions(
         let session_id_str = SessionManager::extract_openai_session_id(&openai_req);
         let session_id = Some(session_id_str.as_str());
 
-        // 重试时强制轮换，除非只是简单的网络抖动但 Claude 逻辑里 attempt > 0 总是 force_rotate
-        let force_rotate = attempt > 0;
+        // 重试时强制轮换，除非命中的重试规则要求保留当前账号
+        let force_rotate = attempt > 0 && rotate_on_retry;
+        rotate_on_retry = true;
 
         let (access_token, project_id, email, account_id, _wait_ms) = match token_manager
             .get_token(
@@ -1169,7 +1271,7 @@ pub async fn handle_completions(
             Ok(t) => t,
             Err(e) => {
                 return (
-                    StatusCode::SERVICE_UNAVAILABLE,
+                    token_error_status(&e),
                     [("X-Mapped-Model", mapped_model)],
                     format!("Token error: {}", e),
                 )
@@ -1181,9 +1283,40 @@ pub async fn handle_completions(
 
         info!("✓ Using account: {} (type: {})", email, config.request_type);
 
-        let (gemini_body, session_id, message_count) =
+        let (mut gemini_body, session_id, message_count) =
             transform_openai_request(&openai_req, &project_id, &mapped_model);
 
+        // [NEW] 上下文压力管理 (与 Claude 路径共用 L1/L2/L3 阈值)
+        let experimental = state.experimental.read().await.clone();
+        let summarizer = crate::proxy::mappers::context_compression::Summarizer {
+            upstream: &upstream,
+            access_token: &access_token,
+            project_id: &project_id,
+            account_id: &account_id,
+            model: crate::proxy::common::model_mapping::resolve_model_route(
+                "internal-background-task",
+                &*state.custom_mapping.read().await,
+            ),
+        };
+        if let Err(e) = crate::proxy::mappers::context_compression::compress_gemini_body(
+            &mut gemini_body,
+            &mapped_model,
+            &experimental,
+            &summarizer,
+            &trace_id,
+        )
+        .await
+        {
+            return (
+                [("X-Mapped-Model", mapped_model)],
+                ProxyError::invalid_request(format!(
+                    "Context too long and automatic compression failed: {}",
+                    e
+                )),
+            )
+                .into_response();
+        }
+
         // [New] 打印转换后的报文 (Gemini Body) 供调试 (Codex 路径) ———— 缩减为 simple debug
         debug!(
             "[Codex-Request] Transformed Gemini Body ({} parts)",
@@ -1269,23 +1402,16 @@ pub async fn handle_completions(
                     let mut first_data_chunk = None;
                     let mut retry_this_account = false;
 
+                    // 心跳会持续到达，因此使用总截止时间而非单次读取超时
+                    let peek_deadline =
+                        tokio::time::Instant::now() + std::time::Duration::from_secs(60);
                     loop {
-                        match tokio::time::timeout(
-                            std::time::Duration::from_secs(60),
-                            openai_stream.next(),
-                        )
-                        .await
-                        {
+                        match tokio::time::timeout_at(peek_deadline, openai_stream.next()).await {
                             Ok(Some(Ok(bytes))) => {
-                                if bytes.is_empty() {
+                                if crate::proxy::mappers::heartbeat::is_heartbeat(&bytes) {
                                     continue;
                                 }
                                 let text = String::from_utf8_lossy(&bytes);
-                                if text.trim().starts_with(":")
-                                    || text.trim().starts_with("data: :")
-                                {
-                                    continue;
-                                }
                                 if text.contains("\"error\"") {
                                     last_error = "Error event during peek".to_string();
                                     retry_this_account = true;
@@ -1346,23 +1472,16 @@ pub async fn handle_completions(
                     // Peek Logic (Repeated for safety/correctness on this stream type)
                     let mut first_data_chunk = None;
                     let mut retry_this_account = false;
+                    // 心跳会持续到达，因此使用总截止时间而非单次读取超时
+                    let peek_deadline =
+                        tokio::time::Instant::now() + std::time::Duration::from_secs(60);
                     loop {
-                        match tokio::time::timeout(
-                            std::time::Duration::from_secs(60),
-                            openai_stream.next(),
-                        )
-                        .await
-                        {
+                        match tokio::time::timeout_at(peek_deadline, openai_stream.next()).await {
                             Ok(Some(Ok(bytes))) => {
-                                if bytes.is_empty() {
+                                if crate::proxy::mappers::heartbeat::is_heartbeat(&bytes) {
                                     continue;
                                 }
                                 let text = String::from_utf8_lossy(&bytes);
-                                if text.trim().starts_with(":")
-                                    || text.trim().starts_with("data: :")
-                                {
-                                    continue;
-                                }
                                 if text.contains("\"error\"") {
                                     last_error = "Error event in internal stream".to_string();
                                     retry_this_account = true;
@@ -1434,10 +1553,7 @@ pub async fn handle_completions(
                                 .into_response();
                         }
                         Err(e) => {
-                            return (
-                                StatusCode::INTERNAL_SERVER_ERROR,
-                                format!("Stream collection error: {}", e),
-                            )
+                            return ProxyError::internal(format!("Stream collection error: {}", e))
                                 .into_response();
                         }
                     }
@@ -1448,9 +1564,8 @@ pub async fn handle_completions(
                 Ok(json) => json,
                 Err(e) => {
                     return (
-                        StatusCode::BAD_GATEWAY,
                         [("X-Mapped-Model", mapped_model.as_str())],
-                        format!("Parse error: {}", e),
+                        ProxyError::bad_gateway(format!("Parse error: {}", e)),
                     )
                         .into_response();
                 }
@@ -1524,10 +1639,11 @@ pub async fn handle_completions(
         }
 
         // 确定重试策略
-        let strategy = determine_retry_strategy(status_code, &error_text, false);
+        let decision = resolve_retry_decision(status_code, &error_text, false);
 
-        if apply_retry_strategy(strategy, attempt, max_attempts, status_code, &trace_id).await {
-            // 继续重试 (loop 会增加 attempt, 导致 force_rotate=true)
+        if apply_retry_decision(&decision, &mut retry_attempts, attempt, max_attempts, status_code, &trace_id).await {
+            // 继续重试 (是否轮换账号由命中的规则决定)
+            rotate_on_retry = decision.rotate_account;
             continue;
         } else {
             // 不可重试
@@ -1546,16 +1662,20 @@ pub async fn handle_completions(
     // 所有尝试均失败
     if let Some(email) = last_email {
         (
-            StatusCode::TOO_MANY_REQUESTS,
             [("X-Account-Email", email), ("X-Mapped-Model", mapped_model)],
-            format!("All accounts exhausted. Last error: {}", last_error),
+            ProxyError::new(
+                StatusCode::TOO_MANY_REQUESTS,
+                format!("All accounts exhausted. Last error: {}", last_error),
+            ),
         )
             .into_response()
     } else {
         (
-            StatusCode::TOO_MANY_REQUESTS,
             [("X-Mapped-Model", mapped_model)],
-            format!("All accounts exhausted. Last error: {}", last_error),
+            ProxyError::new(
+                StatusCode::TOO_MANY_REQUESTS,
+                format!("All accounts exhausted. Last error: {}", last_error),
+            ),
         )
             .into_response()
     }
@@ -1588,13 +1708,15 @@ pub async fn handle_list_models(State(state): State<AppState>) -> impl IntoRespo
 /// 处理图像生成请求，转换为 Gemini API 格式
 pub async fn handle_images_generations(
     State(state): State<AppState>,
+    headers: HeaderMap,
+    identity: Option<Extension<UserTokenIdentity>>,
     Json(body): Json<Value>,
-) -> Result<impl IntoResponse, (StatusCode, String)> {
+) -> Result<impl IntoResponse, ProxyError> {
     // 1. 解析请求参数
-    let prompt = body.get("prompt").and_then(|v| v.as_str()).ok_or((
-        StatusCode::BAD_REQUEST,
-        "Missing 'prompt' field".to_string(),
-    ))?;
+    let prompt = body
+        .get("prompt")
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| ProxyError::invalid_request("Missing 'prompt' field"))?;
 
     let model = body
         .get("model")
@@ -1780,7 +1902,8 @@ pub async fn handle_images_generations(
     }
 
     // 5. 收集结果
-    let mut images: Vec<Value> = Vec::new();
+    // (base64 数据, MIME)
+    let mut images: Vec<(String, String)> = Vec::new();
     let mut errors: Vec<String> = Vec::new();
     let mut used_email: Option<String> = None;
 
@@ -1804,19 +1927,11 @@ pub async fn handle_images_generations(
                             if let Some(img) = part.get("inlineData") {
                                 let data = img.get("data").and_then(|v| v.as_str()).unwrap_or("");
                                 if !data.is_empty() {
-                                    if response_format == "url" {
-                                        let mime_type = img
-                                            .get("mimeType")
-                                            .and_then(|v| v.as_str())
-                                            .unwrap_or("image/png");
-                                        images.push(json!({
-                                            "url": format!("data:{};base64,{}", mime_type, data)
-                                        }));
-                                    } else {
-                                        images.push(json!({
-                                            "b64_json": data
-                                        }));
-                                    }
+                                    let mime_type = img
+                                        .get("mimeType")
+                                        .and_then(|v| v.as_str())
+                                        .unwrap_or("image/png");
+                                    images.push((data.to_string(), mime_type.to_string()));
                                     tracing::debug!("[Images] Task {} succeeded", idx);
                                 }
                             }
@@ -1853,7 +1968,7 @@ pub async fn handle_images_generations(
             StatusCode::BAD_GATEWAY
         };
 
-        return Err((status, error_msg));
+        return Err(ProxyError::new(status, error_msg));
     }
 
     // 部分成功时记录警告
@@ -1872,10 +1987,18 @@ pub async fn handle_images_generations(
         n
     );
 
-    // 6. 构建 OpenAI 格式响应
+    // 6. 保存到图库并构建 OpenAI 格式响应
+    let meta = image_store::NewImage {
+        source: "generation".to_string(),
+        prompt: prompt.to_string(),
+        model: "gemini-3-pro-image".to_string(),
+        token_id: identity.as_ref().map(|Extension(id)| id.token_id.clone()),
+        username: identity.as_ref().map(|Extension(id)| id.username.clone()),
+    };
+    let data = files::build_image_entries(images, meta, response_format, &headers).await;
     let openai_response = json!({
         "created": chrono::Utc::now().timestamp(),
-        "data": images
+        "data": data
     });
 
     let email_header = used_email.unwrap_or_default();
@@ -1892,8 +2015,10 @@ pub async fn handle_images_generations(
 
 pub async fn handle_images_edits(
     State(state): State<AppState>,
+    headers: HeaderMap,
+    identity: Option<Extension<UserTokenIdentity>>,
     mut multipart: axum::extract::Multipart,
-) -> Result<impl IntoResponse, (StatusCode, String)> {
+) -> Result<impl IntoResponse, ProxyError> {
     tracing::info!("[Images] Received edit request");
 
     let mut image_data = None;
@@ -1911,7 +2036,7 @@ pub async fn handle_images_edits(
     while let Some(field) = multipart
         .next_field()
         .await
-        .map_err(|e| (StatusCode::BAD_REQUEST, format!("Multipart error: {}", e)))?
+        .map_err(|e| ProxyError::invalid_request(format!("Multipart error: {}", e)))?
     {
         let name = field.name().unwrap_or("").to_string();
 
@@ -1919,28 +2044,25 @@ pub async fn handle_images_edits(
             let data = field
                 .bytes()
                 .await
-                .map_err(|e| (StatusCode::BAD_REQUEST, format!("Image read error: {}", e)))?;
+                .map_err(|e| ProxyError::invalid_request(format!("Image read error: {}", e)))?;
             image_data = Some(base64::engine::general_purpose::STANDARD.encode(data));
         } else if name == "mask" {
             let data = field
                 .bytes()
                 .await
-                .map_err(|e| (StatusCode::BAD_REQUEST, format!("Mask read error: {}", e)))?;
+                .map_err(|e| ProxyError::invalid_request(format!("Mask read error: {}", e)))?;
             mask_data = Some(base64::engine::general_purpose::STANDARD.encode(data));
         } else if name.starts_with("image") && name != "image_size" {
             // Support image1, image2, etc.
             let data = field.bytes().await.map_err(|e| {
-                (
-                    StatusCode::BAD_REQUEST,
-                    format!("Reference image read error: {}", e),
-                )
+                ProxyError::invalid_request(format!("Reference image read error: {}", e))
             })?;
             reference_images.push(base64::engine::general_purpose::STANDARD.encode(data));
         } else if name == "prompt" {
             prompt = field
                 .text()
                 .await
-                .map_err(|e| (StatusCode::BAD_REQUEST, format!("Prompt read error: {}", e)))?;
+                .map_err(|e| ProxyError::invalid_request(format!("Prompt read error: {}", e)))?;
         } else if name == "n" {
             if let Ok(val) = field.text().await {
                 n = val.parse().unwrap_or(1);
@@ -1977,7 +2099,7 @@ pub async fn handle_images_edits(
     // Validation: Require either 'image' (standard edit) OR 'prompt' (generation)
     // If reference images are present, we treat it as generation with image context
     if prompt.is_empty() {
-        return Err((StatusCode::BAD_REQUEST, "Missing prompt".to_string()));
+        return Err(ProxyError::invalid_request("Missing prompt"));
     }
 
     tracing::info!(
@@ -2180,14 +2302,15 @@ pub async fn handle_images_edits(
     }
 
     // 5. Collect Results
-    let mut images: Vec<Value> = Vec::new();
+    // (base64 数据, MIME)
+    let mut images: Vec<(String, String)> = Vec::new();
     let mut errors: Vec<String> = Vec::new();
     let mut used_email: Option<String> = None;
 
     for (idx, task) in tasks.into_iter().enumerate() {
         match task.await {
             Ok(result) => match result {
-                Ok((gemini_resp, response_format, email_used)) => {
+                Ok((gemini_resp, _response_format, email_used)) => {
                     if used_email.is_none() {
                         used_email = Some(email_used);
                     }
@@ -2203,19 +2326,11 @@ pub async fn handle_images_edits(
                             if let Some(img) = part.get("inlineData") {
                                 let data = img.get("data").and_then(|v| v.as_str()).unwrap_or("");
                                 if !data.is_empty() {
-                                    if response_format == "url" {
-                                        let mime_type = img
-                                            .get("mimeType")
-                                            .and_then(|v| v.as_str())
-                                            .unwrap_or("image/png");
-                                        images.push(json!({
-                                            "url": format!("data:{};base64,{}", mime_type, data)
-                                        }));
-                                    } else {
-                                        images.push(json!({
-                                            "b64_json": data
-                                        }));
-                                    }
+                                    let mime_type = img
+                                        .get("mimeType")
+                                        .and_then(|v| v.as_str())
+                                        .unwrap_or("image/png");
+                                    images.push((data.to_string(), mime_type.to_string()));
                                     tracing::debug!("[Images] Task {} succeeded", idx);
                                 }
                             }
@@ -2246,7 +2361,7 @@ pub async fn handle_images_edits(
             n,
             error_msg
         );
-        return Err((StatusCode::BAD_GATEWAY, error_msg));
+        return Err(ProxyError::bad_gateway(error_msg));
     }
 
     if !errors.is_empty() {
@@ -2264,9 +2379,17 @@ pub async fn handle_images_edits(
         n
     );
 
+    let meta = image_store::NewImage {
+        source: "edit".to_string(),
+        prompt: prompt.clone(),
+        model: model.clone(),
+        token_id: identity.as_ref().map(|Extension(id)| id.token_id.clone()),
+        username: identity.as_ref().map(|Extension(id)| id.username.clone()),
+    };
+    let data = files::build_image_entries(images, meta, &response_format, &headers).await;
     let openai_response = json!({
         "created": chrono::Utc::now().timestamp(),
-        "data": images
+        "data": data
     });
 
     let email_header = used_email.unwrap_or_default();
@@ -0,0 +1,291 @@
+// 非流式短请求对冲 (Request Hedging)
+// 后台任务等短小的非流式调用偶尔会卡在慢账号上。启用后，若上游在阈值内仍未响应，
+// 就在第二个账号上发出相同请求，取先成功者；落败的请求 future 被直接丢弃 (即取消上游连接)，
+// 因此只有胜出者的响应 (及其账号、用量) 会返回给客户端并进入监控日志与 token_stats。
+//
+// 目前只覆盖 Claude 协议中被识别为后台任务 (标题生成、摘要等) 的非流式请求；
+// OpenAI / Gemini 协议没有后台任务识别，不做对冲。
+//
+// 阈值可固定配置，也可按模型学习最近请求延迟的 p95。
+
+use crate::proxy::config::HedgingConfig;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+static HEDGER: Lazy<Hedger> = Lazy::new(Hedger::new);
+
+/// 每个模型保留的延迟样本数
+const LATENCY_WINDOW: usize = 200;
+/// 计算 p95 所需的最少样本数
+const MIN_SAMPLES: usize = 20;
+
+/// 对冲统计 (供 Admin API 展示)
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct HedgingStats {
+    /// 发出了对冲请求的次数
+    pub total_hedged: u64,
+    /// 对冲请求先完成的次数
+    pub hedge_wins: u64,
+    /// 按模型学习到的 p95 延迟 (毫秒，样本不足时不出现)
+    pub p95_ms_by_model: BTreeMap<String, u64>,
+}
+
+#[derive(Debug, Default)]
+pub struct Hedger {
+    latencies: Mutex<HashMap<String, VecDeque<u64>>>,
+    total_hedged: AtomicU64,
+    hedge_wins: AtomicU64,
+}
+
+impl Hedger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn latencies(&self) -> MutexGuard<'_, HashMap<String, VecDeque<u64>>> {
+        self.latencies.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// 记录一次成功请求的完成延迟
+    pub fn record_latency(&self, model: &str, latency: Duration) {
+        let mut latencies = self.latencies();
+        let samples = latencies.entry(model.to_string()).or_default();
+        if samples.len() >= LATENCY_WINDOW {
+            samples.pop_front();
+        }
+        samples.push_back(latency.as_millis() as u64);
+    }
+
+    /// 该模型最近请求延迟的 p95 (毫秒)
+    pub fn p95_ms(&self, model: &str) -> Option<u64> {
+        self.latencies().get(model).and_then(|s| percentile_95(s))
+    }
+
+    /// 该模型的对冲阈值；未启用时返回 None
+    pub fn threshold(&self, cfg: &HedgingConfig, model: &str) -> Option<Duration> {
+        if !cfg.enabled {
+            return None;
+        }
+        let ms = if cfg.threshold_ms > 0 {
+            cfg.threshold_ms
+        } else {
+            self.p95_ms(model)
+                .map(|p95| p95.max(cfg.min_threshold_ms))
+                .unwrap_or(cfg.cold_start_threshold_ms)
+        };
+        Some(Duration::from_millis(ms))
+    }
+
+    /// 执行 primary；超过 delay 仍未完成时调用 hedge 发出对冲请求，返回先成功者。
+    /// 两者都失败时返回 primary 的错误。返回值中的 bool 表示是否由对冲请求胜出。
+    pub async fn race<T, P, H, HF>(
+        &self,
+        model: &str,
+        delay: Duration,
+        primary: P,
+        hedge: H,
+    ) -> Result<(T, bool), String>
+    where
+        P: Future<Output = Result<T, String>>,
+        H: FnOnce() -> HF,
+        HF: Future<Output = Result<T, String>>,
+    {
+        let primary_started = Instant::now();
+        tokio::pin!(primary);
+
+        tokio::select! {
+            res = &mut primary => {
+                if res.is_ok() {
+                    self.record_latency(model, primary_started.elapsed());
+                }
+                return res.map(|v| (v, false));
+            }
+            _ = tokio::time::sleep(delay) => {}
+        }
+
+        self.total_hedged.fetch_add(1, Ordering::Relaxed);
+        tracing::info!(
+            "[Hedging] No response for {} after {}ms, firing hedge request",
+            model,
+            delay.as_millis()
+        );
+        let hedge_started = Instant::now();
+        let hedged = hedge();
+        tokio::pin!(hedged);
+
+        let mut primary_err: Option<String> = None;
+        let mut hedge_failed = false;
+        loop {
+            tokio::select! {
+                res = &mut primary, if primary_err.is_none() => match res {
+                    Ok(v) => {
+                        self.record_latency(model, primary_started.elapsed());
+                        return Ok((v, false));
+                    }
+                    Err(e) if hedge_failed => return Err(e),
+                    Err(e) => primary_err = Some(e),
+                },
+                res = &mut hedged, if !hedge_failed => match res {
+                    Ok(v) => {
+                        self.hedge_wins.fetch_add(1, Ordering::Relaxed);
+                        self.record_latency(model, hedge_started.elapsed());
+                        return Ok((v, true));
+                    }
+                    Err(e) => {
+                        tracing::debug!("[Hedging] Hedge request for {} failed: {}", model, e);
+                        if let Some(pe) = primary_err {
+                            return Err(pe);
+                        }
+                        hedge_failed = true;
+                    }
+                },
+            }
+        }
+    }
+
+    pub fn stats(&self) -> HedgingStats {
+        let p95_ms_by_model = self
+            .latencies()
+            .iter()
+            .filter_map(|(model, s)| percentile_95(s).map(|p| (model.clone(), p)))
+            .collect();
+        HedgingStats {
+            total_hedged: self.total_hedged.load(Ordering::Relaxed),
+            hedge_wins: self.hedge_wins.load(Ordering::Relaxed),
+            p95_ms_by_model,
+        }
+    }
+}
+
+fn percentile_95(samples: &VecDeque<u64>) -> Option<u64> {
+    if samples.len() < MIN_SAMPLES {
+        return None;
+    }
+    let mut sorted: Vec<u64> = samples.iter().copied().collect();
+    sorted.sort_unstable();
+    let idx = (sorted.len() * 95).div_ceil(100).saturating_sub(1);
+    sorted.get(idx).copied()
+}
+
+/// 使用全局对冲器计算阈值
+pub fn threshold(cfg: &HedgingConfig, model: &str) -> Option<Duration> {
+    HEDGER.threshold(cfg, model)
+}
+
+/// 使用全局对冲器执行对冲竞速
+pub async fn race<T, P, H, HF>(
+    model: &str,
+    delay: Duration,
+    primary: P,
+    hedge: H,
+) -> Result<(T, bool), String>
+where
+    P: Future<Output = Result<T, String>>,
+    H: FnOnce() -> HF,
+    HF: Future<Output = Result<T, String>>,
+{
+    HEDGER.race(model, delay, primary, hedge).await
+}
+
+pub fn stats() -> HedgingStats {
+    HEDGER.stats()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cfg() -> HedgingConfig {
+        HedgingConfig {
+            enabled: true,
+            ..HedgingConfig::default()
+        }
+    }
+
+    async fn after(ms: u64, result: Result<&'static str, String>) -> Result<&'static str, String> {
+        tokio::time::sleep(Duration::from_millis(ms)).await;
+        result
+    }
+
+    #[test]
+    fn test_threshold_uses_learned_p95() {
+        let hedger = Hedger::new();
+        assert_eq!(hedger.threshold(&HedgingConfig::default(), "m"), None);
+        assert_eq!(hedger.threshold(&cfg(), "m"), Some(Duration::from_millis(5_000)));
+
+        for ms in 1..=100 {
+            hedger.record_latency("m", Duration::from_millis(ms * 100));
+        }
+        assert_eq!(hedger.p95_ms("m"), Some(9_500));
+        assert_eq!(hedger.threshold(&cfg(), "m"), Some(Duration::from_millis(9_500)));
+
+        let fixed = HedgingConfig {
+            threshold_ms: 250,
+            ..cfg()
+        };
+        assert_eq!(hedger.threshold(&fixed, "m"), Some(Duration::from_millis(250)));
+    }
+
+    #[tokio::test]
+    async fn test_fast_primary_never_hedges() {
+        let hedger = Hedger::new();
+        let res = hedger
+            .race("m", Duration::from_millis(100), after(10, Ok("primary")), || {
+                after(0, Ok("hedge"))
+            })
+            .await;
+        assert_eq!(res, Ok(("primary", false)));
+        assert_eq!(hedger.stats().total_hedged, 0);
+    }
+
+    #[tokio::test]
+    async fn test_hedge_wins_and_primary_is_cancelled() {
+        let hedger = Hedger::new();
+        let cancelled = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(true));
+        let flag = cancelled.clone();
+        let primary = async move {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            flag.store(false, Ordering::SeqCst);
+            Ok("primary")
+        };
+        let res = hedger
+            .race("m", Duration::from_millis(20), primary, || after(10, Ok("hedge")))
+            .await;
+        assert_eq!(res, Ok(("hedge", true)));
+        assert!(cancelled.load(Ordering::SeqCst));
+
+        let stats = hedger.stats();
+        assert_eq!(stats.total_hedged, 1);
+        assert_eq!(stats.hedge_wins, 1);
+    }
+
+    #[tokio::test]
+    async fn test_falls_back_when_one_side_fails() {
+        let hedger = Hedger::new();
+        let res = hedger
+            .race("m", Duration::from_millis(20), after(100, Ok("primary")), || {
+                after(10, Err("no alternative account".to_string()))
+            })
+            .await;
+        assert_eq!(res, Ok(("primary", false)));
+
+        let res = hedger
+            .race("m", Duration::from_millis(20), after(40, Err("timeout".to_string())), || {
+                after(60, Ok("hedge"))
+            })
+            .await;
+        assert_eq!(res, Ok(("hedge", true)));
+
+        let res: Result<(&str, bool), String> = hedger
+            .race("m", Duration::from_millis(20), after(40, Err("primary".to_string())), || {
+                after(10, Err("hedge".to_string()))
+            })
+            .await;
+        assert_eq!(res, Err("primary".to_string()));
+    }
+}
@@ -0,0 +1,206 @@
+// 管理员登录失败限速
+// /auth/login 分别按 (用户名, 客户端 IP) 与客户端 IP 统计失败次数：窗口内失败过多时锁定一段时间，
+// 锁定期间直接拒绝 (不再校验密码)，防止在线暴力破解与 Argon2 校验耗尽 CPU。
+// 用户名计数按来源 IP 区分，他人无法通过故意输错密码把账号所有者锁在外面；
+// IP 上限较高，避免同一出口下的多个管理员相互影响。记录数有上限，防止伪造大量来源撑爆内存。
+
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+static THROTTLE: Lazy<LoginThrottle> = Lazy::new(LoginThrottle::new);
+
+/// 统计失败次数的窗口
+const WINDOW: Duration = Duration::from_secs(15 * 60);
+/// 达到上限后的锁定时长
+const LOCKOUT: Duration = Duration::from_secs(15 * 60);
+/// 同一来源对同一用户名在窗口内允许的失败次数
+const MAX_FAILURES_PER_USER: u32 = 5;
+/// 同一 IP 在窗口内允许的失败次数
+const MAX_FAILURES_PER_IP: u32 = 20;
+/// 最多保留的记录数，超出时优先淘汰未锁定的最旧记录
+const MAX_ENTRIES: usize = 10_000;
+
+#[derive(Debug)]
+struct Attempts {
+    failures: u32,
+    window_start: Instant,
+    locked_until: Option<Instant>,
+}
+
+impl Attempts {
+    fn is_stale(&self, now: Instant) -> bool {
+        now.duration_since(self.window_start) >= WINDOW
+            && self.locked_until.map_or(true, |until| until <= now)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct LoginThrottle {
+    entries: Mutex<HashMap<String, Attempts>>,
+}
+
+fn user_key(ip: Option<&str>, username: &str) -> String {
+    format!(
+        "user:{}@{}",
+        username.trim().to_lowercase(),
+        ip.unwrap_or("unknown")
+    )
+}
+
+fn keys(ip: Option<&str>, username: &str) -> Vec<(String, u32)> {
+    let mut keys = vec![(user_key(ip, username), MAX_FAILURES_PER_USER)];
+    if let Some(ip) = ip {
+        keys.push((format!("ip:{}", ip), MAX_FAILURES_PER_IP));
+    }
+    keys
+}
+
+/// 腾出一条记录的空间：优先淘汰未锁定的记录，其次按窗口开始时间从旧到新
+fn evict_one(entries: &mut HashMap<String, Attempts>) {
+    let oldest = entries
+        .iter()
+        .min_by_key(|(_, a)| (a.locked_until.is_some(), a.window_start))
+        .map(|(key, _)| key.clone());
+    if let Some(key) = oldest {
+        entries.remove(&key);
+    }
+}
+
+impl LoginThrottle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn entries(&self) -> MutexGuard<'_, HashMap<String, Attempts>> {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// 登录前检查；被锁定时返回剩余锁定时长
+    pub fn check(&self, ip: Option<&str>, username: &str, now: Instant) -> Result<(), Duration> {
+        let entries = self.entries();
+        let remaining = keys(ip, username)
+            .iter()
+            .filter_map(|(key, _)| entries.get(key)?.locked_until)
+            .filter(|until| *until > now)
+            .map(|until| until - now)
+            .max();
+        match remaining {
+            Some(remaining) => Err(remaining),
+            None => Ok(()),
+        }
+    }
+
+    /// 记录一次失败，达到上限时开始锁定
+    pub fn record_failure(&self, ip: Option<&str>, username: &str, now: Instant) {
+        let mut entries = self.entries();
+        entries.retain(|_, a| !a.is_stale(now));
+        for (key, max_failures) in keys(ip, username) {
+            if !entries.contains_key(&key) && entries.len() >= MAX_ENTRIES {
+                evict_one(&mut entries);
+            }
+            let attempts = entries.entry(key).or_insert(Attempts {
+                failures: 0,
+                window_start: now,
+                locked_until: None,
+            });
+            if now.duration_since(attempts.window_start) >= WINDOW {
+                attempts.failures = 0;
+                attempts.window_start = now;
+            }
+            attempts.failures += 1;
+            if attempts.failures >= max_failures {
+                attempts.locked_until = Some(now + LOCKOUT);
+                attempts.failures = 0;
+                attempts.window_start = now;
+            }
+        }
+    }
+
+    /// 登录成功：清除该来源对该用户名的失败记录 (IP 计数保留，避免用自己的账号重置对其他账号的尝试)
+    pub fn record_success(&self, ip: Option<&str>, username: &str) {
+        self.entries().remove(&user_key(ip, username));
+    }
+}
+
+/// 使用全局限速器检查
+pub fn check(ip: Option<&str>, username: &str) -> Result<(), Duration> {
+    THROTTLE.check(ip, username, Instant::now())
+}
+
+pub fn record_failure(ip: Option<&str>, username: &str) {
+    THROTTLE.record_failure(ip, username, Instant::now())
+}
+
+pub fn record_success(ip: Option<&str>, username: &str) {
+    THROTTLE.record_success(ip, username)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_username_lockout_and_expiry() {
+        let throttle = LoginThrottle::new();
+        let now = Instant::now();
+        for _ in 0..MAX_FAILURES_PER_USER - 1 {
+            throttle.record_failure(Some("10.0.0.1"), "Admin", now);
+        }
+        assert!(throttle.check(Some("10.0.0.1"), "admin", now).is_ok());
+
+        throttle.record_failure(Some("10.0.0.1"), "admin ", now);
+        assert_eq!(throttle.check(Some("10.0.0.1"), "admin", now), Err(LOCKOUT));
+        assert!(throttle.check(Some("10.0.0.1"), "other", now).is_ok());
+        // 其他来源 (如账号所有者) 不受影响
+        assert!(throttle.check(Some("10.0.0.2"), "admin", now).is_ok());
+
+        assert!(throttle.check(Some("10.0.0.1"), "admin", now + LOCKOUT).is_ok());
+    }
+
+    #[test]
+    fn test_ip_lockout_across_usernames() {
+        let throttle = LoginThrottle::new();
+        let now = Instant::now();
+        for i in 0..MAX_FAILURES_PER_IP {
+            throttle.record_failure(Some("10.0.0.1"), &format!("user{}", i), now);
+        }
+        assert!(throttle.check(Some("10.0.0.1"), "someone", now).is_err());
+        assert!(throttle.check(Some("10.0.0.2"), "someone", now).is_ok());
+    }
+
+    #[test]
+    fn test_success_and_window_reset_failures() {
+        let throttle = LoginThrottle::new();
+        let now = Instant::now();
+        for _ in 0..MAX_FAILURES_PER_USER - 1 {
+            throttle.record_failure(None, "admin", now);
+        }
+        throttle.record_success(None, "admin");
+        throttle.record_failure(None, "admin", now);
+        assert!(throttle.check(None, "admin", now).is_ok());
+
+        // 窗口过后重新计数
+        let later = now + WINDOW;
+        for _ in 0..MAX_FAILURES_PER_USER - 1 {
+            throttle.record_failure(None, "admin", later);
+        }
+        assert!(throttle.check(None, "admin", later).is_ok());
+    }
+
+    #[test]
+    fn test_entries_are_capped() {
+        let throttle = LoginThrottle::new();
+        let now = Instant::now();
+        for _ in 0..MAX_FAILURES_PER_USER {
+            throttle.record_failure(None, "admin", now);
+        }
+        for i in 0..MAX_ENTRIES {
+            throttle.record_failure(None, &format!("user{}", i), now);
+        }
+        assert_eq!(throttle.entries().len(), MAX_ENTRIES);
+        // 已锁定的记录不会被大量新来源挤掉
+        assert!(throttle.check(None, "admin", now).is_err());
+    }
+}
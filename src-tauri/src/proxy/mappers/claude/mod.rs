@@ -16,6 +16,7 @@ pub use streaming::{PartProcessor, StreamingState};
 pub use thinking_utils::{close_tool_loop_for_thinking, filter_invalid_thinking_blocks_with_family};
 pub use collector::collect_stream_to_json;
 use crate::proxy::common::client_adapter::ClientAdapter; // [NEW]
+use crate::proxy::mappers::heartbeat::{Heartbeat, HeartbeatStyle};
 
 use bytes::Bytes;
 use futures::Stream;
@@ -29,7 +30,7 @@ pub fn create_claude_sse_stream(
     session_id: Option<String>, // [NEW v3.3.17] Session ID for signature caching
     scaling_enabled: bool, // [NEW] Flag for context usage scaling
     context_limit: u32,
-    estimated_prompt_tokens: Option<u32>, // [FIX] Estimated tokens for calibrator learning
+    estimated_prompt_tokens: Option<crate::proxy::mappers::token_counter::TokenEstimate>, // [FIX] Estimated tokens for calibrator learning
     message_count: usize, // [NEW v4.0.0] Message count for rewind detection
     client_adapter: Option<std::sync::Arc<dyn ClientAdapter>>, // [NEW] Adapter reference
 ) -> Pin<Box<dyn Stream<Item = Result<Bytes, String>> + Send>> {
@@ -46,16 +47,21 @@ pub fn create_claude_sse_stream(
         state.estimated_prompt_tokens = estimated_prompt_tokens; // [FIX] Pass estimated tokens
         state.set_client_adapter(client_adapter); // [NEW] Set adapter
         let mut buffer = BytesMut::new();
+        // 上游静默时发送 Anthropic ping 事件保活
+        let mut heartbeat = Heartbeat::new(HeartbeatStyle::Anthropic, trace_id.clone());
 
         loop {
-            // [NEW] 60秒心跳保活: 延长超时时间以增加网络抖动容错
-            let next_chunk = tokio::time::timeout(
-                std::time::Duration::from_secs(60),
-                gemini_stream.next()
-            ).await;
+            let next_chunk = tokio::select! {
+                item = gemini_stream.next() => item,
+                ping = heartbeat.tick() => {
+                    yield Ok(ping);
+                    continue;
+                }
+            };
+            heartbeat.reset();
 
             match next_chunk {
-                Ok(Some(chunk_result)) => {
+                Some(chunk_result) => {
                     match chunk_result {
                         Ok(chunk) => {
                             buffer.extend_from_slice(&chunk);
@@ -81,11 +87,7 @@ pub fn create_claude_sse_stream(
                         }
                     }
                 }
-                Ok(None) => break, // Stream 正常结束
-                Err(_) => {
-                    // 超时，发送心跳包 (SSE Comment 格式)
-                    yield Ok(Bytes::from(": ping\n\n"));
-                }
+                None => break, // Stream 正常结束
             }
         }
         
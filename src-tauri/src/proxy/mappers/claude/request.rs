@@ -1280,8 +1280,14 @@ fn build_contents(
                         // [FIX #593] 工具输出压缩: 处理超大工具输出
                         // 使用智能压缩策略(浏览器快照、大文件提示等)
                         let mut compacted_content = content.clone();
-                        if let Some(blocks) = compacted_content.as_array_mut() {
-                            tool_result_compressor::sanitize_tool_result_blocks(blocks);
+                        match &mut compacted_content {
+                            serde_json::Value::Array(blocks) => {
+                                tool_result_compressor::sanitize_tool_result_blocks(&func_name, blocks);
+                            }
+                            serde_json::Value::String(text) => {
+                                *text = tool_result_compressor::compress_tool_result(&func_name, text);
+                            }
+                            _ => {}
                         }
 
                         // Smart Truncation: strict image removal
@@ -4,6 +4,7 @@
 use super::models::*;
 use super::utils::to_claude_usage;
 use crate::proxy::mappers::estimation_calibrator::get_calibrator;
+use crate::proxy::mappers::token_counter::TokenEstimate;
 // use crate::proxy::mappers::signature_store::store_thought_signature; // Deprecated
 use crate::proxy::SignatureCache;
 use crate::proxy::common::client_adapter::{ClientAdapter, SignatureBufferStrategy}; // [NEW]
@@ -226,7 +227,7 @@ pub struct StreamingState {
     pub mcp_xml_buffer: String,
     pub in_mcp_xml: bool,
     // [FIX] Estimated prompt tokens for calibrator learning
-    pub estimated_prompt_tokens: Option<u32>,
+    pub estimated_prompt_tokens: Option<TokenEstimate>,
     // [FIX #859] Post-thinking interruption tracking
     pub has_thinking: bool,
     pub has_content: bool,
@@ -488,13 +489,14 @@ impl StreamingState {
                 if let (Some(estimated), Some(actual)) =
                     (self.estimated_prompt_tokens, u.prompt_token_count)
                 {
-                    if estimated > 0 && actual > 0 {
-                        get_calibrator().record(estimated, actual);
+                    if estimated.total() > 0 && actual > 0 {
+                        get_calibrator().record(&estimated, actual);
                         tracing::debug!(
-                            "[Calibrator] Recorded: estimated={}, actual={}, ratio={:.2}x",
-                            estimated,
+                            "[Calibrator] Recorded ({}): estimated={}, actual={}, ratio={:.2}x",
+                            estimated.family.as_str(),
+                            estimated.total(),
                             actual,
-                            actual as f64 / estimated as f64
+                            actual as f64 / estimated.total() as f64
                         );
                     }
                 }
@@ -0,0 +1,541 @@
+// 协议无关的渐进式上下文压缩
+// ContextManager 的三层压缩只作用于 Claude 请求结构，OpenAI (Codex、OpenCode) 与 Gemini 原生客户端
+// 因此会直接撞上上下文长度错误。这里把同一套机制搬到映射后的 Gemini `contents` 上：
+// - Layer 1: 裁剪旧的工具调用轮次 (functionCall + functionResponse)，不破坏缓存
+// - Layer 2: 压缩旧的思考内容，保留 thoughtSignature
+// - Layer 3: 调用后台模型生成 XML 摘要并分叉对话
+//
+// 阈值沿用 ExperimentalConfig 中的 L1/L2/L3，且与 Claude 路径一样仅在启用用量缩放时生效。
+
+use super::estimation_calibrator::get_calibrator;
+use super::token_counter::{add_gemini_part, ModelFamily, TokenEstimate};
+use crate::proxy::config::ExperimentalConfig;
+use crate::proxy::upstream::client::UpstreamClient;
+use serde_json::{json, Value};
+use tracing::{debug, info};
+
+// ===== Layer 3: XML Summary Prompt Template =====
+// Borrowed from Practical-Guide-to-Context-Engineering + Claude Code official practice
+// This prompt generates a structured 8-section XML summary for context compression
+pub const CONTEXT_SUMMARY_PROMPT: &str = r#"You are a context compression specialist. Your task is to create a structured XML snapshot of the conversation history.
+
+This snapshot will become the Agent's ONLY memory of the past. All key details, plans, errors, and user instructions MUST be preserved.
+
+First, think through the entire history in a private <scratchpad>. Review the user's overall goal, the agent's actions, tool outputs, file modifications, and any unresolved issues. Identify every piece of information critical for future actions.
+
+After reasoning, generate the final <state_snapshot> XML object. Information must be extremely dense. Omit any irrelevant conversational filler.
+
+The structure MUST be as follows:
+
+<state_snapshot>
+  <overall_goal>
+    <!-- Describe the user's high-level goal in one concise sentence -->
+  </overall_goal>
+  
+  <technical_context>
+    <!-- Tech stack: frameworks, languages, toolchain, dependency versions -->
+  </technical_context>
+  
+  <file_system_state>
+    <!-- List files that were created, read, modified, or deleted. Note their status -->
+  </file_system_state>
+  
+  <code_changes>
+    <!-- Key code snippets (preserve function signatures and important logic) -->
+  </code_changes>
+  
+  <debugging_history>
+    <!-- List all errors encountered, with stack traces, and how they were fixed -->
+  </debugging_history>
+  
+  <current_plan>
+    <!-- Step-by-step plan. Mark completed steps -->
+  </current_plan>
+  
+  <user_preferences>
+    <!-- User's work preferences for this project (test commands, code style, etc.) -->
+  </user_preferences>
+  
+  <key_decisions>
+    <!-- Critical architectural decisions and design choices -->
+  </key_decisions>
+  
+  <latest_thinking_signature>
+    <!-- [CRITICAL] Preserve the last valid thinking signature -->
+    <!-- Format: base64-encoded signature string -->
+    <!-- This MUST be copied exactly as-is, no modifications -->
+  </latest_thinking_signature>
+</state_snapshot>
+
+**IMPORTANT**:
+1. Code snippets must be complete, including function signatures and key logic
+2. Error messages must be preserved verbatim, including line numbers and stacks
+3. File paths must use absolute paths
+4. The thinking signature must be copied exactly, no modifications
+"#;
+/// Layer 1 保留的最近工具调用轮次
+const KEEP_TOOL_ROUNDS: usize = 5;
+/// Layer 2 不压缩最近的 contents 条数 (~2 轮)
+const PROTECTED_CONTENTS: usize = 4;
+/// 有效签名的最小长度 (与 SignatureCache 一致)
+const MIN_SIGNATURE_LEN: usize = 50;
+
+const FORK_SUMMARY_PREFIX: &str =
+    "Context has been compressed. Here is the structured summary of our conversation history:\n\n";
+const FORK_ACKNOWLEDGEMENT: &str =
+    "I have reviewed the compressed context summary. I understand the current state and will continue from here.";
+
+/// 按模型估计上下文窗口 (Flash: ~1M, Pro: ~2M)
+pub fn compression_context_limit(model: &str) -> u32 {
+    if model.contains("flash") {
+        1_000_000
+    } else {
+        2_000_000
+    }
+}
+
+/// Layer 3 生成摘要所需的上游调用信息
+pub struct Summarizer<'a> {
+    pub upstream: &'a UpstreamClient,
+    pub access_token: &'a str,
+    pub project_id: &'a str,
+    pub account_id: &'a str,
+    /// 生成摘要使用的模型 (internal-background-task 的路由结果)
+    pub model: String,
+}
+
+/// 对 v1internal 请求体 (`request.contents`) 执行渐进式压缩，返回生效的最高层级 (0 = 未压缩)。
+/// Layer 3 摘要失败时返回错误，由调用方按各自协议返回给客户端。
+pub async fn compress_gemini_body(
+    body: &mut Value,
+    model: &str,
+    experimental: &ExperimentalConfig,
+    summarizer: &Summarizer<'_>,
+    trace_id: &str,
+) -> Result<u8, String> {
+    if !experimental.enable_usage_scaling {
+        return Ok(0);
+    }
+    let context_limit = compression_context_limit(model);
+    let calibrator = get_calibrator();
+    let family = ModelFamily::from_model(model);
+    let usage_of = |body: &Value| calibrator.calibrate(&estimate_gemini_tokens(body, family));
+
+    let mut estimated_usage = usage_of(body);
+    let mut usage_ratio = estimated_usage as f32 / context_limit as f32;
+    let mut layer = 0u8;
+    let mut compression_applied = false;
+    info!(
+        "[{}] [ContextCompression] Context pressure: {:.1}% (calibrated: {} / {})",
+        trace_id, usage_ratio * 100.0, estimated_usage, context_limit
+    );
+
+    // ===== Layer 1: 工具调用轮次裁剪 =====
+    if usage_ratio > experimental.context_compression_threshold_l1 {
+        if let Some(contents) = contents_mut(body) {
+            if trim_tool_rounds(contents, KEEP_TOOL_ROUNDS) {
+                layer = 1;
+                compression_applied = true;
+                let new_usage = usage_of(body);
+                let new_ratio = new_usage as f32 / context_limit as f32;
+                info!(
+                    "[{}] [Layer-1] Tool round trimming: {:.1}% → {:.1}%",
+                    trace_id, usage_ratio * 100.0, new_ratio * 100.0
+                );
+                if new_ratio >= 0.7 {
+                    // 压力仍然较高，允许进入 Layer 2
+                    compression_applied = false;
+                }
+                estimated_usage = new_usage;
+                usage_ratio = new_ratio;
+            }
+        }
+    }
+
+    // ===== Layer 2: 思考内容压缩 (保留签名) =====
+    if usage_ratio > experimental.context_compression_threshold_l2 && !compression_applied {
+        if let Some(contents) = contents_mut(body) {
+            if compress_thoughts(contents, PROTECTED_CONTENTS) {
+                layer = 2;
+                compression_applied = true;
+                let new_usage = usage_of(body);
+                let new_ratio = new_usage as f32 / context_limit as f32;
+                info!(
+                    "[{}] [Layer-2] Thought compression: {:.1}% → {:.1}%",
+                    trace_id, usage_ratio * 100.0, new_ratio * 100.0
+                );
+                estimated_usage = new_usage;
+                usage_ratio = new_ratio;
+            }
+        }
+    }
+
+    // ===== Layer 3: 摘要 + 分叉对话 =====
+    if usage_ratio > experimental.context_compression_threshold_l3 && !compression_applied {
+        info!(
+            "[{}] [Layer-3] Context pressure ({:.1}%) exceeded threshold ({:.1}%), attempting Fork+Summary",
+            trace_id,
+            usage_ratio * 100.0,
+            experimental.context_compression_threshold_l3 * 100.0
+        );
+        let Some(contents) = contents_mut(body) else {
+            return Ok(layer);
+        };
+        let summary = request_summary(contents, summarizer, trace_id).await?;
+        let forked = fork_contents(contents, &summary);
+        info!(
+            "[{}] [Layer-3] Fork successful: {} → {} contents",
+            trace_id,
+            contents.len(),
+            forked.len()
+        );
+        *contents = forked;
+        layer = 3;
+        debug!(
+            "[{}] [Layer-3] Compression result: {} → {} tokens",
+            trace_id,
+            estimated_usage,
+            usage_of(body)
+        );
+    }
+
+    Ok(layer)
+}
+
+fn contents_mut(body: &mut Value) -> Option<&mut Vec<Value>> {
+    body.get_mut("request")
+        .and_then(|r| r.get_mut("contents"))
+        .and_then(|c| c.as_array_mut())
+}
+
+fn role(content: &Value) -> &str {
+    content.get("role").and_then(|r| r.as_str()).unwrap_or("user")
+}
+
+fn parts(content: &Value) -> &[Value] {
+    content
+        .get("parts")
+        .and_then(|p| p.as_array())
+        .map(|p| p.as_slice())
+        .unwrap_or(&[])
+}
+
+fn has_part(content: &Value, key: &str) -> bool {
+    parts(content).iter().any(|p| p.get(key).is_some())
+}
+
+/// 估算 v1internal 请求体的 token 用量 (系统指令、contents 与工具声明)
+/// 文本按本地 BPE 分词计数，媒体按 `family` 对应上游的计费规则计数
+pub fn estimate_gemini_tokens(body: &Value, family: ModelFamily) -> TokenEstimate {
+    let request = body.get("request").unwrap_or(body);
+    let mut estimate = TokenEstimate::new(family);
+
+    if let Some(system) = request.get("systemInstruction") {
+        for part in parts(system) {
+            add_gemini_part(&mut estimate, part);
+        }
+    }
+    if let Some(contents) = request.get("contents").and_then(|c| c.as_array()) {
+        for content in contents {
+            // 消息开销
+            estimate.add_overhead(4);
+            for part in parts(content) {
+                add_gemini_part(&mut estimate, part);
+            }
+        }
+    }
+    if let Some(tools) = request.get("tools") {
+        estimate.add_text(&tools.to_string());
+    }
+    estimate
+}
+
+/// Layer 1: 移除较早的工具调用轮次，只保留最近 keep_last_n_rounds 轮
+///
+/// 一轮由带 functionCall 的 model 消息及其后带 functionResponse 的 user 消息组成，
+/// 普通 user 消息结束当前轮次。只删除整条消息，不修改内容，因此不会破坏缓存。
+pub fn trim_tool_rounds(contents: &mut Vec<Value>, keep_last_n_rounds: usize) -> bool {
+    let mut rounds: Vec<Vec<usize>> = Vec::new();
+    let mut current: Option<Vec<usize>> = None;
+
+    for (i, content) in contents.iter().enumerate() {
+        if role(content) == "model" {
+            if has_part(content, "functionCall") {
+                rounds.extend(current.take());
+                current = Some(vec![i]);
+            }
+        } else if has_part(content, "functionResponse") {
+            if let Some(round) = current.as_mut() {
+                round.push(i);
+            }
+        } else {
+            rounds.extend(current.take());
+        }
+    }
+    rounds.extend(current);
+
+    if rounds.len() <= keep_last_n_rounds {
+        return false;
+    }
+    let to_remove: std::collections::HashSet<usize> = rounds[..rounds.len() - keep_last_n_rounds]
+        .iter()
+        .flatten()
+        .copied()
+        .collect();
+
+    let before = contents.len();
+    let mut index = 0;
+    contents.retain(|_| {
+        let keep = !to_remove.contains(&index);
+        index += 1;
+        keep
+    });
+    info!(
+        "[ContextCompression] [Layer-1] Trimmed {} tool contents, kept last {} rounds",
+        before - contents.len(),
+        keep_last_n_rounds
+    );
+    true
+}
+
+/// Layer 2: 把受保护范围之外、带签名的思考内容压缩为 "..."，签名原样保留
+pub fn compress_thoughts(contents: &mut [Value], protected_last_n: usize) -> bool {
+    let start_protection_idx = contents.len().saturating_sub(protected_last_n);
+    let mut compressed_count = 0;
+
+    for content in contents.iter_mut().take(start_protection_idx) {
+        if role(content) != "model" {
+            continue;
+        }
+        let Some(parts) = content.get_mut("parts").and_then(|p| p.as_array_mut()) else {
+            continue;
+        };
+        for part in parts.iter_mut() {
+            let is_thought = part.get("thought").and_then(|t| t.as_bool()).unwrap_or(false);
+            let signed = part.get("thoughtSignature").is_some();
+            let long = part
+                .get("text")
+                .and_then(|t| t.as_str())
+                .is_some_and(|t| t.len() > 10);
+            if is_thought && signed && long {
+                part["text"] = json!("...");
+                compressed_count += 1;
+            }
+        }
+    }
+
+    if compressed_count > 0 {
+        info!(
+            "[ContextCompression] [Layer-2] Compressed {} thought parts (signatures preserved)",
+            compressed_count
+        );
+    }
+    compressed_count > 0
+}
+
+/// 历史中最后一个有效的 thoughtSignature
+fn last_thought_signature(contents: &[Value]) -> Option<String> {
+    contents
+        .iter()
+        .rev()
+        .filter(|c| role(c) == "model")
+        .flat_map(|c| parts(c).iter().rev())
+        .filter_map(|p| p.get("thoughtSignature").and_then(|s| s.as_str()))
+        .find(|s| s.len() >= MIN_SIGNATURE_LEN)
+        .map(|s| s.to_string())
+}
+
+/// Layer 3: 调用后台模型生成 XML 摘要
+async fn request_summary(
+    contents: &[Value],
+    summarizer: &Summarizer<'_>,
+    trace_id: &str,
+) -> Result<String, String> {
+    let signature_instruction = match last_thought_signature(contents) {
+        Some(sig) => format!("\n\n**CRITICAL**: The last thinking signature is:\n```\n{}\n```\nYou MUST include this EXACTLY in the <latest_thinking_signature> section.", sig),
+        None => "\n\n**Note**: No thinking signature found in history. Leave <latest_thinking_signature> empty.".to_string(),
+    };
+    let mut summary_contents = contents.to_vec();
+    summary_contents.push(json!({
+        "role": "user",
+        "parts": [{ "text": format!("{}{}", CONTEXT_SUMMARY_PROMPT, signature_instruction) }]
+    }));
+
+    let body = json!({
+        "project": summarizer.project_id,
+        "requestId": format!("agent-{}", uuid::Uuid::new_v4()),
+        "model": summarizer.model,
+        "userAgent": "antigravity",
+        "requestType": "agent",
+        "request": {
+            "contents": summary_contents,
+            "generationConfig": { "maxOutputTokens": 8000, "temperature": 0.3 }
+        }
+    });
+
+    debug!("[{}] [Layer-3] Calling {} for summary generation", trace_id, summarizer.model);
+    let response = summarizer
+        .upstream
+        .call_v1_internal(
+            "generateContent",
+            summarizer.access_token,
+            body,
+            None,
+            Some(summarizer.account_id),
+        )
+        .await?
+        .response;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(format!(
+            "API returned {}: {}",
+            status,
+            response.text().await.unwrap_or_default()
+        ));
+    }
+    let json: Value = response
+        .json()
+        .await
+        .map_err(|e| format!("Failed to parse response: {}", e))?;
+    let data = json.get("response").unwrap_or(&json);
+    let summary: String = data
+        .get("candidates")
+        .and_then(|c| c.get(0))
+        .map(|c| parts(c.get("content").unwrap_or(&Value::Null)))
+        .unwrap_or(&[])
+        .iter()
+        .filter(|p| !p.get("thought").and_then(|t| t.as_bool()).unwrap_or(false))
+        .filter_map(|p| p.get("text").and_then(|t| t.as_str()))
+        .collect();
+    if summary.trim().is_empty() {
+        return Err("Failed to extract text from response".to_string());
+    }
+    info!("[{}] [Layer-3] Generated XML summary (len: {} chars)", trace_id, summary.len());
+    Ok(summary)
+}
+
+/// 以摘要开头重建对话，并保留最后一条用户输入
+/// (若最后一条是工具结果，连同对应的 functionCall 一起保留，保证调用与结果成对)
+fn fork_contents(contents: &[Value], summary: &str) -> Vec<Value> {
+    let mut ack_parts = vec![json!({ "text": FORK_ACKNOWLEDGEMENT })];
+    let mut tail = Vec::new();
+
+    if let Some(last) = contents.last().filter(|c| role(c) != "model") {
+        if !has_part(last, "functionResponse") {
+            tail.push(last.clone());
+        } else if let Some(call) = contents
+            .len()
+            .checked_sub(2)
+            .map(|i| &contents[i])
+            .filter(|c| role(c) == "model" && has_part(c, "functionCall"))
+        {
+            ack_parts.extend(
+                parts(call)
+                    .iter()
+                    .filter(|p| p.get("functionCall").is_some())
+                    .cloned(),
+            );
+            tail.push(last.clone());
+        }
+    }
+
+    let mut forked = vec![
+        json!({ "role": "user", "parts": [{ "text": format!("{}{}", FORK_SUMMARY_PREFIX, summary) }] }),
+        json!({ "role": "model", "parts": ack_parts }),
+    ];
+    forked.extend(tail);
+    forked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(role: &str, text: &str) -> Value {
+        json!({ "role": role, "parts": [{ "text": text }] })
+    }
+
+    fn call(name: &str) -> Value {
+        json!({ "role": "model", "parts": [{ "functionCall": { "name": name, "args": {} } }] })
+    }
+
+    fn result(name: &str) -> Value {
+        json!({ "role": "user", "parts": [{ "functionResponse": { "name": name, "response": { "output": "ok" } } }] })
+    }
+
+    #[test]
+    fn test_trim_tool_rounds_keeps_recent() {
+        let mut contents = vec![text("user", "start")];
+        for i in 0..4 {
+            contents.push(call(&format!("t{}", i)));
+            contents.push(result(&format!("t{}", i)));
+        }
+        contents.push(text("model", "done"));
+
+        assert!(!trim_tool_rounds(&mut contents.clone(), 4));
+        assert!(trim_tool_rounds(&mut contents, 2));
+        assert_eq!(contents.len(), 6);
+        assert_eq!(contents[1]["parts"][0]["functionCall"]["name"], "t2");
+        assert_eq!(contents[3]["parts"][0]["functionCall"]["name"], "t3");
+    }
+
+    #[test]
+    fn test_compress_thoughts_preserves_signatures() {
+        let thought = |sig: Option<&str>| {
+            let mut part = json!({ "text": "long internal reasoning", "thought": true });
+            if let Some(sig) = sig {
+                part["thoughtSignature"] = json!(sig);
+            }
+            json!({ "role": "model", "parts": [part, { "text": "answer" }] })
+        };
+        let mut contents = vec![
+            text("user", "q1"),
+            thought(Some("sig-1")),
+            text("user", "q2"),
+            thought(None),
+            text("user", "q3"),
+            thought(Some("sig-3")),
+        ];
+
+        assert!(compress_thoughts(&mut contents, 2));
+        assert_eq!(contents[1]["parts"][0]["text"], "...");
+        assert_eq!(contents[1]["parts"][0]["thoughtSignature"], "sig-1");
+        assert_eq!(contents[1]["parts"][1]["text"], "answer");
+        // 无签名的思考与受保护范围内的思考保持不变
+        assert_eq!(contents[3]["parts"][0]["text"], "long internal reasoning");
+        assert_eq!(contents[5]["parts"][0]["text"], "long internal reasoning");
+    }
+
+    #[test]
+    fn test_fork_keeps_pending_tool_result_paired() {
+        let contents = vec![text("user", "q"), call("ls"), result("ls")];
+        let forked = fork_contents(&contents, "<state_snapshot/>");
+        assert_eq!(forked.len(), 3);
+        assert!(forked[0]["parts"][0]["text"].as_str().unwrap().ends_with("<state_snapshot/>"));
+        assert_eq!(forked[1]["role"], "model");
+        assert_eq!(forked[1]["parts"][1]["functionCall"]["name"], "ls");
+        assert_eq!(forked[2], result("ls"));
+
+        let forked = fork_contents(&[text("user", "old"), text("model", "a"), text("user", "next")], "s");
+        assert_eq!(forked.len(), 3);
+        assert_eq!(forked[2], text("user", "next"));
+    }
+
+    #[test]
+    fn test_estimate_counts_tool_payloads() {
+        let body = json!({ "request": {
+            "systemInstruction": { "parts": [{ "text": "You are helpful." }] },
+            "contents": [text("user", "hello"), call("read_file"), result("read_file")]
+        } });
+        let estimated = estimate_gemini_tokens(&body, ModelFamily::Gemini);
+        let text_only = estimate_gemini_tokens(
+            &json!({ "request": {
+                "systemInstruction": { "parts": [{ "text": "You are helpful." }] },
+                "contents": [text("user", "hello")]
+            } }),
+            ModelFamily::Gemini,
+        );
+        assert!(estimated.total() > text_only.total() + 8);
+        assert_eq!(estimated.media, 0);
+    }
+}
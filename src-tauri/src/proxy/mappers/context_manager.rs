@@ -4,38 +4,9 @@
 //! to prevent "Prompt is too long" errors and avoid invalid signatures.
 
 use super::claude::models::{ClaudeRequest, ContentBlock, Message, MessageContent, SystemPrompt};
+use super::token_counter::{ModelFamily, TokenEstimate};
 use tracing::{debug, info};
 
-/// Helper to estimate tokens from text with multi-language awareness
-///
-/// Improved estimation algorithm:
-/// - ASCII/English: ~4 characters per token
-/// - Unicode/CJK: ~1.5 characters per token (Chinese, Japanese, Korean are tokenized differently)
-/// - Adds 15% safety margin to prevent underestimation
-fn estimate_tokens_from_str(s: &str) -> u32 {
-    if s.is_empty() {
-        return 0;
-    }
-
-    let mut ascii_chars = 0u32;
-    let mut unicode_chars = 0u32;
-
-    for c in s.chars() {
-        if c.is_ascii() {
-            ascii_chars += 1;
-        } else {
-            unicode_chars += 1;
-        }
-    }
-
-    // ASCII: ~4 chars/token, Unicode/CJK: ~1.5 chars/token
-    let ascii_tokens = (ascii_chars as f32 / 4.0).ceil() as u32;
-    let unicode_tokens = (unicode_chars as f32 / 1.5).ceil() as u32;
-
-    // Add 15% safety margin to account for tokenizer variations
-    ((ascii_tokens + unicode_tokens) as f32 * 1.15).ceil() as u32
-}
-
 /// Strategy for context purification
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum PurificationStrategy {
@@ -104,18 +75,18 @@ impl ContextManager {
 impl ContextManager {
     /// Estimate token usage for a Claude Request
     ///
-    /// This is a lightweight estimation, not a precise count.
-    /// It iterates through all messages and blocks to sum up estimated tokens.
-    pub fn estimate_token_usage(request: &ClaudeRequest) -> u32 {
-        let mut total = 0;
+    /// Text is counted with the local BPE tokenizer, media blocks with the billing rules of
+    /// the family `model` (the upstream model) belongs to. Calibrate the result before use.
+    pub fn estimate_token_usage(request: &ClaudeRequest, model: &str) -> TokenEstimate {
+        let mut estimate = TokenEstimate::new(ModelFamily::from_model(model));
 
         // System prompt
         if let Some(sys) = &request.system {
             match sys {
-                SystemPrompt::String(s) => total += estimate_tokens_from_str(s),
+                SystemPrompt::String(s) => estimate.add_text(s),
                 SystemPrompt::Array(blocks) => {
                     for block in blocks {
-                        total += estimate_tokens_from_str(&block.text);
+                        estimate.add_text(&block.text);
                     }
                 }
             }
@@ -124,52 +95,34 @@ impl ContextManager {
         // Messages
         for msg in &request.messages {
             // Message overhead
-            total += 4;
+            estimate.add_overhead(4);
 
             match &msg.content {
-                MessageContent::String(s) => {
-                    total += estimate_tokens_from_str(s);
-                }
+                MessageContent::String(s) => estimate.add_text(s),
                 MessageContent::Array(blocks) => {
                     for block in blocks {
                         match block {
-                            ContentBlock::Text { text } => {
-                                total += estimate_tokens_from_str(text);
-                            }
+                            ContentBlock::Text { text } => estimate.add_text(text),
                             ContentBlock::Thinking { thinking, .. } => {
-                                total += estimate_tokens_from_str(thinking);
+                                estimate.add_text(thinking);
                                 // Signature overhead
-                                total += 100;
+                                estimate.add_overhead(100);
+                            }
+                            ContentBlock::RedactedThinking { data } => estimate.add_text(data),
+                            ContentBlock::Image { source, .. } => {
+                                estimate.add_inline_data(&source.media_type, &source.data);
                             }
-                            ContentBlock::RedactedThinking { data } => {
-                                total += estimate_tokens_from_str(data);
+                            ContentBlock::Document { source, .. } => {
+                                estimate.add_inline_data(&source.media_type, &source.data);
                             }
                             ContentBlock::ToolUse { name, input, .. } => {
-                                total += 20; // Function call overhead
-                                total += estimate_tokens_from_str(name);
-                                if let Ok(json_str) = serde_json::to_string(input) {
-                                    total += estimate_tokens_from_str(&json_str);
-                                }
+                                estimate.add_overhead(20); // Function call overhead
+                                estimate.add_text(name);
+                                estimate.add_text(&input.to_string());
                             }
                             ContentBlock::ToolResult { content, .. } => {
-                                total += 10; // Result overhead
-                                             // content is serde_json::Value
-                                if let Some(s) = content.as_str() {
-                                    total += estimate_tokens_from_str(s);
-                                } else if let Some(arr) = content.as_array() {
-                                    for item in arr {
-                                        if let Some(text) =
-                                            item.get("text").and_then(|t| t.as_str())
-                                        {
-                                            total += estimate_tokens_from_str(text);
-                                        }
-                                    }
-                                } else {
-                                    // Fallback for objects or other types
-                                    if let Ok(s) = serde_json::to_string(content) {
-                                        total += estimate_tokens_from_str(&s);
-                                    }
-                                }
+                                estimate.add_overhead(10); // Result overhead
+                                Self::add_tool_result(&mut estimate, content);
                             }
                             _ => {}
                         }
@@ -178,11 +131,11 @@ impl ContextManager {
             }
         }
 
-        // Tools definition overhead (rough estimate)
+        // Tools definition overhead
         if let Some(tools) = &request.tools {
             for tool in tools {
                 if let Ok(json_str) = serde_json::to_string(tool) {
-                    total += estimate_tokens_from_str(&json_str);
+                    estimate.add_text(&json_str);
                 }
             }
         }
@@ -191,11 +144,34 @@ impl ContextManager {
         if let Some(thinking) = &request.thinking {
             if let Some(budget) = thinking.budget_tokens {
                 // Reserve budget in estimation
-                total += budget;
+                estimate.add_overhead(budget);
             }
         }
 
-        total
+        estimate
+    }
+
+    /// Tool result content is a string or an array of text / image blocks
+    fn add_tool_result(estimate: &mut TokenEstimate, content: &serde_json::Value) {
+        if let Some(s) = content.as_str() {
+            estimate.add_text(s);
+        } else if let Some(arr) = content.as_array() {
+            for item in arr {
+                if let Some(text) = item.get("text").and_then(|t| t.as_str()) {
+                    estimate.add_text(text);
+                } else if let Some(source) = item.get("source") {
+                    let media_type = source
+                        .get("media_type")
+                        .and_then(|v| v.as_str())
+                        .unwrap_or("");
+                    let data = source.get("data").and_then(|v| v.as_str()).unwrap_or("");
+                    estimate.add_inline_data(media_type, data);
+                }
+            }
+        } else {
+            // Fallback for objects or other types
+            estimate.add_text(&content.to_string());
+        }
     }
 
     // ===== [Layer 2] Thinking Content Compression + Signature Preservation =====
@@ -470,9 +446,11 @@ mod tests {
             content: MessageContent::String("Hello World".into()),
         }];
 
-        let tokens = ContextManager::estimate_token_usage(&req);
-        assert!(tokens > 0);
-        assert!(tokens < 50);
+        let tokens = ContextManager::estimate_token_usage(&req, "gemini-2.5-flash");
+        assert_eq!(tokens.family, ModelFamily::Gemini);
+        assert_eq!(tokens.media, 0);
+        // 4 message overhead + "Hello World" (2 BPE tokens)
+        assert_eq!(tokens.total(), 6);
     }
 
     #[test]
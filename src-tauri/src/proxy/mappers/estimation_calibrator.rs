@@ -2,10 +2,29 @@
 //!
 //! Learns from historical request/response pairs to improve token estimation accuracy.
 //! Uses actual token counts from Google API responses to calibrate future estimates.
+//!
+//! Each model family keeps its own calibrator, because the local BPE tokenizer drifts
+//! differently from the Claude and Gemini tokenizers. Only the text portion of an
+//! estimate is calibrated; media tokens follow upstream rules and are passed through.
+//! Calibration state is persisted to `token_calibration.json` in the data directory.
+//! Writes are debounced onto a background task so request threads never touch the disk.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+use tracing::{info, warn};
+
+use super::token_counter::{ModelFamily, TokenEstimate};
 
-use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::RwLock;
-use tracing::info;
+/// Clamp range for the calibration factor
+const MIN_FACTOR: f32 = 0.5;
+const MAX_FACTOR: f32 = 3.0;
+
+/// Delay between a factor update and writing it to disk; updates in between are coalesced
+const SAVE_DEBOUNCE: Duration = Duration::from_secs(30);
 
 /// Estimation Calibrator - learns estimation error from historical requests
 ///
@@ -22,25 +41,33 @@ pub struct EstimationCalibrator {
     calibration_factor: RwLock<f32>,
 }
 
+/// Persisted calibrator state
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CalibrationState {
+    pub total_estimated: u64,
+    pub total_actual: u64,
+    pub sample_count: u64,
+    pub factor: f32,
+}
+
 impl EstimationCalibrator {
-    /// Create a new calibrator with default settings
-    pub const fn new() -> Self {
+    /// Create a new calibrator with the given initial factor
+    pub const fn new(initial_factor: f32) -> Self {
         Self {
             total_estimated: AtomicU64::new(0),
             total_actual: AtomicU64::new(0),
             sample_count: AtomicU64::new(0),
-            // Initial assumption: estimates are 2.0x lower than actual
-            // This is conservative and will be adjusted based on real data
-            calibration_factor: RwLock::new(2.0),
+            calibration_factor: RwLock::new(initial_factor),
         }
     }
 
     /// Record a request's estimated vs actual token counts
     ///
     /// Call this after receiving a response from Google API with actual token usage.
-    pub fn record(&self, estimated: u32, actual: u32) {
+    /// Returns true when the calibration factor was updated.
+    pub fn record(&self, estimated: u32, actual: u32) -> bool {
         if estimated == 0 || actual == 0 {
-            return;
+            return false;
         }
 
         self.total_estimated
@@ -52,7 +79,9 @@ impl EstimationCalibrator {
         // Update calibration factor every 5 requests
         if count % 5 == 0 {
             self.update_calibration();
+            return true;
         }
+        false
     }
 
     /// Update the calibration factor based on accumulated data
@@ -62,10 +91,9 @@ impl EstimationCalibrator {
 
         if estimated > 0.0 {
             let new_factor = (actual / estimated) as f32;
-            // Clamp to reasonable range [0.8, 4.0]
-            // - Below 0.8 means we're overestimating (rare)
-            // - Above 4.0 means severe underestimation
-            let clamped = new_factor.clamp(0.8, 4.0);
+            // Clamp to a reasonable range; the tokenizer is never off by more than this,
+            // larger ratios come from prompts injected upstream
+            let clamped = new_factor.clamp(MIN_FACTOR, MAX_FACTOR);
 
             if let Ok(mut factor) = self.calibration_factor.write() {
                 // Exponential moving average: 60% old + 40% new
@@ -88,31 +116,206 @@ impl EstimationCalibrator {
     ///
     /// Multiplies the raw estimate by the current calibration factor.
     pub fn calibrate(&self, estimated: u32) -> u32 {
-        let factor = self.calibration_factor.read().map(|f| *f).unwrap_or(2.0);
-
-        (estimated as f32 * factor).ceil() as u32
+        (estimated as f32 * self.get_factor()).ceil() as u32
     }
 
     /// Get the current calibration factor
     pub fn get_factor(&self) -> f32 {
-        self.calibration_factor.read().map(|f| *f).unwrap_or(2.0)
+        self.calibration_factor.read().map(|f| *f).unwrap_or(1.0)
+    }
+
+    pub fn snapshot(&self) -> CalibrationState {
+        CalibrationState {
+            total_estimated: self.total_estimated.load(Ordering::Relaxed),
+            total_actual: self.total_actual.load(Ordering::Relaxed),
+            sample_count: self.sample_count.load(Ordering::Relaxed),
+            factor: self.get_factor(),
+        }
+    }
+
+    pub fn restore(&self, state: &CalibrationState) {
+        self.total_estimated
+            .store(state.total_estimated, Ordering::Relaxed);
+        self.total_actual
+            .store(state.total_actual, Ordering::Relaxed);
+        self.sample_count
+            .store(state.sample_count, Ordering::Relaxed);
+        if let Ok(mut factor) = self.calibration_factor.write() {
+            *factor = state.factor.clamp(MIN_FACTOR, MAX_FACTOR);
+        }
     }
 }
 
 impl Default for EstimationCalibrator {
     fn default() -> Self {
-        Self::new()
+        Self::new(1.0)
+    }
+}
+
+/// Debounced writer for the calibration file
+struct Persister {
+    path: PathBuf,
+    /// Latest serialized state not yet written
+    pending: Mutex<Option<String>>,
+    scheduled: AtomicBool,
+}
+
+impl Persister {
+    /// Queue `content` for writing; the latest content wins
+    fn schedule(self: &Arc<Self>, content: String) {
+        *self.pending.lock().unwrap_or_else(|e| e.into_inner()) = Some(content);
+        if self.scheduled.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        match tokio::runtime::Handle::try_current() {
+            Ok(handle) => {
+                let this = self.clone();
+                handle.spawn(async move {
+                    tokio::time::sleep(SAVE_DEBOUNCE).await;
+                    this.scheduled.store(false, Ordering::Release);
+                    let _ = tokio::task::spawn_blocking(move || this.flush()).await;
+                });
+            }
+            // Outside a runtime (CLI tools, tests) there is no request thread to protect
+            Err(_) => {
+                self.scheduled.store(false, Ordering::Release);
+                self.flush();
+            }
+        }
+    }
+
+    /// Write pending state now, if any
+    fn flush(&self) {
+        let Some(content) = self.pending.lock().unwrap_or_else(|e| e.into_inner()).take() else {
+            return;
+        };
+        if let Err(e) = std::fs::write(&self.path, content) {
+            warn!("[Calibrator] Failed to save calibration state: {}", e);
+        }
+    }
+}
+
+/// Per-family calibrators with optional persistence
+pub struct FamilyCalibrators {
+    claude: EstimationCalibrator,
+    gemini: EstimationCalibrator,
+    other: EstimationCalibrator,
+    persister: Option<Arc<Persister>>,
+}
+
+impl FamilyCalibrators {
+    /// Create calibrators with default initial factors.
+    /// The Claude tokenizer splits text finer than o200k; Gemini's is close to it.
+    pub fn new(path: Option<PathBuf>) -> Self {
+        Self {
+            claude: EstimationCalibrator::new(1.15),
+            gemini: EstimationCalibrator::new(1.0),
+            other: EstimationCalibrator::new(1.0),
+            persister: path.map(|path| {
+                Arc::new(Persister {
+                    path,
+                    pending: Mutex::new(None),
+                    scheduled: AtomicBool::new(false),
+                })
+            }),
+        }
+    }
+
+    /// Create calibrators and restore state from `path` if it exists
+    pub fn load(path: PathBuf) -> Self {
+        let calibrators = Self::new(Some(path.clone()));
+        match std::fs::read_to_string(&path) {
+            Ok(content) => {
+                match serde_json::from_str::<HashMap<String, CalibrationState>>(&content) {
+                    Ok(states) => {
+                        for family in ModelFamily::ALL {
+                            if let Some(state) = states.get(family.as_str()) {
+                                calibrators.get(family).restore(state);
+                            }
+                        }
+                        info!("[Calibrator] Restored calibration state from {:?}", path);
+                    }
+                    Err(e) => warn!("[Calibrator] Ignoring invalid calibration file: {}", e),
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => warn!("[Calibrator] Failed to read calibration file: {}", e),
+        }
+        calibrators
+    }
+
+    pub fn get(&self, family: ModelFamily) -> &EstimationCalibrator {
+        match family {
+            ModelFamily::Claude => &self.claude,
+            ModelFamily::Gemini => &self.gemini,
+            ModelFamily::Other => &self.other,
+        }
+    }
+
+    /// Calibrated token count: calibrated text plus rule-based media
+    pub fn calibrate(&self, estimate: &TokenEstimate) -> u32 {
+        self.get(estimate.family).calibrate(estimate.text) + estimate.media
+    }
+
+    pub fn get_factor(&self, family: ModelFamily) -> f32 {
+        self.get(family).get_factor()
+    }
+
+    /// Record an estimate against the actual prompt token count reported upstream
+    pub fn record(&self, estimate: &TokenEstimate, actual: u32) {
+        let actual_text = actual.saturating_sub(estimate.media);
+        if self.get(estimate.family).record(estimate.text, actual_text) {
+            self.save();
+        }
+    }
+
+    /// Queue the current state for a debounced write
+    fn save(&self) {
+        let Some(persister) = &self.persister else {
+            return;
+        };
+        let states: HashMap<&str, CalibrationState> = ModelFamily::ALL
+            .iter()
+            .map(|family| (family.as_str(), self.get(*family).snapshot()))
+            .collect();
+        match serde_json::to_string_pretty(&states) {
+            Ok(content) => persister.schedule(content),
+            Err(e) => warn!("[Calibrator] Failed to serialize calibration state: {}", e),
+        }
+    }
+
+    /// Write any pending state immediately (on shutdown)
+    pub fn flush(&self) {
+        if let Some(persister) = &self.persister {
+            persister.flush();
+        }
     }
 }
 
 // Global singleton instance
 use std::sync::OnceLock;
 
-static CALIBRATOR: OnceLock<EstimationCalibrator> = OnceLock::new();
+static CALIBRATOR: OnceLock<FamilyCalibrators> = OnceLock::new();
 
-/// Get the global calibrator instance
-pub fn get_calibrator() -> &'static EstimationCalibrator {
-    CALIBRATOR.get_or_init(EstimationCalibrator::new)
+/// Get the global calibrator instance (restored from the data directory on first use)
+pub fn get_calibrator() -> &'static FamilyCalibrators {
+    CALIBRATOR.get_or_init(|| match crate::modules::account::get_data_dir() {
+        Ok(dir) => FamilyCalibrators::load(dir.join("token_calibration.json")),
+        Err(e) => {
+            warn!(
+                "[Calibrator] Calibration state will not be persisted: {}",
+                e
+            );
+            FamilyCalibrators::new(None)
+        }
+    })
+}
+
+/// Write pending calibration state if the calibrator was ever used
+pub fn flush_calibrator() {
+    if let Some(calibrators) = CALIBRATOR.get() {
+        calibrators.flush();
+    }
 }
 
 #[cfg(test)]
@@ -121,34 +324,34 @@ mod tests {
 
     #[test]
     fn test_calibrator_basic() {
-        let calibrator = EstimationCalibrator::new();
+        let calibrator = EstimationCalibrator::new(1.0);
 
-        // Initial factor should be 2.0
-        assert!((calibrator.get_factor() - 2.0).abs() < 0.01);
+        // Initial factor should be 1.0
+        assert!((calibrator.get_factor() - 1.0).abs() < 0.01);
 
-        // Record some samples where actual is 3x estimated
+        // Record some samples where actual is 2x estimated
         for _ in 0..10 {
-            calibrator.record(100, 300);
+            calibrator.record(100, 200);
         }
 
-        // Factor should have moved towards 3.0
+        // Factor should have moved towards 2.0
         let factor = calibrator.get_factor();
-        assert!(factor > 2.0);
-        assert!(factor < 3.5);
+        assert!(factor > 1.0);
+        assert!(factor < 2.5);
     }
 
     #[test]
     fn test_calibrate() {
-        let calibrator = EstimationCalibrator::new();
+        let calibrator = EstimationCalibrator::new(2.0);
 
-        // With default factor of 2.0, 100 should become 200
+        // With a factor of 2.0, 100 should become 200
         let calibrated = calibrator.calibrate(100);
         assert_eq!(calibrated, 200);
     }
 
     #[test]
     fn test_zero_handling() {
-        let calibrator = EstimationCalibrator::new();
+        let calibrator = EstimationCalibrator::default();
 
         // Recording zeros should not affect anything
         calibrator.record(0, 100);
@@ -156,4 +359,65 @@ mod tests {
 
         assert_eq!(calibrator.sample_count.load(Ordering::Relaxed), 0);
     }
+
+    #[test]
+    fn test_families_are_independent_and_media_is_not_scaled() {
+        let calibrators = FamilyCalibrators::new(None);
+        let mut estimate = TokenEstimate::new(ModelFamily::Gemini);
+        estimate.text = 100;
+        estimate.media = 258;
+
+        // Upstream reports twice the text tokens; media is exact
+        for _ in 0..5 {
+            calibrators.record(&estimate, 200 + 258);
+        }
+        let gemini = calibrators.get_factor(ModelFamily::Gemini);
+        assert!((gemini - 1.4).abs() < 0.01);
+        assert!((calibrators.get_factor(ModelFamily::Claude) - 1.15).abs() < 0.01);
+        let calibrated = calibrators.calibrate(&estimate);
+        assert!((140 + 258..=141 + 258).contains(&calibrated));
+    }
+
+    #[test]
+    fn test_state_persists_across_restarts() {
+        let path = std::env::temp_dir().join(format!(
+            "token_calibration_test_{}.json",
+            uuid::Uuid::new_v4().simple()
+        ));
+        let calibrators = FamilyCalibrators::new(Some(path.clone()));
+        let mut estimate = TokenEstimate::new(ModelFamily::Claude);
+        estimate.text = 100;
+        for _ in 0..5 {
+            calibrators.record(&estimate, 150);
+        }
+
+        let restored = FamilyCalibrators::load(path.clone());
+        assert_eq!(
+            restored.get(ModelFamily::Claude).snapshot(),
+            calibrators.get(ModelFamily::Claude).snapshot()
+        );
+        assert_eq!(restored.get(ModelFamily::Claude).snapshot().sample_count, 5);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[tokio::test]
+    async fn test_saves_are_debounced_inside_runtime() {
+        let path = std::env::temp_dir().join(format!(
+            "token_calibration_test_{}.json",
+            uuid::Uuid::new_v4().simple()
+        ));
+        let calibrators = FamilyCalibrators::new(Some(path.clone()));
+        let mut estimate = TokenEstimate::new(ModelFamily::Gemini);
+        estimate.text = 100;
+        for _ in 0..10 {
+            calibrators.record(&estimate, 150);
+        }
+        // Two factor updates, but nothing is written until the debounce fires
+        assert!(!path.exists());
+
+        calibrators.flush();
+        let restored = FamilyCalibrators::load(path.clone());
+        assert_eq!(restored.get(ModelFamily::Gemini).snapshot().sample_count, 10);
+        let _ = std::fs::remove_file(path);
+    }
 }
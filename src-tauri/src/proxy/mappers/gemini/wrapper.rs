@@ -76,6 +76,9 @@ pub fn wrap_request(
                                     .insert("id".to_string(), json!(call_id));
                                 tracing::debug!("[Gemini-Wrap] Request stage: Injected synced response_id '{}' for Claude model", call_id);
                             }
+
+                            // 按工具名压缩超大工具输出
+                            crate::proxy::mappers::tool_result_compressor::compress_function_response(fr);
                         }
 
                         // 3. 处理 thoughtSignature (原有逻辑保持)
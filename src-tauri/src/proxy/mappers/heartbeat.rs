@@ -0,0 +1,145 @@
+// SSE 心跳
+// 模型长时间思考时上游可能数十秒没有任何输出，部分客户端与反向代理 (Cloudflare Tunnel、nginx 等)
+// 会在 60-100 秒空闲后断开连接。流式 mapper 在上游静默超过配置的秒数后按协议发送心跳：
+// Anthropic 使用 `ping` 事件，OpenAI 使用 SSE 注释，Gemini 使用空白行。
+
+use bytes::Bytes;
+use std::time::Duration;
+use tokio::time::Instant;
+
+const ANTHROPIC_PING: &str = "event: ping\ndata: {\"type\": \"ping\"}\n\n";
+const OPENAI_PING: &str = ": ping\n\n";
+const GEMINI_PING: &str = "\n";
+
+/// 心跳格式 (按客户端协议)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HeartbeatStyle {
+    Anthropic,
+    OpenAI,
+    Gemini,
+}
+
+impl HeartbeatStyle {
+    pub fn payload(&self) -> Bytes {
+        match self {
+            Self::Anthropic => Bytes::from_static(ANTHROPIC_PING.as_bytes()),
+            Self::OpenAI => Bytes::from_static(OPENAI_PING.as_bytes()),
+            Self::Gemini => Bytes::from_static(GEMINI_PING.as_bytes()),
+        }
+    }
+}
+
+/// 上游静默计时器：在 tokio::select! 中与上游 next() 并列使用
+#[derive(Debug)]
+pub struct Heartbeat {
+    style: HeartbeatStyle,
+    /// None = 心跳已关闭
+    idle: Option<Duration>,
+    last_activity: Instant,
+    count: u32,
+    label: String,
+}
+
+impl Heartbeat {
+    /// 按全局配置创建；label 用于调试日志 (trace_id 或模型名)
+    pub fn new(style: HeartbeatStyle, label: impl Into<String>) -> Self {
+        let cfg = crate::proxy::config::get_stream_heartbeat_config();
+        let idle = (cfg.enabled && cfg.idle_seconds > 0)
+            .then(|| Duration::from_secs(cfg.idle_seconds));
+        Self::with_idle(style, idle, label)
+    }
+
+    pub fn with_idle(style: HeartbeatStyle, idle: Option<Duration>, label: impl Into<String>) -> Self {
+        Self {
+            style,
+            idle,
+            last_activity: Instant::now(),
+            count: 0,
+            label: label.into(),
+        }
+    }
+
+    /// 收到上游数据，重新开始计时
+    pub fn reset(&mut self) {
+        self.last_activity = Instant::now();
+    }
+
+    /// 等到上游静默满 idle 秒后返回一个心跳包；心跳关闭时永不返回
+    pub async fn tick(&mut self) -> Bytes {
+        let Some(idle) = self.idle else {
+            return std::future::pending().await;
+        };
+        tokio::time::sleep_until(self.last_activity + idle).await;
+        self.last_activity = Instant::now();
+        self.count += 1;
+        tracing::debug!(
+            "[Heartbeat] {} upstream idle for {}s, sent {:?} heartbeat #{}",
+            self.label,
+            idle.as_secs(),
+            self.style,
+            self.count
+        );
+        self.style.payload()
+    }
+}
+
+impl Drop for Heartbeat {
+    fn drop(&mut self) {
+        if self.count > 0 {
+            tracing::debug!(
+                "[Heartbeat] {} stream finished after {} heartbeat(s)",
+                self.label,
+                self.count
+            );
+        }
+    }
+}
+
+/// 是否为心跳包 (handler 预读首个有效数据块时跳过)
+pub fn is_heartbeat(chunk: &[u8]) -> bool {
+    let text = String::from_utf8_lossy(chunk);
+    let text = text.trim();
+    text.is_empty()
+        || text.starts_with(':')
+        || text.starts_with("data: :")
+        || text == ANTHROPIC_PING.trim()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_tick_fires_only_after_idle() {
+        let mut hb = Heartbeat::with_idle(HeartbeatStyle::Anthropic, Some(Duration::from_millis(30)), "t");
+        let early = tokio::time::timeout(Duration::from_millis(10), hb.tick()).await;
+        assert!(early.is_err());
+
+        let ping = hb.tick().await;
+        assert!(is_heartbeat(&ping));
+        assert_eq!(hb.count, 1);
+
+        // 收到数据后重新计时
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        hb.reset();
+        let early = tokio::time::timeout(Duration::from_millis(20), hb.tick()).await;
+        assert!(early.is_err());
+        assert_eq!(hb.count, 1);
+    }
+
+    #[tokio::test]
+    async fn test_disabled_never_fires() {
+        let mut hb = Heartbeat::with_idle(HeartbeatStyle::OpenAI, None, "t");
+        assert!(tokio::time::timeout(Duration::from_millis(20), hb.tick()).await.is_err());
+        assert_eq!(hb.count, 0);
+    }
+
+    #[test]
+    fn test_is_heartbeat() {
+        for style in [HeartbeatStyle::Anthropic, HeartbeatStyle::OpenAI, HeartbeatStyle::Gemini] {
+            assert!(is_heartbeat(&style.payload()));
+        }
+        assert!(!is_heartbeat(b"event: message_start\ndata: {}\n\n"));
+        assert!(!is_heartbeat(b"data: {\"choices\":[]}\n\n"));
+    }
+}
@@ -3,10 +3,13 @@
 
 pub mod claude;
 pub mod common_utils;
+pub mod context_compression; // 协议无关的渐进式上下文压缩
 pub mod context_manager;
 pub mod error_classifier;
 pub mod estimation_calibrator;
 pub mod gemini;
+pub mod heartbeat;
 pub mod openai;
 pub mod signature_store;
+pub mod token_counter; // BPE 分词 token 计数
 pub mod tool_result_compressor;
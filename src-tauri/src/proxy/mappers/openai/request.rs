@@ -246,46 +246,14 @@ pub fn transform_openai_request(
                                             }));
                                         }
                                     } else if image_url.url.starts_with("http") {
+                                        // handler 已通过 image_url_resolver 把远程图片下载为 data URL，
+                                        // 这里仅兜底处理未经解析的地址
                                         parts.push(json!({
-                                            "fileData": { "fileUri": &image_url.url, "mimeType": "image/jpeg" }
+                                            "fileData": { "fileUri": &image_url.url, "mimeType": guess_image_mime_from_url(&image_url.url) }
                                         }));
                                     } else {
-                                        // [NEW] 处理本地文件路径 (file:// 或 Windows/Unix 路径)
-                                        let file_path = if image_url.url.starts_with("file://") {
-                                            // 移除 file:// 前缀
-                                            #[cfg(target_os = "windows")]
-                                            { image_url.url.trim_start_matches("file:///").replace('/', "\\") }
-                                            #[cfg(not(target_os = "windows"))]
-                                            { image_url.url.trim_start_matches("file://").to_string() }
-                                        } else {
-                                            image_url.url.clone()
-                                        };
-                                        
-                                        tracing::debug!("[OpenAI-Request] Reading local image: {}", file_path);
-                                        
-                                        // 读取文件并转换为 base64
-                                        if let Ok(file_bytes) = std::fs::read(&file_path) {
-                                            use base64::Engine as _;
-                                            let b64 = base64::engine::general_purpose::STANDARD.encode(&file_bytes);
-                                            
-                                            // 根据文件扩展名推断 MIME 类型
-                                            let mime_type = if file_path.to_lowercase().ends_with(".png") {
-                                                "image/png"
-                                            } else if file_path.to_lowercase().ends_with(".gif") {
-                                                "image/gif"
-                                            } else if file_path.to_lowercase().ends_with(".webp") {
-                                                "image/webp"
-                                            } else {
-                                                "image/jpeg"
-                                            };
-                                            
-                                            parts.push(json!({
-                                                "inlineData": { "mimeType": mime_type, "data": b64 }
-                                            }));
-                                            tracing::debug!("[OpenAI-Request] Successfully loaded image: {} ({} bytes)", file_path, file_bytes.len());
-                                        } else {
-                                            tracing::debug!("[OpenAI-Request] Failed to read local image: {}", file_path);
-                                        }
+                                        // 本地文件只能经 image_url_resolver 按目录白名单读取，不在协议转换中直接访问文件系统
+                                        tracing::warn!("[OpenAI-Request] Skipping unresolved local image_url");
                                     }
                                 }
                                 OpenAIContentBlock::AudioUrl { audio_url: _ } => {
@@ -355,6 +323,8 @@ pub fn transform_openai_request(
                     Some(OpenAIContent::Array(blocks)) => blocks.iter().filter_map(|b| if let OpenAIContentBlock::Text { text } = b { Some(text.clone()) } else { None }).collect::<Vec<_>>().join("\n"),
                     None => "".to_string()
                 };
+                // 按工具名压缩超大工具输出
+                let content_val = crate::proxy::mappers::tool_result_compressor::compress_tool_result(final_name, &content_val);
 
                 parts.push(json!({
                     "functionResponse": {
@@ -704,6 +674,22 @@ pub fn transform_openai_request(
     (final_body, session_id, message_count)
 }
 
+/// 按 URL 路径扩展名推断图片 MIME 类型 (未知时按 JPEG)
+fn guess_image_mime_from_url(url: &str) -> &'static str {
+    let path = url.split(['?', '#']).next().unwrap_or(url).to_ascii_lowercase();
+    if path.ends_with(".png") {
+        "image/png"
+    } else if path.ends_with(".gif") {
+        "image/gif"
+    } else if path.ends_with(".webp") {
+        "image/webp"
+    } else if path.ends_with(".heic") {
+        "image/heic"
+    } else {
+        "image/jpeg"
+    }
+}
+
 fn enforce_uppercase_types(value: &mut Value) {
     if let Value::Object(map) = value {
         if let Some(type_val) = map.get_mut("type") {
@@ -8,6 +8,8 @@ use std::pin::Pin;
 use tracing::debug;
 use uuid::Uuid;
 
+use crate::proxy::mappers::heartbeat::{Heartbeat, HeartbeatStyle};
+
 
 
 /// 保存 thoughtSignature 到会话缓存
@@ -78,12 +80,13 @@ pub fn create_openai_sse_stream(
         let mut final_usage: Option<super::models::OpenAIUsage> = None;
         let mut error_occurred = false;
 
-        let mut heartbeat_interval = tokio::time::interval(std::time::Duration::from_secs(15));
-        heartbeat_interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+        // 上游静默时发送 SSE 注释心跳
+        let mut heartbeat = Heartbeat::new(HeartbeatStyle::OpenAI, model.clone());
 
         loop {
             tokio::select! {
                 item = gemini_stream.next() => {
+                    heartbeat.reset();
                     match item {
                         Some(Ok(bytes)) => {
                             buffer.extend_from_slice(&bytes);
@@ -281,8 +284,8 @@ pub fn create_openai_sse_stream(
                         None => break,
                     }
                 }
-                _ = heartbeat_interval.tick() => {
-                    yield Ok::<Bytes, String>(Bytes::from(": ping\n\n"));
+                ping = heartbeat.tick() => {
+                    yield Ok::<Bytes, String>(ping);
                 }
             }
         }
@@ -329,12 +332,13 @@ pub fn create_legacy_sse_stream(
     let stream = async_stream::stream! {
         let mut final_usage: Option<super::models::OpenAIUsage> = None;
         let mut error_occurred = false;
-        let mut heartbeat_interval = tokio::time::interval(std::time::Duration::from_secs(15));
-        heartbeat_interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+        // 上游静默时发送 SSE 注释心跳
+        let mut heartbeat = Heartbeat::new(HeartbeatStyle::OpenAI, model.clone());
 
         loop {
             tokio::select! {
                 item = gemini_stream.next() => {
+                    heartbeat.reset();
                     match item {
                         Some(Ok(bytes)) => {
                             buffer.extend_from_slice(&bytes);
@@ -398,7 +402,7 @@ pub fn create_legacy_sse_stream(
                         None => break,
                     }
                 }
-                _ = heartbeat_interval.tick() => { yield Ok::<Bytes, String>(Bytes::from(": ping\n\n")); }
+                ping = heartbeat.tick() => { yield Ok::<Bytes, String>(ping); }
             }
         }
         if !error_occurred {
@@ -428,12 +432,13 @@ pub fn create_codex_sse_stream(
         yield Ok::<Bytes, String>(Bytes::from(format!("data: {}\n\n", serde_json::to_string(&created_ev).unwrap())));
 
         let mut emitted_tool_calls = std::collections::HashSet::new();
-        let mut heartbeat_interval = tokio::time::interval(std::time::Duration::from_secs(15));
-        heartbeat_interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+        // 上游静默时发送 SSE 注释心跳
+        let mut heartbeat = Heartbeat::new(HeartbeatStyle::OpenAI, response_id.clone());
 
         loop {
             tokio::select! {
                 item = gemini_stream.next() => {
+                    heartbeat.reset();
                     match item {
                         Some(Ok(bytes)) => {
                             buffer.extend_from_slice(&bytes);
@@ -477,7 +482,7 @@ pub fn create_codex_sse_stream(
                         None => break,
                     }
                 }
-                _ = heartbeat_interval.tick() => { yield Ok::<Bytes, String>(Bytes::from(": ping\n\n")); }
+                ping = heartbeat.tick() => { yield Ok::<Bytes, String>(ping); }
             }
         }
     };
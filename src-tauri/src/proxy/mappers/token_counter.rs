@@ -0,0 +1,484 @@
+//! Token Counter Module
+//!
+//! Tokenizer-based local token counting, used for context pressure estimation and
+//! the `count_tokens` / `countTokens` endpoints.
+//!
+//! Neither Claude nor Gemini publish their tokenizers, so text is counted with the
+//! o200k BPE vocabulary and the remaining per-family drift is learned by the
+//! estimation calibrator. Media parts are not tokenized at all; they are counted
+//! with each upstream's published rules, which are exact and must not be calibrated.
+
+use base64::Engine as _;
+use serde_json::Value;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::{Mutex, OnceLock};
+
+use crate::proxy::common::image_preprocess::estimate_image_tokens;
+
+/// Model families with separate counting rules and calibration
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ModelFamily {
+    Claude,
+    Gemini,
+    Other,
+}
+
+impl ModelFamily {
+    pub const ALL: [ModelFamily; 3] = [Self::Claude, Self::Gemini, Self::Other];
+
+    /// Determine the family from an (upstream) model name
+    pub fn from_model(model: &str) -> Self {
+        let model = model.to_lowercase();
+        if model.contains("claude") {
+            Self::Claude
+        } else if model.contains("gemini") || model.contains("gemma") {
+            Self::Gemini
+        } else {
+            Self::Other
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Claude => "claude",
+            Self::Gemini => "gemini",
+            Self::Other => "other",
+        }
+    }
+}
+
+/// Token estimate split into tokenizer-counted text and rule-counted media
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TokenEstimate {
+    pub family: ModelFamily,
+    /// Text, structure and tool payloads (subject to calibration)
+    pub text: u32,
+    /// Images, documents, audio and video (counted by upstream rules)
+    pub media: u32,
+}
+
+impl TokenEstimate {
+    pub fn new(family: ModelFamily) -> Self {
+        Self {
+            family,
+            text: 0,
+            media: 0,
+        }
+    }
+
+    /// Uncalibrated total
+    pub fn total(&self) -> u32 {
+        self.text + self.media
+    }
+
+    pub fn add_text(&mut self, text: &str) {
+        self.text += count_text(text);
+    }
+
+    /// Fixed per-message / per-block overhead
+    pub fn add_overhead(&mut self, tokens: u32) {
+        self.text += tokens;
+    }
+
+    /// Count an inline media part (base64 payload) by its MIME type
+    pub fn add_inline_data(&mut self, mime_type: &str, data: &str) {
+        let mime_type = mime_type.to_ascii_lowercase();
+        let Ok(bytes) = base64::engine::general_purpose::STANDARD.decode(data.trim()) else {
+            return;
+        };
+        if mime_type.starts_with("text/") || mime_type == "application/json" {
+            self.add_text(&String::from_utf8_lossy(&bytes));
+        } else {
+            self.media += media_tokens(self.family, &mime_type, &bytes);
+        }
+    }
+
+    /// Count a media part referenced by URI (content unavailable locally)
+    pub fn add_file_reference(&mut self, mime_type: &str) {
+        if mime_type.to_ascii_lowercase().starts_with("image/") {
+            self.media += match self.family {
+                ModelFamily::Claude => CLAUDE_MAX_IMAGE_TOKENS,
+                _ => GEMINI_IMAGE_TOKENS,
+            };
+        }
+    }
+}
+
+// ===== Text =====
+
+/// Texts are encoded in slices so a single huge "word" (minified code, base64)
+/// cannot trigger quadratic BPE merging; the boundary error is negligible.
+const ENCODE_SLICE_BYTES: usize = 8 * 1024;
+/// Texts longer than this are extrapolated from evenly spaced samples instead of
+/// being fully encoded, which bounds the BPE work done on async request threads
+const SAMPLE_THRESHOLD_BYTES: usize = 64 * 1024;
+const SAMPLE_SLICES: usize = 8;
+/// Only texts at least this long are memoized (history repeats across turns)
+const CACHE_MIN_BYTES: usize = 256;
+const CACHE_MAX_ENTRIES: usize = 4096;
+
+static TEXT_CACHE: OnceLock<Mutex<HashMap<u64, u32>>> = OnceLock::new();
+
+/// Count text tokens with the o200k BPE vocabulary
+pub fn count_text(text: &str) -> u32 {
+    if text.is_empty() {
+        return 0;
+    }
+    if text.len() < CACHE_MIN_BYTES {
+        return encode_len(text);
+    }
+
+    let mut hasher = DefaultHasher::new();
+    text.hash(&mut hasher);
+    let key = hasher.finish();
+    let cache = TEXT_CACHE.get_or_init(|| Mutex::new(HashMap::new()));
+    if let Some(count) = cache.lock().ok().and_then(|c| c.get(&key).copied()) {
+        return count;
+    }
+
+    let count = if text.len() > SAMPLE_THRESHOLD_BYTES {
+        sampled_len(text)
+    } else {
+        encode_len(text)
+    };
+    if let Ok(mut cache) = cache.lock() {
+        if cache.len() >= CACHE_MAX_ENTRIES {
+            cache.clear();
+        }
+        cache.insert(key, count);
+    }
+    count
+}
+
+fn encode_len(text: &str) -> u32 {
+    let bpe = tiktoken_rs::o200k_base_singleton();
+    let mut total = 0usize;
+    let mut rest = text;
+    while !rest.is_empty() {
+        let mut end = rest.len().min(ENCODE_SLICE_BYTES);
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+        total += bpe.encode_ordinary(&rest[..end]).len();
+        rest = &rest[end..];
+    }
+    total.min(u32::MAX as usize) as u32
+}
+
+/// Estimate from SAMPLE_SLICES slices spread over the text, scaled by byte length
+fn sampled_len(text: &str) -> u32 {
+    let bpe = tiktoken_rs::o200k_base_singleton();
+    let stride = text.len() / SAMPLE_SLICES;
+    let mut sampled_bytes = 0usize;
+    let mut tokens = 0usize;
+    for i in 0..SAMPLE_SLICES {
+        let mut start = i * stride;
+        while !text.is_char_boundary(start) {
+            start += 1;
+        }
+        let mut end = (start + ENCODE_SLICE_BYTES).min(text.len());
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        if end > start {
+            tokens += bpe.encode_ordinary(&text[start..end]).len();
+            sampled_bytes += end - start;
+        }
+    }
+    if sampled_bytes == 0 {
+        return encode_len(text);
+    }
+    let estimate = (tokens as f64 * text.len() as f64 / sampled_bytes as f64).ceil();
+    estimate.min(u32::MAX as f64) as u32
+}
+
+// ===== Media =====
+
+/// Gemini: every image (or 768x768 tile) costs 258 tokens
+const GEMINI_IMAGE_TOKENS: u32 = 258;
+/// Gemini: every PDF page is billed as one image
+const GEMINI_PDF_PAGE_TOKENS: u32 = 258;
+/// Gemini: audio is billed at 32 tokens per second
+const GEMINI_AUDIO_TOKENS_PER_SEC: f64 = 32.0;
+/// Gemini: video is billed at 263 tokens per second (frames + audio track)
+const GEMINI_VIDEO_TOKENS_PER_SEC: f64 = 263.0;
+
+/// Claude: images are scaled to fit 1568px / 1.15MP, then billed at w*h/750
+const CLAUDE_MAX_IMAGE_EDGE: f64 = 1568.0;
+const CLAUDE_MAX_IMAGE_PIXELS: f64 = 1_150_000.0;
+const CLAUDE_MAX_IMAGE_TOKENS: u32 = 1600;
+/// Claude: each PDF page is a rendered page image plus its extracted text layer
+const CLAUDE_PDF_PAGE_TOKENS: u32 = 2000;
+
+/// Fallback bitrates when the container does not expose a duration (bytes/sec)
+const DEFAULT_AUDIO_BYTES_PER_SEC: f64 = 16_000.0;
+const DEFAULT_VIDEO_BYTES_PER_SEC: f64 = 250_000.0;
+
+fn media_tokens(family: ModelFamily, mime_type: &str, bytes: &[u8]) -> u32 {
+    if mime_type.starts_with("image/") {
+        image_tokens(family, bytes)
+    } else if mime_type == "application/pdf" {
+        let per_page = match family {
+            ModelFamily::Claude => CLAUDE_PDF_PAGE_TOKENS,
+            _ => GEMINI_PDF_PAGE_TOKENS,
+        };
+        pdf_page_count(bytes) * per_page
+    } else if mime_type.starts_with("audio/") {
+        let secs =
+            wav_duration_secs(bytes).unwrap_or(bytes.len() as f64 / DEFAULT_AUDIO_BYTES_PER_SEC);
+        (secs * GEMINI_AUDIO_TOKENS_PER_SEC).ceil() as u32
+    } else if mime_type.starts_with("video/") {
+        let secs = bytes.len() as f64 / DEFAULT_VIDEO_BYTES_PER_SEC;
+        (secs * GEMINI_VIDEO_TOKENS_PER_SEC).ceil() as u32
+    } else {
+        0
+    }
+}
+
+/// Image tokens according to the family's billing rule
+pub fn image_tokens(family: ModelFamily, bytes: &[u8]) -> u32 {
+    let dims = image_dimensions(bytes);
+    match family {
+        ModelFamily::Claude => match dims {
+            Some((w, h)) => claude_image_tokens(w, h),
+            None => CLAUDE_MAX_IMAGE_TOKENS,
+        },
+        _ => match dims {
+            Some((w, h)) => estimate_image_tokens(w, h),
+            None => GEMINI_IMAGE_TOKENS,
+        },
+    }
+}
+
+fn claude_image_tokens(width: u32, height: u32) -> u32 {
+    if width == 0 || height == 0 {
+        return 0;
+    }
+    let (w, h) = (width as f64, height as f64);
+    let scale = 1f64
+        .min(CLAUDE_MAX_IMAGE_EDGE / w.max(h))
+        .min((CLAUDE_MAX_IMAGE_PIXELS / (w * h)).sqrt());
+    (((w * scale).round() * (h * scale).round()) / 750.0).ceil() as u32
+}
+
+/// Read image dimensions from the header without decoding pixels
+fn image_dimensions(bytes: &[u8]) -> Option<(u32, u32)> {
+    if let Ok(dims) = image::ImageReader::new(std::io::Cursor::new(bytes))
+        .with_guessed_format()
+        .ok()?
+        .into_dimensions()
+    {
+        return Some(dims);
+    }
+    // The GIF decoder is not compiled in, and the JPEG decoder rejects truncated
+    // headers; parse their dimensions directly
+    if bytes.starts_with(b"GIF8") && bytes.len() >= 10 {
+        let w = u16::from_le_bytes([bytes[6], bytes[7]]) as u32;
+        let h = u16::from_le_bytes([bytes[8], bytes[9]]) as u32;
+        return Some((w, h));
+    }
+    jpeg_dimensions(bytes)
+}
+
+fn jpeg_dimensions(bytes: &[u8]) -> Option<(u32, u32)> {
+    if !bytes.starts_with(&[0xFF, 0xD8]) {
+        return None;
+    }
+    let mut pos = 2;
+    while pos + 4 <= bytes.len() {
+        if bytes[pos] != 0xFF {
+            return None;
+        }
+        let marker = bytes[pos + 1];
+        // Padding bytes and standalone markers carry no length
+        if marker == 0xFF {
+            pos += 1;
+            continue;
+        }
+        if marker == 0xD8 || marker == 0x01 || (0xD0..=0xD7).contains(&marker) {
+            pos += 2;
+            continue;
+        }
+        let len = u16::from_be_bytes([bytes[pos + 2], bytes[pos + 3]]) as usize;
+        // SOF0-SOF15, excluding DHT (C4), JPG (C8) and DAC (CC)
+        let is_sof = (0xC0..=0xCF).contains(&marker) && !matches!(marker, 0xC4 | 0xC8 | 0xCC);
+        if is_sof && pos + 9 <= bytes.len() {
+            let h = u16::from_be_bytes([bytes[pos + 5], bytes[pos + 6]]) as u32;
+            let w = u16::from_be_bytes([bytes[pos + 7], bytes[pos + 8]]) as u32;
+            return Some((w, h));
+        }
+        pos += 2 + len;
+    }
+    None
+}
+
+/// Count PDF pages from `/Type /Page` objects, falling back to the page tree `/Count`
+fn pdf_page_count(bytes: &[u8]) -> u32 {
+    let mut pages = 0u32;
+    let mut max_count = 0u32;
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i..].starts_with(b"/Type") {
+            let mut j = i + 5;
+            while j < bytes.len() && bytes[j].is_ascii_whitespace() {
+                j += 1;
+            }
+            if bytes[j..].starts_with(b"/Page") && bytes.get(j + 5) != Some(&b's') {
+                pages += 1;
+            }
+            i = j;
+            continue;
+        }
+        if bytes[i..].starts_with(b"/Count") {
+            let digits: String = bytes[i + 6..]
+                .iter()
+                .skip_while(|b| b.is_ascii_whitespace())
+                .take_while(|b| b.is_ascii_digit())
+                .map(|&b| b as char)
+                .collect();
+            max_count = max_count.max(digits.parse().unwrap_or(0));
+            i += 6;
+            continue;
+        }
+        i += 1;
+    }
+    // Page objects inside compressed object streams are invisible to the scan
+    pages.max(max_count).max(1)
+}
+
+/// Duration of a PCM WAV file from its byte rate
+fn wav_duration_secs(bytes: &[u8]) -> Option<f64> {
+    if bytes.len() < 44 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+        return None;
+    }
+    let byte_rate = u32::from_le_bytes([bytes[28], bytes[29], bytes[30], bytes[31]]);
+    (byte_rate > 0).then(|| (bytes.len() - 44) as f64 / byte_rate as f64)
+}
+
+// ===== Gemini parts =====
+
+/// Count a Gemini `Part` (text, inline media, file references and tool payloads)
+pub fn add_gemini_part(estimate: &mut TokenEstimate, part: &Value) {
+    if let Some(text) = part.get("text").and_then(|t| t.as_str()) {
+        estimate.add_text(text);
+        return;
+    }
+    if let Some(inline) = part.get("inlineData").or_else(|| part.get("inline_data")) {
+        let mime_type = inline
+            .get("mimeType")
+            .or_else(|| inline.get("mime_type"))
+            .and_then(|v| v.as_str())
+            .unwrap_or("");
+        let data = inline.get("data").and_then(|v| v.as_str()).unwrap_or("");
+        estimate.add_inline_data(mime_type, data);
+        return;
+    }
+    if let Some(file) = part.get("fileData").or_else(|| part.get("file_data")) {
+        let mime_type = file
+            .get("mimeType")
+            .or_else(|| file.get("mime_type"))
+            .and_then(|v| v.as_str())
+            .unwrap_or("");
+        estimate.add_file_reference(mime_type);
+        return;
+    }
+    for key in ["functionCall", "functionResponse"] {
+        if let Some(v) = part.get(key) {
+            estimate.add_text(&v.to_string());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn b64(bytes: &[u8]) -> String {
+        base64::engine::general_purpose::STANDARD.encode(bytes)
+    }
+
+    #[test]
+    fn test_count_text() {
+        assert_eq!(count_text(""), 0);
+        assert_eq!(count_text("Hello world"), 2);
+        // Long texts are sliced and cached; the second call must agree
+        let long = "The quick brown fox jumps over the lazy dog. ".repeat(500);
+        let first = count_text(&long);
+        assert!(first > 4000 && first < 6000, "got {}", first);
+        assert_eq!(count_text(&long), first);
+        // CJK is far denser than the old 1.5 chars/token heuristic assumed
+        assert!(count_text("今天天气很好，我们去公园散步吧。") < 16);
+    }
+
+    #[test]
+    fn test_huge_text_is_sampled() {
+        let huge = "fn main() { println!(\"你好, world\"); }\n".repeat(4000);
+        assert!(huge.len() > SAMPLE_THRESHOLD_BYTES);
+        let exact = encode_len(&huge) as f64;
+        let sampled = sampled_len(&huge) as f64;
+        assert!((sampled - exact).abs() / exact < 0.05, "exact {} sampled {}", exact, sampled);
+    }
+
+    #[test]
+    fn test_model_family() {
+        assert_eq!(
+            ModelFamily::from_model("claude-sonnet-4-5"),
+            ModelFamily::Claude
+        );
+        assert_eq!(
+            ModelFamily::from_model("gemini-2.5-flash"),
+            ModelFamily::Gemini
+        );
+        assert_eq!(ModelFamily::from_model("gpt-4o"), ModelFamily::Other);
+    }
+
+    #[test]
+    fn test_image_rules() {
+        // Minimal JPEG header: SOI, APP0 (len 16), SOF0 with 1000x2000
+        let mut jpeg = vec![0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x10];
+        jpeg.extend_from_slice(&[0; 14]);
+        jpeg.extend_from_slice(&[0xFF, 0xC0, 0x00, 0x11, 0x08, 0x07, 0xD0, 0x03, 0xE8]);
+        assert_eq!(jpeg_dimensions(&jpeg), Some((1000, 2000)));
+
+        // Claude: 1000x2000 is scaled to 758x1517 (1.15MP), ~1534 tokens
+        let tokens = image_tokens(ModelFamily::Claude, &jpeg);
+        assert!((1500..=1600).contains(&tokens), "got {}", tokens);
+        assert_eq!(claude_image_tokens(200, 200), 54);
+        // Gemini: tiled by short edge / 1.5 (666px) -> 2 x 4 tiles
+        assert_eq!(image_tokens(ModelFamily::Gemini, &jpeg), 258 * 8);
+        // Unknown formats fall back to the flat per-image cost
+        assert_eq!(image_tokens(ModelFamily::Gemini, b"????"), 258);
+    }
+
+    #[test]
+    fn test_document_and_audio_rules() {
+        let pdf = b"%PDF-1.4\n1 0 obj << /Type /Pages /Kids [2 0 R 3 0 R] /Count 2 >>\n2 0 obj << /Type /Page >>\n3 0 obj << /Type/Page >>\n";
+        assert_eq!(pdf_page_count(pdf), 2);
+        assert_eq!(pdf_page_count(b"%PDF-1.7 compressed"), 1);
+
+        let mut estimate = TokenEstimate::new(ModelFamily::Gemini);
+        estimate.add_inline_data("application/pdf", &b64(pdf));
+        assert_eq!(estimate.media, 2 * 258);
+
+        // 2 seconds of 16kHz mono 16-bit PCM -> 64 tokens
+        let mut wav = b"RIFF\0\0\0\0WAVEfmt ".to_vec();
+        wav.extend_from_slice(&[16, 0, 0, 0, 1, 0, 1, 0]);
+        wav.extend_from_slice(&16_000u32.to_le_bytes());
+        wav.extend_from_slice(&32_000u32.to_le_bytes());
+        wav.extend_from_slice(&[2, 0, 16, 0]);
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&64_000u32.to_le_bytes());
+        wav.resize(wav.len() + 64_000, 0);
+        let mut estimate = TokenEstimate::new(ModelFamily::Gemini);
+        estimate.add_inline_data("audio/wav", &b64(&wav));
+        assert_eq!(estimate.media, 64);
+        assert_eq!(estimate.text, 0);
+
+        // Plain-text documents are tokenized, not billed as media
+        let mut estimate = TokenEstimate::new(ModelFamily::Claude);
+        estimate.add_inline_data("text/plain", &b64(b"Hello world"));
+        assert_eq!((estimate.text, estimate.media), (2, 0));
+    }
+}
@@ -1,17 +1,21 @@
 //! 工具结果输出压缩模块
 //! 
-//! 提供智能压缩功能:
-//! - 浏览器快照压缩 (头+尾保留)
-//! - 大文件提示压缩 (提取关键信息)
-//! - 通用截断 (200,000 字符限制)
+//! 按工具名从策略注册表中选择压缩方式与字符预算 (见 `ToolResultCompressionConfig`):
+//! - 命令行输出: 保留头部与尾部
+//! - 测试运行器: 折叠堆栈帧
+//! - diff 工具: 丢弃未变更的上下文行
+//! - 搜索/列表类工具: 概括 JSON 数组
+//! - 其他: 通用启发式 (大文件提示、浏览器快照、HTML 清理、截断)
+//!
+//! 作用于 Claude tool_result、OpenAI tool 消息与 Gemini functionResponse。
 
+use crate::proxy::config::{
+    get_tool_result_compression_config, ToolCompressionStrategy, ToolResultCompressionConfig,
+};
 use regex::Regex;
 use serde_json::Value;
 use tracing::{debug, info};
 
-/// 最大工具结果字符数 (约 20 万,防止 prompt 超长)
-const MAX_TOOL_RESULT_CHARS: usize = 200_000;
-
 /// 浏览器快照检测阈值
 const SNAPSHOT_DETECTION_THRESHOLD: usize = 20_000;
 
@@ -25,6 +29,273 @@ const SNAPSHOT_HEAD_RATIO: f64 = 0.7;
 #[allow(dead_code)]
 const SNAPSHOT_TAIL_RATIO: f64 = 0.3;
 
+/// 头尾保留时为省略提示预留的字符数
+const HEAD_TAIL_MARKER_RESERVE: usize = 100;
+
+/// 每段堆栈中保留的开头/结尾帧数
+const STACK_KEEP_HEAD_FRAMES: usize = 3;
+const STACK_KEEP_TAIL_FRAMES: usize = 2;
+
+/// 为工具选择压缩策略与字符预算 (按顺序匹配规则，未命中时使用通用策略)
+pub fn resolve_strategy(
+    cfg: &ToolResultCompressionConfig,
+    tool_name: &str,
+) -> (ToolCompressionStrategy, usize) {
+    let name = tool_name.to_lowercase();
+    cfg.rules
+        .iter()
+        .find(|rule| {
+            rule.patterns.iter().any(|p| {
+                crate::proxy::common::model_mapping::wildcard_match(&p.to_lowercase(), &name)
+            })
+        })
+        .map(|rule| (rule.strategy, rule.max_chars))
+        .unwrap_or((ToolCompressionStrategy::Default, cfg.default_max_chars))
+}
+
+/// 按工具名压缩工具结果文本
+pub fn compress_tool_result(tool_name: &str, text: &str) -> String {
+    let (strategy, max_chars) = resolve_strategy(&get_tool_result_compression_config(), tool_name);
+    compress_with_strategy(strategy, text, max_chars)
+}
+
+/// 使用指定策略压缩；策略处理后仍超出预算时回退到通用压缩
+pub fn compress_with_strategy(strategy: ToolCompressionStrategy, text: &str, max_chars: usize) -> String {
+    if text.len() <= max_chars {
+        return text.to_string();
+    }
+    let compacted = match strategy {
+        ToolCompressionStrategy::Default => return compact_tool_result_text(text, max_chars),
+        ToolCompressionStrategy::HeadTail => keep_head_tail(text, max_chars),
+        ToolCompressionStrategy::StackTrace => {
+            let collapsed = collapse_stack_traces(text);
+            if collapsed.len() <= max_chars {
+                collapsed
+            } else {
+                // 测试汇总通常在末尾
+                keep_head_tail(&collapsed, max_chars)
+            }
+        }
+        ToolCompressionStrategy::Diff => drop_unchanged_diff_lines(text),
+        ToolCompressionStrategy::JsonArray => {
+            summarize_json_array(text, max_chars).unwrap_or_else(|| text.to_string())
+        }
+    };
+    debug!(
+        "[ToolCompressor] {:?} strategy: {} → {} chars",
+        strategy,
+        text.len(),
+        compacted.len()
+    );
+    if compacted.len() <= max_chars {
+        compacted
+    } else {
+        compact_tool_result_text(&compacted, max_chars)
+    }
+}
+
+/// 压缩 Gemini functionResponse.response 中的文本字段
+pub fn compress_function_response(function_response: &mut Value) {
+    let name = function_response
+        .get("name")
+        .and_then(|n| n.as_str())
+        .unwrap_or("unknown")
+        .to_string();
+    let (strategy, max_chars) = resolve_strategy(&get_tool_result_compression_config(), &name);
+    let Some(response) = function_response.get_mut("response") else {
+        return;
+    };
+    let fields: Vec<&mut Value> = match response {
+        Value::Object(map) => map.values_mut().collect(),
+        other => vec![other],
+    };
+    for field in fields {
+        if let Value::String(text) = field {
+            if text.len() > max_chars {
+                *text = compress_with_strategy(strategy, text, max_chars);
+            }
+        }
+    }
+}
+
+fn floor_boundary(text: &str, mut idx: usize) -> usize {
+    idx = idx.min(text.len());
+    while !text.is_char_boundary(idx) {
+        idx -= 1;
+    }
+    idx
+}
+
+fn ceil_boundary(text: &str, mut idx: usize) -> usize {
+    while idx < text.len() && !text.is_char_boundary(idx) {
+        idx += 1;
+    }
+    idx
+}
+
+/// 保留头部 40% 与尾部 60% (命令行的错误信息多在末尾)，尽量在行边界切分
+fn keep_head_tail(text: &str, max_chars: usize) -> String {
+    let budget = max_chars.saturating_sub(HEAD_TAIL_MARKER_RESERVE);
+    let head_budget = budget * 2 / 5;
+    let tail_budget = budget - head_budget;
+
+    let head_end = floor_boundary(text, head_budget);
+    let head_end = text[..head_end].rfind('\n').map(|i| i + 1).unwrap_or(head_end);
+    let tail_start = ceil_boundary(text, text.len().saturating_sub(tail_budget));
+    let tail_start = text[tail_start..]
+        .find('\n')
+        .map(|i| tail_start + i + 1)
+        .filter(|&i| i < text.len())
+        .unwrap_or(tail_start);
+    if tail_start <= head_end {
+        return truncate_text_safe(text, max_chars);
+    }
+
+    let omitted = &text[head_end..tail_start];
+    format!(
+        "{}...[omitted {} lines, {} chars]...\n{}",
+        &text[..head_end],
+        omitted.lines().count(),
+        omitted.len(),
+        &text[tail_start..]
+    )
+}
+
+/// 是否为堆栈帧行 (JS/Java `at ...`、Python `File "..."`、Rust backtrace `N: ...`)
+fn is_stack_frame(line: &str) -> bool {
+    let trimmed = line.trim_start();
+    if trimmed.len() == line.len() {
+        return false;
+    }
+    trimmed.starts_with("at ")
+        || trimmed.starts_with("File \"")
+        || trimmed
+            .split_once(": ")
+            .is_some_and(|(n, _)| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// 折叠连续的堆栈帧，每段只保留开头与结尾几帧
+fn collapse_stack_traces(text: &str) -> String {
+    let lines: Vec<&str> = text.lines().collect();
+    let mut out: Vec<String> = Vec::with_capacity(lines.len());
+    let mut i = 0;
+    while i < lines.len() {
+        if !is_stack_frame(lines[i]) {
+            out.push(lines[i].to_string());
+            i += 1;
+            continue;
+        }
+        // 帧行及其后更深缩进的源码行 (Python) 构成一段
+        let start = i;
+        while i < lines.len()
+            && (is_stack_frame(lines[i])
+                || (i > start && lines[i].starts_with("    ") && !lines[i].trim().is_empty()))
+        {
+            i += 1;
+        }
+        let run = &lines[start..i];
+        if run.len() > STACK_KEEP_HEAD_FRAMES + STACK_KEEP_TAIL_FRAMES + 1 {
+            out.extend(run[..STACK_KEEP_HEAD_FRAMES].iter().map(|l| l.to_string()));
+            out.push(format!(
+                "    ... {} frames collapsed ...",
+                run.len() - STACK_KEEP_HEAD_FRAMES - STACK_KEEP_TAIL_FRAMES
+            ));
+            out.extend(run[run.len() - STACK_KEEP_TAIL_FRAMES..].iter().map(|l| l.to_string()));
+        } else {
+            out.extend(run.iter().map(|l| l.to_string()));
+        }
+    }
+    out.join("\n")
+}
+
+/// 丢弃 unified diff 中未变更的上下文行，只保留文件头、hunk 头与增删行
+fn drop_unchanged_diff_lines(text: &str) -> String {
+    let mut out: Vec<String> = Vec::new();
+    let mut in_hunk = false;
+    let mut dropped = 0usize;
+
+    for line in text.lines() {
+        if line.starts_with("@@") {
+            in_hunk = true;
+        } else if line.starts_with("diff ") || line.starts_with("--- ") || line.starts_with("+++ ") {
+            in_hunk = false;
+        } else if in_hunk && (line.starts_with(' ') || line.is_empty()) {
+            dropped += 1;
+            continue;
+        }
+        if dropped > 0 {
+            out.push(format!("... {} unchanged lines", dropped));
+            dropped = 0;
+        }
+        out.push(line.to_string());
+    }
+    if dropped > 0 {
+        out.push(format!("... {} unchanged lines", dropped));
+    }
+    out.join("\n")
+}
+
+/// 概括 JSON 数组：保留能放入预算的前若干项，并注明总数与字段
+/// 支持顶层数组，或对象中最大的数组字段
+fn summarize_json_array(text: &str, max_chars: usize) -> Option<String> {
+    let mut value: Value = serde_json::from_str(text.trim()).ok()?;
+    let field = match &value {
+        Value::Array(_) => None,
+        Value::Object(map) => Some(
+            map.iter()
+                .filter_map(|(k, v)| v.as_array().map(|a| (k.clone(), a.len())))
+                .max_by_key(|(_, len)| *len)?
+                .0,
+        ),
+        _ => return None,
+    };
+    let items = match &field {
+        None => value.as_array_mut()?,
+        Some(key) => value.get_mut(key)?.as_array_mut()?,
+    };
+    let total = items.len();
+    let item_keys: Vec<String> = items
+        .first()
+        .and_then(|item| item.as_object())
+        .map(|obj| obj.keys().cloned().collect())
+        .unwrap_or_default();
+
+    // 逐项累加直到接近预算 (为说明与外层结构预留 10%)
+    let budget = max_chars - max_chars / 10;
+    let mut used = text
+        .len()
+        .saturating_sub(serde_json::to_string(&*items).map(|s| s.len()).unwrap_or(0));
+    let mut kept = 0;
+    for item in items.iter() {
+        let len = serde_json::to_string(item).map(|s| s.len()).unwrap_or(0) + 1;
+        if used + len > budget {
+            break;
+        }
+        used += len;
+        kept += 1;
+    }
+    if kept == total {
+        return None;
+    }
+    items.truncate(kept);
+
+    let location = field.map(|k| format!(" in field \"{}\"", k)).unwrap_or_default();
+    let keys = if item_keys.is_empty() {
+        String::new()
+    } else {
+        format!(" Item keys: {}.", item_keys.join(", "))
+    };
+    Some(format!(
+        "[JSON array{} with {} items; showing first {}, {} omitted.{}]\n{}",
+        location,
+        total,
+        kept,
+        total - kept,
+        keys,
+        serde_json::to_string(&value).ok()?
+    ))
+}
+
 /// 压缩工具结果文本
 /// 
 /// 根据内容类型自动选择最佳压缩策略:
@@ -248,20 +519,24 @@ fn deep_clean_html(html: &str) -> String {
 /// 
 /// 处理逻辑:
 /// 1. 移除 base64 图片 (避免体积过大)
-/// 2. 压缩文本内容 (使用智能压缩策略)
-/// 3. 限制总字符数 (默认 200,000)
+/// 2. 压缩文本内容 (按工具名选择压缩策略)
+/// 3. 限制总字符数 (按工具配置的预算，默认 200,000)
 /// 
 /// 清理并截断工具调用结果内容块
-pub fn sanitize_tool_result_blocks(blocks: &mut Vec<Value>) {
+pub fn sanitize_tool_result_blocks(tool_name: &str, blocks: &mut Vec<Value>) {
+    let (strategy, max_tool_result_chars) =
+        resolve_strategy(&get_tool_result_compression_config(), tool_name);
     let mut used_chars = 0;
     let mut cleaned_blocks = Vec::new();
     let mut removed_image = false;
     
     if !blocks.is_empty() {
         info!(
-            "[ToolCompressor] Processing {} blocks for truncation (MAX: {} chars)",
+            "[ToolCompressor] Processing {} blocks of {} for truncation ({:?}, MAX: {} chars)",
             blocks.len(),
-            MAX_TOOL_RESULT_CHARS
+            tool_name,
+            strategy,
+            max_tool_result_chars
         );
     }
     
@@ -275,13 +550,13 @@ pub fn sanitize_tool_result_blocks(blocks: &mut Vec<Value>) {
         
         // 压缩文本内容
         if let Some(text) = block.get("text").and_then(|v| v.as_str()) {
-            let remaining = MAX_TOOL_RESULT_CHARS.saturating_sub(used_chars);
+            let remaining = max_tool_result_chars.saturating_sub(used_chars);
             if remaining == 0 {
                 debug!("[ToolCompressor] Reached character limit, stopping");
                 break;
             }
             
-            let compacted = compact_tool_result_text(text, remaining);
+            let compacted = compress_with_strategy(strategy, text, remaining);
             let mut new_block = block.clone();
             new_block["text"] = Value::String(compacted.clone());
             cleaned_blocks.push(new_block);
@@ -297,7 +572,7 @@ pub fn sanitize_tool_result_blocks(blocks: &mut Vec<Value>) {
             used_chars += 100; // 估算非文本块大小
         }
         
-        if used_chars >= MAX_TOOL_RESULT_CHARS {
+        if used_chars >= max_tool_result_chars {
             break;
         }
     }
@@ -386,7 +661,7 @@ Please read the file locally."#;
             }),
         ];
 
-        sanitize_tool_result_blocks(&mut blocks);
+        sanitize_tool_result_blocks("unknown", &mut blocks);
 
         assert_eq!(blocks.len(), 2);
         // 第一个块应该保持原样
@@ -411,7 +686,7 @@ Please read the file locally."#;
             }),
         ];
 
-        sanitize_tool_result_blocks(&mut blocks);
+        sanitize_tool_result_blocks("unknown", &mut blocks);
 
         // 图片应该被移除,添加了提示文本
         assert_eq!(blocks.len(), 2);
@@ -437,4 +712,78 @@ Please read the file locally."#;
         });
         assert!(!is_base64_image(&text_block));
     }
+
+    #[test]
+    fn test_resolve_strategy_by_tool_name() {
+        let cfg = ToolResultCompressionConfig::default();
+        assert_eq!(resolve_strategy(&cfg, "Bash").0, ToolCompressionStrategy::HeadTail);
+        assert_eq!(resolve_strategy(&cfg, "run_tests").0, ToolCompressionStrategy::StackTrace);
+        assert_eq!(resolve_strategy(&cfg, "git_diff").0, ToolCompressionStrategy::Diff);
+        assert_eq!(resolve_strategy(&cfg, "web_search").0, ToolCompressionStrategy::JsonArray);
+        assert_eq!(
+            resolve_strategy(&cfg, "Read"),
+            (ToolCompressionStrategy::Default, 200_000)
+        );
+    }
+
+    #[test]
+    fn test_head_tail_keeps_both_ends() {
+        let text: String = (0..2_000).map(|i| format!("line {}\n", i)).collect();
+        let result = compress_with_strategy(ToolCompressionStrategy::HeadTail, &text, 2_000);
+        assert!(result.len() <= 2_000);
+        assert!(result.starts_with("line 0\n"));
+        assert!(result.trim_end().ends_with("line 1999"));
+        assert!(result.contains("...[omitted"));
+    }
+
+    #[test]
+    fn test_collapse_stack_traces() {
+        let mut text = String::from("FAIL src/app.test.js\nTypeError: boom\n");
+        for i in 0..20 {
+            text.push_str(&format!("    at fn{} (src/app.js:{}:1)\n", i, i));
+        }
+        text.push_str("Tests: 1 failed, 10 passed");
+        let collapsed = collapse_stack_traces(&text);
+        assert!(collapsed.contains("at fn0 "));
+        assert!(collapsed.contains("at fn19 "));
+        assert!(!collapsed.contains("at fn10 "));
+        assert!(collapsed.contains("15 frames collapsed"));
+        assert!(collapsed.ends_with("Tests: 1 failed, 10 passed"));
+    }
+
+    #[test]
+    fn test_drop_unchanged_diff_lines() {
+        let diff = "diff --git a/x b/x\n--- a/x\n+++ b/x\n@@ -1,5 +1,5 @@\n a\n b\n-c\n+C\n d\n";
+        let result = drop_unchanged_diff_lines(diff);
+        assert_eq!(
+            result,
+            "diff --git a/x b/x\n--- a/x\n+++ b/x\n@@ -1,5 +1,5 @@\n... 2 unchanged lines\n-c\n+C\n... 1 unchanged lines"
+        );
+    }
+
+    #[test]
+    fn test_summarize_json_array() {
+        let items: Vec<Value> = (0..500)
+            .map(|i| serde_json::json!({ "id": i, "title": format!("result number {}", i) }))
+            .collect();
+        let text = serde_json::json!({ "query": "rust", "results": items }).to_string();
+        let result = compress_with_strategy(ToolCompressionStrategy::JsonArray, &text, 2_000);
+        assert!(result.len() <= 2_000);
+        assert!(result.starts_with("[JSON array in field \"results\" with 500 items; showing first"));
+        assert!(result.contains("Item keys: id, title."));
+        let json_part = result.split_once('\n').unwrap().1;
+        let parsed: Value = serde_json::from_str(json_part).unwrap();
+        assert_eq!(parsed["query"], "rust");
+    }
+
+    #[test]
+    fn test_compress_function_response_fields() {
+        let mut fr = serde_json::json!({
+            "name": "bash",
+            "response": { "output": "x\n".repeat(40_000), "exit_code": 0 }
+        });
+        compress_function_response(&mut fr);
+        assert!(fr["response"]["output"].as_str().unwrap().len() <= 30_000);
+        assert_eq!(fr["response"]["exit_code"], 0);
+    }
 }
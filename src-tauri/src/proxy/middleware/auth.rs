@@ -2,13 +2,14 @@
 use axum::{
     extract::State,
     extract::Request,
-    http::{header, StatusCode},
+    http::{header, Method, StatusCode},
     middleware::Next,
     response::Response,
 };
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
+use crate::modules::admin_user_db::{self, AdminRole};
 use crate::proxy::{ProxyAuthMode, ProxySecurityConfig};
 
 /// API Key 认证中间件 (代理接口使用，遵循 auth_mode)
@@ -42,6 +43,8 @@ async fn auth_middleware_internal(
     // 过滤心跳和健康检查请求,避免日志噪音
     let is_health_check = path == "/healthz" || path == "/api/health" || path == "/health";
     let is_internal_endpoint = path.starts_with("/internal/");
+    // 图库图片地址使用随机 ID，与 OpenAI 返回的临时链接一样无需鉴权即可访问
+    let is_public_file = method == axum::http::Method::GET && path.starts_with("/v1/files/images/");
     if !path.contains("event_logging") && !is_health_check {
         tracing::info!("Request: {} {}", method, path);
     } else {
@@ -81,6 +84,7 @@ async fn auth_middleware_internal(
                         token_id: user_token.id,
                         token: user_token.token,
                         username: user_token.username,
+                        priority: user_token.priority,
                     };
                     // 注入 identity 到请求
                     let (mut parts, body) = request.into_parts();
@@ -102,17 +106,43 @@ async fn auth_middleware_internal(
             tracing::debug!("Internal endpoint bypassed auth: {}", path);
             return Ok(next.run(request).await);
         }
+
+        if is_public_file {
+            return Ok(next.run(request).await);
+        }
     } else {
         // 管理接口 (/api/*)
         // 1. 如果全局鉴权关闭，则管理接口也放行 (除非是强制局域网模式)
         if matches!(effective_mode, ProxyAuthMode::Off) {
-            return Ok(next.run(request).await);
+            return Ok(next.run(with_admin_identity(request, AdminIdentity::legacy_owner())).await);
         }
 
-        // 2. 健康检查在所有模式下对管理接口放行
-        if is_health_check {
+        // 2. 健康检查与登录接口在所有模式下对管理接口放行
+        if is_health_check || is_admin_login_path(&path) {
             return Ok(next.run(request).await);
         }
+
+        // 3. 多用户会话令牌 (登录后获得)
+        if let Some(session_token) = extract_bearer(&request)
+            .filter(|k| k.starts_with(admin_user_db::SESSION_TOKEN_PREFIX))
+        {
+            return match admin_user_db::validate_session(session_token) {
+                Ok(Some(user)) => {
+                    let identity = AdminIdentity {
+                        user_id: Some(user.id),
+                        username: user.username,
+                        role: user.role,
+                        source_ip: None,
+                    };
+                    authorize_admin_route(request, next, identity).await
+                }
+                Ok(None) => Err(StatusCode::UNAUTHORIZED),
+                Err(e) => {
+                    tracing::error!("Admin session validation error: {}", e);
+                    Err(StatusCode::INTERNAL_SERVER_ERROR)
+                }
+            };
+        }
     }
     
     // 从 header 中提取 API key
@@ -160,7 +190,10 @@ async fn auth_middleware_internal(
         api_key.map(|k| k == security.api_key).unwrap_or(false)
     };
 
-    if authorized {
+    if authorized && force_strict {
+        // admin_password / api_key 视为所有者 (兼容单密码模式)
+        authorize_admin_route(request, next, AdminIdentity::legacy_owner()).await
+    } else if authorized {
         Ok(next.run(request).await)
     } else if !force_strict && api_key.is_some() {
         // 尝试验证 UserToken
@@ -190,6 +223,7 @@ async fn auth_middleware_internal(
                         token_id: user_token.id,
                         token: user_token.token,
                         username: user_token.username,
+                        priority: user_token.priority,
                     };
                     
                     // [FIX] 将身份信息注入到请求 extensions 中，而不是响应
@@ -236,6 +270,133 @@ async fn auth_middleware_internal(
     }
 }
 
+/// 管理接口调用者身份 (注入到请求 extensions，供 handler 使用)
+#[derive(Clone, Debug)]
+pub struct AdminIdentity {
+    /// 使用 admin_password / api_key 登录时为 None
+    pub user_id: Option<String>,
+    pub username: String,
+    pub role: AdminRole,
+    pub source_ip: Option<String>,
+}
+
+impl AdminIdentity {
+    fn legacy_owner() -> Self {
+        Self {
+            user_id: None,
+            username: "admin".to_string(),
+            role: AdminRole::Owner,
+            source_ip: None,
+        }
+    }
+
+    /// 转换为审计日志操作者
+    pub fn to_audit_actor(&self) -> crate::modules::audit_log::AuditActor {
+        crate::modules::audit_log::AuditActor {
+            actor: self.username.clone(),
+            role: Some(self.role.as_str().to_string()),
+            source_ip: self.source_ip.clone(),
+        }
+    }
+}
+
+fn is_admin_login_path(path: &str) -> bool {
+    path == "/auth/login" || path == "/api/auth/login"
+}
+
+fn extract_bearer(request: &Request) -> Option<&str> {
+    request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|s| s.strip_prefix("Bearer ").or(Some(s)))
+        .or_else(|| {
+            request
+                .headers()
+                .get("x-api-key")
+                .and_then(|h| h.to_str().ok())
+        })
+}
+
+fn with_admin_identity(request: Request, mut identity: AdminIdentity) -> Request {
+    identity.source_ip = super::ip_filter::extract_client_ip(&request);
+    let (mut parts, body) = request.into_parts();
+    parts.extensions.insert(identity);
+    Request::from_parts(parts, body)
+}
+
+/// 管理接口所需的最低角色
+/// - GET/HEAD 默认 viewer，其余写操作默认 operator
+/// - 凭据导出/导入、账号删除、用户令牌、安全配置与管理员用户管理仅限 owner
+pub fn required_admin_role(method: &Method, path: &str) -> AdminRole {
+    const OWNER_PREFIXES: &[&str] = &[
+        "/admin-users",
+        "/user-tokens",
+        "/accounts/export",
+        "/accounts/bulk-delete",
+        "/accounts/import",
+        "/accounts/sync/db",
+        "/proxy/api-key/generate",
+        "/proxy/cli/config",
+        "/proxy/opencode/config",
+        "/proxy/droid/config",
+    ];
+
+    let path = path.strip_prefix("/api").unwrap_or(path);
+    if OWNER_PREFIXES.iter().any(|p| path.starts_with(p)) {
+        return AdminRole::Owner;
+    }
+    if path.starts_with("/auth/") {
+        return AdminRole::Viewer;
+    }
+
+    let is_read = method == Method::GET || method == Method::HEAD;
+    if is_read {
+        return AdminRole::Viewer;
+    }
+
+    match path {
+        "/config" | "/security/config" | "/system/http-api/settings" => AdminRole::Owner,
+        // POST /audit/:id/revert
+        p if p.starts_with("/audit") => AdminRole::Owner,
+        // DELETE /accounts/:accountId
+        p if method == Method::DELETE
+            && p.starts_with("/accounts/")
+            && p.matches('/').count() == 2 =>
+        {
+            AdminRole::Owner
+        }
+        _ => AdminRole::Operator,
+    }
+}
+
+async fn authorize_admin_route(
+    request: Request,
+    next: Next,
+    identity: AdminIdentity,
+) -> Result<Response, StatusCode> {
+    let required = required_admin_role(request.method(), request.uri().path());
+    if !identity.role.allows(required) {
+        tracing::warn!(
+            "Admin '{}' ({}) denied: {} {} requires {}",
+            identity.username,
+            identity.role.as_str(),
+            request.method(),
+            request.uri().path(),
+            required.as_str()
+        );
+        let body = serde_json::json!({
+            "error": format!("Permission denied: requires {} role", required.as_str())
+        });
+        return Ok(axum::response::Response::builder()
+            .status(StatusCode::FORBIDDEN)
+            .header("Content-Type", "application/json")
+            .body(axum::body::Body::from(body.to_string()))
+            .unwrap());
+    }
+    Ok(next.run(with_admin_identity(request, identity)).await)
+}
+
 /// 用户令牌身份信息 (传递给 Monitor 使用)
 #[derive(Clone, Debug)]
 pub struct UserTokenIdentity {
@@ -243,6 +404,7 @@ pub struct UserTokenIdentity {
     #[allow(dead_code)] // 保留原始 token 便于审计/调试
     pub token: String,
     pub username: String,
+    pub priority: i32, // 准入队列排队优先级
 }
 
 #[cfg(test)]
@@ -272,6 +434,25 @@ mod tests {
         // 我们在 auth_middleware_internal 基础上做了逻辑校验即可
     }
 
+    #[test]
+    fn test_required_admin_role() {
+        assert_eq!(required_admin_role(&Method::GET, "/accounts"), AdminRole::Viewer);
+        assert_eq!(required_admin_role(&Method::GET, "/api/logs"), AdminRole::Viewer);
+        assert_eq!(required_admin_role(&Method::POST, "/proxy/start"), AdminRole::Operator);
+        assert_eq!(required_admin_role(&Method::POST, "/proxy/stop"), AdminRole::Operator);
+        assert_eq!(required_admin_role(&Method::POST, "/accounts/export"), AdminRole::Owner);
+        assert_eq!(required_admin_role(&Method::GET, "/user-tokens"), AdminRole::Owner);
+        assert_eq!(required_admin_role(&Method::POST, "/config"), AdminRole::Owner);
+        assert_eq!(required_admin_role(&Method::DELETE, "/accounts/abc"), AdminRole::Owner);
+        assert_eq!(
+            required_admin_role(&Method::DELETE, "/accounts/abc/device-versions/v1"),
+            AdminRole::Operator
+        );
+        assert_eq!(required_admin_role(&Method::POST, "/auth/logout"), AdminRole::Viewer);
+        assert_eq!(required_admin_role(&Method::GET, "/audit"), AdminRole::Viewer);
+        assert_eq!(required_admin_role(&Method::POST, "/audit/1/revert"), AdminRole::Owner);
+    }
+
     #[test]
     fn test_auth_placeholder() {
         assert!(true);
@@ -1,6 +1,6 @@
 // CORS 中间件
 use tower_http::cors::{CorsLayer, Any};
-use axum::http::Method;
+use axum::http::{HeaderName, Method};
 
 /// 创建 CORS layer
 pub fn cors_layer() -> CorsLayer {
@@ -16,6 +16,7 @@ pub fn cors_layer() -> CorsLayer {
             Method::PATCH,
         ])
         .allow_headers(Any)
+        .expose_headers([HeaderName::from_static("x-next-cursor")])
         .allow_credentials(false)
         .max_age(std::time::Duration::from_secs(3600))
 }
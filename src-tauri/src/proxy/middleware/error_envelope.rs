@@ -0,0 +1,139 @@
+use crate::proxy::common::error::{extract_message, is_protocol_envelope, ApiProtocol, ProxyError};
+use axum::{
+    body::Body,
+    extract::Request,
+    http::{header, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+
+/// 规范化时最多读取的错误体大小
+const MAX_ERROR_BODY_SIZE: usize = 1024 * 1024;
+
+/// 错误信封中间件：按入口路由把错误响应渲染为对应协议 (OpenAI / Anthropic / Google) 的错误格式
+/// 位于最外层，覆盖 handler、鉴权、IP 过滤、服务状态与请求体解析失败等所有来源
+pub async fn error_envelope_middleware(request: Request, next: Next) -> Response {
+    let Some(protocol) = ApiProtocol::from_path(request.uri().path()) else {
+        return next.run(request).await;
+    };
+    let response = next.run(request).await;
+    if !response.status().is_client_error() && !response.status().is_server_error() {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    if let Some(error) = parts.extensions.remove::<ProxyError>() {
+        return with_json_body(parts, error.to_body(protocol));
+    }
+    let is_stream = parts
+        .headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map_or(false, |ct| ct.contains("text/event-stream"));
+    if is_stream {
+        return Response::from_parts(parts, body);
+    }
+
+    let message = match axum::body::to_bytes(body, MAX_ERROR_BODY_SIZE).await {
+        Ok(bytes) => {
+            if let Ok(value) = serde_json::from_slice::<serde_json::Value>(&bytes) {
+                if is_protocol_envelope(&value, protocol) {
+                    return Response::from_parts(parts, Body::from(bytes));
+                }
+            }
+            extract_message(&bytes)
+                .filter(|m| !m.trim().is_empty())
+                .unwrap_or_else(|| fallback_message(parts.status))
+        }
+        Err(e) => {
+            // 错误体过大或读取中断：原始内容已不可用，只能返回带状态码的通用信息
+            tracing::warn!(
+                "[ErrorEnvelope] Failed to read {} error body: {}",
+                parts.status,
+                e
+            );
+            fallback_message(parts.status)
+        }
+    };
+    tracing::debug!(
+        "[ErrorEnvelope] Normalized {} error body to {:?} format",
+        parts.status,
+        protocol
+    );
+    let error = ProxyError::new(parts.status, message);
+    with_json_body(parts, error.to_body(protocol))
+}
+
+/// 无法从错误体中得到信息时使用的通用信息，如 "Bad Gateway (HTTP 502)"
+fn fallback_message(status: axum::http::StatusCode) -> String {
+    match status.canonical_reason() {
+        Some(reason) => format!("{} (HTTP {})", reason, status.as_u16()),
+        None => format!("Upstream request failed (HTTP {})", status.as_u16()),
+    }
+}
+
+fn with_json_body(mut parts: axum::http::response::Parts, body: serde_json::Value) -> Response {
+    parts.headers.remove(header::CONTENT_LENGTH);
+    parts.headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+    Response::from_parts(parts, Body::from(body.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{http::StatusCode, routing::post, Router};
+    use tower::ServiceExt;
+
+    async fn envelope_for(status: StatusCode, body: fn() -> Body) -> serde_json::Value {
+        let app = Router::new()
+            .route(
+                "/v1/chat/completions",
+                post(move || async move { Response::builder().status(status).body(body()).unwrap() }),
+            )
+            .layer(axum::middleware::from_fn(error_envelope_middleware));
+        let request = Request::builder()
+            .method("POST")
+            .uri("/v1/chat/completions")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), status);
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_unreadable_body_gets_generic_message() {
+        let broken = || {
+            Body::from_stream(futures::stream::iter(vec![Err::<bytes::Bytes, _>(
+                std::io::Error::other("connection reset"),
+            )]))
+        };
+        let envelope = envelope_for(StatusCode::BAD_GATEWAY, broken).await;
+        assert_eq!(envelope["error"]["message"], "Bad Gateway (HTTP 502)");
+
+        let oversized = || Body::from(vec![b'x'; MAX_ERROR_BODY_SIZE + 1]);
+        let envelope = envelope_for(StatusCode::from_u16(529).unwrap(), oversized).await;
+        assert_eq!(
+            envelope["error"]["message"],
+            "Upstream request failed (HTTP 529)"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_plain_text_body_is_wrapped() {
+        let envelope = envelope_for(StatusCode::NOT_FOUND, || Body::from("model not found")).await;
+        assert_eq!(envelope["error"]["message"], "model not found");
+
+        let envelope = envelope_for(StatusCode::INTERNAL_SERVER_ERROR, Body::empty).await;
+        assert_eq!(
+            envelope["error"]["message"],
+            "Internal Server Error (HTTP 500)"
+        );
+    }
+}
@@ -144,29 +144,34 @@ pub async fn ip_filter_middleware(
 }
 
 /// 从请求中提取客户端 IP
-fn extract_client_ip(request: &Request) -> Option<String> {
+pub(crate) fn extract_client_ip(request: &Request) -> Option<String> {
+    let remote_addr = request
+        .extensions()
+        .get::<axum::extract::ConnectInfo<std::net::SocketAddr>>()
+        .map(|info| info.0);
+    client_ip_from(request.headers(), remote_addr)
+}
+
+/// 从请求头与 TCP 连接地址中提取客户端 IP (供已拆分出请求头的处理器使用)
+pub(crate) fn client_ip_from(
+    headers: &axum::http::HeaderMap,
+    remote_addr: Option<std::net::SocketAddr>,
+) -> Option<String> {
     // 1. 优先从 X-Forwarded-For 提取 (取第一个 IP)
-    request
-        .headers()
+    headers
         .get("x-forwarded-for")
         .and_then(|v| v.to_str().ok())
         .map(|s| s.split(',').next().unwrap_or(s).trim().to_string())
         .or_else(|| {
             // 2. 备选从 X-Real-IP 提取
-            request
-                .headers()
+            headers
                 .get("x-real-ip")
                 .and_then(|v| v.to_str().ok())
                 .map(|s| s.to_string())
         })
-        .or_else(|| {
-            // 3. 最后尝试从 ConnectInfo 获取 (TCP 连接 IP)
-            // 这可以解决本地开发/测试时没有代理头导致 IP 获取失败的问题
-            request
-                .extensions()
-                .get::<axum::extract::ConnectInfo<std::net::SocketAddr>>()
-                .map(|info| info.0.ip().to_string())
-        })
+        // 3. 最后使用 ConnectInfo (TCP 连接 IP)
+        // 这可以解决本地开发/测试时没有代理头导致 IP 获取失败的问题
+        .or_else(|| remote_addr.map(|addr| addr.ip().to_string()))
 }
 
 /// 创建被封禁的响应
@@ -2,6 +2,7 @@
 
 pub mod auth;
 pub mod cors;
+pub mod error_envelope;
 pub mod logging;
 pub mod monitor;
 pub mod ip_filter;
@@ -9,7 +10,8 @@ pub mod ip_filter;
 pub mod service_status;
 
 pub use cors::cors_layer;
+pub use error_envelope::error_envelope_middleware;
 pub use monitor::monitor_middleware;
 pub use service_status::service_status_middleware;
-pub use auth::{auth_middleware, admin_auth_middleware};
+pub use auth::{auth_middleware, admin_auth_middleware, AdminIdentity};
 pub use ip_filter::ip_filter_middleware;
@@ -1,8 +1,9 @@
 use axum::{
     extract::{Request, State},
     middleware::Next,
-    response::Response,
+    response::{IntoResponse, Response},
     body::Body,
+    http::StatusCode,
 };
 use std::time::Instant;
 use crate::proxy::server::AppState;
@@ -10,10 +11,20 @@ use crate::proxy::monitor::ProxyRequestLog;
 use serde_json::Value;
 use crate::proxy::middleware::auth::UserTokenIdentity;
 use futures::StreamExt;
+use crate::proxy::common::{image_preprocess, retry_budget};
+use crate::proxy::{account_load, admission, fair_share};
+use crate::proxy::upstream::resume;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
 
 const MAX_REQUEST_LOG_SIZE: usize = 100 * 1024 * 1024; // 100MB
 const MAX_RESPONSE_LOG_SIZE: usize = 100 * 1024 * 1024; // 100MB for image responses
 
+/// 读取数值型响应头 (用于无法在响应体中携带 usage 的音频接口)
+fn header_u32(headers: &axum::http::HeaderMap, name: &str) -> Option<u32> {
+    headers.get(name)?.to_str().ok()?.parse().ok()
+}
+
 /// Helper function to record User Token usage
 fn record_user_token_usage(
     user_token_identity: &Option<UserTokenIdentity>,
@@ -43,7 +54,14 @@ pub async fn monitor_middleware(
     let method = request.method().to_string();
     let uri = request.uri().to_string();
     
-    if uri.contains("event_logging") || uri.contains("/api/") || uri.starts_with("/internal/") {
+    if uri.contains("event_logging")
+        || uri.contains("/api/")
+        || uri.starts_with("/internal/")
+        || uri.starts_with("/v1/files/")
+        || uri.starts_with("/upload/")
+        || uri.starts_with("/v1beta/files")
+        || uri.starts_with("/v1beta/cachedContents")
+    {
         return next.run(request).await;
     }
     
@@ -112,7 +130,60 @@ pub async fn monitor_middleware(
         request
     };
     
-    let response = next.run(request).await;
+    // 按用户令牌 (无令牌时按客户端 IP) 获取公平调度许可，持有到响应 (含 SSE 流) 结束
+    let fair_cfg = crate::proxy::config::get_fair_scheduling_config();
+    let (flow, weight) = match &user_token_identity {
+        Some(identity) => (
+            format!("token:{}", identity.token_id),
+            fair_cfg.weight_for(&identity.token_id, Some(&identity.username)),
+        ),
+        None => (
+            format!("ip:{}", client_ip.as_deref().unwrap_or("local")),
+            fair_cfg.default_weight.max(1),
+        ),
+    };
+    let fair_permit = match fair_share::acquire(&flow, weight, &fair_cfg).await {
+        Ok(permit) => permit,
+        Err(e) => {
+            tracing::warn!("[Fair-Share] Rejected request from {}: {}", flow, e);
+            return (
+                StatusCode::TOO_MANY_REQUESTS,
+                axum::Json(serde_json::json!({
+                    "error": {
+                        "type": "rate_limit_error",
+                        "message": e,
+                    }
+                })),
+            )
+                .into_response();
+        }
+    };
+
+    // 每个代理请求向全局重试预算存入令牌，并统计本次请求的上游重试次数
+    retry_budget::record_request();
+    let retry_counter = Arc::new(AtomicU32::new(0));
+    // 流式响应中断续传次数 (续传发生在 SSE 流消费期间，流结束后再写入日志)
+    let resume_counter = Arc::new(AtomicU32::new(0));
+    // 图片预处理节省量 (handler 解析请求时累计)
+    let image_savings = Arc::new(image_preprocess::ImageSavings::default());
+    // 账号租约槽位：持有到响应 (含 SSE 流) 结束，用于统计账号在途请求数
+    let account_lease = account_load::new_slot();
+    // 准入队列按用户令牌优先级排队
+    let priority = user_token_identity.as_ref().map_or(0, |identity| identity.priority);
+    let response = account_load::scope_request(
+        account_lease.clone(),
+        admission::scope_priority(
+            priority,
+            retry_budget::scope_request(
+                retry_counter.clone(),
+                resume::scope_request(
+                    resume_counter.clone(),
+                    image_preprocess::scope_request(image_savings.clone(), next.run(request)),
+                ),
+            ),
+        ),
+    )
+    .await;
     
     // user_token_identity 已在上面从请求 extensions 中提取
     
@@ -173,6 +244,10 @@ pub async fn monitor_middleware(
         output_tokens: None,
         protocol,
         username,
+        retries: Some(retry_counter.load(Ordering::Relaxed)),
+        resumes: Some(resume_counter.load(Ordering::Relaxed)),
+        image_bytes_saved: (image_savings.images() > 0).then(|| image_savings.bytes()),
+        image_tokens_saved: (image_savings.images() > 0).then(|| image_savings.tokens()),
     };
 
 
@@ -426,6 +501,12 @@ pub async fn monitor_middleware(
                 }
             }
             
+            log.resumes = Some(resume_counter.load(Ordering::Relaxed));
+
+            // 上游流已结束，释放账号租约与公平调度许可
+            drop(account_lease);
+            drop(fair_permit);
+
             if log.status >= 400 {
                 log.error = Some("Stream Error or Failed".to_string());
             }
@@ -464,6 +545,11 @@ pub async fn monitor_middleware(
                             }
                         }
                     }
+                    // 纯文本响应 (如 srt/vtt 转录) 通过响应头上报用量
+                    if log.input_tokens.is_none() && log.output_tokens.is_none() {
+                        log.input_tokens = header_u32(&parts.headers, "X-Usage-Input-Tokens");
+                        log.output_tokens = header_u32(&parts.headers, "X-Usage-Output-Tokens");
+                    }
                     log.response_body = Some(s.to_string());
                 } else {
                     log.response_body = Some("[Binary Response Data]".to_string());
@@ -491,6 +577,9 @@ pub async fn monitor_middleware(
         }
     } else {
         log.response_body = Some(format!("[{}]", content_type));
+        // 二进制响应 (如 TTS 音频) 通过响应头上报用量
+        log.input_tokens = header_u32(response.headers(), "X-Usage-Input-Tokens");
+        log.output_tokens = header_u32(response.headers(), "X-Usage-Output-Tokens");
 
         // Record User Token Usage
         record_user_token_usage(&user_token_identity, &log, user_agent);
@@ -8,9 +8,14 @@ pub mod server;
 pub mod token_manager;
 
 // 新架构模块
+pub mod account_load; // 账号在途请求与延迟跟踪
+pub mod admission; // 全局准入队列
 pub mod audio; // 音频处理模块
 pub mod cli_sync; // CLI 配置同步 (v3.3.35)
 pub mod droid_sync; // Droid (Factory CLI) 配置同步
+pub mod fair_share; // 用户令牌间的加权公平调度
+pub mod hedging; // 非流式短请求对冲
+pub mod login_throttle; // 管理员登录失败限速
 pub mod common; // 公共工具
 pub mod debug_logger;
 pub mod handlers; // API 端点处理器
@@ -31,6 +36,17 @@ pub mod zai_vision_tools; // Built-in Vision MCP tools (z.ai vision API) // 调
 pub use config::update_global_system_prompt_config;
 pub use config::update_thinking_budget_config;
 pub use config::update_image_thinking_mode;
+pub use config::update_log_retention_config;
+pub use config::update_retry_policy_config;
+pub use config::update_upstream_endpoints_config;
+pub use config::update_fair_scheduling_config;
+pub use config::update_hedging_config;
+pub use config::update_stream_heartbeat_config;
+pub use config::update_auto_continue_config;
+pub use config::update_tool_result_compression_config;
+pub use config::update_image_preprocess_config;
+pub use config::update_image_url_config;
+pub use config::update_image_store_config;
 pub use config::ProxyAuthMode;
 pub use config::ProxyConfig;
 pub use config::ProxyPoolConfig;
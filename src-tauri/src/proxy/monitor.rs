@@ -23,6 +23,14 @@ pub struct ProxyRequestLog {
     pub output_tokens: Option<u32>,
     pub protocol: Option<String>,     // 协议类型: "openai", "anthropic", "gemini"
     pub username: Option<String>,     // User token username
+    #[serde(default)]
+    pub retries: Option<u32>,         // 本次请求发生的上游重试次数
+    #[serde(default)]
+    pub resumes: Option<u32>,         // 流式响应中断后的续传次数
+    #[serde(default)]
+    pub image_bytes_saved: Option<u32>,  // 图片预处理节省的字节数
+    #[serde(default)]
+    pub image_tokens_saved: Option<u32>, // 图片预处理节省的估算 token 数
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -47,19 +55,8 @@ impl ProxyMonitor {
             tracing::error!("Failed to initialize proxy DB: {}", e);
         }
 
-        // Auto cleanup old logs (keep last 30 days)
-        tokio::spawn(async {
-            match crate::modules::proxy_db::cleanup_old_logs(30) {
-                Ok(deleted) => {
-                    if deleted > 0 {
-                        tracing::info!("Auto cleanup: removed {} old logs (>30 days)", deleted);
-                    }
-                }
-                Err(e) => {
-                    tracing::error!("Failed to cleanup old logs: {}", e);
-                }
-            }
-        });
+        // Periodic retention cleanup / VACUUM (per-table retention from LogRetentionConfig)
+        crate::modules::log_retention::start_maintenance_loop();
 
         Self {
             logs: RwLock::new(VecDeque::with_capacity(max_logs)),
@@ -78,7 +75,7 @@ impl ProxyMonitor {
         self.enabled.load(Ordering::Relaxed)
     }
 
-    pub async fn log_request(&self, log: ProxyRequestLog) {
+    pub async fn log_request(&self, mut log: ProxyRequestLog) {
         if let (Some(account), Some(input), Some(output)) = (
             &log.account_email,
             log.input_tokens,
@@ -97,6 +94,10 @@ impl ProxyMonitor {
             return;
         }
         tracing::info!("[Monitor] Logging request: {} {}", log.method, log.url);
+
+        // Apply body sampling / truncation before the log is kept anywhere
+        let retention = crate::proxy::config::get_log_retention_config();
+        crate::modules::log_retention::apply_body_policy(&mut log, &retention);
         // Update stats
         {
             let mut stats = self.stats.write().await;
@@ -179,6 +180,10 @@ impl ProxyMonitor {
                 output_tokens: log.output_tokens,
                 protocol: log.protocol.clone(),
                 username: log.username.clone(),
+                retries: log.retries,
+                resumes: log.resumes,
+                image_bytes_saved: log.image_bytes_saved,
+                image_tokens_saved: log.image_tokens_saved,
             };
             let _ = app.emit("proxy://request", &log_summary);
         }
@@ -1,5 +1,9 @@
 use crate::models::AppConfig;
 use crate::modules::{account, config, logger, migration, proxy_db, security_db, token_stats};
+use crate::modules::admin_user_db::{self, AdminRole};
+use crate::modules::audit_log::{self, AuditActor};
+use crate::modules::image_store;
+use crate::proxy::middleware::AdminIdentity;
 use crate::proxy::TokenManager;
 use axum::{
     extract::{DefaultBodyLimit, Path, Query, State},
@@ -340,6 +344,7 @@ impl AxumServer {
                 if user_agent_override.is_some() {
                     u.set_user_agent_override(user_agent_override).await;
                 }
+                u.start_health_probe();
                 u
             },
             zai: zai_state.clone(),
@@ -364,8 +369,8 @@ impl AxumServer {
         // 构建路由 - 使用新架构的 handlers！
         use crate::proxy::handlers;
         use crate::proxy::middleware::{
-            admin_auth_middleware, auth_middleware, cors_layer, ip_filter_middleware,
-            monitor_middleware, service_status_middleware,
+            admin_auth_middleware, auth_middleware, cors_layer, error_envelope_middleware,
+            ip_filter_middleware, monitor_middleware, service_status_middleware,
         };
 
         // 1. 构建主 AI 代理路由 (遵循 auth_mode 配置)
@@ -395,6 +400,15 @@ impl AxumServer {
                 "/v1/audio/transcriptions",
                 post(handlers::audio::handle_audio_transcription),
             ) // 音频转录 API
+            .route(
+                "/v1/audio/translations",
+                post(handlers::audio::handle_audio_translation),
+            ) // 音频翻译 API
+            .route("/v1/audio/speech", post(handlers::audio::handle_audio_speech)) // 语音合成 API
+            .route(
+                "/v1/files/images/:id",
+                get(handlers::files::handle_get_image),
+            ) // 图库图片
             // Claude Protocol
             .route("/v1/messages", post(handlers::claude::handle_messages))
             .route(
@@ -426,6 +440,30 @@ impl AxumServer {
                 "/v1beta/models/:model/countTokens",
                 post(handlers::gemini::handle_count_tokens),
             ) // Specific route priority
+            .route(
+                "/v1beta/files",
+                get(handlers::gemini_resources::handle_list_files),
+            )
+            .route(
+                "/v1beta/files/:id",
+                get(handlers::gemini_resources::handle_get_file)
+                    .delete(handlers::gemini_resources::handle_delete_file),
+            )
+            .route(
+                "/upload/v1beta/files",
+                post(handlers::gemini_resources::handle_upload_file),
+            ) // 文件上传 (resumable / multipart / raw)
+            .route(
+                "/v1beta/cachedContents",
+                get(handlers::gemini_resources::handle_list_cached_contents)
+                    .post(handlers::gemini_resources::handle_create_cached_content),
+            )
+            .route(
+                "/v1beta/cachedContents/:id",
+                get(handlers::gemini_resources::handle_get_cached_content)
+                    .patch(handlers::gemini_resources::handle_update_cached_content)
+                    .delete(handlers::gemini_resources::handle_delete_cached_content),
+            )
             .route(
                 "/v1/models/detect",
                 post(handlers::common::handle_detect_model),
@@ -561,9 +599,22 @@ impl AxumServer {
             .route("/proxy/cloudflared/stop", post(admin_cloudflared_stop))
             .route("/system/open-folder", post(admin_open_folder))
             .route("/proxy/stats", get(admin_get_proxy_stats))
+            .route("/proxy/upstream-endpoints", get(admin_get_upstream_endpoints))
+            .route("/proxy/account-load", get(admin_get_account_load))
+            .route("/proxy/admission-queue", get(admin_get_admission_queue))
+            .route("/proxy/fair-share", get(admin_get_fair_share))
+            .route("/proxy/hedging", get(admin_get_hedging))
             .route("/logs", get(admin_get_proxy_logs_filtered))
             .route("/logs/count", get(admin_get_proxy_logs_count_filtered))
             .route("/logs/clear", post(admin_clear_proxy_logs))
+            .route("/logs/export", get(admin_export_proxy_logs))
+            // 导入以流式读取请求体，不受全局请求体大小限制
+            .route(
+                "/logs/import",
+                post(admin_import_proxy_logs).layer(DefaultBodyLimit::disable()),
+            )
+            .route("/logs/storage", get(admin_get_log_storage_usage))
+            .route("/logs/maintenance", post(admin_run_log_maintenance))
             .route("/logs/:logId", get(admin_get_proxy_log_detail))
             // Debug Console (Log Bridge)
             .route("/debug/enable", post(admin_enable_debug_console))
@@ -650,6 +701,22 @@ impl AxumServer {
             .route("/user-tokens/summary", get(admin_get_user_token_summary))
             .route("/user-tokens/:id/renew", post(admin_renew_user_token))
             .route("/user-tokens/:id", delete(admin_delete_user_token).patch(admin_update_user_token))
+            // Audit Log
+            .route("/audit", get(admin_query_audit_log))
+            .route("/audit/:id/revert", post(admin_revert_config))
+            // Generated Image Gallery
+            .route("/images", get(admin_list_images).delete(admin_delete_images))
+            .route("/images/:id", delete(admin_delete_image))
+            .route("/images/:id/content", get(admin_get_image_content))
+            // Admin Users & Sessions
+            .route("/auth/login", post(admin_login))
+            .route("/auth/logout", post(admin_logout))
+            .route("/auth/me", get(admin_get_current_identity))
+            .route("/admin-users", get(admin_list_admin_users).post(admin_create_admin_user))
+            .route(
+                "/admin-users/:id",
+                delete(admin_delete_admin_user).patch(admin_update_admin_user),
+            )
             // OAuth (Web) - Admin 接口
             .route("/auth/url", get(admin_prepare_oauth_url_web))
             // 应用管理特定鉴权层 (强制校验)
@@ -676,6 +743,8 @@ impl AxumServer {
                 state.clone(),
                 service_status_middleware,
             ))
+            // 按入口协议统一错误响应格式
+            .layer(axum::middleware::from_fn(error_envelope_middleware))
             .layer(cors_layer())
             .layer(DefaultBodyLimit::max(max_body_size)) // 放宽 body 大小限制
             .with_state(state.clone());
@@ -870,6 +939,7 @@ struct ExportAccountsRequest {
 
 async fn admin_export_accounts(
     State(_state): State<AppState>,
+    identity: Option<axum::Extension<AdminIdentity>>,
     Json(payload): Json<ExportAccountsRequest>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
     let response = account::export_accounts_by_ids(&payload.account_ids).map_err(|e| {
@@ -878,6 +948,11 @@ async fn admin_export_accounts(
             Json(ErrorResponse { error: e }),
         )
     })?;
+    audit_log::record(
+        &audit_actor(&identity),
+        "account.export",
+        Some(&payload.account_ids.join(",")),
+    );
 
     Ok(Json(response))
 }
@@ -945,6 +1020,7 @@ struct AddAccountRequest {
 
 async fn admin_add_account(
     State(state): State<AppState>,
+    identity: Option<axum::Extension<AdminIdentity>>,
     Json(payload): Json<AddAccountRequest>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
     let account = state
@@ -957,6 +1033,7 @@ async fn admin_add_account(
                 Json(ErrorResponse { error: e }),
             )
         })?;
+    audit_log::record(&audit_actor(&identity), "account.add", Some(&account.email));
 
     // [FIX #1166] 账号变动后立即重新加载 TokenManager
     if let Err(e) = state.token_manager.load_accounts().await {
@@ -977,6 +1054,7 @@ async fn admin_add_account(
 
 async fn admin_delete_account(
     State(state): State<AppState>,
+    identity: Option<axum::Extension<AdminIdentity>>,
     Path(account_id): Path<String>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
     state
@@ -988,6 +1066,7 @@ async fn admin_delete_account(
                 Json(ErrorResponse { error: e }),
             )
         })?;
+    audit_log::record(&audit_actor(&identity), "account.delete", Some(&account_id));
 
     // [FIX #1166] 账号变动后立即重新加载 TokenManager
     if let Err(e) = state.token_manager.load_accounts().await {
@@ -1236,13 +1315,26 @@ async fn admin_get_logs(
     })))
 }
 
-async fn admin_get_config() -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
-    let cfg = config::load_app_config().map_err(|e| {
+async fn admin_get_config(
+    identity: Option<axum::Extension<AdminIdentity>>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+    let mut cfg = config::load_app_config().map_err(|e| {
         (
             StatusCode::INTERNAL_SERVER_ERROR,
             Json(ErrorResponse { error: e }),
         )
     })?;
+
+    // 非 owner 角色不返回凭据类字段
+    let is_owner = identity
+        .map(|axum::Extension(id)| id.role == AdminRole::Owner)
+        .unwrap_or(true);
+    if !is_owner {
+        cfg.proxy.api_key = String::new();
+        cfg.proxy.admin_password = None;
+        cfg.proxy.zai.api_key = String::new();
+        cfg.cloudflared.token = None;
+    }
     Ok(Json(cfg))
 }
 
@@ -1252,11 +1344,26 @@ struct SaveConfigWrapper {
     config: AppConfig,
 }
 
+/// 从请求身份构造审计操作者
+fn audit_actor(identity: &Option<axum::Extension<AdminIdentity>>) -> AuditActor {
+    identity
+        .as_ref()
+        .map(|axum::Extension(id)| id.to_audit_actor())
+        .unwrap_or_else(|| AuditActor {
+            actor: "admin".to_string(),
+            role: None,
+            source_ip: None,
+        })
+}
+
 async fn admin_save_config(
     State(state): State<AppState>,
+    identity: Option<axum::Extension<AdminIdentity>>,
     Json(payload): Json<SaveConfigWrapper>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
     let new_config = payload.config;
+    let old_config = config::load_app_config().ok();
+
     // 1. 持久化
     config::save_app_config(&new_config).map_err(|e| {
         (
@@ -1265,7 +1372,18 @@ async fn admin_save_config(
         )
     })?;
 
+    if let Some(old) = old_config.as_ref() {
+        audit_log::record_config_change(&audit_actor(&identity), "config.save", None, old, &new_config);
+    }
+
     // 2. 热更新内存状态
+    apply_config_to_state(&state, &new_config).await;
+
+    Ok(StatusCode::OK)
+}
+
+/// 将配置热更新到运行中的反代状态
+async fn apply_config_to_state(state: &AppState, new_config: &AppConfig) {
     // 这里我们直接复用内部组件的 update 方法
     // 注意：AppState 本身持有各个组件的 Arc<RwLock> 或直接持有引用
 
@@ -1276,13 +1394,13 @@ async fn admin_save_config(
     // 更新模型映射
     {
         let mut mapping = state.custom_mapping.write().await;
-        *mapping = new_config.clone().proxy.custom_mapping;
+        *mapping = new_config.proxy.custom_mapping.clone();
     }
 
     // 更新上游代理
     {
         let mut proxy = state.upstream_proxy.write().await;
-        *proxy = new_config.clone().proxy.upstream_proxy;
+        *proxy = new_config.proxy.upstream_proxy.clone();
     }
 
     // 更新安全策略
@@ -1294,22 +1412,210 @@ async fn admin_save_config(
     // 更新 z.ai 配置
     {
         let mut zai = state.zai.write().await;
-        *zai = new_config.clone().proxy.zai;
+        *zai = new_config.proxy.zai.clone();
     }
 
     // 更新实验性配置
     {
         let mut exp = state.experimental.write().await;
-        *exp = new_config.clone().proxy.experimental;
+        *exp = new_config.proxy.experimental.clone();
     }
 
     // 更新代理池配置（Web/Docker 保存配置时热更新）
     {
         let mut pool = state.proxy_pool_state.write().await;
-        *pool = new_config.clone().proxy.proxy_pool;
+        *pool = new_config.proxy.proxy_pool.clone();
     }
 
-    Ok(StatusCode::OK)
+    // 更新日志保留策略
+    crate::proxy::update_log_retention_config(new_config.proxy.log_retention.clone());
+    // 更新准入队列配置
+    state
+        .token_manager
+        .update_admission_config(new_config.proxy.admission_queue.clone())
+        .await;
+    // 更新重试策略
+    crate::proxy::update_retry_policy_config(new_config.proxy.retry_policy.clone());
+    // 更新上游端点配置
+    crate::proxy::update_upstream_endpoints_config(new_config.proxy.upstream_endpoints.clone());
+    // 更新公平调度配置
+    crate::proxy::update_fair_scheduling_config(new_config.proxy.fair_scheduling.clone());
+    crate::proxy::update_hedging_config(new_config.proxy.hedging.clone());
+    crate::proxy::update_stream_heartbeat_config(new_config.proxy.stream_heartbeat.clone());
+    crate::proxy::update_auto_continue_config(new_config.proxy.auto_continue.clone());
+    crate::proxy::update_tool_result_compression_config(new_config.proxy.tool_result_compression.clone());
+    crate::proxy::update_image_preprocess_config(new_config.proxy.image_preprocess.clone());
+    crate::proxy::update_image_url_config(new_config.proxy.image_url.clone());
+    crate::proxy::update_image_store_config(new_config.proxy.image_store.clone());
+}
+
+/// 查询图库图片 (可按用户令牌 / 用户名过滤)
+async fn admin_list_images(
+    Query(query): Query<image_store::ImageQuery>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+    match tokio::task::spawn_blocking(move || image_store::query(&query)).await {
+        Ok(Ok(records)) => Ok(Json(records)),
+        Ok(Err(e)) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse { error: e }),
+        )),
+        Err(e) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                error: e.to_string(),
+            }),
+        )),
+    }
+}
+
+/// 管理端预览图片内容
+async fn admin_get_image_content(
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+    match tokio::task::spawn_blocking(move || image_store::get_image(&id)).await {
+        Ok(Ok(Some((record, bytes)))) => Ok((
+            [(axum::http::header::CONTENT_TYPE, record.mime_type)],
+            bytes,
+        )),
+        Ok(Ok(None)) => Err((
+            StatusCode::NOT_FOUND,
+            Json(ErrorResponse {
+                error: "Image not found".to_string(),
+            }),
+        )),
+        Ok(Err(e)) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse { error: e }),
+        )),
+        Err(e) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                error: e.to_string(),
+            }),
+        )),
+    }
+}
+
+/// 删除单张图片
+async fn admin_delete_image(
+    identity: Option<axum::Extension<AdminIdentity>>,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+    let target = id.clone();
+    match tokio::task::spawn_blocking(move || image_store::delete_image(&id)).await {
+        Ok(Ok(true)) => {
+            audit_log::record(&audit_actor(&identity), "image.delete", Some(&target));
+            Ok(StatusCode::NO_CONTENT)
+        }
+        Ok(Ok(false)) => Err((
+            StatusCode::NOT_FOUND,
+            Json(ErrorResponse {
+                error: "Image not found".to_string(),
+            }),
+        )),
+        Ok(Err(e)) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse { error: e }),
+        )),
+        Err(e) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                error: e.to_string(),
+            }),
+        )),
+    }
+}
+
+#[derive(Deserialize)]
+struct DeleteImagesQuery {
+    token_id: String,
+}
+
+/// 删除某个用户令牌生成的全部图片
+async fn admin_delete_images(
+    identity: Option<axum::Extension<AdminIdentity>>,
+    Query(query): Query<DeleteImagesQuery>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+    let token_id = query.token_id;
+    if token_id.trim().is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse {
+                error: "token_id is required".to_string(),
+            }),
+        ));
+    }
+    let target = token_id.clone();
+    match tokio::task::spawn_blocking(move || image_store::delete_by_token(&token_id)).await {
+        Ok(Ok(deleted)) => {
+            audit_log::record(&audit_actor(&identity), "image.delete_by_token", Some(&target));
+            Ok(Json(serde_json::json!({ "deleted": deleted })))
+        }
+        Ok(Err(e)) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse { error: e }),
+        )),
+        Err(e) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                error: e.to_string(),
+            }),
+        )),
+    }
+}
+
+/// 查询审计日志
+async fn admin_query_audit_log(
+    Query(query): Query<audit_log::AuditQuery>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+    let res = tokio::task::spawn_blocking(move || audit_log::query(&query)).await;
+    match res {
+        Ok(Ok(records)) => Ok(Json(records)),
+        Ok(Err(e)) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse { error: e }),
+        )),
+        Err(e) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                error: e.to_string(),
+            }),
+        )),
+    }
+}
+
+/// 将配置回滚到某条审计记录变更之前的版本
+async fn admin_revert_config(
+    State(state): State<AppState>,
+    identity: Option<axum::Extension<AdminIdentity>>,
+    Path(id): Path<i64>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+    let target = audit_log::get_config_before(id)
+        .map_err(|e| (StatusCode::BAD_REQUEST, Json(ErrorResponse { error: e })))?;
+    let current = config::load_app_config().map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse { error: e }),
+        )
+    })?;
+
+    config::save_app_config(&target).map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse { error: e }),
+        )
+    })?;
+    audit_log::record_config_change(
+        &audit_actor(&identity),
+        "config.revert",
+        Some(&id.to_string()),
+        &current,
+        &target,
+    );
+    apply_config_to_state(&state, &target).await;
+
+    logger::log_info(&format!("[API] 配置已回滚到审计记录 #{} 之前的版本", id));
+    Ok(Json(target))
 }
 
 // [FIX Web Mode] Get proxy pool config
@@ -1445,6 +1751,7 @@ struct UpdateMappingWrapper {
 
 async fn admin_update_model_mapping(
     State(state): State<AppState>,
+    identity: Option<axum::Extension<AdminIdentity>>,
     Json(payload): Json<UpdateMappingWrapper>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
     let config = payload.config;
@@ -1464,6 +1771,7 @@ async fn admin_update_model_mapping(
         )
     })?;
 
+    let old_config = app_config.clone();
     app_config.proxy.custom_mapping = config.custom_mapping;
 
     crate::modules::config::save_app_config(&app_config).map_err(|e| {
@@ -1472,6 +1780,13 @@ async fn admin_update_model_mapping(
             Json(ErrorResponse { error: e }),
         )
     })?;
+    audit_log::record_config_change(
+        &audit_actor(&identity),
+        "config.model_mapping",
+        None,
+        &old_config,
+        &app_config,
+    );
 
     logger::log_info("[API] 模型映射已通过 API 热更新并保存");
     Ok(StatusCode::OK)
@@ -1615,12 +1930,9 @@ async fn admin_set_proxy_monitor_enabled(
 }
 
 async fn admin_get_proxy_logs_count_filtered(
-    Query(params): Query<LogsRequest>,
+    Query(params): Query<proxy_db::LogQuery>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
-    let res = tokio::task::spawn_blocking(move || {
-        proxy_db::get_logs_count_filtered(&params.filter, params.errors_only)
-    })
-    .await;
+    let res = tokio::task::spawn_blocking(move || proxy_db::count_logs(&params)).await;
 
     match res {
         Ok(Ok(count)) => Ok(Json(count)),
@@ -1648,6 +1960,153 @@ async fn admin_clear_proxy_logs() -> impl IntoResponse {
     StatusCode::OK
 }
 
+/// 将同步 Write 转为 HTTP 流式响应 (在 spawn_blocking 中使用)
+struct ChannelWriter {
+    tx: tokio::sync::mpsc::Sender<Result<bytes::Bytes, std::io::Error>>,
+    buf: Vec<u8>,
+}
+
+impl std::io::Write for ChannelWriter {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        self.buf.extend_from_slice(data);
+        if self.buf.len() >= 64 * 1024 {
+            self.flush()?;
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        let chunk = bytes::Bytes::from(std::mem::take(&mut self.buf));
+        self.tx.blocking_send(Ok(chunk)).map_err(|_| {
+            std::io::Error::new(std::io::ErrorKind::BrokenPipe, "client disconnected")
+        })
+    }
+}
+
+#[derive(Deserialize)]
+struct LogExportParams {
+    #[serde(default)]
+    format: Option<crate::modules::log_export::LogExportFormat>,
+}
+
+/// 流式导出过滤后的日志 (?format=jsonl|csv|har|json，其余参数同 /logs)
+async fn admin_export_proxy_logs(
+    Query(export): Query<LogExportParams>,
+    Query(query): Query<proxy_db::LogQuery>,
+) -> Response {
+    let format = export
+        .format
+        .unwrap_or(crate::modules::log_export::LogExportFormat::Jsonl);
+    let (tx, rx) = tokio::sync::mpsc::channel::<Result<bytes::Bytes, std::io::Error>>(8);
+
+    tokio::task::spawn_blocking(move || {
+        let writer = ChannelWriter {
+            tx: tx.clone(),
+            buf: Vec::new(),
+        };
+        match crate::modules::log_export::export_logs(&query, format, writer) {
+            Ok(count) => debug!("[API] 已导出 {} 条反代日志", count),
+            Err(e) => {
+                error!("[API] 导出反代日志失败: {}", e);
+                let _ = tx.blocking_send(Err(std::io::Error::new(std::io::ErrorKind::Other, e)));
+            }
+        }
+    });
+
+    let filename = format!(
+        "proxy_logs_{}.{}",
+        chrono::Local::now().format("%Y%m%d_%H%M%S"),
+        format.extension()
+    );
+    (
+        [
+            (axum::http::header::CONTENT_TYPE, format.content_type().to_string()),
+            (
+                axum::http::header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", filename),
+            ),
+        ],
+        axum::body::Body::from_stream(tokio_stream::wrappers::ReceiverStream::new(rx)),
+    )
+        .into_response()
+}
+
+/// 导入 JSONL 格式的日志导出文件 (请求体为文件内容)
+async fn admin_import_proxy_logs(
+    body: axum::body::Body,
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+    // 逐行读取请求体并分批写库，导出文件无需整体载入内存
+    let stream = futures::TryStreamExt::map_err(body.into_data_stream(), std::io::Error::other);
+    let reader = tokio_util::io::StreamReader::new(stream);
+    let res = tokio::task::spawn_blocking(move || {
+        let reader = std::io::BufReader::new(tokio_util::io::SyncIoBridge::new(reader));
+        crate::modules::log_export::import_jsonl(reader)
+    })
+    .await;
+
+    match res {
+        Ok(Ok(report)) => {
+            logger::log_info(&format!(
+                "[API] 已导入 {} 条反代日志 (重复 {}, 无效 {})",
+                report.imported, report.duplicates, report.invalid_lines
+            ));
+            Ok(Json(report))
+        }
+        Ok(Err(e)) => Err((
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse { error: e }),
+        )),
+        Err(e) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                error: e.to_string(),
+            }),
+        )),
+    }
+}
+
+async fn admin_get_log_storage_usage(
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+    let res = tokio::task::spawn_blocking(crate::modules::log_retention::get_storage_usage).await;
+
+    match res {
+        Ok(Ok(usage)) => Ok(Json(usage)),
+        Ok(Err(e)) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse { error: e }),
+        )),
+        Err(e) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                error: e.to_string(),
+            }),
+        )),
+    }
+}
+
+async fn admin_run_log_maintenance(
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+    let cfg = crate::proxy::config::get_log_retention_config();
+    let report = tokio::task::spawn_blocking(move || {
+        crate::modules::log_retention::run_maintenance(&cfg, true)
+    })
+    .await
+    .map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                error: e.to_string(),
+            }),
+        )
+    })?;
+
+    logger::log_info("[API] 已手动执行日志清理与压缩");
+    Ok(Json(report))
+}
+
 async fn admin_get_proxy_log_detail(
     Path(log_id): Path<String>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
@@ -1670,34 +2129,29 @@ async fn admin_get_proxy_log_detail(
     }
 }
 
-#[derive(Deserialize, Debug, Default)]
-#[serde(rename_all = "camelCase")]
-struct LogsFilterQuery {
-    #[serde(default)]
-    filter: String,
-    #[serde(default)]
-    errors_only: bool,
-    #[serde(default)]
-    limit: usize,
-    #[serde(default)]
-    offset: usize,
-}
-
+/// 结构化日志查询
+/// 返回日志数组 (兼容旧版前端)，下一页游标通过 `X-Next-Cursor` 响应头返回
 async fn admin_get_proxy_logs_filtered(
-    Query(params): Query<LogsFilterQuery>,
+    Query(params): Query<proxy_db::LogQuery>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
-    let res = tokio::task::spawn_blocking(move || {
-        crate::modules::proxy_db::get_logs_filtered(
-            &params.filter,
-            params.errors_only,
-            params.limit,
-            params.offset,
-        )
-    })
-    .await;
+    // 只有游标等参数解析失败属于客户端错误，数据库错误返回 500
+    if let Err(e) = params.validate() {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse { error: e })));
+    }
+    let res = tokio::task::spawn_blocking(move || proxy_db::query_logs(&params)).await;
 
     match res {
-        Ok(Ok(logs)) => Ok(Json(logs)),
+        Ok(Ok(page)) => {
+            let mut headers = HeaderMap::new();
+            if let Some(cursor) = page
+                .next_cursor
+                .as_deref()
+                .and_then(|c| axum::http::HeaderValue::from_str(c).ok())
+            {
+                headers.insert("x-next-cursor", cursor);
+            }
+            Ok((headers, Json(page.logs)))
+        }
         Ok(Err(e)) => Err((
             StatusCode::INTERNAL_SERVER_ERROR,
             Json(ErrorResponse { error: e }),
@@ -1718,6 +2172,55 @@ async fn admin_get_proxy_stats(
     Ok(Json(stats))
 }
 
+/// v1internal 端点配置及健康状态
+async fn admin_get_upstream_endpoints(
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+    let cfg = crate::proxy::config::get_upstream_endpoints_config();
+    Ok(Json(serde_json::json!({
+        "config": cfg,
+        "endpoints": state.upstream.endpoint_health(),
+    })))
+}
+
+/// 各账号在途请求数 (含并发上限配置)
+async fn admin_get_account_load(
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+    let scheduling = state.token_manager.get_sticky_config().await;
+    Ok(Json(serde_json::json!({
+        "limits": scheduling.concurrency_limits(),
+        "accounts": state.token_manager.account_load_snapshot().await,
+    })))
+}
+
+/// 准入队列配置与排队指标
+async fn admin_get_admission_queue(
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+    let (config, metrics) = state.token_manager.admission_status().await;
+    Ok(Json(serde_json::json!({
+        "config": config,
+        "metrics": metrics,
+    })))
+}
+
+/// 用户令牌公平调度配置与各流状态
+async fn admin_get_fair_share() -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+    Ok(Json(serde_json::json!({
+        "config": crate::proxy::config::get_fair_scheduling_config(),
+        "state": crate::proxy::fair_share::snapshot(),
+    })))
+}
+
+/// 请求对冲配置与统计 (含按模型学习的 p95 延迟)
+async fn admin_get_hedging() -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+    Ok(Json(serde_json::json!({
+        "config": crate::proxy::config::get_hedging_config(),
+        "stats": crate::proxy::hedging::stats(),
+    })))
+}
+
 async fn admin_get_data_dir_path() -> impl IntoResponse {
     match crate::modules::account::get_data_dir() {
         Ok(p) => Json(p.to_string_lossy().to_string()),
@@ -1727,6 +2230,170 @@ async fn admin_get_data_dir_path() -> impl IntoResponse {
 
 // --- User Token Handlers ---
 
+#[derive(Deserialize)]
+struct AdminLoginRequest {
+    username: String,
+    password: String,
+}
+
+async fn admin_login(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    connect_info: Option<axum::extract::ConnectInfo<std::net::SocketAddr>>,
+    Json(payload): Json<AdminLoginRequest>,
+) -> Result<impl IntoResponse, Response> {
+    // 代理头可由客户端任意伪造，仅在部署于可信反向代理之后时采用
+    let remote_addr = connect_info.map(|info| info.0);
+    let client_ip = if state.security.read().await.security_monitor.trust_proxy_headers {
+        crate::proxy::middleware::ip_filter::client_ip_from(&headers, remote_addr)
+    } else {
+        remote_addr.map(|addr| addr.ip().to_string())
+    };
+    if let Err(retry_after) = crate::proxy::login_throttle::check(client_ip.as_deref(), &payload.username) {
+        logger::log_warn(&format!(
+            "[API] 管理员 {} 登录失败次数过多，已暂时锁定 (IP: {})",
+            payload.username,
+            client_ip.as_deref().unwrap_or("unknown")
+        ));
+        return Err((
+            StatusCode::TOO_MANY_REQUESTS,
+            [(axum::http::header::RETRY_AFTER, retry_after.as_secs().max(1).to_string())],
+            Json(ErrorResponse {
+                error: "Too many failed login attempts, please try again later".to_string(),
+            }),
+        )
+            .into_response());
+    }
+
+    let username = payload.username.clone();
+    let res = tokio::task::spawn_blocking(move || {
+        admin_user_db::login(&payload.username, &payload.password)
+    })
+    .await
+    .map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                error: e.to_string(),
+            }),
+        )
+            .into_response()
+    })?;
+
+    match res {
+        Ok(Some(session)) => {
+            crate::proxy::login_throttle::record_success(client_ip.as_deref(), &username);
+            logger::log_info(&format!(
+                "[API] 管理员 {} ({}) 登录成功",
+                session.user.username,
+                session.user.role.as_str()
+            ));
+            Ok(Json(session))
+        }
+        Ok(None) => {
+            crate::proxy::login_throttle::record_failure(client_ip.as_deref(), &username);
+            Err((
+                StatusCode::UNAUTHORIZED,
+                Json(ErrorResponse {
+                    error: "Invalid username or password".to_string(),
+                }),
+            )
+                .into_response())
+        }
+        Err(e) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse { error: e }),
+        )
+            .into_response()),
+    }
+}
+
+async fn admin_logout(headers: HeaderMap) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+    let token = headers
+        .get(axum::http::header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .map(|s| s.strip_prefix("Bearer ").unwrap_or(s).to_string());
+
+    if let Some(token) = token.filter(|t| t.starts_with(admin_user_db::SESSION_TOKEN_PREFIX)) {
+        admin_user_db::revoke_session(&token).map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse { error: e }),
+            )
+        })?;
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn admin_get_current_identity(
+    identity: Option<axum::Extension<AdminIdentity>>,
+) -> impl IntoResponse {
+    match identity {
+        Some(axum::Extension(id)) => Json(serde_json::json!({
+            "user_id": id.user_id,
+            "username": id.username,
+            "role": id.role,
+        })),
+        None => Json(serde_json::json!({
+            "user_id": null,
+            "username": "admin",
+            "role": AdminRole::Owner,
+        })),
+    }
+}
+
+async fn admin_list_admin_users() -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+    let users = crate::commands::admin_user::list_admin_users().await.map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse { error: e }),
+        )
+    })?;
+    Ok(Json(users))
+}
+
+async fn admin_create_admin_user(
+    identity: Option<axum::Extension<AdminIdentity>>,
+    Json(payload): Json<crate::commands::admin_user::CreateAdminUserRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+    let user = crate::commands::admin_user::create_admin_user(payload)
+        .await
+        .map_err(|e| (StatusCode::BAD_REQUEST, Json(ErrorResponse { error: e })))?;
+    audit_log::record(
+        &audit_actor(&identity),
+        "admin_user.create",
+        Some(&format!("{} ({})", user.username, user.role.as_str())),
+    );
+    Ok(Json(user))
+}
+
+async fn admin_update_admin_user(
+    identity: Option<axum::Extension<AdminIdentity>>,
+    Path(id): Path<String>,
+    Json(payload): Json<crate::commands::admin_user::UpdateAdminUserRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+    let user = crate::commands::admin_user::update_admin_user(id, payload)
+        .await
+        .map_err(|e| (StatusCode::BAD_REQUEST, Json(ErrorResponse { error: e })))?;
+    audit_log::record(
+        &audit_actor(&identity),
+        "admin_user.update",
+        Some(&format!("{} ({})", user.username, user.role.as_str())),
+    );
+    Ok(Json(user))
+}
+
+async fn admin_delete_admin_user(
+    identity: Option<axum::Extension<AdminIdentity>>,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+    crate::commands::admin_user::delete_admin_user(id.clone())
+        .await
+        .map_err(|e| (StatusCode::BAD_REQUEST, Json(ErrorResponse { error: e })))?;
+    audit_log::record(&audit_actor(&identity), "admin_user.delete", Some(&id));
+    Ok(StatusCode::NO_CONTENT)
+}
+
 async fn admin_list_user_tokens() -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
     let tokens = crate::commands::user_token::list_user_tokens().await.map_err(|e| {
         (
@@ -2188,6 +2855,7 @@ struct BulkDeleteRequest {
 }
 
 async fn admin_delete_accounts(
+    identity: Option<axum::Extension<AdminIdentity>>,
     Json(payload): Json<BulkDeleteRequest>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
     crate::modules::account::delete_accounts(&payload.account_ids).map_err(|e| {
@@ -2196,6 +2864,11 @@ async fn admin_delete_accounts(
             Json(ErrorResponse { error: e }),
         )
     })?;
+    audit_log::record(
+        &audit_actor(&identity),
+        "account.bulk_delete",
+        Some(&payload.account_ids.join(",")),
+    );
     Ok(StatusCode::OK)
 }
 
@@ -3284,16 +3957,25 @@ struct UpdateSecurityConfigWrapper {
 
 async fn admin_update_security_config(
     State(state): State<AppState>,
+    identity: Option<axum::Extension<AdminIdentity>>,
     Json(payload): Json<UpdateSecurityConfigWrapper>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
     let config = payload.config;
     let mut app_config = crate::modules::config::load_app_config()
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse { error: e.to_string() })))?;
+    let old_config = app_config.clone();
         
     app_config.proxy.security_monitor = config.clone();
     
     crate::modules::config::save_app_config(&app_config)
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse { error: e.to_string() })))?;
+    audit_log::record_config_change(
+        &audit_actor(&identity),
+        "config.security",
+        None,
+        &old_config,
+        &app_config,
+    );
 
     {
         let mut sec = state.security.write().await;
@@ -1,3 +1,4 @@
+use crate::proxy::account_load::ConcurrencyLimits;
 use serde::{Deserialize, Serialize};
 
 /// 调度模式枚举
@@ -9,6 +10,8 @@ pub enum SchedulingMode {
     Balance,
     /// 性能优先 (Performance-first): 纯轮询模式 (Round-robin)，账号负载最均衡，但不利用缓存
     PerformanceFirst,
+    /// 加权调度 (Weighted): 无会话绑定，按剩余配额、健康分、延迟 EWMA 与在途请求数加权随机选择账号
+    Weighted,
 }
 
 impl Default for SchedulingMode {
@@ -17,6 +20,13 @@ impl Default for SchedulingMode {
     }
 }
 
+impl SchedulingMode {
+    /// 是否使用会话粘性与 60s 账号锁定
+    pub fn is_sticky(&self) -> bool {
+        matches!(self, Self::CacheFirst | Self::Balance)
+    }
+}
+
 /// 粘性会话配置
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(default)]
@@ -25,6 +35,20 @@ pub struct StickySessionConfig {
     pub mode: SchedulingMode,
     /// 缓存优先模式下的最大等待时间 (秒)
     pub max_wait_seconds: u64,
+    /// 加权模式: 剩余配额权重指数 (0 = 忽略配额)
+    pub quota_weight: f32,
+    /// 加权模式: 健康分权重指数 (0 = 忽略健康分)
+    pub health_weight: f32,
+    /// 加权模式: 延迟权重指数，越大越偏向低延迟账号 (0 = 忽略延迟)
+    pub latency_weight: f32,
+    /// 加权模式: 每个在途请求的惩罚系数，权重除以 (1 + in_flight * in_flight_weight)
+    pub in_flight_weight: f32,
+    /// 账号延迟 EWMA 平滑系数 (0.0 - 1.0)
+    pub latency_ewma_alpha: f32,
+    /// 单账号最大在途请求数 (含流式响应全程，0 = 不限制)
+    pub max_concurrent_per_account: u32,
+    /// 单账号单模型最大在途请求数 (0 = 不限制)
+    pub max_concurrent_per_account_model: u32,
 }
 
 impl Default for StickySessionConfig {
@@ -32,6 +56,22 @@ impl Default for StickySessionConfig {
         Self {
             mode: SchedulingMode::Balance,
             max_wait_seconds: 60,
+            quota_weight: 1.0,
+            health_weight: 2.0,
+            latency_weight: 1.0,
+            in_flight_weight: 1.0,
+            latency_ewma_alpha: 0.2,
+            max_concurrent_per_account: 0,
+            max_concurrent_per_account_model: 0,
+        }
+    }
+}
+
+impl StickySessionConfig {
+    pub fn concurrency_limits(&self) -> ConcurrencyLimits {
+        ConcurrencyLimits {
+            per_account: self.max_concurrent_per_account as usize,
+            per_account_model: self.max_concurrent_per_account_model as usize,
         }
     }
 }
@@ -1,10 +1,27 @@
-//! 测试 determine_retry_strategy 和 should_rotate_account 的所有分支，
+//! 测试默认重试规则下 resolve_retry_decision_with 的所有分支，
 //! 重点覆盖 404 重试与账号轮换逻辑。
 
 use std::time::Duration;
-use crate::proxy::handlers::common::{determine_retry_strategy, should_rotate_account, RetryStrategy};
+use crate::proxy::config::{default_retry_rules, BackoffKind, RetryErrorClass, RetryRule};
+use crate::proxy::handlers::common::{
+    apply_retry_decision, classify_error, resolve_retry_decision_with, RetryAttempts,
+    RetryStrategy,
+};
 
-// ===== determine_retry_strategy =====
+fn determine_retry_strategy(
+    status_code: u16,
+    error_text: &str,
+    retried_without_thinking: bool,
+) -> RetryStrategy {
+    let rules = default_retry_rules();
+    resolve_retry_decision_with(&rules, status_code, error_text, retried_without_thinking).strategy
+}
+
+fn should_rotate_account(status_code: u16) -> bool {
+    resolve_retry_decision_with(&default_retry_rules(), status_code, "", false).rotate_account
+}
+
+// ===== 默认规则的退避策略 =====
 
 #[test]
 fn test_retry_strategy_404() {
@@ -109,7 +126,7 @@ fn test_retry_strategy_400_no_signature() {
     );
 }
 
-// ===== should_rotate_account =====
+// ===== 默认规则的账号轮换 =====
 
 #[test]
 fn test_rotate_account_true_cases() {
@@ -132,3 +149,105 @@ fn test_rotate_account_false_cases() {
         );
     }
 }
+
+// ===== 可配置重试规则 =====
+
+#[test]
+fn test_classify_error() {
+    assert_eq!(
+        classify_error(400, "Corrupted thought signature"),
+        RetryErrorClass::ThinkingSignature
+    );
+    assert_eq!(
+        classify_error(429, "QUOTA_EXHAUSTED"),
+        RetryErrorClass::QuotaExhausted
+    );
+    assert_eq!(classify_error(429, ""), RetryErrorClass::RateLimited);
+    assert_eq!(classify_error(502, ""), RetryErrorClass::ServerError);
+    assert_eq!(classify_error(418, ""), RetryErrorClass::Other);
+}
+
+#[test]
+fn test_thinking_signature_retried_only_once() {
+    let rules = default_retry_rules();
+    let decision = resolve_retry_decision_with(&rules, 400, "thinking.signature", true);
+    assert!(matches!(decision.strategy, RetryStrategy::NoRetry));
+}
+
+#[test]
+fn test_custom_rule_order_and_class() {
+    // 配额耗尽时不重试，其余 429 固定 1s 且保留账号
+    let rules = vec![
+        RetryRule {
+            statuses: vec![429],
+            error_class: Some(RetryErrorClass::QuotaExhausted),
+            max_attempts: 0,
+            backoff: BackoffKind::None,
+            base_ms: 0,
+            max_ms: 0,
+            jitter: 0.0,
+            rotate_account: true,
+            respect_retry_after: false,
+        },
+        RetryRule {
+            statuses: vec![429],
+            error_class: None,
+            max_attempts: 2,
+            backoff: BackoffKind::Fixed,
+            base_ms: 1000,
+            max_ms: 0,
+            jitter: 0.5,
+            rotate_account: false,
+            respect_retry_after: false,
+        },
+    ];
+
+    let quota = resolve_retry_decision_with(&rules, 429, "QUOTA_EXHAUSTED", false);
+    assert!(matches!(quota.strategy, RetryStrategy::NoRetry));
+
+    let limited = resolve_retry_decision_with(&rules, 429, "rate limited", false);
+    match limited.strategy {
+        RetryStrategy::FixedDelay(d) => assert_eq!(d, Duration::from_millis(1000)),
+        other => panic!("Expected FixedDelay(1000ms), got {:?}", other),
+    }
+    assert_eq!(limited.max_attempts, 2);
+    assert!(!limited.rotate_account);
+    assert_eq!(limited.jitter, 0.5);
+
+    // 未命中任何规则 -> 不重试
+    let other = resolve_retry_decision_with(&rules, 500, "", false);
+    assert!(matches!(other.strategy, RetryStrategy::NoRetry));
+}
+
+#[tokio::test]
+async fn test_rule_attempt_limit_counts_only_matching_failures() {
+    let rule = |status: u16, max_attempts: usize| RetryRule {
+        statuses: vec![status],
+        error_class: None,
+        max_attempts,
+        backoff: BackoffKind::Fixed,
+        base_ms: 0,
+        max_ms: 0,
+        jitter: 0.0,
+        rotate_account: false,
+        respect_retry_after: false,
+    };
+    let rules = vec![rule(429, 2), rule(503, 0)];
+    let mut attempts = RetryAttempts::default();
+    // 每次都作为请求的最后一次尝试，只检查规则级上限 (不消耗全局预算、不等待)
+    async fn retry(
+        rules: &[RetryRule],
+        attempts: &mut RetryAttempts,
+        status: u16,
+        attempt: usize,
+    ) -> bool {
+        let decision = resolve_retry_decision_with(rules, status, "", false);
+        apply_retry_decision(&decision, attempts, attempt, attempt + 1, status, "test").await
+    }
+
+    assert!(retry(&rules, &mut attempts, 503, 0).await);
+    assert!(retry(&rules, &mut attempts, 503, 1).await);
+    // 之前的 503 不占用 429 规则的次数
+    assert!(retry(&rules, &mut attempts, 429, 2).await);
+    assert!(!retry(&rules, &mut attempts, 429, 3).await);
+}
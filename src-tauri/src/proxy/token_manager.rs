@@ -6,6 +6,9 @@ use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use tokio_util::sync::CancellationToken;
 
+use crate::proxy::account_load::{AccountLoadSnapshot, AccountLoadTracker};
+use crate::proxy::admission::{AdmissionQueue, AdmissionQueueMetrics};
+use crate::proxy::config::AdmissionQueueConfig;
 use crate::proxy::rate_limit::RateLimitTracker;
 use crate::proxy::sticky_config::StickySessionConfig;
 
@@ -47,6 +50,9 @@ pub struct TokenManager {
     preferred_account_id: Arc<tokio::sync::RwLock<Option<String>>>, // [FIX #820] 优先使用的账号ID（固定账号模式）
     health_scores: Arc<DashMap<String, f32>>,                       // account_id -> health_score
     circuit_breaker_config: Arc<tokio::sync::RwLock<crate::models::CircuitBreakerConfig>>, // [NEW] 熔断配置缓存
+    account_load: Arc<AccountLoadTracker>, // 账号在途请求数与延迟 EWMA
+    admission: Arc<AdmissionQueue>,        // 账号不可用时的请求排队
+    admission_config: Arc<tokio::sync::RwLock<AdmissionQueueConfig>>,
     /// 支持优雅关闭时主动 abort 后台任务
     auto_cleanup_handle: Arc<tokio::sync::Mutex<Option<tokio::task::JoinHandle<()>>>>,
     cancel_token: CancellationToken,
@@ -68,6 +74,9 @@ impl TokenManager {
             circuit_breaker_config: Arc::new(tokio::sync::RwLock::new(
                 crate::models::CircuitBreakerConfig::default(),
             )),
+            account_load: Arc::new(AccountLoadTracker::new()),
+            admission: Arc::new(AdmissionQueue::new()),
+            admission_config: Arc::new(tokio::sync::RwLock::new(AdmissionQueueConfig::default())),
             auto_cleanup_handle: Arc::new(tokio::sync::Mutex::new(None)),
             cancel_token: CancellationToken::new(),
         }
@@ -201,8 +210,9 @@ impl TokenManager {
             tracing::info!("[Proxy] Removed account {} from memory cache", account_id);
         }
 
-        // 2. 清理相关的健康分数
+        // 2. 清理相关的健康分数与延迟统计
         self.health_scores.remove(account_id);
+        self.account_load.remove(account_id);
 
         // 3. 清理该账号的所有限流记录
         self.clear_rate_limit(account_id);
@@ -895,6 +905,118 @@ impl TokenManager {
         Some(selected)
     }
 
+    /// 加权调度: 计算单个账号的选择权重
+    /// quota^qw * health^hw * (best_latency / latency)^lw / (1 + in_flight * iw)
+    /// 尚无延迟样本的账号延迟因子按 1.0 计算 (乐观探索)
+    fn weighted_score(
+        quota: i32,
+        health: f32,
+        latency_ms: Option<f64>,
+        best_latency_ms: Option<f64>,
+        in_flight: usize,
+        cfg: &StickySessionConfig,
+    ) -> f64 {
+        let quota = (quota.clamp(0, 100) as f64 / 100.0).max(0.01);
+        let health = (health as f64).clamp(0.01, 1.0);
+        let latency_factor = match (latency_ms, best_latency_ms) {
+            (Some(latency), Some(best)) if latency > 0.0 => (best / latency).clamp(0.01, 1.0),
+            _ => 1.0,
+        };
+        let in_flight_penalty = 1.0 + in_flight as f64 * cfg.in_flight_weight.max(0.0) as f64;
+
+        quota.powf(cfg.quota_weight.max(0.0) as f64)
+            * health.powf(cfg.health_weight.max(0.0) as f64)
+            * latency_factor.powf(cfg.latency_weight.max(0.0) as f64)
+            / in_flight_penalty
+    }
+
+    /// 按权重选择下标，`r` 为 [0, 1) 区间的随机数
+    fn weighted_pick(weights: &[f64], r: f64) -> Option<usize> {
+        let total: f64 = weights.iter().sum();
+        if weights.is_empty() || total <= 0.0 || !total.is_finite() {
+            return None;
+        }
+        let mut target = r * total;
+        for (idx, w) in weights.iter().enumerate() {
+            if target < *w {
+                return Some(idx);
+            }
+            target -= w;
+        }
+        Some(weights.len() - 1)
+    }
+
+    /// 加权随机选择 (SchedulingMode::Weighted)
+    /// 权重由剩余配额、健康分、延迟 EWMA 与在途请求数共同决定，流量按权重平滑分摊
+    fn select_weighted<'a>(
+        &self,
+        candidates: &'a [ProxyToken],
+        attempted: &HashSet<String>,
+        normalized_target: &str,
+        quota_protection_enabled: bool,
+        cfg: &StickySessionConfig,
+    ) -> Option<&'a ProxyToken> {
+        use rand::Rng;
+
+        let available: Vec<&ProxyToken> = candidates
+            .iter()
+            .filter(|t| !attempted.contains(&t.account_id))
+            .filter(|t| !quota_protection_enabled || !t.protected_models.contains(normalized_target))
+            .collect();
+
+        if available.is_empty() {
+            return None;
+        }
+        if available.len() == 1 {
+            return Some(available[0]);
+        }
+
+        let best_latency = available
+            .iter()
+            .filter_map(|t| self.account_load.latency_ms(&t.account_id))
+            .fold(None, |acc: Option<f64>, l| Some(acc.map_or(l, |a| a.min(l))));
+
+        let weights: Vec<f64> = available
+            .iter()
+            .map(|t| {
+                let quota = t
+                    .model_quotas
+                    .get(normalized_target)
+                    .copied()
+                    .or(t.remaining_quota)
+                    .unwrap_or(0);
+                let health = self
+                    .health_scores
+                    .get(&t.account_id)
+                    .map(|v| *v)
+                    .unwrap_or(t.health_score);
+                Self::weighted_score(
+                    quota,
+                    health,
+                    self.account_load.latency_ms(&t.account_id),
+                    best_latency,
+                    self.account_load.in_flight(&t.account_id),
+                    cfg,
+                )
+            })
+            .collect();
+
+        let r: f64 = rand::thread_rng().gen();
+        let idx = Self::weighted_pick(&weights, r).unwrap_or(0);
+        let selected = available[idx];
+
+        tracing::debug!(
+            "⚖️ [Weighted] Selected {} (weight={:.4}, total={:.4}, in_flight={}, latency={:?})",
+            selected.email,
+            weights[idx],
+            weights.iter().sum::<f64>(),
+            self.account_load.in_flight(&selected.account_id),
+            self.account_load.latency_ms(&selected.account_id)
+        );
+
+        Some(selected)
+    }
+
     /// 先发送取消信号，再带超时等待任务完成
     ///
     /// # 参数
@@ -974,19 +1096,148 @@ impl TokenManager {
             );
         }
 
-        // 【优化 Issue #284】添加 5 秒超时，防止死锁
-        let timeout_duration = std::time::Duration::from_secs(5);
-        match tokio::time::timeout(
-            timeout_duration,
-            self.get_token_internal(quota_group, force_rotate, session_id, target_model),
-        )
-        .await
-        {
-            Ok(result) => result,
-            Err(_) => Err(
-                "Token acquisition timeout (5s) - system too busy or deadlock detected".to_string(),
-            ),
+        let limits = self.sticky_config.read().await.concurrency_limits();
+        let queue_cfg = self.admission_config.read().await.clone();
+        let normalized_target =
+            crate::proxy::common::model_mapping::normalize_to_standard_id(target_model)
+                .unwrap_or_else(|| target_model.to_string());
+        let priority = crate::proxy::admission::current_priority();
+        let deadline = tokio::time::Instant::now()
+            + std::time::Duration::from_secs(queue_cfg.max_wait_seconds);
+
+        // 同模型已有请求在排队时，新请求直接排到队尾，保证先到先得
+        let mut ticket = None;
+        if queue_cfg.enabled && self.admission.has_waiters(&normalized_target) {
+            ticket = Some(self.admission.enqueue(
+                &normalized_target,
+                priority,
+                queue_cfg.max_queue_length,
+            )?);
+        }
+
+        loop {
+            // 先注册释放通知，避免在检查与等待之间错过租约释放
+            let released = self.account_load.released();
+            tokio::pin!(released);
+            released.as_mut().enable();
+
+            // 排队中：等待轮到自己 (同模型内优先级高、入队早的请求先尝试)
+            if let Some(t) = &ticket {
+                if !self.admission.wait_turn(t, deadline).await {
+                    if let Some(t) = ticket.take() {
+                        t.time_out();
+                    }
+                    return Err(format!(
+                        "Timed out after {}s waiting in admission queue",
+                        queue_cfg.max_wait_seconds
+                    ));
+                }
+            }
+
+            // 【优化 Issue #284】添加 5 秒超时，防止死锁
+            let timeout_duration = std::time::Duration::from_secs(5);
+            let result = match tokio::time::timeout(
+                timeout_duration,
+                self.get_token_internal(quota_group, force_rotate, session_id, target_model),
+            )
+            .await
+            {
+                Ok(result) => result,
+                Err(_) => {
+                    return Err(
+                        "Token acquisition timeout (5s) - system too busy or deadlock detected"
+                            .to_string(),
+                    )
+                }
+            };
+
+            let error = match result {
+                Ok(token) => {
+                    // 获取并发许可并挂到当前请求，在途计数持续到响应 (含流) 结束
+                    if let Some(lease) =
+                        self.account_load
+                            .try_begin(&token.3, &normalized_target, limits)
+                    {
+                        crate::proxy::account_load::attach(lease);
+                        if let Some(t) = ticket.take() {
+                            t.admit();
+                        }
+                        return Ok(token);
+                    }
+                    // 选中后许可被并发请求抢占：重新选择 (该账号此时已饱和，会被跳过)
+                    tracing::debug!(
+                        "[Concurrency] Account {} saturated before permit was granted, reselecting",
+                        token.2
+                    );
+                    continue;
+                }
+                Err(e) => e,
+            };
+
+            // 只有账号饱和或限流即将在排队时限内重置时，等待才有意义；否则直接返回原始错误
+            let saturated = self.account_load.any_saturated(limits);
+            let reset_wait = self
+                .next_rate_limit_reset(&normalized_target)
+                .await
+                .filter(|secs| *secs <= queue_cfg.max_wait_seconds);
+            if !saturated && reset_wait.is_none() {
+                return Err(error);
+            }
+            if !queue_cfg.enabled {
+                return Err(if saturated {
+                    "All available accounts are at their concurrency limit".to_string()
+                } else {
+                    error
+                });
+            }
+
+            if ticket.is_none() {
+                ticket = Some(self.admission.enqueue(
+                    &normalized_target,
+                    priority,
+                    queue_cfg.max_queue_length,
+                )?);
+                tracing::debug!(
+                    "[Admission] No account available for {} ({}), request queued",
+                    normalized_target,
+                    error
+                );
+            }
+
+            let now = tokio::time::Instant::now();
+            if now >= deadline {
+                if let Some(t) = ticket.take() {
+                    t.time_out();
+                }
+                return Err(format!(
+                    "Timed out after {}s waiting in admission queue: {}",
+                    queue_cfg.max_wait_seconds, error
+                ));
+            }
+
+            // 等待任意租约释放或最近的限流重置
+            let wake_at = reset_wait
+                .map(|secs| now + std::time::Duration::from_secs(secs.max(1)))
+                .unwrap_or(deadline)
+                .min(deadline);
+            tokio::select! {
+                _ = released => {}
+                _ = tokio::time::sleep_until(wake_at) => {}
+            }
+        }
+    }
+
+    /// 目标模型上最近一次限流重置的剩余秒数 (熔断关闭或无限流账号时为 None)
+    async fn next_rate_limit_reset(&self, model: &str) -> Option<u64> {
+        if !self.circuit_breaker_config.read().await.enabled {
+            return None;
         }
+        self.tokens
+            .iter()
+            .filter(|t| t.model_quotas.contains_key(model))
+            .map(|t| self.rate_limit_tracker.get_remaining_wait(&t.account_id, Some(model)))
+            .filter(|secs| *secs > 0)
+            .min()
     }
 
     /// 内部实现：获取 Token 的核心逻辑
@@ -1101,6 +1352,17 @@ impl TokenManager {
         let scheduling = self.sticky_config.read().await.clone();
         use crate::proxy::sticky_config::SchedulingMode;
 
+        // 已达并发上限的账号本轮直接跳过 (与已尝试失败的账号同等对待，不解除会话绑定)
+        let limits = scheduling.concurrency_limits();
+        let saturated: HashSet<String> = tokens_snapshot
+            .iter()
+            .filter(|t| {
+                self.account_load
+                    .is_saturated(&t.account_id, &normalized_target, limits)
+            })
+            .map(|t| t.account_id.clone())
+            .collect();
+
         // 【新增】检查配额保护是否启用（如果关闭，则忽略 protected_models 检查）
         let quota_protection_enabled = crate::modules::config::load_app_config()
             .map(|cfg| cfg.quota_protection.enabled)
@@ -1163,8 +1425,9 @@ impl TokenManager {
                     && preferred_token
                         .protected_models
                         .contains(&normalized_target);
+                let is_saturated = saturated.contains(&preferred_token.account_id);
 
-                if !is_rate_limited && !is_quota_protected {
+                if !is_rate_limited && !is_quota_protected && !is_saturated {
                     tracing::info!(
                         "🔒 [FIX #820] Using preferred account: {} (fixed mode)",
                         preferred_token.email
@@ -1228,6 +1491,8 @@ impl TokenManager {
                 } else {
                     if is_rate_limited {
                         tracing::warn!("🔒 [FIX #820] Preferred account {} is rate-limited, falling back to round-robin", preferred_token.email);
+                    } else if is_saturated {
+                        tracing::debug!("🔒 [FIX #820] Preferred account {} is at its concurrency limit, falling back to round-robin", preferred_token.email);
                     } else {
                         tracing::warn!("🔒 [FIX #820] Preferred account {} is quota-protected for {}, falling back to round-robin", preferred_token.email, target_model);
                     }
@@ -1249,7 +1514,7 @@ impl TokenManager {
             None
         };
 
-        let mut attempted: HashSet<String> = HashSet::new();
+        let mut attempted: HashSet<String> = saturated.clone();
         let mut last_error: Option<String> = None;
         let mut need_update_last_used: Option<(String, std::time::Instant)> = None;
 
@@ -1266,7 +1531,7 @@ impl TokenManager {
             // 模式 A: 粘性会话处理 (CacheFirst 或 Balance 且有 session_id)
             if !rotate
                 && session_id.is_some()
-                && scheduling.mode != SchedulingMode::PerformanceFirst
+                && scheduling.mode.is_sticky()
             {
                 let sid = session_id.unwrap();
 
@@ -1319,7 +1584,7 @@ impl TokenManager {
             if target_token.is_none()
                 && !rotate
                 && quota_group != "image_gen"
-                && scheduling.mode != SchedulingMode::PerformanceFirst
+                && scheduling.mode.is_sticky()
             {
                 // 【优化】使用预先获取的快照，不再在循环内加锁
                 if let Some((account_id, last_time)) = &last_used_account_id {
@@ -1374,8 +1639,13 @@ impl TokenManager {
                         need_update_last_used = Some((selected.account_id.clone(), std::time::Instant::now()));
 
                         // 如果是会话首次分配且需要粘性，在此建立绑定
+                        // 绑定账号仅因并发饱和被临时跳过时保留原绑定，避免破坏缓存亲和
                         if let Some(sid) = session_id {
-                            if scheduling.mode != SchedulingMode::PerformanceFirst {
+                            let bound_saturated = self
+                                .session_accounts
+                                .get(sid)
+                                .map_or(false, |bound| saturated.contains(bound.value()));
+                            if scheduling.mode.is_sticky() && !bound_saturated {
                                 self.session_accounts
                                     .insert(sid.to_string(), selected.account_id.clone());
                                 tracing::debug!(
@@ -1388,9 +1658,10 @@ impl TokenManager {
                     }
                 }
             } else if target_token.is_none() {
-                // 模式 C: P2C 选择 (替代纯轮询)
+                // 模式 C: P2C 选择 (替代纯轮询)，加权模式下按权重随机选择
                 tracing::debug!(
-                    "🔄 [Mode C] P2C selection from {} candidates",
+                    "🔄 [Mode C] {} selection from {} candidates",
+                    if scheduling.mode == SchedulingMode::Weighted { "Weighted" } else { "P2C" },
                     total
                 );
 
@@ -1402,10 +1673,18 @@ impl TokenManager {
                     }
                 }
 
-                if let Some(selected) = self.select_with_p2c(
-                    &non_limited, &attempted, &normalized_target, quota_protection_enabled
-                ) {
-                    tracing::debug!("  {} - SELECTED via P2C", selected.email);
+                let selected = if scheduling.mode == SchedulingMode::Weighted {
+                    self.select_weighted(
+                        &non_limited, &attempted, &normalized_target, quota_protection_enabled, &scheduling
+                    )
+                } else {
+                    self.select_with_p2c(
+                        &non_limited, &attempted, &normalized_target, quota_protection_enabled
+                    )
+                };
+
+                if let Some(selected) = selected {
+                    tracing::debug!("  {} - SELECTED", selected.email);
                     target_token = Some(selected.clone());
 
                     if rotate {
@@ -2167,6 +2446,35 @@ impl TokenManager {
 
     // ===== 调度配置相关方法 =====
 
+    /// 各账号在途请求数与延迟快照 (按在途数降序)
+    pub async fn account_load_snapshot(&self) -> Vec<AccountLoadSnapshot> {
+        let limits = self.sticky_config.read().await.concurrency_limits();
+        let mut list: Vec<AccountLoadSnapshot> = self
+            .tokens
+            .iter()
+            .map(|entry| {
+                let token = entry.value();
+                let (in_flight, in_flight_by_model, latency_ewma_ms) =
+                    self.account_load.snapshot(&token.account_id);
+                let saturated = (limits.per_account > 0 && in_flight >= limits.per_account)
+                    || (limits.per_account_model > 0
+                        && in_flight_by_model
+                            .values()
+                            .any(|n| *n >= limits.per_account_model));
+                AccountLoadSnapshot {
+                    account_id: token.account_id.clone(),
+                    email: token.email.clone(),
+                    in_flight,
+                    in_flight_by_model,
+                    latency_ewma_ms,
+                    saturated,
+                }
+            })
+            .collect();
+        list.sort_by(|a, b| b.in_flight.cmp(&a.in_flight).then_with(|| a.email.cmp(&b.email)));
+        list
+    }
+
     /// 获取当前调度配置
     pub async fn get_sticky_config(&self) -> StickySessionConfig {
         self.sticky_config.read().await.clone()
@@ -2174,11 +2482,27 @@ impl TokenManager {
 
     /// 更新调度配置
     pub async fn update_sticky_config(&self, new_config: StickySessionConfig) {
+        self.account_load.set_latency_alpha(new_config.latency_ewma_alpha);
         let mut config = self.sticky_config.write().await;
         *config = new_config;
         tracing::debug!("Scheduling configuration updated: {:?}", *config);
     }
 
+    /// 更新准入队列配置
+    pub async fn update_admission_config(&self, config: AdmissionQueueConfig) {
+        let mut lock = self.admission_config.write().await;
+        *lock = config;
+        tracing::debug!("Admission queue configuration updated: {:?}", *lock);
+    }
+
+    /// 准入队列配置与排队指标
+    pub async fn admission_status(&self) -> (AdmissionQueueConfig, AdmissionQueueMetrics) {
+        (
+            self.admission_config.read().await.clone(),
+            self.admission.metrics(),
+        )
+    }
+
     /// [NEW] 更新熔断器配置
     pub async fn update_circuit_breaker_config(&self, config: crate::models::CircuitBreakerConfig) {
         let mut lock = self.circuit_breaker_config.write().await;
@@ -2669,6 +2993,110 @@ mod tests {
         let _ = std::fs::remove_dir_all(&tmp_root);
     }
 
+    #[tokio::test]
+    async fn test_concurrency_limit_skips_saturated_accounts() {
+        use crate::proxy::account_load::{new_slot, scope_request};
+
+        let tmp_root = std::env::temp_dir().join(format!(
+            "antigravity-token-manager-test-concurrency-{}",
+            uuid::Uuid::new_v4()
+        ));
+        let accounts_dir = tmp_root.join("accounts");
+        std::fs::create_dir_all(&accounts_dir).unwrap();
+
+        let now = chrono::Utc::now().timestamp();
+        for (id, email, percentage) in [("acc1", "a@test.com", 90), ("acc2", "b@test.com", 10)] {
+            let json = serde_json::json!({
+                "id": id,
+                "email": email,
+                "token": {
+                    "access_token": format!("atk-{}", id),
+                    "refresh_token": format!("rtk-{}", id),
+                    "expires_in": 3600,
+                    "expiry_timestamp": now + 3600,
+                    "project_id": format!("pid-{}", id)
+                },
+                "quota": {
+                    "models": [
+                        { "name": "gemini-1.5-flash", "percentage": percentage }
+                    ]
+                },
+                "disabled": false,
+                "created_at": now,
+                "last_used": now
+            });
+            std::fs::write(
+                accounts_dir.join(format!("{}.json", id)),
+                serde_json::to_string_pretty(&json).unwrap(),
+            )
+            .unwrap();
+        }
+
+        let manager = TokenManager::new(tmp_root.clone());
+        manager.load_accounts().await.unwrap();
+        manager
+            .update_sticky_config(StickySessionConfig {
+                max_concurrent_per_account: 1,
+                ..Default::default()
+            })
+            .await;
+        manager
+            .update_admission_config(AdmissionQueueConfig {
+                enabled: false,
+                ..Default::default()
+            })
+            .await;
+
+        let acquire = |slot| {
+            let manager = &manager;
+            scope_request(slot, async move {
+                manager
+                    .get_token("gemini", false, Some("sid1"), "gemini-1.5-flash")
+                    .await
+                    .map(|(_, _, _, account_id, _)| account_id)
+            })
+        };
+
+        // 首个请求绑定 acc1 并持有其许可
+        let first = new_slot();
+        assert_eq!(acquire(first.clone()).await.unwrap(), "acc1");
+
+        // acc1 已饱和：跳过但不解除会话绑定
+        let second = new_slot();
+        assert_eq!(acquire(second.clone()).await.unwrap(), "acc2");
+        assert_eq!(
+            manager.session_accounts.get("sid1").map(|v| v.clone()),
+            Some("acc1".to_string())
+        );
+
+        // 全部饱和且不允许等待
+        let err = acquire(new_slot()).await.unwrap_err();
+        assert!(err.contains("concurrency limit"), "{}", err);
+
+        let load = manager.account_load_snapshot().await;
+        assert!(load.iter().all(|a| a.in_flight == 1 && a.saturated));
+
+        // 开启准入队列：排队等待直到有许可释放
+        manager
+            .update_admission_config(AdmissionQueueConfig {
+                max_wait_seconds: 5,
+                ..Default::default()
+            })
+            .await;
+        let (queued, _) = tokio::join!(acquire(new_slot()), async move {
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+            drop(first);
+        });
+        // 许可释放后重新回到绑定账号
+        assert_eq!(queued.unwrap(), "acc1");
+        let (_, metrics) = manager.admission_status().await;
+        assert_eq!(metrics.total_admitted, 1);
+        assert_eq!(metrics.depth, 0);
+        drop(second);
+
+        let _ = std::fs::remove_dir_all(&tmp_root);
+    }
+
     /// 创建测试用的 ProxyToken
     fn create_test_token(
         email: &str,
@@ -3053,6 +3481,56 @@ mod tests {
         assert!(result.is_none());
     }
 
+    // ===== 加权调度测试 =====
+
+    #[test]
+    fn test_weighted_score_factors() {
+        let cfg = StickySessionConfig::default();
+
+        let base = TokenManager::weighted_score(80, 1.0, None, None, 0, &cfg);
+        // 配额越高权重越大
+        assert!(TokenManager::weighted_score(40, 1.0, None, None, 0, &cfg) < base);
+        // 健康分越低权重越小
+        assert!(TokenManager::weighted_score(80, 0.5, None, None, 0, &cfg) < base);
+        // 在途请求越多权重越小
+        let busy = TokenManager::weighted_score(80, 1.0, None, None, 3, &cfg);
+        assert!((busy - base / 4.0).abs() < 1e-9);
+        // 延迟是最优账号的两倍时权重减半
+        let slow = TokenManager::weighted_score(80, 1.0, Some(400.0), Some(200.0), 0, &cfg);
+        assert!((slow - base / 2.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_weighted_pick_distribution() {
+        let weights = [1.0, 3.0];
+        assert_eq!(TokenManager::weighted_pick(&weights, 0.0), Some(0));
+        assert_eq!(TokenManager::weighted_pick(&weights, 0.24), Some(0));
+        assert_eq!(TokenManager::weighted_pick(&weights, 0.26), Some(1));
+        assert_eq!(TokenManager::weighted_pick(&weights, 0.999), Some(1));
+        assert_eq!(TokenManager::weighted_pick(&[], 0.5), None);
+        assert_eq!(TokenManager::weighted_pick(&[0.0, 0.0], 0.5), None);
+    }
+
+    #[test]
+    fn test_weighted_skips_attempted_and_busy_accounts_lose_weight() {
+        let manager = TokenManager::new(PathBuf::from("/tmp/test"));
+        let cfg = StickySessionConfig::default();
+
+        let token_a = create_test_token("a@test.com", Some("PRO"), 1.0, None, Some(80));
+        let token_b = create_test_token("b@test.com", Some("PRO"), 1.0, None, Some(80));
+        let candidates = vec![token_a, token_b];
+
+        let mut attempted: HashSet<String> = HashSet::new();
+        attempted.insert("a@test.com".to_string());
+        for _ in 0..10 {
+            let result = manager.select_weighted(&candidates, &attempted, "claude-sonnet", false, &cfg);
+            assert_eq!(result.unwrap().account_id, "b@test.com");
+        }
+
+        let result = manager.select_weighted(&candidates, &HashSet::new(), "claude-sonnet", false, &cfg);
+        assert!(result.is_some());
+    }
+
     // ===== Ultra 优先逻辑测试 =====
 
     /// 测试 is_ultra_required_model 辅助函数
@@ -8,6 +8,9 @@ use std::sync::Arc;
 use tokio::sync::RwLock;
 use tokio::time::Duration;
 
+use super::endpoint_health::{EndpointHealthSnapshot, EndpointHealthTracker};
+use crate::proxy::config::UpstreamEndpointsConfig;
+
 /// 端点降级尝试的记录信息
 #[derive(Debug, Clone)]
 pub struct FallbackAttemptLog {
@@ -43,24 +46,15 @@ pub fn mask_email(email: &str) -> String {
     }
 }
 
-// Cloud Code v1internal endpoints (fallback order: Sandbox → Daily → Prod)
-// 优先使用 Sandbox/Daily 环境以避免 Prod环境的 429 错误 (Ref: Issue #1176)
-const V1_INTERNAL_BASE_URL_PROD: &str = "https://cloudcode-pa.googleapis.com/v1internal";
-const V1_INTERNAL_BASE_URL_DAILY: &str = "https://daily-cloudcode-pa.googleapis.com/v1internal";
-const V1_INTERNAL_BASE_URL_SANDBOX: &str =
-    "https://daily-cloudcode-pa.sandbox.googleapis.com/v1internal";
-
-const V1_INTERNAL_BASE_URL_FALLBACKS: [&str; 3] = [
-    V1_INTERNAL_BASE_URL_SANDBOX, // 优先级 1: Sandbox (已知有效且稳定)
-    V1_INTERNAL_BASE_URL_DAILY,   // 优先级 2: Daily (备用)
-    V1_INTERNAL_BASE_URL_PROD,    // 优先级 3: Prod (仅作为兜底)
-];
+// v1internal 端点列表 (默认 Sandbox → Daily → Prod) 由 ProxyConfig.upstream_endpoints 配置，
+// 见 config::default_upstream_endpoints
 
 pub struct UpstreamClient {
     default_client: Client,
     proxy_pool: Option<Arc<crate::proxy::proxy_pool::ProxyPoolManager>>,
     client_cache: DashMap<String, Client>, // proxy_id -> Client
     user_agent_override: RwLock<Option<String>>,
+    endpoint_health: EndpointHealthTracker,
 }
 
 impl UpstreamClient {
@@ -76,6 +70,73 @@ impl UpstreamClient {
             proxy_pool,
             client_cache: DashMap::new(),
             user_agent_override: RwLock::new(None),
+            endpoint_health: EndpointHealthTracker::new(),
+        }
+    }
+
+    /// 各 v1internal 端点的健康状态
+    pub fn endpoint_health(&self) -> Vec<EndpointHealthSnapshot> {
+        let cfg = crate::proxy::config::get_upstream_endpoints_config();
+        self.endpoint_health.snapshot(&cfg)
+    }
+
+    /// 启动后台探测任务：定期检查冷却中的端点，恢复后提前结束冷却
+    /// 客户端被释放后任务自动退出
+    pub fn start_health_probe(self: &Arc<Self>) {
+        let weak = Arc::downgrade(self);
+        tokio::spawn(async move {
+            loop {
+                let interval = crate::proxy::config::get_upstream_endpoints_config()
+                    .probe_interval_secs;
+                tokio::time::sleep(Duration::from_secs(if interval == 0 { 30 } else { interval }))
+                    .await;
+
+                let Some(client) = weak.upgrade() else {
+                    break;
+                };
+                let cfg = crate::proxy::config::get_upstream_endpoints_config();
+                if cfg.probe_interval_secs == 0 {
+                    continue;
+                }
+                for endpoint in client.endpoint_health.cooling_endpoints(&cfg) {
+                    client.probe_endpoint(&endpoint.base_url, &cfg).await;
+                }
+            }
+        });
+    }
+
+    /// 探测单个端点 (无凭证请求，只要端点给出非 5xx / 408 响应即视为可用)
+    async fn probe_endpoint(&self, base_url: &str, cfg: &UpstreamEndpointsConfig) {
+        let url = Self::build_url(base_url, "fetchAvailableModels", None);
+        let started = std::time::Instant::now();
+        let result = self
+            .default_client
+            .post(&url)
+            .timeout(Duration::from_secs(10))
+            .json(&serde_json::json!({}))
+            .send()
+            .await;
+
+        match result {
+            Ok(resp) if !Self::is_endpoint_failure(resp.status()) => {
+                self.endpoint_health.mark_recovered(base_url);
+            }
+            Ok(resp) => {
+                self.endpoint_health.record_failure(
+                    base_url,
+                    Some(started.elapsed()),
+                    format!("Probe returned {}", resp.status()),
+                    cfg,
+                );
+            }
+            Err(e) => {
+                self.endpoint_health.record_failure(
+                    base_url,
+                    None,
+                    format!("Probe failed: {}", e),
+                    cfg,
+                );
+            }
         }
     }
 
@@ -185,6 +246,7 @@ impl UpstreamClient {
 
     /// Build v1internal URL
     fn build_url(base_url: &str, method: &str, query_string: Option<&str>) -> String {
+        let base_url = base_url.trim_end_matches('/');
         if let Some(qs) = query_string {
             format!("{}:{}?{}", base_url, method, qs)
         } else {
@@ -200,6 +262,12 @@ impl UpstreamClient {
             || status.is_server_error()
     }
 
+    /// 是否计入端点错误率 (端点级故障，而非账号或请求本身的问题)
+    /// 429 通常是账号配额问题，只触发降级、不计入端点健康度
+    fn is_endpoint_failure(status: StatusCode) -> bool {
+        status == StatusCode::REQUEST_TIMEOUT || status.is_server_error()
+    }
+
     /// Call v1internal API (Basic Method)
     ///
     /// Initiates a basic network request, supporting multi-endpoint auto-fallback.
@@ -271,11 +339,17 @@ impl UpstreamClient {
         // [NEW] 收集降级尝试记录
         let mut fallback_attempts: Vec<FallbackAttemptLog> = Vec::new();
 
+        // 按健康状态筛选端点 (冷却中的端点被跳过)
+        let endpoints_cfg = crate::proxy::config::get_upstream_endpoints_config();
+        let endpoints = self.endpoint_health.select(&endpoints_cfg);
+
         // 遍历所有端点，失败时自动切换
-        for (idx, base_url) in V1_INTERNAL_BASE_URL_FALLBACKS.iter().enumerate() {
+        for (idx, endpoint) in endpoints.iter().enumerate() {
+            let base_url = endpoint.base_url.as_str();
             let url = Self::build_url(base_url, method, query_string);
-            let has_next = idx + 1 < V1_INTERNAL_BASE_URL_FALLBACKS.len();
+            let has_next = idx + 1 < endpoints.len();
 
+            let started = std::time::Instant::now();
             let response = client
                 .post(&url)
                 .headers(headers.clone())
@@ -286,13 +360,30 @@ impl UpstreamClient {
             match response {
                 Ok(resp) => {
                     let status = resp.status();
+                    if Self::is_endpoint_failure(status) {
+                        self.endpoint_health.record_failure(
+                            base_url,
+                            Some(started.elapsed()),
+                            format!("HTTP {}", status.as_u16()),
+                            &endpoints_cfg,
+                        );
+                    } else {
+                        self.endpoint_health.record_success(
+                            base_url,
+                            started.elapsed(),
+                            &endpoints_cfg,
+                        );
+                    }
+
                     if status.is_success() {
+                        // 记录当前请求账号的首字节延迟 (用于加权调度)
+                        crate::proxy::account_load::record_first_byte();
                         if idx > 0 {
                             tracing::info!(
                                 "✓ Upstream fallback succeeded | Endpoint: {} | Status: {} | Next endpoints available: {}",
                                 base_url,
                                 status,
-                                V1_INTERNAL_BASE_URL_FALLBACKS.len() - idx - 1
+                                endpoints.len() - idx - 1
                             );
                         } else {
                             tracing::debug!(
@@ -335,6 +426,8 @@ impl UpstreamClient {
                 Err(e) => {
                     let msg = format!("HTTP request failed at {}: {}", base_url, e);
                     tracing::debug!("{}", msg);
+                    self.endpoint_health
+                        .record_failure(base_url, None, e.to_string(), &endpoints_cfg);
                     // [NEW] 记录网络错误的降级尝试
                     fallback_attempts.push(FallbackAttemptLog {
                         endpoint_url: url.clone(),
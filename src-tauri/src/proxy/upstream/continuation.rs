@@ -0,0 +1,432 @@
+// MAX_TOKENS 自动续写
+// 上游单次输出存在上限，长代码生成常常在客户端要求的 max_tokens 之前就以 MAX_TOKENS 结束。
+// 启用后，这里在 Gemini 原始流这一层拦截 MAX_TOKENS：扣下该事件的 finishReason，流结束后把累计输出
+// 作为 model 角色 prefill 发起后续请求，续写内容接入同一条流，直到正常结束、达到续写次数上限，
+// 或输出 token 达到预算 (客户端 max_tokens 与配置的总预算取较小者)。
+//
+// 各分段的 usageMetadata 会被累加，mapper 最终看到的用量为所有分段之和。
+// 续写请求关闭思考 (prefill 之后不应再出现思考块)，已输出工具调用时不续写。
+
+use super::resume::{
+    build_resume_body, flush_pending_event, is_thought, parse_data_line, response_of_mut,
+    rewrite_continuation, BoundaryDedup, GeminiByteStream, ResumeContext,
+};
+use crate::proxy::account_load;
+use crate::proxy::config::AutoContinueConfig;
+use bytes::{Bytes, BytesMut};
+use futures::StreamExt;
+use serde_json::{json, Map, Value};
+use std::future::Future;
+use std::sync::Arc;
+
+/// 跟踪各分段的输出与用量，并改写事件
+#[derive(Debug)]
+struct ContinuationState {
+    cfg: AutoContinueConfig,
+    /// 输出 token 预算
+    budget: u64,
+    /// 已输出给客户端的正文文本 (prefill)
+    partial: String,
+    has_function_call: bool,
+    /// 已完成分段的用量之和
+    carried: Map<String, Value>,
+    /// 当前分段最近一次上报的用量 (上游为分段内累计值)
+    segment_usage: Map<String, Value>,
+    continuations: u32,
+    /// 当前分段以 MAX_TOKENS 结束且 finishReason 已被扣下
+    withheld: bool,
+    dedup: Option<BoundaryDedup>,
+}
+
+impl ContinuationState {
+    fn new(cfg: AutoContinueConfig, client_max_tokens: Option<u32>) -> Self {
+        let budget = client_max_tokens
+            .map(|m| (m as u64).min(cfg.max_total_tokens))
+            .unwrap_or(cfg.max_total_tokens);
+        Self {
+            cfg,
+            budget,
+            partial: String::new(),
+            has_function_call: false,
+            carried: Map::new(),
+            segment_usage: Map::new(),
+            continuations: 0,
+            withheld: false,
+            dedup: None,
+        }
+    }
+
+    /// 目前为止 (含当前分段) 的输出 token 数
+    fn output_tokens(&self) -> u64 {
+        output_tokens_of(&self.carried) + output_tokens_of(&self.segment_usage)
+    }
+
+    fn remaining(&self) -> u64 {
+        self.budget.saturating_sub(self.output_tokens())
+    }
+
+    fn can_continue(&self) -> bool {
+        !self.has_function_call
+            && !self.partial.is_empty()
+            && self.continuations < self.cfg.max_continuations
+            && self.remaining() > 0
+    }
+
+    /// 当前分段结束，进入下一段续写
+    fn begin_continuation(&mut self) {
+        let segment = std::mem::take(&mut self.segment_usage);
+        add_usage(&mut self.carried, &segment);
+        self.continuations += 1;
+        self.withheld = false;
+        self.dedup = Some(BoundaryDedup::default());
+    }
+
+    /// 处理一行完整的 SSE 数据，返回需要向下游输出的字节
+    fn process_line(&mut self, line: &[u8]) -> Option<Bytes> {
+        let Some(mut event) = parse_data_line(line) else {
+            return Some(Bytes::copy_from_slice(line));
+        };
+
+        let continued = self.continuations > 0;
+        let forward = if continued {
+            rewrite_continuation(&mut event, &self.partial, &mut self.dedup)
+        } else {
+            true
+        };
+        self.observe(&mut event);
+        if !continued && !self.withheld {
+            return Some(Bytes::copy_from_slice(line));
+        }
+        forward.then(|| Bytes::from(format!("data: {}\n", event)))
+    }
+
+    /// 记录输出与用量；续写分段的用量改写为累计值，可续写的 MAX_TOKENS 结束原因被扣下
+    fn observe(&mut self, event: &mut Value) {
+        let carried = &self.carried;
+        let response = response_of_mut(event);
+        if let Some(usage) = response.get_mut("usageMetadata").and_then(|u| u.as_object_mut()) {
+            self.segment_usage = usage.clone();
+            if !carried.is_empty() {
+                let mut total = carried.clone();
+                add_usage(&mut total, &self.segment_usage);
+                *usage = total;
+            }
+        }
+
+        let Some(candidate) = response
+            .get("candidates")
+            .and_then(|c| c.get(0))
+        else {
+            return;
+        };
+        if let Some(parts) = candidate
+            .get("content")
+            .and_then(|c| c.get("parts"))
+            .and_then(|p| p.as_array())
+        {
+            for part in parts {
+                if part.get("functionCall").is_some() {
+                    self.has_function_call = true;
+                } else if let Some(text) = part.get("text").and_then(|t| t.as_str()) {
+                    if !is_thought(part) {
+                        self.partial.push_str(text);
+                    }
+                }
+            }
+        }
+
+        let max_tokens = candidate.get("finishReason").and_then(|f| f.as_str()) == Some("MAX_TOKENS");
+        if max_tokens && self.can_continue() {
+            self.withheld = true;
+            if let Some(candidate) = response_of_mut(event)
+                .get_mut("candidates")
+                .and_then(|c| c.get_mut(0))
+                .and_then(|c| c.as_object_mut())
+            {
+                candidate.remove("finishReason");
+            }
+        }
+    }
+
+    /// 续写分段在边界确定前结束且没有结束事件时，补发观察中的非重叠文本
+    fn flush_pending(&mut self) -> Option<Bytes> {
+        let mut event = flush_pending_event(&self.partial, &mut self.dedup)?;
+        self.observe(&mut event);
+        Some(Bytes::from(format!("data: {}\n\n", event)))
+    }
+
+    /// 续写请求失败时补发被扣下的结束事件 (含累计用量)，保证下游 mapper 正常收尾
+    fn finish_event(&self) -> Bytes {
+        let mut usage = self.carried.clone();
+        add_usage(&mut usage, &self.segment_usage);
+        Bytes::from(format!(
+            "data: {}\n\n",
+            json!({
+                "candidates": [{ "content": { "role": "model", "parts": [] }, "finishReason": "MAX_TOKENS" }],
+                "usageMetadata": usage,
+            })
+        ))
+    }
+}
+
+fn output_tokens_of(usage: &Map<String, Value>) -> u64 {
+    ["candidatesTokenCount", "thoughtsTokenCount"]
+        .iter()
+        .filter_map(|k| usage.get(*k).and_then(|v| v.as_u64()))
+        .sum()
+}
+
+/// 按字段累加数值型用量
+fn add_usage(total: &mut Map<String, Value>, usage: &Map<String, Value>) {
+    for (key, value) in usage {
+        let Some(n) = value.as_u64() else {
+            continue;
+        };
+        let sum = total.get(key).and_then(|v| v.as_u64()).unwrap_or(0) + n;
+        total.insert(key.clone(), json!(sum));
+    }
+}
+
+/// 续写请求体：在续传请求体的基础上关闭思考，并把输出上限收紧到剩余预算
+fn build_continuation_body(original: &Value, project_id: &str, prefill: &str, remaining: u64) -> Value {
+    let mut body = build_resume_body(original, project_id, prefill);
+    if let Some(gen_config) = body
+        .get_mut("request")
+        .and_then(|r| r.get_mut("generationConfig"))
+        .and_then(|g| g.as_object_mut())
+    {
+        gen_config.remove("thinkingConfig");
+        let max_output = gen_config
+            .get("maxOutputTokens")
+            .and_then(|v| v.as_u64())
+            .map_or(remaining, |m| m.min(remaining));
+        gen_config.insert("maxOutputTokens".to_string(), json!(max_output));
+    }
+    body
+}
+
+/// 包装上游 Gemini 流：以 MAX_TOKENS 结束时按配置自动续写
+pub fn continuing_stream(
+    initial: GeminiByteStream,
+    ctx: ResumeContext,
+    cfg: AutoContinueConfig,
+    client_max_tokens: Option<u32>,
+) -> GeminiByteStream {
+    let lease_slot = account_load::current_slot();
+    let ctx = Arc::new(ctx);
+    continuing_with(initial, cfg, client_max_tokens, move |prefill, remaining| {
+        let ctx = ctx.clone();
+        let lease_slot = lease_slot.clone();
+        let prefill = prefill.to_string();
+        async move {
+            ctx.open_stream(lease_slot, false, "Auto-Continue", |project_id| {
+                build_continuation_body(&ctx.body, project_id, &prefill, remaining)
+            })
+            .await
+        }
+    })
+}
+
+fn continuing_with<F, Fut>(
+    initial: GeminiByteStream,
+    cfg: AutoContinueConfig,
+    client_max_tokens: Option<u32>,
+    reissue: F,
+) -> GeminiByteStream
+where
+    F: Fn(&str, u64) -> Fut + Send + 'static,
+    Fut: Future<Output = Result<GeminiByteStream, String>> + Send,
+{
+    Box::pin(async_stream::stream! {
+        let mut current = initial;
+        let mut state = ContinuationState::new(cfg, client_max_tokens);
+        let mut buffer = BytesMut::new();
+
+        loop {
+            match current.next().await {
+                Some(Ok(chunk)) => {
+                    buffer.extend_from_slice(&chunk);
+                    while let Some(pos) = buffer.iter().position(|&b| b == b'\n') {
+                        let line = buffer.split_to(pos + 1);
+                        if let Some(out) = state.process_line(&line) {
+                            yield Ok(out);
+                        }
+                    }
+                }
+                Some(Err(e)) => {
+                    yield Err(e);
+                    break;
+                }
+                None => {
+                    if !buffer.is_empty() {
+                        let line = buffer.split();
+                        if let Some(out) = state.process_line(&line) {
+                            yield Ok(out);
+                        }
+                    }
+                    if let Some(out) = state.flush_pending() {
+                        yield Ok(out);
+                    }
+                    if !state.withheld {
+                        break;
+                    }
+                    tracing::info!(
+                        "[Auto-Continue] MAX_TOKENS reached after {} output tokens, continuing with {} chars of prefill ({}/{})",
+                        state.output_tokens(),
+                        state.partial.chars().count(),
+                        state.continuations + 1,
+                        state.cfg.max_continuations
+                    );
+                    match reissue(&state.partial, state.remaining()).await {
+                        Ok(next) => {
+                            current = next;
+                            state.begin_continuation();
+                        }
+                        Err(err) => {
+                            tracing::warn!("[Auto-Continue] Continuation request failed: {}", err);
+                            yield Ok(state.finish_event());
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy::upstream::resume::response_of;
+
+    fn event(text: &str, finish: Option<&str>, output_tokens: u64) -> String {
+        let mut candidate = json!({ "content": { "role": "model", "parts": [{ "text": text }] } });
+        if let Some(reason) = finish {
+            candidate["finishReason"] = json!(reason);
+        }
+        format!(
+            "data: {}\n\n",
+            json!({ "response": {
+                "candidates": [candidate],
+                "usageMetadata": { "promptTokenCount": 10, "candidatesTokenCount": output_tokens }
+            } })
+        )
+    }
+
+    fn stream_of(chunks: Vec<String>) -> GeminiByteStream {
+        Box::pin(futures::stream::iter(
+            chunks.into_iter().map(|c| Ok(Bytes::from(c))),
+        ))
+    }
+
+    fn cfg() -> AutoContinueConfig {
+        AutoContinueConfig {
+            enabled: true,
+            ..AutoContinueConfig::default()
+        }
+    }
+
+    async fn collect_events(stream: GeminiByteStream) -> Vec<Value> {
+        let output: Vec<Result<Bytes, reqwest::Error>> = stream.collect().await;
+        let bytes: Vec<u8> = output.into_iter().flat_map(|c| c.unwrap().to_vec()).collect();
+        bytes
+            .split(|&b| b == b'\n')
+            .filter_map(parse_data_line)
+            .map(|e| response_of(&e).clone())
+            .collect()
+    }
+
+    fn text_of(events: &[Value]) -> String {
+        events
+            .iter()
+            .filter_map(|e| e["candidates"][0]["content"]["parts"].as_array())
+            .flatten()
+            .filter_map(|p| p["text"].as_str())
+            .collect()
+    }
+
+    fn finish_reasons(events: &[Value]) -> Vec<String> {
+        events
+            .iter()
+            .filter_map(|e| e["candidates"][0]["finishReason"].as_str())
+            .map(String::from)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_continues_and_sums_usage() {
+        let initial = stream_of(vec![
+            event("fn main() {", None, 2),
+            event("\n    println!(\"hi\");", Some("MAX_TOKENS"), 4),
+        ]);
+        let calls = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = calls.clone();
+        let stream = continuing_with(initial, cfg(), Some(100), move |prefill, remaining| {
+            seen.lock().unwrap().push((prefill.to_string(), remaining));
+            async { Ok(stream_of(vec![event("\n}\n", Some("STOP"), 3)])) }
+        });
+        let events = collect_events(stream).await;
+
+        assert_eq!(text_of(&events), "fn main() {\n    println!(\"hi\");\n}\n");
+        assert_eq!(finish_reasons(&events), vec!["STOP"]);
+        assert_eq!(
+            *calls.lock().unwrap(),
+            vec![("fn main() {\n    println!(\"hi\");".to_string(), 96)]
+        );
+        let usage = &events.last().unwrap()["usageMetadata"];
+        assert_eq!(usage["candidatesTokenCount"], 7);
+        assert_eq!(usage["promptTokenCount"], 20);
+    }
+
+    #[tokio::test]
+    async fn test_stops_at_client_max_tokens() {
+        // 上游恰好在客户端 max_tokens 处结束，属于客户端要求的截断
+        let initial = stream_of(vec![event("truncated", Some("MAX_TOKENS"), 50)]);
+        let stream = continuing_with(initial, cfg(), Some(50), |_, _| async {
+            Err::<GeminiByteStream, String>("unexpected continuation".to_string())
+        });
+        let events = collect_events(stream).await;
+        assert_eq!(finish_reasons(&events), vec!["MAX_TOKENS"]);
+    }
+
+    #[tokio::test]
+    async fn test_failed_continuation_restores_finish() {
+        let initial = stream_of(vec![event("partial", Some("MAX_TOKENS"), 5)]);
+        let stream = continuing_with(initial, cfg(), None, |_, _| async {
+            Err::<GeminiByteStream, String>("no account".to_string())
+        });
+        let events = collect_events(stream).await;
+        assert_eq!(finish_reasons(&events), vec!["MAX_TOKENS"]);
+        assert_eq!(events.last().unwrap()["usageMetadata"]["candidatesTokenCount"], 5);
+    }
+
+    #[tokio::test]
+    async fn test_flushes_short_continuation_held_for_dedup() {
+        // 续写内容短于最小重叠长度且恰好出现在 prefill 末尾，去重时被暂扣
+        for finish in [Some("STOP"), None] {
+            let initial = stream_of(vec![event("let x = 1;\nlet y", Some("MAX_TOKENS"), 5)]);
+            let stream = continuing_with(initial, cfg(), None, move |_, _| async move {
+                Ok(stream_of(vec![event(" = 1;", finish, 2)]))
+            });
+            let events = collect_events(stream).await;
+            assert_eq!(text_of(&events), "let x = 1;\nlet y = 1;");
+            assert_eq!(finish_reasons(&events), finish.into_iter().collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn test_continuation_body_disables_thinking() {
+        let original = json!({
+            "project": "p1",
+            "request": {
+                "contents": [{ "role": "user", "parts": [{ "text": "write" }] }],
+                "generationConfig": { "maxOutputTokens": 65536, "thinkingConfig": { "thinkingBudget": 1024 } }
+            }
+        });
+        let body = build_continuation_body(&original, "p1", "partial", 4000);
+        let gen_config = &body["request"]["generationConfig"];
+        assert!(gen_config.get("thinkingConfig").is_none());
+        assert_eq!(gen_config["maxOutputTokens"], 4000);
+        assert_eq!(body["request"]["contents"][1]["parts"][0]["text"], "partial");
+    }
+}
@@ -0,0 +1,305 @@
+// v1internal 端点健康跟踪
+// 为每个端点维护错误率与延迟的 EWMA，错误率超过阈值的端点在冷却期内被跳过，
+// 冷却期间由后台探测任务定期检查是否恢复
+
+use crate::proxy::config::{UpstreamEndpoint, UpstreamEndpointsConfig};
+use dashmap::DashMap;
+use serde::Serialize;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Default)]
+struct EndpointHealth {
+    /// 错误率 EWMA (0.0 - 1.0)
+    error_rate: f64,
+    /// 响应延迟 EWMA (毫秒，仅统计收到响应的请求)
+    latency_ms: Option<f64>,
+    /// 自上次恢复以来的样本数
+    samples: u64,
+    successes: u64,
+    failures: u64,
+    unhealthy_until: Option<Instant>,
+    last_error: Option<String>,
+    last_checked_at: Option<i64>,
+}
+
+/// 端点健康状态快照 (供 Admin API 展示)
+#[derive(Debug, Clone, Serialize)]
+pub struct EndpointHealthSnapshot {
+    pub name: String,
+    pub base_url: String,
+    pub enabled: bool,
+    pub healthy: bool,
+    pub error_rate: f64,
+    pub latency_ewma_ms: Option<f64>,
+    pub samples: u64,
+    pub successes: u64,
+    pub failures: u64,
+    /// 剩余冷却时间 (秒, 0 = 未冷却)
+    pub cooldown_remaining_secs: u64,
+    pub last_error: Option<String>,
+    pub last_checked_at: Option<i64>,
+}
+
+#[derive(Debug, Default)]
+pub struct EndpointHealthTracker {
+    entries: DashMap<String, EndpointHealth>,
+}
+
+impl EndpointHealthTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 记录一次成功响应 (端点可达且未返回 5xx / 408)
+    pub fn record_success(&self, base_url: &str, latency: Duration, cfg: &UpstreamEndpointsConfig) {
+        self.record_at(base_url, true, Some(latency), None, cfg, Instant::now());
+    }
+
+    /// 记录一次失败 (网络错误时 latency 为 None)
+    pub fn record_failure(
+        &self,
+        base_url: &str,
+        latency: Option<Duration>,
+        error: String,
+        cfg: &UpstreamEndpointsConfig,
+    ) {
+        self.record_at(base_url, false, latency, Some(error), cfg, Instant::now());
+    }
+
+    fn record_at(
+        &self,
+        base_url: &str,
+        ok: bool,
+        latency: Option<Duration>,
+        error: Option<String>,
+        cfg: &UpstreamEndpointsConfig,
+        now: Instant,
+    ) {
+        let alpha = cfg.ewma_alpha.clamp(0.01, 1.0);
+        let mut entry = self.entries.entry(base_url.to_string()).or_default();
+
+        let sample = if ok { 0.0 } else { 1.0 };
+        entry.error_rate = if entry.samples == 0 {
+            sample
+        } else {
+            alpha * sample + (1.0 - alpha) * entry.error_rate
+        };
+        if let Some(latency) = latency {
+            let ms = latency.as_secs_f64() * 1000.0;
+            entry.latency_ms = Some(match entry.latency_ms {
+                Some(prev) => alpha * ms + (1.0 - alpha) * prev,
+                None => ms,
+            });
+        }
+        entry.samples += 1;
+        entry.last_checked_at = Some(chrono::Utc::now().timestamp());
+
+        if ok {
+            entry.successes += 1;
+            entry.unhealthy_until = None;
+            return;
+        }
+
+        entry.failures += 1;
+        entry.last_error = error;
+        if entry.samples >= cfg.min_samples.max(1) as u64
+            && entry.error_rate >= cfg.error_rate_threshold
+        {
+            let already_cooling = entry.unhealthy_until.map_or(false, |t| t > now);
+            entry.unhealthy_until = Some(now + Duration::from_secs(cfg.cooldown_secs));
+            if !already_cooling {
+                tracing::warn!(
+                    "[Upstream-Endpoints] {} marked unhealthy (error_rate={:.2}), cooling down for {}s",
+                    base_url,
+                    entry.error_rate,
+                    cfg.cooldown_secs
+                );
+            }
+        }
+    }
+
+    /// 探测成功: 立即结束冷却并重置统计 (半开状态重新累计样本)
+    pub fn mark_recovered(&self, base_url: &str) {
+        if let Some(mut entry) = self.entries.get_mut(base_url) {
+            if entry.unhealthy_until.take().is_some() {
+                tracing::info!("[Upstream-Endpoints] {} recovered after probe", base_url);
+            }
+            entry.error_rate = 0.0;
+            entry.samples = 0;
+            entry.last_checked_at = Some(chrono::Utc::now().timestamp());
+        }
+    }
+
+    fn is_cooling_at(&self, base_url: &str, now: Instant) -> bool {
+        self.entries
+            .get(base_url)
+            .and_then(|e| e.unhealthy_until)
+            .map_or(false, |until| until > now)
+    }
+
+    /// 按优先级返回本次调用应尝试的端点
+    /// 冷却中的端点被跳过；若全部在冷却中则仍按原顺序全部尝试
+    pub fn select(&self, cfg: &UpstreamEndpointsConfig) -> Vec<UpstreamEndpoint> {
+        self.select_at(cfg, Instant::now())
+    }
+
+    fn select_at(&self, cfg: &UpstreamEndpointsConfig, now: Instant) -> Vec<UpstreamEndpoint> {
+        let mut enabled: Vec<UpstreamEndpoint> = cfg
+            .endpoints
+            .iter()
+            .filter(|e| e.enabled && !e.base_url.trim().is_empty())
+            .cloned()
+            .collect();
+        if enabled.is_empty() {
+            enabled = crate::proxy::config::default_upstream_endpoints();
+        }
+
+        let available: Vec<UpstreamEndpoint> = enabled
+            .iter()
+            .filter(|e| !self.is_cooling_at(&e.base_url, now))
+            .cloned()
+            .collect();
+
+        if available.is_empty() {
+            enabled
+        } else {
+            available
+        }
+    }
+
+    /// 当前处于冷却期的端点 (后台探测目标)
+    pub fn cooling_endpoints(&self, cfg: &UpstreamEndpointsConfig) -> Vec<UpstreamEndpoint> {
+        let now = Instant::now();
+        cfg.endpoints
+            .iter()
+            .filter(|e| e.enabled && self.is_cooling_at(&e.base_url, now))
+            .cloned()
+            .collect()
+    }
+
+    pub fn snapshot(&self, cfg: &UpstreamEndpointsConfig) -> Vec<EndpointHealthSnapshot> {
+        let now = Instant::now();
+        cfg.endpoints
+            .iter()
+            .map(|endpoint| {
+                let health = self
+                    .entries
+                    .get(&endpoint.base_url)
+                    .map(|e| e.clone())
+                    .unwrap_or_default();
+                let cooldown_remaining_secs = health
+                    .unhealthy_until
+                    .map(|until| until.saturating_duration_since(now).as_secs())
+                    .unwrap_or(0);
+                EndpointHealthSnapshot {
+                    name: endpoint.name.clone(),
+                    base_url: endpoint.base_url.clone(),
+                    enabled: endpoint.enabled,
+                    healthy: !self.is_cooling_at(&endpoint.base_url, now),
+                    error_rate: health.error_rate,
+                    latency_ewma_ms: health.latency_ms,
+                    samples: health.samples,
+                    successes: health.successes,
+                    failures: health.failures,
+                    cooldown_remaining_secs,
+                    last_error: health.last_error,
+                    last_checked_at: health.last_checked_at,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cfg() -> UpstreamEndpointsConfig {
+        UpstreamEndpointsConfig {
+            endpoints: vec![
+                UpstreamEndpoint {
+                    name: "a".to_string(),
+                    base_url: "https://a.example.com/v1internal".to_string(),
+                    enabled: true,
+                },
+                UpstreamEndpoint {
+                    name: "b".to_string(),
+                    base_url: "https://b.example.com/v1internal".to_string(),
+                    enabled: true,
+                },
+                UpstreamEndpoint {
+                    name: "c".to_string(),
+                    base_url: "https://c.example.com/v1internal".to_string(),
+                    enabled: false,
+                },
+            ],
+            error_rate_threshold: 0.5,
+            min_samples: 2,
+            cooldown_secs: 60,
+            probe_interval_secs: 30,
+            ewma_alpha: 0.5,
+        }
+    }
+
+    fn names(list: &[UpstreamEndpoint]) -> Vec<&str> {
+        list.iter().map(|e| e.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_unhealthy_endpoint_skipped_during_cooldown() {
+        let cfg = cfg();
+        let tracker = EndpointHealthTracker::new();
+        let now = Instant::now();
+        let a = &cfg.endpoints[0].base_url;
+
+        assert_eq!(names(&tracker.select_at(&cfg, now)), vec!["a", "b"]);
+
+        // 未达到最少样本数前不会被标记
+        tracker.record_at(a, false, None, Some("boom".into()), &cfg, now);
+        assert_eq!(names(&tracker.select_at(&cfg, now)), vec!["a", "b"]);
+
+        tracker.record_at(a, false, None, Some("boom".into()), &cfg, now);
+        assert_eq!(names(&tracker.select_at(&cfg, now)), vec!["b"]);
+
+        // 冷却结束后重新可用
+        let later = now + Duration::from_secs(61);
+        assert_eq!(names(&tracker.select_at(&cfg, later)), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn test_all_cooling_falls_back_to_full_list() {
+        let cfg = cfg();
+        let tracker = EndpointHealthTracker::new();
+        let now = Instant::now();
+        for endpoint in &cfg.endpoints[..2] {
+            for _ in 0..2 {
+                tracker.record_at(&endpoint.base_url, false, None, None, &cfg, now);
+            }
+        }
+        assert_eq!(names(&tracker.select_at(&cfg, now)), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn test_ewma_and_recovery() {
+        let cfg = cfg();
+        let tracker = EndpointHealthTracker::new();
+        let now = Instant::now();
+        let a = &cfg.endpoints[0].base_url;
+
+        tracker.record_at(a, true, Some(Duration::from_millis(100)), None, &cfg, now);
+        tracker.record_at(a, true, Some(Duration::from_millis(300)), None, &cfg, now);
+        tracker.record_at(a, false, None, Some("503".into()), &cfg, now);
+
+        let snap = &tracker.snapshot(&cfg)[0];
+        assert_eq!(snap.latency_ewma_ms, Some(200.0));
+        assert_eq!(snap.error_rate, 0.5);
+        assert!(!snap.healthy);
+        assert_eq!(snap.failures, 1);
+
+        tracker.mark_recovered(a);
+        let snap = &tracker.snapshot(&cfg)[0];
+        assert!(snap.healthy);
+        assert_eq!(snap.error_rate, 0.0);
+        assert_eq!(snap.cooldown_remaining_secs, 0);
+    }
+}
@@ -2,5 +2,8 @@
 // 对应上游通讯接口
 
 pub mod client;
+pub mod continuation; // MAX_TOKENS 自动续写
+pub mod endpoint_health;
+pub mod resume; // 流式响应中断续传
 pub mod retry;
 pub mod models;
@@ -0,0 +1,660 @@
+// 流式响应中断续传
+// 上游 SSE 在输出途中断开时，mapper 只能向客户端发送错误事件，已输出的部分回答随之作废。
+// 启用续传后，这里在 Gemini 原始流这一层拦截中断：把已输出的文本作为 model 角色 prefill 追加到原请求，
+// (可选轮换账号) 重新发起请求，并把续写内容无缝拼接进同一条流。mapper 的流状态保持不变，
+// 因此 Claude / OpenAI 客户端看到的仍是同一条消息。
+//
+// 边界去重：模型续写时偶尔会重复 prefill 末尾的若干字符，续写开头与已输出文本末尾的重叠部分会被裁掉。
+// 续写中的思考内容会被丢弃 (思考块已结束或已被正文取代)。已输出工具调用时不续传，避免重复调用。
+
+use crate::proxy::account_load::{self, LeaseSlot};
+use crate::proxy::config::StreamResumeConfig;
+use crate::proxy::upstream::client::UpstreamClient;
+use crate::proxy::TokenManager;
+use bytes::{Bytes, BytesMut};
+use futures::{Stream, StreamExt};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+tokio::task_local! {
+    /// 当前请求的续传次数 (由 monitor 中间件设置作用域)
+    static REQUEST_RESUMES: Arc<AtomicU32>;
+}
+
+pub type GeminiByteStream = Pin<Box<dyn Stream<Item = Result<Bytes, reqwest::Error>> + Send>>;
+
+/// 重叠长度低于该值时视为巧合 (如空格、标点)，不做裁剪
+const MIN_OVERLAP_BYTES: usize = 8;
+/// 只在已输出文本末尾的这一范围内查找重叠
+const MAX_OVERLAP_BYTES: usize = 512;
+
+/// 重新发起请求所需的上下文
+pub struct ResumeContext {
+    pub token_manager: Arc<TokenManager>,
+    pub upstream: Arc<UpstreamClient>,
+    /// 原始 v1internal 请求体
+    pub body: Value,
+    pub request_type: String,
+    pub model: String,
+    pub extra_headers: HashMap<String, String>,
+    pub trace_id: String,
+}
+
+impl ResumeContext {
+    /// 以 prefill 续写的方式重新发起流式请求
+    async fn reissue(
+        &self,
+        lease_slot: Option<LeaseSlot>,
+        prefill: &str,
+        rotate: bool,
+    ) -> Result<GeminiByteStream, String> {
+        self.open_stream(lease_slot, rotate, "Stream-Resume", |project_id| {
+            build_resume_body(&self.body, project_id, prefill)
+        })
+        .await
+    }
+
+    /// 取得账号并发起流式请求，请求体由 build 根据账号的项目 ID 生成
+    ///
+    /// 流在请求作用域外被消费，新账号的租约存入创建流时捕获的 lease_slot，
+    /// 替换并释放原上游流的租约，使续传请求同样受并发上限约束
+    pub(super) async fn open_stream(
+        &self,
+        lease_slot: Option<LeaseSlot>,
+        rotate: bool,
+        tag: &str,
+        build: impl FnOnce(&str) -> Value,
+    ) -> Result<GeminiByteStream, String> {
+        account_load::reacquire(lease_slot, self.open_stream_scoped(rotate, tag, build)).await
+    }
+
+    async fn open_stream_scoped(
+        &self,
+        rotate: bool,
+        tag: &str,
+        build: impl FnOnce(&str) -> Value,
+    ) -> Result<GeminiByteStream, String> {
+        let (access_token, project_id, email, account_id, _) = self
+            .token_manager
+            .get_token(&self.request_type, rotate, None, &self.model)
+            .await?;
+
+        let body = build(&project_id);
+        let call_result = self
+            .upstream
+            .call_v1_internal_with_headers(
+                "streamGenerateContent",
+                &access_token,
+                body,
+                Some("alt=sse"),
+                self.extra_headers.clone(),
+                Some(account_id.as_str()),
+            )
+            .await?;
+        let response = call_result.response;
+        let status = response.status();
+        if !status.is_success() {
+            let text = response.text().await.unwrap_or_default();
+            return Err(format!("HTTP {}: {}", status.as_u16(), text));
+        }
+        tracing::info!(
+            "[{}] [{}] Continuation stream opened on {}",
+            self.trace_id,
+            tag,
+            crate::proxy::upstream::client::mask_email(&email)
+        );
+        Ok(Box::pin(response.bytes_stream()))
+    }
+}
+
+/// 复制原请求体，替换项目 ID 与 requestId，并把已输出的文本作为 model 消息追加到 contents 末尾
+pub(super) fn build_resume_body(original: &Value, project_id: &str, prefill: &str) -> Value {
+    let mut body = original.clone();
+    body["project"] = json!(project_id);
+    body["requestId"] = json!(format!("agent-{}", uuid::Uuid::new_v4()));
+    if !prefill.is_empty() {
+        if let Some(contents) = body
+            .get_mut("request")
+            .and_then(|r| r.get_mut("contents"))
+            .and_then(|c| c.as_array_mut())
+        {
+            contents.push(json!({ "role": "model", "parts": [{ "text": prefill }] }));
+        }
+    }
+    body
+}
+
+/// 续写开头与已输出文本末尾的重叠裁剪
+#[derive(Debug, Default)]
+pub(super) struct BoundaryDedup {
+    /// 已收到但尚不能确定是否重叠的续写文本
+    pending: String,
+}
+
+impl BoundaryDedup {
+    /// 返回可输出的文本；第二项为 true 表示边界已确定，不再需要去重
+    pub(super) fn apply(&mut self, partial: &str, text: &str) -> (String, bool) {
+        let candidate = format!("{}{}", self.pending, text);
+        if candidate.len() < MAX_OVERLAP_BYTES && overlap_tail(partial).contains(candidate.as_str()) {
+            // 目前收到的内容仍可能是重叠的开头 (如 "fox " 之于 "fox jumps")，继续观察
+            self.pending = candidate;
+            return (String::new(), false);
+        }
+        self.pending.clear();
+        (trim_overlap(partial, &candidate).to_string(), true)
+    }
+
+    /// 续写流结束：按已确定的重叠裁剪仍在观察的文本并返回剩余部分
+    pub(super) fn flush(&mut self, partial: &str) -> String {
+        let pending = std::mem::take(&mut self.pending);
+        trim_overlap(partial, &pending).to_string()
+    }
+}
+
+fn trim_overlap<'a>(partial: &str, candidate: &'a str) -> &'a str {
+    let overlap = overlap_len(partial, candidate);
+    let overlap = if overlap < MIN_OVERLAP_BYTES { 0 } else { overlap };
+    &candidate[overlap..]
+}
+
+/// 已输出文本末尾用于查找重叠的范围
+fn overlap_tail(partial: &str) -> &str {
+    let tail_start = partial.len().saturating_sub(MAX_OVERLAP_BYTES);
+    let tail_start = (tail_start..=partial.len())
+        .find(|&i| partial.is_char_boundary(i))
+        .unwrap_or(partial.len());
+    &partial[tail_start..]
+}
+
+/// candidate 的最长前缀，使其恰好是 partial 的后缀
+fn overlap_len(partial: &str, candidate: &str) -> usize {
+    let tail = overlap_tail(partial);
+    (1..=candidate.len().min(tail.len()))
+        .rev()
+        .filter(|&k| candidate.is_char_boundary(k))
+        .find(|&k| tail.ends_with(&candidate[..k]))
+        .unwrap_or(0)
+}
+
+/// 跟踪已输出的内容，并在续写阶段改写上游事件
+#[derive(Debug, Default)]
+struct ResumeState {
+    /// 已输出给客户端的正文文本 (prefill)
+    partial: String,
+    has_output: bool,
+    has_function_call: bool,
+    finished: bool,
+    /// 是否处于续写阶段
+    resumed: bool,
+    dedup: Option<BoundaryDedup>,
+}
+
+impl ResumeState {
+    fn can_resume(&self) -> bool {
+        self.has_output && !self.has_function_call && !self.finished
+    }
+
+    fn begin_continuation(&mut self) {
+        self.resumed = true;
+        self.dedup = Some(BoundaryDedup::default());
+    }
+
+    /// 处理一行完整的 SSE 数据，返回需要向下游输出的字节
+    fn process_line(&mut self, line: &[u8]) -> Option<Bytes> {
+        let Some(mut event) = parse_data_line(line) else {
+            return Some(Bytes::copy_from_slice(line));
+        };
+
+        if !self.resumed {
+            self.observe(&event);
+            return Some(Bytes::copy_from_slice(line));
+        }
+
+        let forward = rewrite_continuation(&mut event, &self.partial, &mut self.dedup);
+        self.observe(&event);
+        forward.then(|| Bytes::from(format!("data: {}\n", event)))
+    }
+
+    fn observe(&mut self, event: &Value) {
+        let candidate = response_of(event)
+            .get("candidates")
+            .and_then(|c| c.get(0));
+        let Some(candidate) = candidate else {
+            return;
+        };
+        if let Some(parts) = candidate
+            .get("content")
+            .and_then(|c| c.get("parts"))
+            .and_then(|p| p.as_array())
+        {
+            for part in parts {
+                if part.get("functionCall").is_some() {
+                    self.has_function_call = true;
+                    self.has_output = true;
+                } else if let Some(text) = part.get("text").and_then(|t| t.as_str()) {
+                    if !text.is_empty() {
+                        self.has_output = true;
+                    }
+                    if !is_thought(part) {
+                        self.partial.push_str(text);
+                    }
+                }
+            }
+        }
+        if candidate.get("finishReason").is_some() {
+            self.finished = true;
+        }
+    }
+
+    /// 续写流在边界确定前结束且没有结束事件时，补发观察中的非重叠文本
+    fn flush_pending(&mut self) -> Option<Bytes> {
+        let event = flush_pending_event(&self.partial, &mut self.dedup)?;
+        self.observe(&event);
+        Some(Bytes::from(format!("data: {}\n\n", event)))
+    }
+}
+
+/// 改写续写事件：丢弃思考内容、裁剪边界重叠；返回该事件是否仍需输出
+pub(super) fn rewrite_continuation(
+    event: &mut Value,
+    partial: &str,
+    dedup: &mut Option<BoundaryDedup>,
+) -> bool {
+    let response = response_of_mut(event);
+    let has_usage = response.get("usageMetadata").is_some();
+    let Some(candidate) = response
+        .get_mut("candidates")
+        .and_then(|c| c.get_mut(0))
+    else {
+        return has_usage;
+    };
+    let finished = candidate.get("finishReason").is_some();
+
+    if let Some(parts) = candidate
+        .get_mut("content")
+        .and_then(|c| c.get_mut("parts"))
+        .and_then(|p| p.as_array_mut())
+    {
+        parts.retain(|part| !is_thought(part));
+        for part in parts.iter_mut() {
+            let Some(state) = dedup.as_mut() else {
+                break;
+            };
+            let Some(text) = part.get("text").and_then(|t| t.as_str()) else {
+                continue;
+            };
+            let (emit, settled) = state.apply(partial, text);
+            part["text"] = json!(emit);
+            if settled {
+                *dedup = None;
+            }
+        }
+        if finished {
+            // 续写在边界未确定前就结束了，输出观察中的非重叠部分
+            if let Some(rest) = dedup.take().map(|mut d| d.flush(partial)) {
+                if !rest.is_empty() {
+                    parts.push(json!({ "text": rest }));
+                }
+            }
+        }
+        parts.retain(|part| {
+            part.get("text").and_then(|t| t.as_str()) != Some("")
+                || part.get("functionCall").is_some()
+        });
+        if !parts.is_empty() {
+            return true;
+        }
+    }
+    finished || has_usage
+}
+
+/// 续写流结束时仍有观察中的文本：裁剪重叠后生成只含剩余文本的事件
+pub(super) fn flush_pending_event(partial: &str, dedup: &mut Option<BoundaryDedup>) -> Option<Value> {
+    let rest = dedup.take()?.flush(partial);
+    if rest.is_empty() {
+        return None;
+    }
+    Some(json!({
+        "candidates": [{ "content": { "role": "model", "parts": [{ "text": rest }] } }]
+    }))
+}
+
+pub(super) fn parse_data_line(line: &[u8]) -> Option<Value> {
+    let text = std::str::from_utf8(line).ok()?.trim();
+    let data = text.strip_prefix("data:")?.trim();
+    serde_json::from_str(data).ok()
+}
+
+pub(super) fn is_thought(part: &Value) -> bool {
+    part.get("thought").and_then(|t| t.as_bool()).unwrap_or(false)
+}
+
+/// v1internal 事件的实际响应 (可能包在 response 字段中)
+pub(super) fn response_of(event: &Value) -> &Value {
+    event.get("response").unwrap_or(event)
+}
+
+pub(super) fn response_of_mut(event: &mut Value) -> &mut Value {
+    if event.get("response").is_some() {
+        &mut event["response"]
+    } else {
+        event
+    }
+}
+
+/// 包装上游 Gemini 流：输出途中断开时按配置续传，续传失败时把原错误交给下游处理
+pub fn resumable_stream(
+    initial: GeminiByteStream,
+    ctx: ResumeContext,
+    cfg: StreamResumeConfig,
+) -> GeminiByteStream {
+    let counter = current_counter();
+    let lease_slot = account_load::current_slot();
+    let ctx = Arc::new(ctx);
+    resumable_with(initial, cfg, counter, move |prefill, rotate| {
+        let ctx = ctx.clone();
+        let lease_slot = lease_slot.clone();
+        let prefill = prefill.to_string();
+        async move { ctx.reissue(lease_slot, &prefill, rotate).await }
+    })
+}
+
+fn resumable_with<F, Fut>(
+    initial: GeminiByteStream,
+    cfg: StreamResumeConfig,
+    counter: Option<Arc<AtomicU32>>,
+    reissue: F,
+) -> GeminiByteStream
+where
+    F: Fn(&str, bool) -> Fut + Send + 'static,
+    Fut: Future<Output = Result<GeminiByteStream, String>> + Send,
+{
+    Box::pin(async_stream::stream! {
+        let mut current = initial;
+        let mut state = ResumeState::default();
+        let mut buffer = BytesMut::new();
+        let mut resumes = 0u32;
+
+        'outer: loop {
+            match current.next().await {
+                Some(Ok(chunk)) => {
+                    buffer.extend_from_slice(&chunk);
+                    while let Some(pos) = buffer.iter().position(|&b| b == b'\n') {
+                        let line = buffer.split_to(pos + 1);
+                        if let Some(out) = state.process_line(&line) {
+                            yield Ok(out);
+                        }
+                    }
+                }
+                Some(Err(e)) => {
+                    if !state.can_resume() {
+                        yield Err(e);
+                        break;
+                    }
+                    // 丢弃中断时未完整接收的半行事件
+                    buffer.clear();
+                    while resumes < cfg.max_resumes {
+                        resumes += 1;
+                        if let Some(counter) = &counter {
+                            counter.fetch_add(1, Ordering::Relaxed);
+                        }
+                        tracing::warn!(
+                            "[Stream-Resume] Upstream stream interrupted ({}), resuming with {} chars of prefill (attempt {}/{})",
+                            e, state.partial.chars().count(), resumes, cfg.max_resumes
+                        );
+                        match reissue(&state.partial, cfg.rotate_account).await {
+                            Ok(next) => {
+                                current = next;
+                                state.begin_continuation();
+                                continue 'outer;
+                            }
+                            Err(err) => {
+                                tracing::warn!("[Stream-Resume] Resume attempt {} failed: {}", resumes, err);
+                            }
+                        }
+                    }
+                    yield Err(e);
+                    break;
+                }
+                None => {
+                    if !buffer.is_empty() {
+                        if let Some(out) = state.process_line(&buffer) {
+                            yield Ok(out);
+                        }
+                    }
+                    if let Some(out) = state.flush_pending() {
+                        yield Ok(out);
+                    }
+                    break;
+                }
+            }
+        }
+    })
+}
+
+/// 在请求作用域内执行 future，期间发生的续传会累计到 counter
+pub async fn scope_request<F: Future>(counter: Arc<AtomicU32>, fut: F) -> F::Output {
+    REQUEST_RESUMES.scope(counter, fut).await
+}
+
+/// 当前请求的续传计数器 (流在请求作用域外被消费，需在创建时捕获)
+fn current_counter() -> Option<Arc<AtomicU32>> {
+    REQUEST_RESUMES.try_with(|c| c.clone()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text_event(text: &str) -> String {
+        format!(
+            "data: {}\n\n",
+            json!({ "response": { "candidates": [{ "content": { "role": "model", "parts": [{ "text": text }] } }] } })
+        )
+    }
+
+    fn final_event(text: &str) -> String {
+        format!(
+            "data: {}\n\n",
+            json!({ "response": {
+                "candidates": [{ "content": { "parts": [{ "text": text }] }, "finishReason": "STOP" }],
+                "usageMetadata": { "candidatesTokenCount": 3 }
+            } })
+        )
+    }
+
+    fn collect_text(output: &[u8]) -> String {
+        let mut state = ResumeState::default();
+        for line in output.split_inclusive(|&b| b == b'\n') {
+            state.process_line(line);
+        }
+        state.partial
+    }
+
+    /// 构造一个在若干数据块后以真实 reqwest 错误结束的流
+    async fn interrupted(chunks: Vec<String>) -> GeminiByteStream {
+        let error = reqwest::Client::new()
+            .get("http://127.0.0.1:1")
+            .send()
+            .await
+            .unwrap_err();
+        let items: Vec<Result<Bytes, reqwest::Error>> = chunks
+            .into_iter()
+            .map(|c| Ok(Bytes::from(c)))
+            .chain(std::iter::once(Err(error)))
+            .collect();
+        Box::pin(futures::stream::iter(items))
+    }
+
+    fn ok_stream(chunks: Vec<String>) -> GeminiByteStream {
+        Box::pin(futures::stream::iter(
+            chunks.into_iter().map(|c| Ok(Bytes::from(c))),
+        ))
+    }
+
+    fn cfg() -> StreamResumeConfig {
+        StreamResumeConfig {
+            enabled: true,
+            ..StreamResumeConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_boundary_dedup() {
+        let partial = "The quick brown fox jumps";
+        let mut dedup = BoundaryDedup::default();
+        // 续写重复了末尾 "fox jumps"，分两块到达
+        assert_eq!(dedup.apply(partial, "fox "), (String::new(), false));
+        assert_eq!(
+            dedup.apply(partial, "jumps over the lazy dog"),
+            (" over the lazy dog".to_string(), true)
+        );
+
+        // 短的巧合重叠 (空格) 不裁剪
+        let mut dedup = BoundaryDedup::default();
+        assert_eq!(dedup.apply("Hello ", " world, again"), (" world, again".to_string(), true));
+        assert_eq!(overlap_len("你好世界", "世界和平"), "世界".len());
+
+        // 续写在边界确定前结束：完整重叠丢弃，其余照常输出
+        let mut dedup = BoundaryDedup::default();
+        assert_eq!(dedup.apply(partial, "fox jumps"), (String::new(), false));
+        assert_eq!(dedup.flush(partial), "");
+        let mut dedup = BoundaryDedup::default();
+        assert_eq!(dedup.apply(partial, "fox"), (String::new(), false));
+        assert_eq!(dedup.flush(partial), "fox");
+    }
+
+    #[test]
+    fn test_resume_body_appends_prefill() {
+        let original = json!({
+            "project": "p1",
+            "requestId": "agent-1",
+            "request": { "contents": [{ "role": "user", "parts": [{ "text": "hi" }] }] }
+        });
+        let body = build_resume_body(&original, "p2", "Hello");
+        assert_eq!(body["project"], "p2");
+        assert_ne!(body["requestId"], "agent-1");
+        let contents = body["request"]["contents"].as_array().unwrap();
+        assert_eq!(contents.len(), 2);
+        assert_eq!(contents[1]["role"], "model");
+        assert_eq!(contents[1]["parts"][0]["text"], "Hello");
+    }
+
+    #[tokio::test]
+    async fn test_splices_continuation_after_interruption() {
+        let initial = interrupted(vec![
+            text_event("The quick brown "),
+            text_event("fox jumps"),
+            // 中断时未完整接收的半行
+            "data: {\"response\": {\"cand".to_string(),
+        ])
+        .await;
+        let prefills = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = prefills.clone();
+        let counter = Arc::new(AtomicU32::new(0));
+
+        let stream = resumable_with(initial, cfg(), Some(counter.clone()), move |prefill, _| {
+            seen.lock().unwrap().push(prefill.to_string());
+            async {
+                Ok(ok_stream(vec![
+                    format!(
+                        "data: {}\n\n",
+                        json!({ "response": { "candidates": [{ "content": { "parts": [
+                            { "text": "planning", "thought": true },
+                            { "text": "fox jumps over" }
+                        ] } }] } })
+                    ),
+                    final_event(" the lazy dog."),
+                ]))
+            }
+        });
+        let output: Vec<Result<Bytes, reqwest::Error>> = stream.collect().await;
+        assert!(output.iter().all(|c| c.is_ok()));
+        let bytes: Vec<u8> = output.into_iter().flat_map(|c| c.unwrap().to_vec()).collect();
+
+        assert_eq!(collect_text(&bytes), "The quick brown fox jumps over the lazy dog.");
+        assert!(!String::from_utf8_lossy(&bytes).contains("planning"));
+        assert_eq!(*prefills.lock().unwrap(), vec!["The quick brown fox jumps".to_string()]);
+        assert_eq!(counter.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn test_gives_up_after_max_resumes() {
+        let initial = interrupted(vec![text_event("partial answer")]).await;
+        let attempts = Arc::new(AtomicU32::new(0));
+        let seen = attempts.clone();
+        let stream = resumable_with(initial, cfg(), None, move |_, _| {
+            seen.fetch_add(1, Ordering::Relaxed);
+            async { Err::<GeminiByteStream, String>("no account".to_string()) }
+        });
+        let output: Vec<Result<Bytes, reqwest::Error>> = stream.collect().await;
+        assert_eq!(attempts.load(Ordering::Relaxed), 2);
+        assert!(output.last().unwrap().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_resume_moves_lease_to_new_account() {
+        use crate::proxy::account_load::{AccountLoadTracker, ConcurrencyLimits};
+
+        let tracker = Arc::new(AccountLoadTracker::new());
+        let slot = account_load::new_slot();
+        let initial = interrupted(vec![text_event("partial answer")]).await;
+
+        // 流在请求作用域内创建，但在作用域外 (响应体轮询时) 被消费
+        let stream = account_load::scope_request(slot.clone(), async {
+            account_load::attach(
+                tracker
+                    .try_begin("acc-1", "m", ConcurrencyLimits::default())
+                    .unwrap(),
+            );
+            let lease_slot = account_load::current_slot();
+            let tracker = tracker.clone();
+            resumable_with(initial, cfg(), None, move |_, _| {
+                let tracker = tracker.clone();
+                account_load::reacquire(lease_slot.clone(), async move {
+                    // 与 get_token 相同：新租约挂到当前请求
+                    account_load::attach(
+                        tracker
+                            .try_begin("acc-2", "m", ConcurrencyLimits::default())
+                            .unwrap(),
+                    );
+                    Ok(ok_stream(vec![final_event(" done")]))
+                })
+            })
+        })
+        .await;
+        assert_eq!(tracker.in_flight("acc-1"), 1);
+
+        let output: Vec<Result<Bytes, reqwest::Error>> = stream.collect().await;
+        assert!(output.iter().all(|r| r.is_ok()));
+        assert_eq!(tracker.in_flight("acc-1"), 0);
+        assert_eq!(tracker.in_flight("acc-2"), 1);
+
+        slot.lock().unwrap().take();
+        assert_eq!(tracker.in_flight("acc-2"), 0);
+    }
+
+    #[tokio::test]
+    async fn test_does_not_resume_after_function_call() {
+        let call = format!(
+            "data: {}\n\n",
+            json!({ "response": { "candidates": [{ "content": { "parts": [
+                { "functionCall": { "name": "ls", "args": {} } }
+            ] } }] } })
+        );
+        let initial = interrupted(vec![call]).await;
+        let attempts = Arc::new(AtomicU32::new(0));
+        let seen = attempts.clone();
+        let stream = resumable_with(initial, cfg(), None, move |_, _| {
+            seen.fetch_add(1, Ordering::Relaxed);
+            async { Err::<GeminiByteStream, String>("unexpected resume".to_string()) }
+        });
+        let output: Vec<Result<Bytes, reqwest::Error>> = stream.collect().await;
+        assert_eq!(attempts.load(Ordering::Relaxed), 0);
+        assert!(output.last().unwrap().is_err());
+    }
+}
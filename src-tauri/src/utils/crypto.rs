@@ -8,6 +8,7 @@ use sha2::Digest;
 
 const FIXED_NONCE: &[u8; 12] = b"antigravsalt";
 const ENCRYPTED_PREFIX: &str = "ag_enc_";
+const BLOB_PREFIX: &str = "ag_blob_";
 
 /// 生成加密密钥 (基于设备 ID)
 fn get_encryption_key() -> [u8; 32] {
@@ -111,6 +112,52 @@ pub fn decrypt_string(encrypted: &str) -> Result<String, String> {
     }
 }
 
+/// 加密任意长度文本 (随机 nonce，适用于日志 body 等大量重复加密场景)
+/// 输出格式: ag_blob_ + Base64(nonce || ciphertext)
+pub fn encrypt_blob(plaintext: &str) -> Result<String, String> {
+    let key = get_encryption_key();
+    let cipher = Aes256Gcm::new(&key.into());
+    let nonce_bytes: [u8; 12] = rand::random();
+    let nonce = Nonce::from_slice(&nonce_bytes);
+
+    let ciphertext = cipher
+        .encrypt(nonce, plaintext.as_bytes())
+        .map_err(|e| format!("Encryption failed: {}", e))?;
+
+    let mut payload = Vec::with_capacity(nonce_bytes.len() + ciphertext.len());
+    payload.extend_from_slice(&nonce_bytes);
+    payload.extend_from_slice(&ciphertext);
+    Ok(format!("{}{}", BLOB_PREFIX, general_purpose::STANDARD.encode(payload)))
+}
+
+/// 判断文本是否为 encrypt_blob 的输出
+pub fn is_encrypted_blob(value: &str) -> bool {
+    value.starts_with(BLOB_PREFIX)
+}
+
+/// 解密 encrypt_blob 的输出；非加密文本原样返回
+pub fn decrypt_blob(value: &str) -> Result<String, String> {
+    let Some(encoded) = value.strip_prefix(BLOB_PREFIX) else {
+        return Ok(value.to_string());
+    };
+
+    let payload = general_purpose::STANDARD
+        .decode(encoded)
+        .map_err(|e| format!("Base64 decode failed: {}", e))?;
+    if payload.len() < 12 {
+        return Err("Encrypted payload too short".to_string());
+    }
+    let (nonce_bytes, ciphertext) = payload.split_at(12);
+
+    let key = get_encryption_key();
+    let cipher = Aes256Gcm::new(&key.into());
+    let plaintext = cipher
+        .decrypt(Nonce::from_slice(nonce_bytes), ciphertext)
+        .map_err(|e| format!("Decryption failed: {}", e))?;
+
+    String::from_utf8(plaintext).map_err(|e| format!("UTF-8 conversion failed: {}", e))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -143,4 +190,20 @@ mod tests {
         let decrypted = decrypt_string(&legacy_encrypted).unwrap();
         assert_eq!(password, decrypted);
     }
+
+    #[test]
+    fn test_blob_encrypt_decrypt_cycle() {
+        let body = r#"{"messages":[{"role":"user","content":"hi"}]}"#;
+        let first = encrypt_blob(body).unwrap();
+        let second = encrypt_blob(body).unwrap();
+
+        assert!(is_encrypted_blob(&first));
+        // 随机 nonce: 相同明文的密文不同
+        assert_ne!(first, second);
+        assert_eq!(decrypt_blob(&first).unwrap(), body);
+        assert_eq!(decrypt_blob(&second).unwrap(), body);
+
+        // 明文直接透传
+        assert_eq!(decrypt_blob("plain text").unwrap(), "plain text");
+    }
 }
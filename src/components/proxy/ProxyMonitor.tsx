@@ -28,6 +28,10 @@ interface ProxyRequestLog {
     output_tokens?: number;
     account_email?: string;
     protocol?: string;  // "openai" | "anthropic" | "gemini"
+    retries?: number;   // 上游重试次数
+    resumes?: number;   // 流式中断续传次数
+    image_bytes_saved?: number;  // 图片预处理节省字节数
+    image_tokens_saved?: number; // 图片预处理节省 token 数
 }
 
 interface ProxyStats {
@@ -17,6 +17,7 @@ interface IpWhitelistConfig {
 interface SecurityMonitorConfig {
     blacklist: IpBlacklistConfig;
     whitelist: IpWhitelistConfig;
+    trust_proxy_headers?: boolean;
 }
 
 export const SecurityConfig: React.FC = () => {
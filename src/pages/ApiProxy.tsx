@@ -1775,7 +1775,7 @@ print(response.text)`;
                                                 </div>
                                             </div>
                                             <div className="grid grid-cols-1 gap-2">
-                                                {(['CacheFirst', 'Balance', 'PerformanceFirst'] as const).map(mode => (
+                                                {(['CacheFirst', 'Balance', 'PerformanceFirst', 'Weighted'] as const).map(mode => (
                                                     <label
                                                         key={mode}
                                                         className={`flex items-start gap-3 p-3 rounded-xl border cursor-pointer transition-all duration-200 ${(appConfig.proxy.scheduling?.mode || 'Balance') === mode
@@ -1797,7 +1797,8 @@ print(response.text)`;
                                                                 {t(`proxy.config.scheduling.modes_desc.${mode}`, {
                                                                     defaultValue: mode === 'CacheFirst' ? 'Binds session to account, waits precisely if limited (Maximizes Prompt Cache hits).' :
                                                                         mode === 'Balance' ? 'Binds session, auto-switches to available account if limited (Balanced cache & availability).' :
-                                                                            'No session binding, pure round-robin rotation (Best for high concurrency).'
+                                                                            mode === 'Weighted' ? 'No session binding, weighted random by quota, health, latency and in-flight load (Smooth load spreading).' :
+                                                                                'No session binding, pure round-robin rotation (Best for high concurrency).'
                                                                 })}
                                                             </div>
                                                         </div>
@@ -22,6 +22,7 @@ interface UserToken {
     last_used_at?: number;
     total_requests: number;
     total_tokens_used: number;
+    priority?: number;
 }
 
 interface UserTokenStats {
@@ -49,6 +50,7 @@ const UserToken: React.FC = () => {
     const [editMaxIps, setEditMaxIps] = useState(0);
     const [editCurfewStart, setEditCurfewStart] = useState('');
     const [editCurfewEnd, setEditCurfewEnd] = useState('');
+    const [editPriority, setEditPriority] = useState(0);
     const [updating, setUpdating] = useState(false);
 
     // Create Form State
@@ -59,6 +61,7 @@ const UserToken: React.FC = () => {
     const [newCurfewStart, setNewCurfewStart] = useState('');
     const [newCurfewEnd, setNewCurfewEnd] = useState('');
     const [newCustomExpires, setNewCustomExpires] = useState(''); // datetime-local value
+    const [newPriority, setNewPriority] = useState(0);
 
     const loadData = async () => {
         setLoading(true);
@@ -108,7 +111,8 @@ const UserToken: React.FC = () => {
                     max_ips: newMaxIps,
                     curfew_start: newCurfewStart || null,
                     curfew_end: newCurfewEnd || null,
-                    custom_expires_at: customExpiresAt || null
+                    custom_expires_at: customExpiresAt || null,
+                    priority: newPriority
                 }
             });
             showToast(t('common.create_success') || 'Created successfully', 'success');
@@ -120,6 +124,7 @@ const UserToken: React.FC = () => {
             setNewCurfewStart('');
             setNewCurfewEnd('');
             setNewCustomExpires('');
+            setNewPriority(0);
             loadData();
         } catch (e) {
             console.error('Failed to create token', e);
@@ -147,6 +152,7 @@ const UserToken: React.FC = () => {
         setEditMaxIps(token.max_ips ?? 0);  // 使用 ?? 确保 null/undefined 变为 0
         setEditCurfewStart(token.curfew_start ?? '');
         setEditCurfewEnd(token.curfew_end ?? '');
+        setEditPriority(token.priority ?? 0);
         setShowEditModal(true);
     };
 
@@ -167,7 +173,8 @@ const UserToken: React.FC = () => {
                     max_ips: editMaxIps,
                     // 使用双层包装: undefined = 不更新, null = 清空, string = 设置值
                     curfew_start: editCurfewStart === '' ? null : editCurfewStart,
-                    curfew_end: editCurfewEnd === '' ? null : editCurfewEnd
+                    curfew_end: editCurfewEnd === '' ? null : editCurfewEnd,
+                    priority: editPriority
                 }
             });
             showToast(t('common.update_success') || 'Updated successfully', 'success');
@@ -523,6 +530,21 @@ const UserToken: React.FC = () => {
                             </div>
                         </div>
 
+                        <div className="form-control w-full mb-3">
+                            <label className="label">
+                                <span className="label-text">{t('user_token.priority', { defaultValue: 'Queue Priority' })}</span>
+                            </label>
+                            <input
+                                type="number"
+                                className="input input-bordered w-full"
+                                value={newPriority}
+                                onChange={e => setNewPriority(parseInt(e.target.value) || 0)}
+                            />
+                            <label className="label">
+                                <span className="label-text-alt text-gray-500">{t('user_token.hint_priority', { defaultValue: 'Higher values are served first when requests are queued' })}</span>
+                            </label>
+                        </div>
+
                         {/* Custom Expiration Time Picker */}
                         {newExpiresType === 'custom' && (
                             <div className="form-control w-full mb-3">
@@ -632,6 +654,21 @@ const UserToken: React.FC = () => {
                             </label>
                         </div>
 
+                        <div className="form-control w-full mb-3">
+                            <label className="label">
+                                <span className="label-text">{t('user_token.priority', { defaultValue: 'Queue Priority' })}</span>
+                            </label>
+                            <input
+                                type="number"
+                                className="input input-bordered w-full"
+                                value={editPriority}
+                                onChange={e => setEditPriority(parseInt(e.target.value) || 0)}
+                            />
+                            <label className="label">
+                                <span className="label-text-alt text-gray-500">{t('user_token.hint_priority', { defaultValue: 'Higher values are served first when requests are queued' })}</span>
+                            </label>
+                        </div>
+
                         <div className="form-control w-full mb-3">
                             <label className="label">
                                 <span className="label-text">{t('user_token.curfew', { defaultValue: 'Curfew (Service Unavailable Time)' })}</span>
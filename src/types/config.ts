@@ -25,6 +25,200 @@ export interface ProxyConfig {
     global_system_prompt?: GlobalSystemPromptConfig;
     image_thinking_mode?: 'enabled' | 'disabled'; // [NEW] 图像思维模式开关
     proxy_pool?: ProxyPoolConfig;
+    log_retention?: LogRetentionConfig;
+    retry_policy?: RetryPolicyConfig;
+    upstream_endpoints?: UpstreamEndpointsConfig;
+    admission_queue?: AdmissionQueueConfig;
+    fair_scheduling?: FairSchedulingConfig;
+    hedging?: HedgingConfig;
+    stream_heartbeat?: StreamHeartbeatConfig;
+    auto_continue?: AutoContinueConfig;
+    tool_result_compression?: ToolResultCompressionConfig;
+    image_preprocess?: ImagePreprocessConfig;
+    image_url?: ImageUrlConfig;
+    image_store?: ImageStoreConfig;
+}
+
+// ============================================================================
+// 日志保留策略配置 (保留天数为 0 表示永久保留)
+// ============================================================================
+
+export interface LogRetentionConfig {
+    request_logs_days: number;
+    ip_access_logs_days: number;
+    token_usage_logs_days: number;
+    token_usage_days: number;
+    /** 单个 body 最大存储字节数 (0 = 不限制) */
+    max_body_bytes: number;
+    /** 成功请求 body 采样率 (0-1) */
+    success_body_sample_rate: number;
+    /** 失败请求 body 采样率 (0-1) */
+    error_body_sample_rate: number;
+    encrypt_bodies: boolean;
+    /** VACUUM 间隔 (小时, 0 = 禁用) */
+    vacuum_interval_hours: number;
+}
+
+export type BackoffKind = 'none' | 'fixed' | 'linear' | 'exponential';
+
+export type RetryErrorClass =
+    | 'thinking_signature'
+    | 'quota_exhausted'
+    | 'rate_limited'
+    | 'overloaded'
+    | 'server_error'
+    | 'auth'
+    | 'not_found'
+    | 'other';
+
+export interface RetryRule {
+    /** 匹配的状态码 (空 = 任意) */
+    statuses: number[];
+    error_class?: RetryErrorClass | null;
+    /** 该类错误最多尝试次数 (0 = 仅受请求级上限约束) */
+    max_attempts: number;
+    backoff: BackoffKind;
+    base_ms: number;
+    max_ms: number;
+    /** 抖动比例 (0-1) */
+    jitter: number;
+    rotate_account: boolean;
+    respect_retry_after: boolean;
+}
+
+export interface RetryBudgetConfig {
+    enabled: boolean;
+    /** 每个请求存入的重试令牌数 */
+    ratio: number;
+    min_retries_per_sec: number;
+    max_tokens: number;
+}
+
+export interface StreamResumeConfig {
+    enabled: boolean;
+    /** 单个请求最多续传次数 */
+    max_resumes: number;
+    rotate_account: boolean;
+}
+
+export interface RetryPolicyConfig {
+    rules: RetryRule[];
+    budget: RetryBudgetConfig;
+    stream_resume?: StreamResumeConfig;
+}
+
+export interface UpstreamEndpoint {
+    name: string;
+    base_url: string;
+    enabled: boolean;
+}
+
+export interface AdmissionQueueConfig {
+    enabled: boolean;
+    /** 最大排队请求数，超出时返回 429 / 529 */
+    max_queue_length: number;
+    /** 单个请求的最长排队时间 (秒) */
+    max_wait_seconds: number;
+}
+
+export interface FairSchedulingConfig {
+    enabled: boolean;
+    /** 全局最大并发请求数 (0 = 不限制) */
+    max_concurrent_requests: number;
+    max_wait_seconds: number;
+    default_weight: number;
+    /** 用户令牌 ID 或用户名 -> 权重 */
+    weights: Record<string, number>;
+}
+
+export interface StreamHeartbeatConfig {
+    enabled: boolean;
+    /** 上游静默多少秒后发送心跳 */
+    idle_seconds: number;
+}
+
+export type ToolCompressionStrategy = 'default' | 'head_tail' | 'stack_trace' | 'diff' | 'json_array';
+
+export interface ToolCompressionRule {
+    /** 工具名匹配模式 (支持 * 通配符，不区分大小写) */
+    patterns: string[];
+    strategy: ToolCompressionStrategy;
+    max_chars: number;
+}
+
+export interface ToolResultCompressionConfig {
+    /** 按顺序匹配，命中第一条规则即停止 */
+    rules: ToolCompressionRule[];
+    default_max_chars: number;
+}
+
+export type ImageOutputFormat = 'jpeg' | 'webp' | 'png';
+
+export interface ImagePreprocessConfig {
+    enabled: boolean;
+    /** 最大宽高 (像素)，超出时等比缩放 */
+    max_width: number;
+    max_height: number;
+    output_format: ImageOutputFormat;
+    /** 有损编码质量 (1-100，仅 JPEG 输出使用) */
+    quality: number;
+    /** 小于该字节数且无需缩放/转换的图片保持原样 */
+    min_bytes: number;
+}
+
+export interface ImageUrlConfig {
+    /** 单张图片最大字节数 */
+    max_bytes: number;
+    timeout_secs: number;
+    /** 按 URL 缓存的时长 (秒，0 = 不缓存) */
+    cache_ttl_secs: number;
+    cache_max_entries: number;
+    /** 允许下载内网地址的图片 (默认拒绝，防止 SSRF) */
+    allow_private_networks: boolean;
+    /** file:// 图片目录白名单 (空 = 禁用) */
+    local_file_dirs: string[];
+    /** 允许非本机客户端读取本地文件 */
+    allow_remote_local_files: boolean;
+}
+
+export interface ImageStoreConfig {
+    enabled: boolean;
+    /** 保留天数 (0 = 不按时间清理) */
+    retention_days: number;
+    /** 最多保留图片数 (0 = 不限) */
+    max_images: number;
+    /** 图片总大小上限 (MB，0 = 不限) */
+    max_total_mb: number;
+    /** 生成 URL 使用的外部地址；为空时按请求 Host 推断 */
+    public_base_url: string;
+}
+
+export interface AutoContinueConfig {
+    enabled: boolean;
+    /** 单个请求最多续写次数 */
+    max_continuations: number;
+    /** 所有分段合计的输出 token 预算 */
+    max_total_tokens: number;
+}
+
+export interface HedgingConfig {
+    enabled: boolean;
+    /** 固定对冲阈值 (毫秒)，0 = 按模型学习的 p95 */
+    threshold_ms: number;
+    min_threshold_ms: number;
+    cold_start_threshold_ms: number;
+}
+
+export interface UpstreamEndpointsConfig {
+    /** 按优先级排列的 v1internal 端点 */
+    endpoints: UpstreamEndpoint[];
+    /** 错误率阈值 (0-1)，超过后进入冷却 */
+    error_rate_threshold: number;
+    min_samples: number;
+    cooldown_secs: number;
+    /** 后台探测间隔 (秒, 0 = 禁用) */
+    probe_interval_secs: number;
+    ewma_alpha: number;
 }
 
 // ============================================================================
@@ -64,11 +258,24 @@ export interface DebugLoggingConfig {
     output_dir?: string;
 }
 
-export type SchedulingMode = 'CacheFirst' | 'Balance' | 'PerformanceFirst';
+export type SchedulingMode = 'CacheFirst' | 'Balance' | 'PerformanceFirst' | 'Weighted';
 
 export interface StickySessionConfig {
     mode: SchedulingMode;
     max_wait_seconds: number;
+    /** 加权模式: 剩余配额权重指数 */
+    quota_weight?: number;
+    /** 加权模式: 健康分权重指数 */
+    health_weight?: number;
+    /** 加权模式: 延迟权重指数 */
+    latency_weight?: number;
+    /** 加权模式: 每个在途请求的惩罚系数 */
+    in_flight_weight?: number;
+    latency_ewma_alpha?: number;
+    /** 单账号最大在途请求数 (0 = 不限制) */
+    max_concurrent_per_account?: number;
+    /** 单账号单模型最大在途请求数 (0 = 不限制) */
+    max_concurrent_per_account_model?: number;
 }
 
 export type ZaiDispatchMode = 'off' | 'exclusive' | 'pooled' | 'fallback';
@@ -153,6 +153,16 @@ const COMMAND_MAPPING: Record<string, { url: string; method: 'GET' | 'POST' | 'D
   'delete_user_token': { url: '/api/user-tokens/:id', method: 'DELETE' },
   'update_user_token': { url: '/api/user-tokens/:id', method: 'PATCH' },
 
+  // Admin Users (多用户管理后台)
+  'list_admin_users': { url: '/api/admin-users', method: 'GET' },
+  'create_admin_user': { url: '/api/admin-users', method: 'POST' },
+  'update_admin_user': { url: '/api/admin-users/:id', method: 'PATCH' },
+  'delete_admin_user': { url: '/api/admin-users/:id', method: 'DELETE' },
+
+  // Audit Log
+  'query_audit_log': { url: '/api/audit', method: 'GET' },
+  'revert_config_from_audit': { url: '/api/audit/:id/revert', method: 'POST' },
+
   // Proxy Pool (Web Mode Fix)
   'get_proxy_pool_config': { url: '/api/proxy/pool/config', method: 'GET' },
   'get_all_account_bindings': { url: '/api/proxy/pool/bindings', method: 'GET' },